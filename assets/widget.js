@@ -0,0 +1,107 @@
+(function () {
+  var script = document.currentScript;
+  if (!script || !script.getAttribute("data-widget")) return;
+  var key = script.getAttribute("data-widget");
+  var api = new URL(script.src).origin;
+  var storageKey = "alpha-widget:" + key;
+  var ru = (navigator.language || "").toLowerCase().indexOf("ru") === 0;
+  var lang = ru ? "ru" : "en";
+
+  function el(tag, style, text) {
+    var node = document.createElement(tag);
+    if (style) node.style.cssText = style;
+    if (text) node.textContent = text;
+    return node;
+  }
+
+  function render(theme) {
+    var side = theme.position === "bottom-left" ? "left" : "right";
+    var color = theme.primary_color;
+
+    var button = el("button",
+      "position:fixed;bottom:20px;" + side + ":20px;width:56px;height:56px;border-radius:50%;border:0;" +
+      "background:" + color + ";color:#fff;font-size:24px;cursor:pointer;box-shadow:0 4px 12px rgba(0,0,0,.2);z-index:2147483646",
+      "\u{1F4AC}");
+    var panel = el("div",
+      "position:fixed;bottom:88px;" + side + ":20px;width:340px;max-width:calc(100vw - 40px);height:460px;" +
+      "max-height:calc(100vh - 120px);display:none;flex-direction:column;background:#fff;border-radius:12px;" +
+      "box-shadow:0 8px 24px rgba(0,0,0,.2);font:14px/1.4 sans-serif;color:#222;overflow:hidden;z-index:2147483647");
+    var header = el("div", "padding:12px 16px;background:" + color + ";color:#fff;font-weight:600",
+      theme.title || (ru ? "Чат с нами" : "Chat with us"));
+    var log = el("div", "flex:1;overflow-y:auto;padding:12px;display:flex;flex-direction:column;gap:8px");
+    var form = el("form", "display:flex;border-top:1px solid #eee");
+    var input = el("input", "flex:1;border:0;padding:12px;font:inherit;outline:none");
+    input.placeholder = theme.placeholder || (ru ? "Ваш вопрос…" : "Your question…");
+    input.maxLength = 2000;
+    var send = el("button", "border:0;background:none;color:" + color + ";padding:0 16px;font-weight:600;cursor:pointer",
+      ru ? "Отправить" : "Send");
+
+    form.appendChild(input);
+    form.appendChild(send);
+    panel.appendChild(header);
+    panel.appendChild(log);
+    panel.appendChild(form);
+    document.body.appendChild(panel);
+    document.body.appendChild(button);
+
+    function bubble(text, mine) {
+      var b = el("div",
+        "max-width:80%;padding:8px 12px;border-radius:12px;white-space:pre-wrap;word-wrap:break-word;" +
+        (mine ? "align-self:flex-end;background:" + color + ";color:#fff" : "align-self:flex-start;background:#f1f3f5"),
+        text);
+      log.appendChild(b);
+      log.scrollTop = log.scrollHeight;
+      return b;
+    }
+
+    if (theme.greeting) bubble(theme.greeting, false);
+
+    button.addEventListener("click", function () {
+      var open = panel.style.display === "flex";
+      panel.style.display = open ? "none" : "flex";
+      if (!open) input.focus();
+    });
+
+    form.addEventListener("submit", function (e) {
+      e.preventDefault();
+      var text = input.value.trim();
+      if (!text || send.disabled) return;
+      input.value = "";
+      bubble(text, true);
+      var pending = bubble("…", false);
+      send.disabled = true;
+
+      fetch(api + "/api/widget/messages", {
+        method: "POST",
+        headers: { "Content-Type": "application/json", "Accept-Language": lang },
+        body: JSON.stringify({ key: key, session_id: localStorage.getItem(storageKey), message: text })
+      })
+        .then(function (r) {
+          return r.json().then(function (data) {
+            if (!r.ok) throw new Error(data.error || r.status);
+            return data;
+          });
+        })
+        .then(function (data) {
+          localStorage.setItem(storageKey, data.session_id);
+          pending.textContent = data.reply;
+        })
+        .catch(function () {
+          pending.textContent = ru ? "Не удалось получить ответ, попробуйте позже." : "Couldn't get an answer, please try again later.";
+        })
+        .then(function () {
+          send.disabled = false;
+          log.scrollTop = log.scrollHeight;
+        });
+    });
+  }
+
+  fetch(api + "/api/widget/config?key=" + encodeURIComponent(key), { headers: { "Accept-Language": lang } })
+    .then(function (r) { return r.ok ? r.json() : null; })
+    .then(function (data) {
+      if (!data) return;
+      if (document.body) render(data.theme);
+      else document.addEventListener("DOMContentLoaded", function () { render(data.theme); });
+    })
+    .catch(function () {});
+})();
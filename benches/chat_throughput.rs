@@ -0,0 +1,42 @@
+//! Per-request latency of the hot endpoints. Start the server first, e.g.
+//!
+//! ```text
+//! LLM_MOCK_LATENCY_MS=50 DATABASE_MAX_CONNECTIONS=5 cargo run --release
+//! BENCH_BASE_URL=http://127.0.0.1:8080 cargo bench --bench chat_throughput
+//! ```
+//!
+//! Re-run with other DATABASE_MAX_CONNECTIONS / DATABASE_JOURNAL_MODE values on the server
+//! and Criterion reports the change against the previous run.
+
+mod common;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use reqwest::Client;
+use tokio::runtime::Runtime;
+
+use common as scenarios;
+use scenarios::Scenario;
+
+fn endpoints(c: &mut Criterion) {
+    let rt = Runtime::new().expect("tokio runtime");
+    let client = Client::new();
+    let base_url = scenarios::base_url();
+    let fixture = rt
+        .block_on(scenarios::prepare(&client, &base_url))
+        .unwrap_or_else(|e| panic!("benchmark setup against {} failed (is the server running?): {}", base_url, e));
+
+    let mut group = c.benchmark_group("http");
+    for scenario in Scenario::ALL {
+        group.bench_function(scenario.name(), |b| {
+            b.to_async(&rt).iter(|| async {
+                scenarios::run_once(&client, &fixture, scenario)
+                    .await
+                    .unwrap_or_else(|e| panic!("{} failed: {}", scenario.name(), e))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, endpoints);
+criterion_main!(benches);
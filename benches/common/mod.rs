@@ -0,0 +1,100 @@
+//! HTTP scenarios shared by the Criterion benchmarks and the `loadtest` example.
+//! Both run against a server started separately, normally with LLM_MOCK_LATENCY_MS set.
+//! Each of them uses only part of this module.
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use serde_json::{json, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scenario {
+    SendMessage,
+    ListConversations,
+    DownloadFile,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 3] = [Scenario::SendMessage, Scenario::ListConversations, Scenario::DownloadFile];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::SendMessage => "send_message",
+            Scenario::ListConversations => "list_conversations",
+            Scenario::DownloadFile => "download_file",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Scenario::ALL.into_iter().find(|s| s.name() == value)
+    }
+}
+
+/// Ids created once before measuring, so every request hits existing rows
+pub struct Fixture {
+    pub base_url: String,
+    pub user_id: String,
+    pub conversation_id: String,
+    pub file_id: String,
+}
+
+pub fn base_url() -> String {
+    std::env::var("BENCH_BASE_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string())
+}
+
+/// Starts a conversation whose answer carries a generated CSV, giving the other scenarios
+/// a conversation to list and a file to download
+pub async fn prepare(client: &Client, base_url: &str) -> Result<Fixture, Box<dyn std::error::Error>> {
+    let user_id = format!("bench-{}", uuid::Uuid::new_v4().simple());
+    let res: Value = client
+        .post(format!("{}/api/chat/message", base_url))
+        .json(&json!({
+            "message": "Benchmark setup",
+            "user_id": user_id,
+            "output_format": "csv",
+            "table": { "headers": ["item", "qty"], "rows": [["a", "1"], ["b", "2"]] },
+        }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let conversation_id = res["conversation_id"].as_str().ok_or("no conversation_id in setup reply")?.to_string();
+    let file_id = res["files"][0]["id"].as_str().ok_or("no generated file in setup reply")?.to_string();
+    Ok(Fixture { base_url: base_url.to_string(), user_id, conversation_id, file_id })
+}
+
+/// One request of the scenario; the error carries the status for non-2xx replies
+pub async fn run_once(client: &Client, fixture: &Fixture, scenario: Scenario) -> Result<Duration, String> {
+    let started = Instant::now();
+    let request = match scenario {
+        Scenario::SendMessage => client
+            .post(format!("{}/api/chat/message", fixture.base_url))
+            .json(&json!({
+                "message": "How do I price a new service?",
+                "user_id": fixture.user_id,
+                "conversation_id": fixture.conversation_id,
+            })),
+        Scenario::ListConversations => client.get(format!("{}/api/chat/conversations/{}", fixture.base_url, fixture.user_id)),
+        Scenario::DownloadFile => client.get(format!("{}/api/files/{}", fixture.base_url, fixture.file_id)),
+    };
+
+    let res = request.send().await.map_err(|e| e.to_string())?;
+    let status = res.status();
+    // Read the whole body so the timing includes the transfer
+    res.bytes().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(status.to_string());
+    }
+    Ok(started.elapsed())
+}
+
+/// `sqlite_pool_connections_in_use` from /metrics, None when the server does not report it
+pub async fn pool_in_use(client: &Client, base_url: &str) -> Option<u64> {
+    let body = client.get(format!("{}/metrics", base_url)).send().await.ok()?.text().await.ok()?;
+    body.lines()
+        .find_map(|line| line.strip_prefix("sqlite_pool_connections_in_use "))
+        .and_then(|v| v.trim().parse().ok())
+}
@@ -0,0 +1,149 @@
+//! Concurrent load against a running server, reporting throughput, latency percentiles and
+//! how many SQLite connections were in use. Start the server with the mock LLM, then:
+//!
+//! ```text
+//! cargo run --release --example loadtest -- send_message --concurrency 32 --duration 30
+//! ```
+//!
+//! Scenarios: send_message, list_conversations, download_file, or all.
+
+#[path = "../benches/common/mod.rs"]
+mod scenarios;
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use tokio::sync::Mutex;
+
+use scenarios::{Fixture, Scenario};
+
+struct Options {
+    scenarios: Vec<Scenario>,
+    concurrency: usize,
+    duration: Duration,
+    base_url: String,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let which = args.next().ok_or("usage: loadtest <scenario|all> [--concurrency N] [--duration SECS] [--base-url URL]")?;
+    let scenarios = if which == "all" {
+        Scenario::ALL.to_vec()
+    } else {
+        vec![Scenario::parse(&which).ok_or_else(|| format!("unknown scenario {}", which))?]
+    };
+
+    let mut options = Options { scenarios, concurrency: 16, duration: Duration::from_secs(20), base_url: scenarios::base_url() };
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--concurrency" => options.concurrency = value.parse().map_err(|_| "bad --concurrency")?,
+            "--duration" => options.duration = Duration::from_secs(value.parse().map_err(|_| "bad --duration")?),
+            "--base-url" => options.base_url = value,
+            _ => return Err(format!("unknown flag {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+async fn run(client: &Client, fixture: Arc<Fixture>, scenario: Scenario, options: &Options) {
+    let latencies = Arc::new(Mutex::new(Vec::new()));
+    let errors = Arc::new(AtomicU64::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // Samples pool usage while the workers run
+    let sampler = {
+        let (client, base_url, stop) = (client.clone(), options.base_url.clone(), stop.clone());
+        tokio::spawn(async move {
+            let (mut peak, mut total, mut samples) = (0u64, 0u64, 0u64);
+            while !stop.load(Ordering::Relaxed) {
+                if let Some(in_use) = scenarios::pool_in_use(&client, &base_url).await {
+                    peak = peak.max(in_use);
+                    total += in_use;
+                    samples += 1;
+                }
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+            (peak, if samples > 0 { total as f64 / samples as f64 } else { 0.0 })
+        })
+    };
+
+    let started = Instant::now();
+    let workers: Vec<_> = (0..options.concurrency).map(|_| {
+        let (client, fixture, latencies, errors, stop) = (client.clone(), fixture.clone(), latencies.clone(), errors.clone(), stop.clone());
+        tokio::spawn(async move {
+            while !stop.load(Ordering::Relaxed) {
+                match scenarios::run_once(&client, &fixture, scenario).await {
+                    Ok(elapsed) => latencies.lock().await.push(elapsed),
+                    Err(_) => {
+                        errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        })
+    }).collect();
+
+    tokio::time::sleep(options.duration).await;
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        let _ = worker.await;
+    }
+    let wall = started.elapsed();
+    let (pool_peak, pool_avg) = sampler.await.unwrap_or((0, 0.0));
+
+    let mut latencies = std::mem::take(&mut *latencies.lock().await);
+    latencies.sort();
+    let ok = latencies.len();
+    println!(
+        "{:<20} {:>8} ok {:>6} err {:>9.1} req/s  p50 {:>7.1}ms  p95 {:>7.1}ms  p99 {:>7.1}ms  pool in use avg {:.1} peak {}",
+        scenario.name(),
+        ok,
+        errors.load(Ordering::Relaxed),
+        ok as f64 / wall.as_secs_f64(),
+        percentile(&latencies, 50.0).as_secs_f64() * 1000.0,
+        percentile(&latencies, 95.0).as_secs_f64() * 1000.0,
+        percentile(&latencies, 99.0).as_secs_f64() * 1000.0,
+        pool_avg,
+        pool_peak,
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_args() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let client = Client::builder().pool_max_idle_per_host(options.concurrency).build().expect("http client");
+    let fixture = match scenarios::prepare(&client, &options.base_url).await {
+        Ok(f) => Arc::new(f),
+        Err(e) => {
+            eprintln!("Setup against {} failed (is the server running?): {}", options.base_url, e);
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "{} workers for {}s per scenario against {}",
+        options.concurrency,
+        options.duration.as_secs(),
+        options.base_url
+    );
+    for scenario in &options.scenarios {
+        run(&client, fixture.clone(), *scenario, &options).await;
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+
+use crate::services::analytics_refresh::AnalyticsRefreshPolicy;
+use crate::services::archive::ArchivePolicy;
+use crate::services::clarify::ClarificationPolicy;
+use crate::services::daily_digest::DailyDigestPolicy;
+use crate::services::escalation::EscalationPolicy;
+use crate::services::faq::AutoAnswerPolicy;
+use crate::services::greeting::GreetingSettings;
+use crate::services::password::PasswordPolicy;
+use crate::services::quality::QualityPolicy;
+use crate::services::storage::StoragePolicy;
+use crate::services::summary::SummaryPolicy;
+use crate::services::trends::TrendsPolicy;
+
+/// Settings that can change without a restart; everything else stays in env vars
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// Overrides OPENROUTER_MODEL when set
+    pub default_model: Option<String>,
+    /// Overrides OPENROUTER_VISION_MODEL for messages with images
+    pub vision_model: Option<String>,
+    /// Overrides TITLE_MODEL, the small model naming conversations
+    pub title_model: Option<String>,
+    /// Overrides ALLOWED_MODELS: models users may pick per conversation
+    pub allowed_models: Vec<String>,
+    pub feature_flags: HashMap<String, bool>,
+    pub password_policy: PasswordPolicy,
+    pub storage: StoragePolicy,
+    pub support_greeting: GreetingSettings,
+    pub support_escalation: EscalationPolicy,
+    pub support_auto_answer: AutoAnswerPolicy,
+    pub summarization: SummaryPolicy,
+    pub archive: ArchivePolicy,
+    pub clarification: ClarificationPolicy,
+    pub quality_eval: QualityPolicy,
+    pub daily_digest: DailyDigestPolicy,
+    pub analytics_refresh: AnalyticsRefreshPolicy,
+    pub trends: TrendsPolicy,
+}
+
+impl RuntimeConfig {
+    /// Flags missing from the file fall back to `default`
+    pub fn feature_enabled(&self, name: &str, default: bool) -> bool {
+        self.feature_flags.get(name).copied().unwrap_or(default)
+    }
+}
+
+pub type SharedConfig = Arc<ArcSwap<RuntimeConfig>>;
+
+fn config_path() -> String {
+    std::env::var("CONFIG_PATH").unwrap_or_else(|_| "config.json".to_string())
+}
+
+/// Reads the JSON config file; a missing file means defaults
+pub fn load() -> Result<RuntimeConfig, Box<dyn std::error::Error>> {
+    match std::fs::read_to_string(config_path()) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RuntimeConfig::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes `config` back to the JSON file and makes it the active one
+pub fn save(shared: &SharedConfig, config: RuntimeConfig) -> Result<Arc<RuntimeConfig>, Box<dyn std::error::Error>> {
+    std::fs::write(config_path(), serde_json::to_string_pretty(&config)?)?;
+    let fresh = Arc::new(config);
+    shared.store(fresh.clone());
+    Ok(fresh)
+}
+
+/// Swaps in a freshly read config; on error the current one stays active
+pub fn reload(shared: &SharedConfig) -> Result<Arc<RuntimeConfig>, Box<dyn std::error::Error>> {
+    let fresh = Arc::new(load()?);
+    shared.store(fresh.clone());
+    Ok(fresh)
+}
+
+/// Reloads on SIGHUP for the lifetime of the process
+#[cfg(unix)]
+pub fn watch_sighup(shared: SharedConfig) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    actix_web::rt::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Config: SIGHUP handler unavailable: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            match reload(&shared) {
+                Ok(_) => println!("Config: reloaded on SIGHUP"),
+                Err(e) => eprintln!("Config: reload failed, keeping previous settings: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn watch_sighup(_shared: SharedConfig) {}
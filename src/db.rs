@@ -1,4 +1,4 @@
-use sqlx::{sqlite::{SqlitePoolOptions, SqliteConnectOptions}, SqlitePool};
+use sqlx::{sqlite::{SqlitePoolOptions, SqliteConnectOptions, SqliteJournalMode}, SqlitePool};
 use std::str::FromStr;
 use uuid::Uuid;
 
@@ -213,12 +213,26 @@ async fn seed_analytics_data(pool: &SqlitePool) -> Result<(), sqlx::Error> {
 }
 
 pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
-    let connect_opts = SqliteConnectOptions::from_str(database_url)?
+    let mut connect_opts = SqliteConnectOptions::from_str(database_url)?
         .create_if_missing(true)
         .foreign_keys(true);
+    // Tuning knobs for load tests; the defaults are what production has always run with
+    if let Ok(mode) = std::env::var("DATABASE_JOURNAL_MODE") {
+        connect_opts = connect_opts.journal_mode(match mode.to_lowercase().as_str() {
+            "wal" => SqliteJournalMode::Wal,
+            "truncate" => SqliteJournalMode::Truncate,
+            "memory" => SqliteJournalMode::Memory,
+            _ => SqliteJournalMode::Delete,
+        });
+    }
+    let max_connections = std::env::var("DATABASE_MAX_CONNECTIONS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(5);
 
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(max_connections)
         .connect_with(connect_opts)
         .await?;
 
@@ -305,6 +319,10 @@ pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
         .execute(&pool)
         .await;
 
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN analytics_opt_in INTEGER NOT NULL DEFAULT 0;")
+        .execute(&pool)
+        .await;
+
     sqlx::query(
         r#"
         CREATE TABLE IF NOT EXISTS sessions (
@@ -319,6 +337,16 @@ pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
     .execute(&pool)
     .await?;
 
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN remember_me INTEGER NOT NULL DEFAULT 1;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN device_id TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN device_name TEXT;")
+        .execute(&pool)
+        .await;
+
     // New analytics tables structure
     
     // Top weekly trends: stores current top trend, 2nd place, and geo trends
@@ -586,6 +614,16 @@ pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
         .execute(&pool)
         .await;
 
+    // Soft deletion: trashed files are hidden and purged by the scheduler
+    let _ = sqlx::query("ALTER TABLE files ADD COLUMN deleted_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Owner for storage accounting; set on write, older rows are backfilled below
+    let _ = sqlx::query("ALTER TABLE files ADD COLUMN user_id TEXT;")
+        .execute(&pool)
+        .await;
+
     // Support chat tables
     sqlx::query(
         r#"
@@ -663,5 +701,1171 @@ pub async fn init_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
     .execute(&pool)
     .await?;
 
+    // Mini-CRM leads owned by a user
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS leads (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            name TEXT,
+            email TEXT,
+            phone TEXT,
+            company TEXT,
+            notes TEXT,
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_leads_user_email ON leads(user_id, email);")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_leads_user_phone ON leads(user_id, phone);")
+        .execute(&pool)
+        .await?;
+
+    // Bookable services of a business owner; working hours are UTC "HH:MM"
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS booking_services (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            title TEXT NOT NULL,
+            duration_minutes INTEGER NOT NULL CHECK(duration_minutes > 0),
+            work_start TEXT NOT NULL DEFAULT '09:00',
+            work_end TEXT NOT NULL DEFAULT '18:00',
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS bookings (
+            id TEXT PRIMARY KEY,
+            service_id TEXT NOT NULL,
+            owner_user_id TEXT NOT NULL,
+            client_name TEXT NOT NULL,
+            client_email TEXT,
+            client_phone TEXT,
+            starts_at TEXT NOT NULL,
+            ends_at TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'confirmed' CHECK(status IN ('confirmed', 'cancelled')),
+            reminder_sent_at TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY(service_id) REFERENCES booking_services(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_bookings_service_start ON bookings(service_id, starts_at);")
+        .execute(&pool)
+        .await?;
+
+    // Light inventory: low_stock_notified_at is cleared again once the item is restocked
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS inventory_items (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            sku TEXT,
+            quantity REAL NOT NULL DEFAULT 0,
+            unit TEXT,
+            low_stock_threshold REAL,
+            low_stock_notified_at TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_inventory_items_user ON inventory_items(user_id);")
+        .execute(&pool)
+        .await?;
+
+    // Issued invoices, numbered sequentially per user; the PDF lives in files
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS invoices (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            number INTEGER NOT NULL,
+            buyer_name TEXT NOT NULL,
+            currency TEXT NOT NULL,
+            total REAL NOT NULL,
+            file_id TEXT,
+            issued_at TEXT NOT NULL,
+            UNIQUE(user_id, number),
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    let _ = sqlx::query("ALTER TABLE invoices ADD COLUMN status TEXT NOT NULL DEFAULT 'issued';")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE invoices ADD COLUMN paid_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Payment links issued to a user's customers; status is driven by provider webhooks
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS payment_links (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            invoice_id TEXT,
+            provider TEXT NOT NULL,
+            provider_payment_id TEXT,
+            amount REAL NOT NULL,
+            currency TEXT NOT NULL,
+            description TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending' CHECK(status IN ('pending','succeeded','canceled')),
+            confirmation_url TEXT,
+            created_at TEXT NOT NULL,
+            paid_at TEXT,
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+            FOREIGN KEY(invoice_id) REFERENCES invoices(id) ON DELETE SET NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_payment_links_provider_id ON payment_links(provider, provider_payment_id);")
+        .execute(&pool)
+        .await?;
+
+    // Incremental warehouse export progress, one row per exported table
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS export_watermarks (
+            table_name TEXT PRIMARY KEY,
+            watermark TEXT NOT NULL,
+            exported_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Coarse topic per conversation of opted-in users; holds no message content
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS conversation_topics (
+            conversation_id TEXT PRIMARY KEY,
+            topic TEXT NOT NULL,
+            business_type TEXT,
+            classified_at TEXT NOT NULL,
+            FOREIGN KEY(conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Incrementally maintained per-user aggregates (language, category, rating)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_stats (
+            user_id TEXT NOT NULL,
+            dimension TEXT NOT NULL,
+            key TEXT NOT NULL,
+            count INTEGER NOT NULL DEFAULT 0,
+            total REAL NOT NULL DEFAULT 0,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY(user_id, dimension, key)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Full-text index over message content, kept in sync by triggers
+    let fts_exists: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM sqlite_master WHERE type = 'table' AND name = 'messages_fts'"
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+            content,
+            content='messages',
+            content_rowid='rowid',
+            tokenize='unicode61 remove_diacritics 2'
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS messages_fts_ai AFTER INSERT ON messages BEGIN
+            INSERT INTO messages_fts(rowid, content) VALUES (new.rowid, new.content);
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS messages_fts_ad AFTER DELETE ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS messages_fts_au AFTER UPDATE OF content ON messages BEGIN
+            INSERT INTO messages_fts(messages_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+            INSERT INTO messages_fts(rowid, content) VALUES (new.rowid, new.content);
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Index messages written before the FTS table existed
+    if fts_exists == 0 {
+        sqlx::query("INSERT INTO messages_fts(messages_fts) VALUES ('rebuild');")
+            .execute(&pool)
+            .await?;
+    }
+
+    // Paging indexes for history and per-message attachments. History sorts on julianday(timestamp),
+    // so the index has to be on that expression; a plain column index is never used for it.
+    sqlx::query("DROP INDEX IF EXISTS idx_messages_conversation_ts;")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_conversation_jd ON messages(conversation_id, julianday(timestamp));")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_files_message ON files(message_id);")
+        .execute(&pool)
+        .await?;
+
+    // Storage plans decide the per-user quota
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN plan TEXT NOT NULL DEFAULT 'free';")
+        .execute(&pool)
+        .await;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_files_user ON files(user_id);")
+        .execute(&pool)
+        .await?;
+
+    // Content-addressed storage shared by identical files
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_blobs (
+            sha256 TEXT PRIMARY KEY,
+            bytes BLOB NOT NULL,
+            size INTEGER NOT NULL,
+            ref_count INTEGER NOT NULL DEFAULT 0
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    let _ = sqlx::query("ALTER TABLE files ADD COLUMN sha256 TEXT;")
+        .execute(&pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN edited_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Deleting a file row releases its blob once nothing references it
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS files_blob_release AFTER DELETE ON files
+        WHEN old.sha256 IS NOT NULL BEGIN
+            UPDATE file_blobs SET ref_count = ref_count - 1 WHERE sha256 = old.sha256;
+            DELETE FROM file_blobs WHERE sha256 = old.sha256 AND ref_count <= 0;
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Attribute files stored before ownership was recorded
+    sqlx::query(
+        r#"
+        UPDATE files SET user_id = COALESCE(
+            (SELECT c.user_id FROM messages m JOIN conversations c ON c.id = m.conversation_id WHERE m.id = files.message_id),
+            (SELECT i.user_id FROM invoices i WHERE i.file_id = files.id),
+            (SELECT u.id FROM users u WHERE u.profile_picture = files.id)
+        )
+        WHERE user_id IS NULL;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Full-text index over conversation titles, same scheme as messages_fts
+    let titles_fts_exists: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM sqlite_master WHERE type = 'table' AND name = 'conversations_fts'"
+    )
+    .fetch_one(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE VIRTUAL TABLE IF NOT EXISTS conversations_fts USING fts5(
+            title,
+            content='conversations',
+            content_rowid='rowid',
+            tokenize='unicode61 remove_diacritics 2'
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS conversations_fts_ai AFTER INSERT ON conversations BEGIN
+            INSERT INTO conversations_fts(rowid, title) VALUES (new.rowid, new.title);
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS conversations_fts_ad AFTER DELETE ON conversations BEGIN
+            INSERT INTO conversations_fts(conversations_fts, rowid, title) VALUES ('delete', old.rowid, old.title);
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS conversations_fts_au AFTER UPDATE OF title ON conversations BEGIN
+            INSERT INTO conversations_fts(conversations_fts, rowid, title) VALUES ('delete', old.rowid, old.title);
+            INSERT INTO conversations_fts(rowid, title) VALUES (new.rowid, new.title);
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    if titles_fts_exists == 0 {
+        sqlx::query("INSERT INTO conversations_fts(conversations_fts) VALUES ('rebuild');")
+            .execute(&pool)
+            .await?;
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS bundle_jobs (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            file_ids TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            file_id TEXT,
+            error TEXT,
+            created_at TEXT NOT NULL,
+            started_at TEXT,
+            completed_at TEXT
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // 'quarantined' marks uploads the antivirus flagged; they are kept for review but never served
+    let _ = sqlx::query("ALTER TABLE files ADD COLUMN scan_status TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE files ADD COLUMN scan_signature TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Conversations are soft-deleted so mistakes can be undone; the scheduler purges them later
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN archived_at TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN deleted_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;")
+        .execute(&pool)
+        .await;
+
+    // Activity used to order the conversation list; kept current by the trigger below
+    let last_message_added = sqlx::query("ALTER TABLE conversations ADD COLUMN last_message_at TEXT;")
+        .execute(&pool)
+        .await
+        .is_ok();
+    if last_message_added {
+        sqlx::query(
+            "UPDATE conversations SET last_message_at =
+                (SELECT MAX(timestamp) FROM messages WHERE messages.conversation_id = conversations.id)"
+        )
+        .execute(&pool)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS conversations_last_message AFTER INSERT ON messages BEGIN
+            UPDATE conversations SET last_message_at = new.timestamp WHERE id = new.conversation_id;
+        END;
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Matches the ORDER BY of the conversation list
+    sqlx::query("DROP INDEX IF EXISTS idx_conversations_user_created;")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_conversations_user_activity
+         ON conversations(user_id, pinned, julianday(COALESCE(last_message_at, created_at)));"
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS upload_sessions (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            mime TEXT NOT NULL,
+            total_size INTEGER NOT NULL,
+            received_size INTEGER NOT NULL DEFAULT 0,
+            status TEXT NOT NULL DEFAULT 'open',
+            file_id TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS upload_chunks (
+            upload_id TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            bytes BLOB NOT NULL,
+            PRIMARY KEY (upload_id, position)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Text pulled out of chat attachments, replayed to the model with the conversation history
+    let _ = sqlx::query("ALTER TABLE files ADD COLUMN extracted_text TEXT;")
+        .execute(&pool)
+        .await;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_support_messages_user_created ON support_messages(user_id, created_at);")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS conversation_summaries (
+            conversation_id TEXT PRIMARY KEY,
+            summary TEXT NOT NULL,
+            covered_entries INTEGER NOT NULL,
+            covered_digest TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    let _ = sqlx::query("ALTER TABLE support_messages ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';")
+        .execute(&pool)
+        .await;
+
+    // One row per reminder sent for an unanswered support message
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS support_escalations (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            support_message_id TEXT NOT NULL,
+            level INTEGER NOT NULL,
+            chat_id INTEGER NOT NULL,
+            telegram_message_id INTEGER,
+            waited_minutes INTEGER NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE(support_message_id, level),
+            FOREIGN KEY(support_message_id) REFERENCES support_messages(id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS faqs (
+            id TEXT PRIMARY KEY,
+            question TEXT NOT NULL,
+            answer TEXT NOT NULL,
+            locale TEXT NOT NULL,
+            active INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // JSON array of lowercase tags
+    let _ = sqlx::query("ALTER TABLE faqs ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';")
+        .execute(&pool)
+        .await;
+
+    // FAQ auto answers: the reply carries the entry and the question it answers,
+    // the question is marked resolved until the user asks for a human
+    let _ = sqlx::query("ALTER TABLE support_messages ADD COLUMN faq_id TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE support_messages ADD COLUMN in_reply_to TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE support_messages ADD COLUMN auto_resolved_at TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE support_messages ADD COLUMN human_requested_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Category and model of assistant answers, for feedback reporting
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN category TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN model TEXT;")
+        .execute(&pool)
+        .await;
+
+    // One rating per answer; rating is 1 for thumbs up, -1 for thumbs down. Category and
+    // model are copied from the answer so ratings outlive regenerated and purged messages.
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS message_feedback (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL UNIQUE,
+            user_id TEXT NOT NULL,
+            rating INTEGER NOT NULL,
+            comment TEXT,
+            category TEXT,
+            model TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Monthly partitions of chat and support history; see services::archive
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_messages_month ON messages(substr(timestamp, 1, 7));")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_support_messages_month ON support_messages(substr(created_at, 1, 7));")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS archived_partitions (
+            id TEXT PRIMARY KEY,
+            table_name TEXT NOT NULL,
+            month TEXT NOT NULL,
+            rows INTEGER NOT NULL,
+            size_bytes INTEGER NOT NULL,
+            sha256 TEXT NOT NULL,
+            path TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Public read-only links; at most one active (not revoked) link per conversation
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS conversation_shares (
+            token TEXT PRIMARY KEY,
+            conversation_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            revoked_at TEXT
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_conversation_shares_conversation ON conversation_shares(conversation_id);")
+        .execute(&pool)
+        .await?;
+
+    // Set on conversations branched off another one at a given message
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN forked_from TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN forked_from_message_id TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Per-category model overrides, see openai::chat_model
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS category_models (
+            category TEXT PRIMARY KEY,
+            model TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Versioned disclaimers appended to answers server-side; rows are only ever added
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS disclaimers (
+            id TEXT PRIMARY KEY,
+            category TEXT NOT NULL,
+            locale TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            text TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE(category, locale, version)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO disclaimers (id, category, locale, version, text, created_at) VALUES
+            ('legal-ru-1', 'legal', 'ru', 1, 'Это общая информация, а не юридическая консультация. Для решений по вашей ситуации обратитесь к юристу.', strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+            ('legal-en-1', 'legal', 'en', 1, 'This is general information, not legal advice. Consult a qualified lawyer before acting on it.', strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+            ('finance-ru-1', 'finance', 'ru', 1, 'Это общая информация, а не финансовая или налоговая консультация. Перед решениями проконсультируйтесь с бухгалтером или финансовым советником.', strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+            ('finance-en-1', 'finance', 'en', 1, 'This is general information, not financial or tax advice. Check with an accountant or financial adviser before making decisions.', strftime('%Y-%m-%dT%H:%M:%fZ','now'));
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Disclaimer version an assistant answer went out with
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN disclaimer_id TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Clarifying questions asked so far while the conversation waits to be answered, see services::clarify
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN pending_clarification INTEGER;")
+        .execute(&pool)
+        .await;
+
+    // System prompt version an assistant answer was generated with, see openai::PROMPT_VERSION
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN prompt_version TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Judge scores for sampled answers, see services::quality. No foreign key: answers may be
+    // archived or purged while their scores stay for the trend
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS answer_scores (
+            message_id TEXT PRIMARY KEY,
+            conversation_id TEXT NOT NULL,
+            category TEXT,
+            model TEXT,
+            prompt_version TEXT,
+            correctness REAL NOT NULL,
+            actionability REAL NOT NULL,
+            locale_compliance REAL NOT NULL,
+            overall REAL NOT NULL,
+            comment TEXT,
+            judge_model TEXT NOT NULL,
+            scored_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_answer_scores_scored ON answer_scores(scored_at);")
+        .execute(&pool)
+        .await?;
+
+    // Per-user documents indexed for retrieval, see services::knowledge. The file itself lives in `files`
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS knowledge_documents (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            file_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            mime TEXT NOT NULL,
+            chunk_count INTEGER NOT NULL,
+            embedding_model TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_knowledge_documents_user ON knowledge_documents(user_id, created_at);")
+        .execute(&pool)
+        .await?;
+
+    // One row per document chunk; `vector` is little-endian f32s
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS embeddings (
+            id TEXT PRIMARY KEY,
+            document_id TEXT NOT NULL REFERENCES knowledge_documents(id),
+            user_id TEXT NOT NULL,
+            chunk_index INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            vector BLOB NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_embeddings_user ON embeddings(user_id);")
+        .execute(&pool)
+        .await?;
+
+    // Model the user picked for the conversation, see openai::chat_model
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN model TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Outbound deliveries that exhausted their retries, see services::dead_letters
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS dead_letters (
+            id TEXT PRIMARY KEY,
+            channel TEXT NOT NULL,
+            payload TEXT NOT NULL,
+            error TEXT NOT NULL,
+            attempts INTEGER NOT NULL,
+            status TEXT NOT NULL DEFAULT 'pending',
+            created_at TEXT NOT NULL,
+            last_attempt_at TEXT NOT NULL,
+            replayed_at TEXT
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_dead_letters_status ON dead_letters(status, created_at);")
+        .execute(&pool)
+        .await?;
+
+    // 'pending' on user messages accepted while the LLM provider was down, NULL otherwise
+    let _ = sqlx::query("ALTER TABLE messages ADD COLUMN status TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Pending messages waiting for an answer, see services::retry_queue
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS queued_turns (
+            id TEXT PRIMARY KEY,
+            message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+            conversation_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            request TEXT NOT NULL,
+            locale TEXT NOT NULL,
+            region TEXT,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            next_attempt_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_queued_turns_due ON queued_turns(next_attempt_at);")
+        .execute(&pool)
+        .await?;
+
+    // IANA zone the scheduler and history timestamps use for the user; NULL means UTC
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN timezone TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN digest_sent_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // The first version kept one marker per account; markers are only a convenience, so that
+    // table is dropped rather than migrated and unread counts fall back to the last user message
+    let per_identity: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM pragma_table_info('conversation_reads') WHERE name = 'identity'"
+    )
+    .fetch_one(&pool)
+    .await?;
+    if per_identity == 0 {
+        sqlx::query("DROP TABLE IF EXISTS conversation_reads;")
+            .execute(&pool)
+            .await?;
+    }
+
+    // Read marker per identity (account id or Telegram user id) and conversation; `user_id` is
+    // the account owning the conversation, and its unread count starts after the newest marker
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS conversation_reads (
+            identity TEXT NOT NULL,
+            conversation_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            channel TEXT NOT NULL CHECK(channel IN ('app', 'telegram')),
+            last_read_message_id TEXT NOT NULL,
+            last_read_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY(identity, conversation_id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_conversation_reads_conversation ON conversation_reads(conversation_id, user_id);")
+        .execute(&pool)
+        .await?;
+
+    // Chat widgets embedded on users' own sites, see handlers::widgets
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS widgets (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            public_key TEXT NOT NULL UNIQUE,
+            allowed_origins TEXT NOT NULL,
+            theme TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            revoked_at TEXT,
+            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_widgets_user ON widgets(user_id);")
+        .execute(&pool)
+        .await?;
+
+    // Visitor threads are kept apart from the owner's own conversations
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS widget_sessions (
+            id TEXT PRIMARY KEY,
+            widget_id TEXT NOT NULL REFERENCES widgets(id) ON DELETE CASCADE,
+            origin TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            last_seen_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS widget_messages (
+            id TEXT PRIMARY KEY,
+            session_id TEXT NOT NULL REFERENCES widget_sessions(id) ON DELETE CASCADE,
+            role TEXT NOT NULL CHECK(role IN ('user', 'assistant')),
+            content TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_widget_messages_session ON widget_messages(session_id, created_at);")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS widget_usage (
+            widget_id TEXT NOT NULL,
+            day TEXT NOT NULL,
+            messages INTEGER NOT NULL DEFAULT 0,
+            sessions INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY(widget_id, day)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Public feedback board: feature requests and bug reports users vote on
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS feedback_items (
+            id TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            kind TEXT NOT NULL CHECK(kind IN ('feature', 'bug')),
+            title TEXT NOT NULL,
+            description TEXT NOT NULL,
+            screenshot_file_id TEXT,
+            locale TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'open' CHECK(status IN ('open', 'planned', 'in_progress', 'done', 'declined')),
+            admin_note TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_feedback_items_locale_status ON feedback_items(locale, status);")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS feedback_votes (
+            item_id TEXT NOT NULL REFERENCES feedback_items(id) ON DELETE CASCADE,
+            user_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY(item_id, user_id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Opt-in morning digest written by the model into its own conversation
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN daily_digest INTEGER NOT NULL DEFAULT 0;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN daily_digest_conversation_id TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN daily_digest_sent_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Unsent text per conversation, keyed by the owning account so the app and the bot share it
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS conversation_drafts (
+            conversation_id TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            content TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY(conversation_id, user_id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Language the conversation is answered in, set from its first message
+    let _ = sqlx::query("ALTER TABLE conversations ADD COLUMN language TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Current refresh token of the session and the one it replaced, both SHA-256 hex
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN refresh_token_hash TEXT;")
+        .execute(&pool)
+        .await;
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN refresh_previous_hash TEXT;")
+        .execute(&pool)
+        .await;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_sessions_refresh ON sessions(refresh_token_hash);")
+        .execute(&pool)
+        .await?;
+
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN last_used_at TEXT;")
+        .execute(&pool)
+        .await;
+
+    // Google/Apple accounts signed in with, by the provider's stable user id
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS oauth_identities (
+            provider TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id TEXT NOT NULL,
+            email TEXT,
+            created_at TEXT NOT NULL,
+            PRIMARY KEY(provider, subject),
+            FOREIGN KEY(user_id) REFERENCES users(id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_oauth_identities_user ON oauth_identities(user_id);")
+        .execute(&pool)
+        .await?;
+
+    // Pending phone sign-in codes, one per number; only a hash of the code is kept
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS otp_codes (
+            phone TEXT PRIMARY KEY,
+            code_hash TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Onboarding steps the client marked done or skipped
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS onboarding_steps (
+            user_id TEXT NOT NULL,
+            step TEXT NOT NULL,
+            completed_at TEXT NOT NULL,
+            PRIMARY KEY(user_id, step)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Email changes waiting for the link sent to the new address; one per user
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS email_changes (
+            user_id TEXT PRIMARY KEY,
+            new_email TEXT NOT NULL,
+            code_hash TEXT NOT NULL UNIQUE,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            FOREIGN KEY(user_id) REFERENCES users(id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Per-user settings read by chat, push notifications and the daily digest
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_preferences (
+            user_id TEXT PRIMARY KEY,
+            locale TEXT,
+            push_notifications INTEGER NOT NULL DEFAULT 1,
+            default_category TEXT,
+            default_output_format TEXT,
+            updated_at TEXT,
+            FOREIGN KEY(user_id) REFERENCES users(id)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    // Chat model the user picked for conversations without one of their own
+    let _ = sqlx::query("ALTER TABLE user_preferences ADD COLUMN default_model TEXT;").execute(&pool).await;
+
+    // Thumbnail variant of the profile picture
+    let _ = sqlx::query("ALTER TABLE users ADD COLUMN profile_picture_thumb TEXT;").execute(&pool).await;
+
+    // Client behind each session, for the sessions listing and security reviews
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN user_agent TEXT;").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN platform TEXT;").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN created_ip TEXT;").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE sessions ADD COLUMN last_ip TEXT;").execute(&pool).await;
+
+    // Region (ISO country code) and niche of analytics rows; '' is the global dataset
+    let _ = sqlx::query("ALTER TABLE ai_analytics ADD COLUMN region TEXT NOT NULL DEFAULT '';").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE ai_analytics ADD COLUMN niche TEXT NOT NULL DEFAULT '';").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE niches_month ADD COLUMN region TEXT NOT NULL DEFAULT '';").execute(&pool).await;
+    let _ = sqlx::query("ALTER TABLE niches_month ADD COLUMN niche TEXT NOT NULL DEFAULT '';").execute(&pool).await;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_analytics_scope ON ai_analytics(region, niche, created_at);")
+        .execute(&pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_niches_month_scope ON niches_month(month_start, region, niche);")
+        .execute(&pool)
+        .await?;
+
+    // Analytics datasets generated by the model, one row per dataset, scope and week or month
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS analytics_refresh_runs (
+            dataset TEXT NOT NULL,
+            region TEXT NOT NULL DEFAULT '',
+            niche TEXT NOT NULL DEFAULT '',
+            period TEXT NOT NULL,
+            model TEXT NOT NULL,
+            generated_at TEXT NOT NULL,
+            PRIMARY KEY(dataset, region, niche, period)
+        );
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+
+    // Rowid breaks ties between rows exported under the same timestamp; existing watermarks start at 0,
+    // so the last exported timestamp is dumped once more rather than risk skipping rows
+    let _ = sqlx::query("ALTER TABLE export_watermarks ADD COLUMN watermark_rowid INTEGER NOT NULL DEFAULT 0;").execute(&pool).await;
+
+    // Sessions whose bare id still works as a token: only those from before JWTs. A JWT session's id
+    // travels in every access token's `sid`, so it must not be usable on its own. Marked once, when the
+    // column is added; sessions without a refresh token are the ones that predate JWTs.
+    if sqlx::query("ALTER TABLE sessions ADD COLUMN legacy_bearer INTEGER NOT NULL DEFAULT 0;").execute(&pool).await.is_ok() {
+        sqlx::query("UPDATE sessions SET legacy_bearer = 1 WHERE refresh_token_hash IS NULL;")
+            .execute(&pool)
+            .await?;
+    }
+
+    // Whether the owner knows the account's password. OAuth and phone sign-ups get a random one
+    // nobody knows; when the column is added, they are recognized by the phone placeholder email or
+    // by a provider linked within a minute of the account's creation.
+    if sqlx::query("ALTER TABLE users ADD COLUMN has_password INTEGER NOT NULL DEFAULT 1;").execute(&pool).await.is_ok() {
+        sqlx::query(
+            "UPDATE users SET has_password = 0
+             WHERE email LIKE 'phone%@users.invalid'
+                OR EXISTS(SELECT 1 FROM oauth_identities o WHERE o.user_id = users.id
+                          AND abs(julianday(o.created_at) - julianday(users.created_at)) < 1.0 / 1440)"
+        )
+        .execute(&pool)
+        .await?;
+    }
+
     Ok(pool)
 }
@@ -0,0 +1,499 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::config;
+use crate::services::archive::{self, PARTITIONED_TABLES};
+use crate::services::dead_letters::{self, DeadLetter};
+use crate::services::disclaimer::{self, Disclaimer};
+use crate::services::export::{self, EXPORT_TABLES};
+use crate::services::greeting::GreetingSettings;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Admin endpoints are guarded by the `X-Admin-Token` header matching ADMIN_TOKEN
+pub(crate) fn require_admin(req: &HttpRequest, locale: Locale) -> Result<(), HttpResponse> {
+    let expected = std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+    let provided = req.headers().get("X-Admin-Token").and_then(|v| v.to_str().ok());
+
+    match (expected, provided) {
+        (Some(expected), Some(provided)) if expected == provided => Ok(()),
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Доступ запрещен",
+                Locale::En => "admin-access-required",
+            };
+            Err(HttpResponse::Forbidden().json(json!({ "error": error_msg })))
+        }
+    }
+}
+
+pub async fn run_export(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    match export::run_export(&state.pool).await {
+        Ok(results) => HttpResponse::Ok().json(json!({ "exports": results })),
+        Err(e) => {
+            eprintln!("Warehouse export failed: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка экспорта",
+                Locale::En => "export-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+pub async fn export_schema(req: HttpRequest) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let tables: Vec<_> = EXPORT_TABLES.iter().map(|t| json!({
+        "name": t.name,
+        "description": t.description,
+        "format": "csv",
+        "columns": t.columns.iter().map(|c| json!({
+            "name": c.name,
+            "description": c.description,
+            "pseudonymized": c.pseudonymize,
+        })).collect::<Vec<_>>(),
+    })).collect();
+
+    HttpResponse::Ok().json(json!({ "tables": tables }))
+}
+
+#[derive(Deserialize)]
+pub struct TopicReportQuery {
+    pub days: Option<i64>,
+}
+
+/// Buckets smaller than this are withheld so rare segments can't single anyone out
+const MIN_TOPIC_BUCKET: i64 = 5;
+
+pub async fn topic_report(
+    req: HttpRequest,
+    query: web::Query<TopicReportQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    let since = (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+
+    let rows = sqlx::query(
+        "SELECT topic, COALESCE(business_type, '') AS business_type, COUNT(*) AS conversations
+         FROM conversation_topics
+         WHERE classified_at >= ?
+         GROUP BY topic, business_type
+         HAVING COUNT(*) >= ?
+         ORDER BY conversations DESC"
+    )
+    .bind(&since)
+    .bind(MIN_TOPIC_BUCKET)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let mut totals: Vec<(String, i64)> = Vec::new();
+            let breakdown: Vec<_> = rs.iter().map(|r| {
+                let topic: String = r.get("topic");
+                let count: i64 = r.get("conversations");
+                match totals.iter_mut().find(|(t, _)| *t == topic) {
+                    Some(entry) => entry.1 += count,
+                    None => totals.push((topic.clone(), count)),
+                }
+                json!({
+                    "topic": topic,
+                    "business_type": r.get::<String, _>("business_type"),
+                    "conversations": count,
+                })
+            }).collect();
+            totals.sort_by_key(|t| std::cmp::Reverse(t.1));
+
+            HttpResponse::Ok().json(json!({
+                "days": days,
+                "topics": totals.into_iter().map(|(topic, count)| json!({ "topic": topic, "conversations": count })).collect::<Vec<_>>(),
+                "by_business_type": breakdown,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn reload_config(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    match config::reload(&state.config) {
+        Ok(current) => HttpResponse::Ok().json(json!({ "config": *current })),
+        Err(e) => {
+            eprintln!("Config reload failed: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось перечитать конфигурацию",
+                Locale::En => "config-reload-failed",
+            };
+            HttpResponse::BadRequest().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+pub async fn get_support_greeting(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    HttpResponse::Ok().json(json!({ "support_greeting": state.config.load().support_greeting }))
+}
+
+/// Replaces the support greeting settings and persists them to the config file
+pub async fn update_support_greeting(
+    req: HttpRequest,
+    body: web::Json<GreetingSettings>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let settings = body.into_inner();
+    let valid_offset = (-14 * 60..=14 * 60).contains(&settings.utc_offset_minutes);
+    let valid_hours = settings.open_hour < settings.close_hour && settings.close_hour <= 24;
+    let valid_days = settings.work_days.iter().all(|d| (1..=7).contains(d));
+    if !(valid_offset && valid_hours && valid_days) {
+        let error_msg = match locale {
+            Locale::Ru => "Некорректные рабочие часы",
+            Locale::En => "invalid-business-hours",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let mut updated = (**state.config.load()).clone();
+    updated.support_greeting = settings;
+    match config::save(&state.config, updated) {
+        Ok(current) => HttpResponse::Ok().json(json!({ "support_greeting": current.support_greeting })),
+        Err(e) => {
+            eprintln!("Config save failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UserLookupQuery {
+    pub email: String,
+}
+
+/// Support lookup of an account by email; the public check-user endpoint no longer reveals this
+pub async fn lookup_user(
+    req: HttpRequest,
+    query: web::Query<UserLookupQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let row = sqlx::query(
+        "SELECT id, email, business_type, created_at, full_name, telegram_username, profile_picture FROM users WHERE email = ? LIMIT 1"
+    )
+    .bind(query.email.trim())
+    .fetch_optional(&state.pool)
+    .await;
+
+    match row {
+        Ok(Some(r)) => HttpResponse::Ok().json(json!({
+            "exists": true,
+            "user": {
+                "id": r.get::<String, _>("id"),
+                "email": r.get::<String, _>("email"),
+                "business_type": r.get::<String, _>("business_type"),
+                "created_at": r.get::<String, _>("created_at"),
+                "full_name": r.try_get::<Option<String>, _>("full_name").unwrap_or(None),
+                "telegram_username": r.try_get::<Option<String>, _>("telegram_username").unwrap_or(None),
+                "profile_picture": r.try_get::<Option<String>, _>("profile_picture").unwrap_or(None),
+            },
+        })),
+        Ok(None) => HttpResponse::Ok().json(json!({ "exists": false, "user": null })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Per-month row counts of the partitioned tables next to what has already been archived
+pub async fn table_size_report(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let pool = &state.pool;
+    let mut tables = Vec::new();
+    for table in PARTITIONED_TABLES {
+        match archive::partition_stats(pool, table).await {
+            Ok(partitions) => tables.push(json!({
+                "table": table.name,
+                "rows": partitions.iter().map(|p| p.rows).sum::<i64>(),
+                "partitions": partitions,
+            })),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+    let (database_bytes, archived) = match (archive::database_size(pool).await, archive::list_archives(pool).await) {
+        (Ok(size), Ok(archived)) => (size, archived),
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+
+    HttpResponse::Ok().json(json!({
+        "database_bytes": database_bytes,
+        "tables": tables,
+        "archived": archived,
+        "policy": state.config.load().archive,
+    }))
+}
+
+pub async fn list_archives(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    match archive::list_archives(&state.pool).await {
+        Ok(archives) => HttpResponse::Ok().json(json!({ "archives": archives })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Archives old months right away with the configured retention, even while scheduled archival is off
+pub async fn run_archive(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let policy = state.config.load().archive.clone();
+    match archive::run(&state.pool, &policy).await {
+        Ok(archived) => HttpResponse::Ok().json(json!({ "archived": archived })),
+        Err(e) => {
+            eprintln!("Partition archival failed: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка архивации",
+                Locale::En => "archive-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+/// Downloads an archived month as a zip with one NDJSON file
+pub async fn download_archive(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    match archive::read_archive(&state.pool, &path.into_inner()).await {
+        Ok(Some((filename, bytes))) => HttpResponse::Ok()
+            .append_header(("Content-Type", "application/zip"))
+            .append_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+            .body(bytes),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            eprintln!("Reading partition archive failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DisclaimerListQuery {
+    pub category: Option<String>,
+    /// Every version instead of only the current one per category and language
+    #[serde(default)]
+    pub history: bool,
+}
+
+/// Legal disclaimers appended to answers, current versions unless `history=true`
+pub async fn list_disclaimers(
+    req: HttpRequest,
+    query: web::Query<DisclaimerListQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let rows = sqlx::query(
+        "SELECT id, category, locale, version, text, created_at FROM disclaimers d
+         WHERE (? IS NULL OR category = ?)
+           AND (? OR version = (SELECT MAX(version) FROM disclaimers WHERE category = d.category AND locale = d.locale))
+         ORDER BY category, locale, version DESC"
+    )
+    .bind(&query.category)
+    .bind(&query.category)
+    .bind(query.history)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let disclaimers: Vec<Disclaimer> = rs.iter().map(disclaimer::from_row).collect();
+            HttpResponse::Ok().json(json!({ "disclaimers": disclaimers }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PublishDisclaimerRequest {
+    pub category: String,
+    /// `ru` or `en`
+    pub locale: String,
+    pub text: String,
+}
+
+/// Publishes a new disclaimer version; answers from then on carry it and record its id
+pub async fn publish_disclaimer(
+    req: HttpRequest,
+    body: web::Json<PublishDisclaimerRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let category = body.category.trim().to_lowercase();
+    let text = body.text.trim();
+    let lang = body.locale.trim().to_lowercase();
+    if category.is_empty() || text.is_empty() || !matches!(lang.as_str(), "ru" | "en") {
+        let error_msg = match locale {
+            Locale::Ru => "Требуются категория, язык (ru или en) и текст",
+            Locale::En => "category-locale-and-text-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    match disclaimer::publish(&state.pool, &category, &lang, text).await {
+        Ok(d) => HttpResponse::Created().json(d),
+        Err(e) => {
+            eprintln!("Publishing disclaimer failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeadLetterListQuery {
+    /// `pending` (default), `replayed` or `all`
+    pub status: Option<String>,
+    pub channel: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Deliveries to Telegram and FCM that failed every retry, newest first
+pub async fn list_dead_letters(
+    req: HttpRequest,
+    query: web::Query<DeadLetterListQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let status = match query.status.as_deref() {
+        None => Some("pending"),
+        Some("all") => None,
+        Some(s) => Some(s),
+    };
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+    let rows = sqlx::query(
+        "SELECT id, channel, payload, error, attempts, status, created_at, last_attempt_at, replayed_at
+         FROM dead_letters
+         WHERE (? IS NULL OR status = ?) AND (? IS NULL OR channel = ?)
+         ORDER BY created_at DESC LIMIT ?"
+    )
+    .bind(status)
+    .bind(status)
+    .bind(&query.channel)
+    .bind(&query.channel)
+    .bind(limit)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let letters: Vec<DeadLetter> = rs.iter().map(dead_letters::from_row).collect();
+            HttpResponse::Ok().json(json!({ "dead_letters": letters }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Sends a dead letter once more; on failure it stays pending with the new error
+pub async fn replay_dead_letter(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let id = path.into_inner();
+    let pool = &state.pool;
+
+    let letter = match dead_letters::get(pool, &id).await {
+        Ok(Some(l)) => l,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Запись не найдена",
+                Locale::En => "dead-letter-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if letter.status != "pending" {
+        let error_msg = match locale {
+            Locale::Ru => "Уже доставлено",
+            Locale::En => "dead-letter-already-replayed",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg, "dead_letter": letter }));
+    }
+
+    let outcome = dead_letters::deliver(pool, &letter).await;
+    let letter = match dead_letters::finish_replay(pool, &id, &outcome).await {
+        Ok(Some(l)) => l,
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+    match outcome {
+        Ok(()) => HttpResponse::Ok().json(json!({ "dead_letter": letter })),
+        Err(e) => {
+            eprintln!("Dead letter {} replay failed: {}", id, e);
+            let error_msg = match locale {
+                Locale::Ru => "Повторная доставка не удалась",
+                Locale::En => "replay-failed",
+            };
+            HttpResponse::BadGateway().json(json!({ "error": error_msg, "dead_letter": letter }))
+        }
+    }
+}
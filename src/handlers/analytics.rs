@@ -1,10 +1,95 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
 use actix_web::{web, HttpResponse, HttpRequest};
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use uuid::Uuid;
 
+use crate::handlers::admin::require_admin;
+use crate::handlers::auth::AuthedUser;
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::handlers::reference;
+use crate::services::reference as reference_service;
+use crate::services::{abuse, breaker, daily_digest, openai, trends};
 use crate::state::AppState;
-use crate::i18n;
+use crate::i18n::{self, Locale};
+
+// ========== SCOPE ==========
+
+const MAX_NICHE_LEN: usize = 60;
+
+/// Optional audience of AI analytics and niches of the month: `?region=DE&niche=coffee`.
+/// Rows saved without a scope are the global dataset that every reader falls back to.
+#[derive(Debug, Deserialize)]
+pub struct ScopeQuery {
+    pub region: Option<String>,
+    pub niche: Option<String>,
+}
+
+/// Normalized scope. Empty strings stand for "any", which is how global rows are stored.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Scope {
+    /// ISO 3166-1 alpha-2 code
+    pub region: String,
+    /// Lowercase niche slug
+    pub niche: String,
+}
+
+impl Scope {
+    pub(crate) fn new(region: Option<&str>, niche: Option<&str>) -> Scope {
+        Scope {
+            region: region.unwrap_or_default().to_string(),
+            niche: niche.map(|n| n.trim().to_lowercase()).unwrap_or_default(),
+        }
+    }
+
+    fn region(&self) -> Option<String> {
+        Some(self.region.clone()).filter(|r| !r.is_empty())
+    }
+
+    fn niche(&self) -> Option<String> {
+        Some(self.niche.clone()).filter(|n| !n.is_empty())
+    }
+}
+
+fn parse_scope(query: &ScopeQuery, locale: Locale) -> Result<Scope, HttpResponse> {
+    let region = reference::validate_country(query.region.as_deref(), "region", locale)?;
+    let scope = Scope::new(region.as_deref(), query.niche.as_deref());
+    if scope.niche.chars().count() > MAX_NICHE_LEN {
+        let error_msg = match locale {
+            Locale::Ru => "Слишком длинное название ниши",
+            Locale::En => "invalid-niche",
+        };
+        return Err(HttpResponse::BadRequest().json(serde_json::json!({ "error": error_msg, "field": "niche" })));
+    }
+    Ok(scope)
+}
+
+/// The most specific scope of `table` holding data for `wanted`: region and niche, then the
+/// niche anywhere, then the region, then global. `period` optionally narrows to one column value.
+pub(crate) async fn resolve_scope(
+    pool: &sqlx::SqlitePool,
+    table: &str,
+    period: Option<(&str, &str)>,
+    wanted: &Scope,
+) -> Scope {
+    let period_filter = period.map(|(column, _)| format!("{} = ? AND ", column)).unwrap_or_default();
+    let sql = format!(
+        "SELECT region, niche FROM {} WHERE {}region IN (?, '') AND niche IN (?, '')
+         ORDER BY (niche != '') * 2 + (region != '') DESC LIMIT 1",
+        table, period_filter
+    );
+    let mut query = sqlx::query(&sql);
+    if let Some((_, value)) = period {
+        query = query.bind(value);
+    }
+    match query.bind(&wanted.region).bind(&wanted.niche).fetch_optional(pool).await {
+        Ok(Some(r)) => Scope { region: r.get("region"), niche: r.get("niche") },
+        _ => Scope::default(),
+    }
+}
 
 // ========== TOP WEEKLY TRENDS ==========
 
@@ -51,6 +136,9 @@ pub struct AiAnalyticsResponse {
     pub description: String,
     pub level_of_competitiveness: Vec<f64>,
     pub created_at: String,
+    /// Scope the numbers are for; `null` where they are global
+    pub region: Option<String>,
+    pub niche: Option<String>,
 }
 
 // ========== NICHES OF THE MONTH ==========
@@ -70,15 +158,57 @@ pub struct NichesMonthUpsert {
 pub struct NichesMonthResponse {
     pub niches: Vec<NicheItem>,
     pub month_start: String,
+    /// Scope the list is for; `null` where it is global
+    pub region: Option<String>,
+    pub niche: Option<String>,
+}
+
+// ========== LOCALIZED UPSERTS ==========
+
+/// Upsert body: either one payload in the caller's language, or `{"en": ..., "ru": ...}` to write
+/// every language at once. Figures are taken from English when it is given.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LocalizedUpsert<T> {
+    Single(T),
+    PerLocale { en: Option<T>, ru: Option<T> },
+}
+
+impl<T> LocalizedUpsert<T> {
+    /// Payloads by locale, the one carrying the figures first
+    fn versions<'a>(&'a self, caller_locale: &'a str) -> Vec<(&'a str, &'a T)> {
+        match self {
+            LocalizedUpsert::Single(data) => vec![(caller_locale, data)],
+            LocalizedUpsert::PerLocale { en, ru } => [("en", en.as_ref()), ("ru", ru.as_ref())]
+                .into_iter()
+                .filter_map(|(locale, data)| data.map(|d| (locale, d)))
+                .collect(),
+        }
+    }
+}
+
+/// 400 for a localized body with no languages, or whose languages don't describe the same items
+fn invalid_localized_payload(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Переводы не совпадают по составу",
+        Locale::En => "invalid-localized-payload",
+    };
+    HttpResponse::BadRequest().json(serde_json::json!({ "error": error_msg }))
 }
 
 // ========== HANDLERS ==========
 
 pub async fn get_weekly_trends(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let pool = &state.pool;
     let loc = i18n::detect_locale(&req);
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
-    
+    match load_weekly_trends(&state.pool, locale).await {
+        Some(trends) => HttpResponse::Ok().json(trends),
+        None => HttpResponse::Ok().json(serde_json::json!({}))
+    }
+}
+
+/// This week's trends in `locale`; `None` until both places have been saved
+async fn load_weekly_trends(pool: &sqlx::SqlitePool, locale: &str) -> Option<WeeklyTrendsResponse> {
     // Get current week start (Monday of current week)
     let now = chrono::Utc::now();
     let week_start = now.date_naive().week(chrono::Weekday::Mon).first_day();
@@ -145,93 +275,95 @@ pub async fn get_weekly_trends(req: HttpRequest, state: web::Data<AppState>) ->
                 increase: r.get("increase"),
             }).collect();
             
-            HttpResponse::Ok().json(WeeklyTrendsResponse {
+            Some(WeeklyTrendsResponse {
                 current_top,
                 second_place,
                 geo_trends,
                 week_start: week_start_str,
             })
         }
-        _ => HttpResponse::Ok().json(serde_json::json!({}))
+        _ => None
     }
 }
 
-pub async fn upsert_weekly_trends(req: HttpRequest, body: web::Json<WeeklyTrendsUpsert>, state: web::Data<AppState>) -> HttpResponse {
-    let data = body.into_inner();
-    let pool = &state.pool;
+pub async fn upsert_weekly_trends(req: HttpRequest, body: web::Json<LocalizedUpsert<WeeklyTrendsUpsert>>, state: web::Data<AppState>) -> HttpResponse {
     let loc = i18n::detect_locale(&req);
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    let versions = body.versions(locale);
+    let geo_count = |d: &WeeklyTrendsUpsert| d.geo_trends.len().min(3);
+    if versions.is_empty() || versions.iter().any(|(_, v)| geo_count(*v) != geo_count(versions[0].1)) {
+        return invalid_localized_payload(loc);
+    }
+    
+    match save_weekly_trends(&state.pool, &versions).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"status": "ok"})),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to save weekly trends"
+        }))
+    }
+}
+
+/// Replaces this week's trends in one transaction. Figures come from the first version; every
+/// version adds its titles and countries in its own locale, matched by position
+pub(crate) async fn save_weekly_trends(pool: &sqlx::SqlitePool, versions: &[(&str, &WeeklyTrendsUpsert)]) -> Result<(), sqlx::Error> {
+    let data = match versions.first() {
+        Some((_, d)) => *d,
+        None => return Ok(()),
+    };
     
     // Calculate week start
     let now = chrono::Utc::now();
     let week_start = now.date_naive().week(chrono::Weekday::Mon).first_day();
     let week_start_str = week_start.format("%Y-%m-%d").to_string();
     
-    // Ensure only top 3 geo trends
-    let geo_trends: Vec<GeoTrendItem> = data.geo_trends.into_iter().take(3).collect();
+    let mut tx = pool.begin().await?;
     
     // Delete existing entries for this week (i18n will be deleted via CASCADE)
-    let _ = sqlx::query("DELETE FROM top_weekly_trends WHERE week_start = ?")
+    sqlx::query("DELETE FROM top_weekly_trends WHERE week_start = ?")
         .bind(&week_start_str)
-        .execute(pool)
-        .await;
+        .execute(&mut tx)
+        .await?;
     
-    let _ = sqlx::query("DELETE FROM geo_trends WHERE week_start = ?")
+    sqlx::query("DELETE FROM geo_trends WHERE week_start = ?")
         .bind(&week_start_str)
-        .execute(pool)
-        .await;
-    
-    // Insert current top trend
-    let top_id = Uuid::new_v4().to_string();
-    let _ = sqlx::query(
-        "INSERT INTO top_weekly_trends (id, week_start, position, title, increase, request_percent) VALUES (?, ?, 1, ?, ?, ?)"
-    )
-    .bind(&top_id)
-    .bind(&week_start_str)
-    .bind(&data.current_top.title)
-    .bind(data.current_top.increase)
-    .bind(data.current_top.request_percent)
-    .execute(pool)
-    .await;
-    
-    // Insert i18n for top trend
-    let _ = sqlx::query(
-        "INSERT INTO top_weekly_trends_i18n (id, locale, title) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET title = excluded.title"
-    )
-    .bind(&top_id)
-    .bind(locale)
-    .bind(&data.current_top.title)
-    .execute(pool)
-    .await;
-    
-    // Insert second place
-    let second_id = Uuid::new_v4().to_string();
-    let _ = sqlx::query(
-        "INSERT INTO top_weekly_trends (id, week_start, position, title, increase, request_percent) VALUES (?, ?, 2, ?, ?, ?)"
-    )
-    .bind(&second_id)
-    .bind(&week_start_str)
-    .bind(&data.second_place.title)
-    .bind(data.second_place.increase)
-    .bind(data.second_place.request_percent)
-    .execute(pool)
-    .await;
+        .execute(&mut tx)
+        .await?;
     
-    // Insert i18n for second place
-    let _ = sqlx::query(
-        "INSERT INTO top_weekly_trends_i18n (id, locale, title) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET title = excluded.title"
-    )
-    .bind(&second_id)
-    .bind(locale)
-    .bind(&data.second_place.title)
-    .execute(pool)
-    .await;
+    // Insert current top trend (position 1) and second place (position 2)
+    for position in [1, 2] {
+        let title_in = |d: &WeeklyTrendsUpsert| if position == 1 { d.current_top.title.clone() } else { d.second_place.title.clone() };
+        let item = if position == 1 { &data.current_top } else { &data.second_place };
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO top_weekly_trends (id, week_start, position, title, increase, request_percent) VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&id)
+        .bind(&week_start_str)
+        .bind(position as i64)
+        .bind(&item.title)
+        .bind(item.increase)
+        .bind(item.request_percent)
+        .execute(&mut tx)
+        .await?;
+        
+        // Insert i18n for the trend
+        for (locale, version) in versions {
+            sqlx::query(
+                "INSERT INTO top_weekly_trends_i18n (id, locale, title) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET title = excluded.title"
+            )
+            .bind(&id)
+            .bind(*locale)
+            .bind(title_in(*version))
+            .execute(&mut tx)
+            .await?;
+        }
+    }
     
-    // Insert geo trends
-    for (idx, geo) in geo_trends.iter().enumerate() {
+    // Insert geo trends, only the top 3
+    for (idx, geo) in data.geo_trends.iter().take(3).enumerate() {
         let geo_id = Uuid::new_v4().to_string();
         let rank = (idx + 1) as i64;
-        let _ = sqlx::query(
+        sqlx::query(
             "INSERT INTO geo_trends (id, week_start, country, increase, rank) VALUES (?, ?, ?, ?, ?)"
         )
         .bind(&geo_id)
@@ -239,62 +371,115 @@ pub async fn upsert_weekly_trends(req: HttpRequest, body: web::Json<WeeklyTrends
         .bind(&geo.country)
         .bind(geo.increase)
         .bind(rank)
-        .execute(pool)
-        .await;
+        .execute(&mut tx)
+        .await?;
         
         // Insert i18n for geo trend
-        let _ = sqlx::query(
-            "INSERT INTO geo_trends_i18n (id, locale, country) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET country = excluded.country"
-        )
-        .bind(&geo_id)
-        .bind(locale)
-        .bind(&geo.country)
-        .execute(pool)
-        .await;
+        for (locale, version) in versions {
+            let country = match version.geo_trends.get(idx) {
+                Some(g) => &g.country,
+                None => continue,
+            };
+            sqlx::query(
+                "INSERT INTO geo_trends_i18n (id, locale, country) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET country = excluded.country"
+            )
+            .bind(&geo_id)
+            .bind(*locale)
+            .bind(country)
+            .execute(&mut tx)
+            .await?;
+        }
     }
     
-    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+    tx.commit().await
 }
 
-pub async fn get_ai_analytics(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let pool = &state.pool;
+/// `POST /api/admin/analytics/trends/refresh` pulls search interest for the configured keywords now
+/// and answers with the week's trends as saved
+pub async fn refresh_trends(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
     let loc = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, loc) {
+        return resp;
+    }
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
-    
+
+    let policy = state.config.load().trends.clone();
+    match trends::refresh(&state, &policy).await {
+        Ok(()) => match load_weekly_trends(&state.pool, locale).await {
+            Some(saved) => HttpResponse::Ok().json(saved),
+            None => HttpResponse::Ok().json(serde_json::json!({})),
+        },
+        Err(e) => {
+            eprintln!("Trends refresh failed: {}", e);
+            let error_msg = match loc {
+                Locale::Ru => "Не удалось обновить тренды",
+                Locale::En => "trends-refresh-failed",
+            };
+            HttpResponse::BadGateway().json(serde_json::json!({ "error": error_msg }))
+        }
+    }
+}
+
+pub async fn get_ai_analytics(req: HttpRequest, query: web::Query<ScopeQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let loc = i18n::detect_locale(&req);
+    let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    let scope = match parse_scope(&query, loc) {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    match load_ai_analytics(&state.pool, locale, &scope).await {
+        Ok(Some(analytics)) => HttpResponse::Ok().json(analytics),
+        _ => HttpResponse::Ok().json(serde_json::json!({}))
+    }
+}
+
+/// Latest AI analytics in `locale` for the closest scope to `wanted` that has any
+async fn load_ai_analytics(pool: &sqlx::SqlitePool, locale: &str, wanted: &Scope) -> Result<Option<AiAnalyticsResponse>, sqlx::Error> {
+    let scope = resolve_scope(pool, "ai_analytics", None, wanted).await;
     let row = sqlx::query(
         "SELECT a.increase, a.description, a.level_of_competitiveness, a.created_at, a.id,
                 COALESCE(i.description, a.description) AS localized_description
          FROM ai_analytics a
          LEFT JOIN ai_analytics_i18n i
            ON i.id = a.id AND i.locale = ?
+         WHERE a.region = ? AND a.niche = ?
          ORDER BY a.created_at DESC LIMIT 1"
     )
     .bind(locale)
+    .bind(&scope.region)
+    .bind(&scope.niche)
     .fetch_optional(pool)
-    .await;
+    .await?;
     
-    match row {
-        Ok(Some(r)) => {
-            let competitiveness_json: String = r.get("level_of_competitiveness");
-            let competitiveness: Vec<f64> = serde_json::from_str(&competitiveness_json)
-                .unwrap_or_else(|_| vec![]);
-            
-            HttpResponse::Ok().json(AiAnalyticsResponse {
-                increase: r.get("increase"),
-                description: r.get::<String, _>("localized_description"),
-                level_of_competitiveness: competitiveness,
-                created_at: r.get("created_at"),
-            })
+    Ok(row.map(|r| {
+        let competitiveness_json: String = r.get("level_of_competitiveness");
+        let competitiveness: Vec<f64> = serde_json::from_str(&competitiveness_json)
+            .unwrap_or_else(|_| vec![]);
+        
+        AiAnalyticsResponse {
+            increase: r.get("increase"),
+            description: r.get::<String, _>("localized_description"),
+            level_of_competitiveness: competitiveness,
+            created_at: r.get("created_at"),
+            region: scope.region(),
+            niche: scope.niche(),
         }
-        _ => HttpResponse::Ok().json(serde_json::json!({}))
-    }
+    }))
 }
 
-pub async fn upsert_ai_analytics(req: HttpRequest, body: web::Json<AiAnalyticsUpsert>, state: web::Data<AppState>) -> HttpResponse {
-    let data = body.into_inner();
-    let pool = &state.pool;
+pub async fn upsert_ai_analytics(req: HttpRequest, query: web::Query<ScopeQuery>, body: web::Json<LocalizedUpsert<AiAnalyticsUpsert>>, state: web::Data<AppState>) -> HttpResponse {
     let loc = i18n::detect_locale(&req);
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    let scope = match parse_scope(&query, loc) {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    
+    let versions = body.versions(locale);
+    let data = match versions.first() {
+        Some((_, d)) => *d,
+        None => return invalid_localized_payload(loc),
+    };
     
     // Ensure at least 5 data points
     if data.level_of_competitiveness.len() < 5 {
@@ -303,51 +488,75 @@ pub async fn upsert_ai_analytics(req: HttpRequest, body: web::Json<AiAnalyticsUp
         }));
     }
     
+    match save_ai_analytics(&state.pool, &scope, &versions).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"status": "ok"})),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to save AI analytics"
+        }))
+    }
+}
+
+/// Adds an AI analytics entry for `scope` in one transaction. Figures come from the first version;
+/// every version adds its description in its own locale
+pub(crate) async fn save_ai_analytics(
+    pool: &sqlx::SqlitePool,
+    scope: &Scope,
+    versions: &[(&str, &AiAnalyticsUpsert)],
+) -> Result<(), sqlx::Error> {
+    let data = match versions.first() {
+        Some((_, d)) => *d,
+        None => return Ok(()),
+    };
     let competitiveness_json = serde_json::to_string(&data.level_of_competitiveness)
         .unwrap_or_else(|_| "[]".to_string());
     
     let id = Uuid::new_v4().to_string();
+    let mut tx = pool.begin().await?;
     
-    let result = sqlx::query(
-        "INSERT INTO ai_analytics (id, increase, description, level_of_competitiveness) VALUES (?, ?, ?, ?)"
+    sqlx::query(
+        "INSERT INTO ai_analytics (id, increase, description, level_of_competitiveness, region, niche) VALUES (?, ?, ?, ?, ?, ?)"
     )
     .bind(&id)
     .bind(data.increase)
     .bind(&data.description)
     .bind(&competitiveness_json)
-    .execute(pool)
-    .await;
+    .bind(&scope.region)
+    .bind(&scope.niche)
+    .execute(&mut tx)
+    .await?;
     
-    match result {
-        Ok(_) => {
-            // Insert i18n for AI analytics
-            let _ = sqlx::query(
-                "INSERT INTO ai_analytics_i18n (id, locale, description) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET description = excluded.description"
-            )
-            .bind(&id)
-            .bind(locale)
-            .bind(&data.description)
-            .execute(pool)
-            .await;
-            
-            HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
-        }
-        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
-            "error": "Failed to save AI analytics"
-        }))
+    // Insert i18n for AI analytics
+    for (locale, version) in versions {
+        sqlx::query(
+            "INSERT INTO ai_analytics_i18n (id, locale, description) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET description = excluded.description"
+        )
+        .bind(&id)
+        .bind(*locale)
+        .bind(&version.description)
+        .execute(&mut tx)
+        .await?;
     }
+    tx.commit().await
 }
 
-pub async fn get_niches_month(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
-    let pool = &state.pool;
+pub async fn get_niches_month(req: HttpRequest, query: web::Query<ScopeQuery>, state: web::Data<AppState>) -> HttpResponse {
     let loc = i18n::detect_locale(&req);
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
-    
+    let scope = match parse_scope(&query, loc) {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    HttpResponse::Ok().json(load_niches_month(&state.pool, locale, &scope).await)
+}
+
+/// This month's niches in `locale` for the closest scope to `wanted` that has any; empty on errors
+async fn load_niches_month(pool: &sqlx::SqlitePool, locale: &str, wanted: &Scope) -> NichesMonthResponse {
     // Get current month start (first day of current month)
     let now = chrono::Utc::now();
     let today = now.date_naive();
     let formatted = today.format("%Y-%m-%d").to_string();
     let month_start_str = format!("{}-01", &formatted[..7]); // Extract YYYY-MM and append -01
+    let scope = resolve_scope(pool, "niches_month", Some(("month_start", month_start_str.as_str())), wanted).await;
     
     let rows = sqlx::query(
         "SELECT n.title, n.change, n.id,
@@ -355,37 +564,57 @@ pub async fn get_niches_month(req: HttpRequest, state: web::Data<AppState>) -> H
          FROM niches_month n
          LEFT JOIN niches_month_i18n i
            ON i.id = n.id AND i.locale = ?
-         WHERE n.month_start = ? ORDER BY ABS(n.change) DESC"
+         WHERE n.month_start = ? AND n.region = ? AND n.niche = ? ORDER BY ABS(n.change) DESC"
     )
     .bind(locale)
     .bind(&month_start_str)
+    .bind(&scope.region)
+    .bind(&scope.niche)
     .fetch_all(pool)
     .await;
     
-    match rows {
-        Ok(rs) => {
-            let niches: Vec<NicheItem> = rs.into_iter().map(|r| NicheItem {
-                title: r.get::<String, _>("localized_title"),
-                change: r.get("change"),
-            }).collect();
-            
-            HttpResponse::Ok().json(NichesMonthResponse {
-                niches,
-                month_start: month_start_str,
-            })
-        }
-        _ => HttpResponse::Ok().json(NichesMonthResponse {
-            niches: vec![],
-            month_start: month_start_str,
-        })
+    let niches: Vec<NicheItem> = match rows {
+        Ok(rs) => rs.into_iter().map(|r| NicheItem {
+            title: r.get::<String, _>("localized_title"),
+            change: r.get("change"),
+        }).collect(),
+        Err(_) => vec![],
+    };
+    NichesMonthResponse {
+        niches,
+        month_start: month_start_str,
+        region: scope.region(),
+        niche: scope.niche(),
     }
 }
 
-pub async fn upsert_niches_month(req: HttpRequest, body: web::Json<NichesMonthUpsert>, state: web::Data<AppState>) -> HttpResponse {
-    let data = body.into_inner();
-    let pool = &state.pool;
+pub async fn upsert_niches_month(req: HttpRequest, query: web::Query<ScopeQuery>, body: web::Json<LocalizedUpsert<NichesMonthUpsert>>, state: web::Data<AppState>) -> HttpResponse {
     let loc = i18n::detect_locale(&req);
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    let scope = match parse_scope(&query, loc) {
+        Ok(s) => s,
+        Err(resp) => return resp,
+    };
+    let versions = body.versions(locale);
+    if versions.is_empty() || versions.iter().any(|(_, v)| v.niches.len() != versions[0].1.niches.len()) {
+        return invalid_localized_payload(loc);
+    }
+    
+    match save_niches_month(&state.pool, &scope, &versions).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"status": "ok"})),
+        Err(_) => HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to save niches of the month"
+        }))
+    }
+}
+
+/// Replaces this month's niches for `scope` in one transaction. Changes come from the first
+/// version; every version adds its titles in its own locale, matched by position
+pub(crate) async fn save_niches_month(pool: &sqlx::SqlitePool, scope: &Scope, versions: &[(&str, &NichesMonthUpsert)]) -> Result<(), sqlx::Error> {
+    let data = match versions.first() {
+        Some((_, d)) => *d,
+        None => return Ok(()),
+    };
     
     // Get current month start (first day of current month)
     let now = chrono::Utc::now();
@@ -393,37 +622,184 @@ pub async fn upsert_niches_month(req: HttpRequest, body: web::Json<NichesMonthUp
     let formatted = today.format("%Y-%m-%d").to_string();
     let month_start_str = format!("{}-01", &formatted[..7]); // Extract YYYY-MM and append -01
     
-    // Delete existing entries for this month (i18n will be deleted via CASCADE)
-    let _ = sqlx::query("DELETE FROM niches_month WHERE month_start = ?")
+    let mut tx = pool.begin().await?;
+    
+    // Delete existing entries for this month and scope (i18n will be deleted via CASCADE)
+    sqlx::query("DELETE FROM niches_month WHERE month_start = ? AND region = ? AND niche = ?")
         .bind(&month_start_str)
-        .execute(pool)
-        .await;
+        .bind(&scope.region)
+        .bind(&scope.niche)
+        .execute(&mut tx)
+        .await?;
     
     // Insert new niches
-    for niche in data.niches {
+    for (idx, niche) in data.niches.iter().enumerate() {
         let id = Uuid::new_v4().to_string();
-        let _ = sqlx::query(
-            "INSERT INTO niches_month (id, month_start, title, change) VALUES (?, ?, ?, ?)"
+        sqlx::query(
+            "INSERT INTO niches_month (id, month_start, title, change, region, niche) VALUES (?, ?, ?, ?, ?, ?)"
         )
         .bind(&id)
         .bind(&month_start_str)
         .bind(&niche.title)
         .bind(niche.change)
-        .execute(pool)
-        .await;
+        .bind(&scope.region)
+        .bind(&scope.niche)
+        .execute(&mut tx)
+        .await?;
         
         // Insert i18n for niche
-        let _ = sqlx::query(
-            "INSERT INTO niches_month_i18n (id, locale, title) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET title = excluded.title"
-        )
-        .bind(&id)
-        .bind(locale)
-        .bind(&niche.title)
-        .execute(pool)
-        .await;
+        for (locale, version) in versions {
+            let title = match version.niches.get(idx) {
+                Some(n) => &n.title,
+                None => continue,
+            };
+            sqlx::query(
+                "INSERT INTO niches_month_i18n (id, locale, title) VALUES (?, ?, ?) ON CONFLICT(id, locale) DO UPDATE SET title = excluded.title"
+            )
+            .bind(&id)
+            .bind(*locale)
+            .bind(title)
+            .execute(&mut tx)
+            .await?;
+        }
     }
     
-    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+    tx.commit().await
+}
+
+// ========== PERSONALIZED DASHBOARD ==========
+
+/// Commentary is regenerated at most this often per user and language
+const COMMENTARY_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+/// Cache misses allowed per user per hour before the dashboard goes without commentary
+const COMMENTARY_PER_HOUR: usize = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct ForMeQuery {
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForMeResponse {
+    pub user_id: String,
+    /// Region and niche the dashboard was built for, from the user's country and latest business context
+    pub region: Option<String>,
+    pub niche: Option<String>,
+    pub weekly_trends: Option<WeeklyTrendsResponse>,
+    pub ai_analytics: Option<AiAnalyticsResponse>,
+    pub niches_month: NichesMonthResponse,
+    /// `None` when the model is unavailable or the user has asked for too many fresh ones
+    pub commentary: Option<String>,
+    pub commentary_generated_at: Option<String>,
+}
+
+/// Everything the analytics screen shows, narrowed to the caller's region and niche where
+/// scoped data exists, plus a short AI commentary on what it means for them
+pub async fn get_for_me(
+    req: HttpRequest,
+    user: AuthedUser,
+    query: web::Query<ForMeQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let loc = i18n::detect_locale(&req);
+    let locale = match loc { Locale::Ru => "ru", Locale::En => "en" };
+    let pool = &state.pool;
+
+    // Telegram-linked ids share the dashboard with their main account
+    let user_id = match query.user_id.as_deref().filter(|id| !id.is_empty()) {
+        Some(id) => resolve_user_id_for_conversations(pool, id).await,
+        None => user.id.clone(),
+    };
+    if user_id != user.id {
+        let error_msg = match loc {
+            Locale::Ru => "Доступ запрещен",
+            Locale::En => "forbidden",
+        };
+        return HttpResponse::Forbidden().json(serde_json::json!({ "error": error_msg }));
+    }
+
+    let profile = sqlx::query(
+        "SELECT u.country,
+            (SELECT x.business_niche FROM conversation_context x JOIN conversations c ON c.id = x.conversation_id
+             WHERE c.user_id = u.id AND c.deleted_at IS NULL AND x.business_niche IS NOT NULL
+             ORDER BY x.updated_at DESC LIMIT 1) AS business_niche
+         FROM users u WHERE u.id = ?"
+    )
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await;
+    let (country, niche) = match profile {
+        Ok(Some(r)) => (r.get::<Option<String>, _>("country"), r.get::<Option<String>, _>("business_niche")),
+        Ok(None) => (None, None),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let region = country.as_deref().and_then(reference_service::country_code);
+    let scope = Scope::new(region, niche.as_deref());
+
+    let ai_analytics = match load_ai_analytics(pool, locale, &scope).await {
+        Ok(a) => a,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let (commentary, commentary_generated_at) = match commentary(&state, &user_id, niche.as_deref(), &scope, loc).await {
+        Some((text, at)) => (Some(text), Some(at)),
+        None => (None, None),
+    };
+
+    HttpResponse::Ok().json(ForMeResponse {
+        user_id,
+        region: scope.region(),
+        niche: scope.niche(),
+        weekly_trends: load_weekly_trends(pool, locale).await,
+        ai_analytics,
+        niches_month: load_niches_month(pool, locale, &scope).await,
+        commentary,
+        commentary_generated_at,
+    })
+}
+
+/// Cached commentary and when it was written, or a fresh one from the same facts the daily
+/// digest uses. Skipped while the completion breaker is open
+async fn commentary(
+    state: &AppState,
+    user_id: &str,
+    niche: Option<&str>,
+    scope: &Scope,
+    locale: Locale,
+) -> Option<(String, String)> {
+    type Cache = HashMap<(String, &'static str), (String, String, Instant)>;
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    let key = (user_id.to_string(), i18n::language_code(locale));
+
+    if let Some((text, at, stored)) = cache.lock().unwrap().get(&key) {
+        if stored.elapsed() < COMMENTARY_TTL {
+            return Some((text.clone(), at.clone()));
+        }
+    }
+    if breaker::retry_after().is_some()
+        || !abuse::allow("analytics_commentary", user_id, COMMENTARY_PER_HOUR, Duration::from_secs(3600))
+    {
+        return None;
+    }
+
+    let now = chrono::Utc::now();
+    let facts = daily_digest::gather_facts(&state.pool, user_id, niche, scope, locale, now).await.ok()?;
+    let model = openai::current_model(state);
+    let text = match openai::generate_analytics_commentary(&model, locale, &facts).await {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Analytics commentary for {} failed: {}", user_id, e);
+            return None;
+        }
+    };
+    let at = now.to_rfc3339();
+    let mut map = cache.lock().unwrap();
+    // Keep memory bounded under user churn
+    if map.len() > 10_000 {
+        map.retain(|_, (_, _, stored)| stored.elapsed() < COMMENTARY_TTL);
+    }
+    map.insert(key, (text.clone(), at.clone(), Instant::now()));
+    Some((text, at))
 }
 
 // Keep old endpoints for backward compatibility (can be removed later)
@@ -495,41 +871,58 @@ pub async fn get_top_trend(req: HttpRequest, state: web::Data<AppState>) -> Http
     }
 }
 
-pub async fn upsert_top_trend(req: HttpRequest, body: web::Json<TopTrendUpsert>, state: web::Data<AppState>) -> HttpResponse {
-    let b = body.into_inner();
-    let pool = &state.pool;
+pub async fn upsert_top_trend(req: HttpRequest, body: web::Json<LocalizedUpsert<TopTrendUpsert>>, state: web::Data<AppState>) -> HttpResponse {
     let loc = i18n::detect_locale(&req);
     let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    let versions = body.versions(locale);
+    if versions.is_empty() || versions.iter().any(|(_, v)| v.name != versions[0].1.name) {
+        return invalid_localized_payload(loc);
+    }
 
-    let base_res = sqlx::query(
+    match save_top_trend(&state.pool, &versions).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"status": "ok"})),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Upserts a trend and its texts in every given locale in one transaction; the percent comes from the first version
+async fn save_top_trend(pool: &sqlx::SqlitePool, versions: &[(&str, &TopTrendUpsert)]) -> Result<(), sqlx::Error> {
+    let data = match versions.first() {
+        Some((_, d)) => *d,
+        None => return Ok(()),
+    };
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
         "INSERT INTO analytics_trends (name, percent_change, description, why_popular) VALUES (?, ?, COALESCE(?, description), COALESCE(?, why_popular)) \
          ON CONFLICT(name) DO UPDATE SET \
             percent_change = COALESCE(excluded.percent_change, analytics_trends.percent_change), \
             created_at = strftime('%Y-%m-%dT%H:%M:%fZ','now')"
     )
-    .bind(&b.name)
-    .bind(b.percent_change)
-    .bind(b.description.clone())
-    .bind(b.why_popular.clone())
-    .execute(pool)
-    .await;
-
-    if base_res.is_err() { return HttpResponse::InternalServerError().finish(); }
-
-    let _ = sqlx::query(
-        "INSERT INTO analytics_trends_i18n (name, locale, description, why_popular) VALUES (?, ?, ?, ?) \
-         ON CONFLICT(name, locale) DO UPDATE SET \
-            description = COALESCE(excluded.description, analytics_trends_i18n.description), \
-            why_popular = COALESCE(excluded.why_popular, analytics_trends_i18n.why_popular)"
-    )
-    .bind(&b.name)
-    .bind(locale)
-    .bind(b.description)
-    .bind(b.why_popular)
-    .execute(pool)
-    .await;
+    .bind(&data.name)
+    .bind(data.percent_change)
+    .bind(&data.description)
+    .bind(&data.why_popular)
+    .execute(&mut tx)
+    .await?;
+
+    for (locale, version) in versions {
+        sqlx::query(
+            "INSERT INTO analytics_trends_i18n (name, locale, description, why_popular) VALUES (?, ?, ?, ?) \
+             ON CONFLICT(name, locale) DO UPDATE SET \
+                description = COALESCE(excluded.description, analytics_trends_i18n.description), \
+                why_popular = COALESCE(excluded.why_popular, analytics_trends_i18n.why_popular)"
+        )
+        .bind(&data.name)
+        .bind(*locale)
+        .bind(&version.description)
+        .bind(&version.why_popular)
+        .execute(&mut tx)
+        .await?;
+    }
 
-    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+    tx.commit().await
 }
 
 pub async fn get_popularity_trends(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
@@ -564,42 +957,58 @@ pub async fn get_popularity_trends(req: HttpRequest, state: web::Data<AppState>)
     }
 }
 
-pub async fn upsert_popularity_trend(req: HttpRequest, body: web::Json<PopularityUpsert>, state: web::Data<AppState>) -> HttpResponse {
-    let b = body.into_inner();
-    if b.direction != "growing" && b.direction != "decreasing" {
+pub async fn upsert_popularity_trend(req: HttpRequest, body: web::Json<LocalizedUpsert<PopularityUpsert>>, state: web::Data<AppState>) -> HttpResponse {
+    let loc = i18n::detect_locale(&req);
+    let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    let versions = body.versions(locale);
+    if versions.iter().any(|(_, v)| v.direction != "growing" && v.direction != "decreasing") {
         return HttpResponse::BadRequest().json(serde_json::json!({"error": "direction must be 'growing' or 'decreasing'"}));
     }
+    if versions.is_empty() || versions.iter().any(|(_, v)| v.name != versions[0].1.name || v.direction != versions[0].1.direction) {
+        return invalid_localized_payload(loc);
+    }
 
-    let pool = &state.pool;
-    let loc = i18n::detect_locale(&req);
-    let locale = match loc { i18n::Locale::Ru => "ru", _ => "en" };
+    match save_popularity_trend(&state.pool, &versions).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"status": "ok"})),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Upserts a popularity trend and its notes in every given locale in one transaction; figures come from the first version
+async fn save_popularity_trend(pool: &sqlx::SqlitePool, versions: &[(&str, &PopularityUpsert)]) -> Result<(), sqlx::Error> {
+    let data = match versions.first() {
+        Some((_, d)) => *d,
+        None => return Ok(()),
+    };
 
-    let base_res = sqlx::query(
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
         "INSERT INTO popularity_trends (name, direction, percent_change, notes) VALUES (?, ?, ?, COALESCE(?, notes)) \
          ON CONFLICT(name) DO UPDATE SET \
             direction = excluded.direction, \
             percent_change = COALESCE(excluded.percent_change, popularity_trends.percent_change), \
             created_at = strftime('%Y-%m-%dT%H:%M:%fZ','now')"
     )
-    .bind(&b.name)
-    .bind(&b.direction)
-    .bind(b.percent_change)
-    .bind(b.notes.clone())
-    .execute(pool)
-    .await;
-
-    if base_res.is_err() { return HttpResponse::InternalServerError().finish(); }
-
-    let _ = sqlx::query(
-        "INSERT INTO popularity_trends_i18n (name, locale, notes) VALUES (?, ?, ?) \
-         ON CONFLICT(name, locale) DO UPDATE SET \
-            notes = COALESCE(excluded.notes, popularity_trends_i18n.notes)"
-    )
-    .bind(&b.name)
-    .bind(locale)
-    .bind(b.notes)
-    .execute(pool)
-    .await;
+    .bind(&data.name)
+    .bind(&data.direction)
+    .bind(data.percent_change)
+    .bind(&data.notes)
+    .execute(&mut tx)
+    .await?;
+
+    for (locale, version) in versions {
+        sqlx::query(
+            "INSERT INTO popularity_trends_i18n (name, locale, notes) VALUES (?, ?, ?) \
+             ON CONFLICT(name, locale) DO UPDATE SET \
+                notes = COALESCE(excluded.notes, popularity_trends_i18n.notes)"
+        )
+        .bind(&data.name)
+        .bind(*locale)
+        .bind(&version.notes)
+        .execute(&mut tx)
+        .await?;
+    }
 
-    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+    tx.commit().await
 }
@@ -1,17 +1,72 @@
-use actix_web::{Error, HttpRequest, HttpResponse, web};
+use actix_web::dev::Payload;
+use actix_web::error::InternalError;
+use actix_web::{Error, FromRequest, HttpRequest, HttpResponse, web};
 use actix_multipart::Multipart;
+use futures_util::future::LocalBoxFuture;
 use futures_util::TryStreamExt;
 use bcrypt;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
+use sha2::{Digest, Sha256};
 use sqlx::{self};
 use sqlx::Row;
 
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::handlers::files::{ensure_storage_quota, scan_upload, store_file};
+use crate::handlers::{limits, reference};
 use crate::models::{AuthRequest, User};
+use crate::services::{abuse, account, avatar, captcha, email, geoip, jwt, password, timezone};
+use crate::services::fcm::{self, FcmService};
 use crate::state::AppState;
 use crate::i18n::{self, Locale};
 
+// Per-IP velocity limits for unauthenticated account endpoints
+const REGISTER_PER_HOUR: usize = 5;
+const CHECK_USER_PER_MINUTE: usize = 10;
+const LOGIN_PER_15_MINUTES: usize = 10;
+/// Per user: guessing the current password from a stolen session
+const CHANGE_PASSWORD_PER_15_MINUTES: usize = 5;
+/// Per user: each export reads every record and file the user has
+const DATA_EXPORTS_PER_HOUR: usize = 3;
+/// Confirmation emails per user; each one goes to an address the user typed
+const EMAIL_CHANGES_PER_HOUR: usize = 5;
+const EMAIL_CHANGE_TTL_HOURS: i64 = 24;
+/// Per target address: sign-up attempt notices go to whoever owns the email, not the caller
+const SIGNUP_ATTEMPT_EMAILS_PER_DAY: usize = 1;
+
+const REMEMBER_ME_DAYS: i64 = 30;
+const SLIDING_SESSION_HOURS: i64 = 12;
+const SESSION_RENEW_STEP_MINUTES: i64 = 5;
+/// Access tokens are short-lived; clients trade their refresh token for a new one
+const ACCESS_TOKEN_MINUTES: i64 = 15;
+
+fn dummy_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| bcrypt::hash("timing-equalizer", bcrypt::DEFAULT_COST).unwrap_or_default())
+}
+
+/// Validates a new password against the configured policy, listing every failed rule
+pub(crate) async fn enforce_password_policy(state: &AppState, password: &str, locale: Locale) -> Result<(), HttpResponse> {
+    let policy = state.config.load().password_policy.clone();
+    let failed = password::check(&policy, password).await;
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    let error_msg = match locale {
+        Locale::Ru => "Пароль не соответствует требованиям",
+        Locale::En => "weak-password",
+    };
+    let details: Vec<String> = failed.iter().map(|rule| rule.message(locale, &policy)).collect();
+    Err(HttpResponse::BadRequest().json(json!({
+        "error": error_msg,
+        "details": details,
+    })))
+}
+
 #[derive(Deserialize)]
 pub struct TokenCheck {
     pub token: Option<String>,
@@ -35,7 +90,14 @@ pub struct UserProfile {
     pub country: Option<String>,
     pub gender: Option<String>,
     pub profile_picture: Option<String>,
+    /// 64px variant of `profile_picture`; unset for pictures not uploaded through the profile endpoint
+    pub profile_picture_thumb: Option<String>,
     pub telegram_username: Option<String>,
+    pub analytics_opt_in: bool,
+    /// IANA zone id; reminders and digests fire at this local time
+    pub timezone: Option<String>,
+    /// Morning AI digest posted to the "Daily digest" conversation
+    pub daily_digest: bool,
 }
 
 #[derive(Deserialize)]
@@ -48,10 +110,18 @@ pub struct UpdateUserData {
     pub gender: Option<String>,
     pub profile_picture: Option<String>,
     pub telegram_username: Option<String>,
+    pub analytics_opt_in: Option<bool>,
+    pub timezone: Option<String>,
+    pub daily_digest: Option<bool>,
 }
-#[derive(Deserialize)]
-pub struct EmailCheckReq {
-    pub email: String,
+pub(crate) fn too_many_requests(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Слишком много запросов, попробуйте позже",
+        Locale::En => "too-many-requests",
+    };
+    HttpResponse::TooManyRequests().json(json!({
+        "error": error_msg
+    }))
 }
 
 #[derive(Serialize)]
@@ -71,30 +141,19 @@ pub struct TelegramUsernameCheckRes {
 }
 
 pub async fn email_exists(
-    _req: HttpRequest,
-    query: web::Query<EmailCheckReq>,
-    state: web::Data<AppState>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    // Check if user exists and get profile_picture
-    let row = sqlx::query(
-        "SELECT profile_picture FROM users WHERE email = ? LIMIT 1"
-    )
-    .bind(&query.email)
-    .fetch_optional(&state.pool)
-    .await
-    .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    let (exists, profile_picture) = match row {
-        Some(r) => {
-            let pic = r.try_get::<Option<String>, _>("profile_picture").unwrap_or(None);
-            (true, pic)
-        }
-        None => (false, None),
-    };
+    let locale = i18n::detect_locale(&req);
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string());
+    if !abuse::allow("check-user", client_ip.as_deref().unwrap_or("unknown"), CHECK_USER_PER_MINUTE, Duration::from_secs(60)) {
+        return Ok(too_many_requests(locale));
+    }
 
-    Ok(HttpResponse::Ok().json(EmailCheckRes { 
-        exists, 
-        profile_picture 
+    // Existence is never confirmed to unauthenticated callers; the shape is kept for older clients.
+    // Support staff use the admin lookup instead.
+    Ok(HttpResponse::Ok().json(EmailCheckRes {
+        exists: false,
+        profile_picture: None,
     }))
 }
 
@@ -115,32 +174,18 @@ pub async fn telegram_username_exists(
 }
 
 pub async fn check_token(
-    _req: HttpRequest,
-    query: web::Query<TokenCheck>,
+    req: HttpRequest,
     state: web::Data<AppState>,
 ) -> HttpResponse {
-    let status = match &query.token {
+    let status = match jwt::request_token(&req) {
         None => TokenStatus {
             valid: false,
             message: "no-token",
         },
-        Some(t) => {
-            let now = chrono::Utc::now().to_rfc3339();
-            let exists: Option<i64> = sqlx::query_scalar(
-                "SELECT CASE WHEN EXISTS(\n                    SELECT 1 FROM sessions s\n                    JOIN users u ON s.user_id = u.id\n                    WHERE s.token = ? AND (s.expires_at IS NULL OR s.expires_at > ?)\n                ) THEN 1 ELSE 0 END"
-            )
-            .bind(t)
-            .bind(&now)
-            .fetch_optional(&state.pool)
-            .await
-            .ok()
-            .flatten();
-
-            match exists {
-                Some(1) => TokenStatus { valid: true, message: "valid" },
-                _ => TokenStatus { valid: false, message: "expired-or-invalid" },
-            }
-        }
+        Some(t) => match token_user_id(&req, &state.pool, &t).await {
+            Some(_) => TokenStatus { valid: true, message: "valid" },
+            None => TokenStatus { valid: false, message: "expired-or-invalid" },
+        },
     };
 
     HttpResponse::Ok().json(status)
@@ -154,7 +199,7 @@ pub async fn get_profile(
     let user_id = path.into_inner();
 
     let row = sqlx::query(
-        "SELECT id, email, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, telegram_username
+        "SELECT id, email, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, profile_picture_thumb, telegram_username, analytics_opt_in, timezone, daily_digest
          FROM users
          WHERE id = ?
          LIMIT 1",
@@ -190,63 +235,46 @@ pub async fn get_profile(
         country: row.try_get::<Option<String>, _>("country").unwrap_or(None),
         gender: row.try_get::<Option<String>, _>("gender").unwrap_or(None),
         profile_picture: profile_picture_id,
+        profile_picture_thumb: row.try_get::<Option<String>, _>("profile_picture_thumb").unwrap_or(None),
         telegram_username: row.try_get::<Option<String>, _>("telegram_username").unwrap_or(None),
+        analytics_opt_in: row.try_get::<i64, _>("analytics_opt_in").unwrap_or(0) != 0,
+        timezone: row.try_get::<Option<String>, _>("timezone").unwrap_or(None),
+        daily_digest: row.try_get::<i64, _>("daily_digest").unwrap_or(0) != 0,
     };
 
     HttpResponse::Ok().json(profile)
 }
 
+/// Largest profile picture accepted
+const MAX_PROFILE_PICTURE: usize = 5 * 1024 * 1024;
+
+/// The picture plus a few client-side extras; anything beyond is not a profile form
+const MAX_PROFILE_FORM_FIELDS: usize = 4;
+
 pub async fn upload_profile_picture(
     req: HttpRequest,
-    query: web::Query<TokenCheck>,
+    user: AuthedUser,
     mut payload: Multipart,
     state: web::Data<AppState>,
 ) -> HttpResponse {
     let locale = i18n::detect_locale(&req);
-    let token = match &query.token {
-        Some(t) if !t.is_empty() => t,
-        _ => {
-            let error_msg = match locale {
-                Locale::Ru => "Токен не предоставлен",
-                Locale::En => "no-token",
-            };
-            return HttpResponse::Unauthorized().json(json!({
-                "error": error_msg,
-            }));
-        }
-    };
-
-    let now = chrono::Utc::now().to_rfc3339();
-
-    // Get user_id from token
-    let user_id_row = sqlx::query_scalar::<_, String>(
-        "SELECT user_id FROM sessions WHERE token = ? AND (expires_at IS NULL OR expires_at > ?)"
-    )
-    .bind(token)
-    .bind(&now)
-    .fetch_optional(&state.pool)
-    .await;
-
-    let user_id = match user_id_row {
-        Ok(Some(id)) => id,
-        _ => {
-            let error_msg = match locale {
-                Locale::Ru => "Недействительный или истекший токен",
-                Locale::En => "invalid-or-expired-token",
-            };
-            return HttpResponse::Unauthorized().json(json!({
-                "error": error_msg,
-            }));
-        }
-    };
+    let user_id = user.id;
 
     // Process multipart form data
     let mut file_data: Option<Vec<u8>> = None;
     let mut filename: Option<String> = None;
     let mut mime_type: Option<String> = None;
 
+    let mut fields = 0;
     while let Ok(Some(mut field)) = payload.try_next().await {
+        fields += 1;
+        if fields > MAX_PROFILE_FORM_FIELDS {
+            return limits::too_many_files(locale, 1);
+        }
         if field.name() == "profile_picture" {
+            if file_data.is_some() {
+                return limits::too_many_files(locale, 1);
+            }
             let content_disposition = field.content_disposition();
             if let Some(name) = content_disposition.get_filename() {
                 filename = Some(name.to_string());
@@ -257,11 +285,19 @@ pub async fn upload_profile_picture(
                 mime_type = Some(ct.to_string());
             }
 
-            // Read file data
-            let mut bytes = Vec::new();
-            while let Ok(Some(chunk)) = field.try_next().await {
-                bytes.extend_from_slice(&chunk);
-            }
+            // Read file data, rejecting it as soon as it passes the limit
+            let bytes = match limits::read_field(&mut field, MAX_PROFILE_PICTURE).await {
+                Some(b) => b,
+                None => {
+                    let error_msg = match locale {
+                        Locale::Ru => "Файл слишком большой (максимум 5MB)",
+                        Locale::En => "file-too-large-max-5mb",
+                    };
+                    return HttpResponse::PayloadTooLarge().json(json!({
+                        "error": error_msg,
+                    }));
+                }
+            };
             
             if !bytes.is_empty() {
                 file_data = Some(bytes);
@@ -287,58 +323,54 @@ pub async fn upload_profile_picture(
         }
     };
 
-    // Validate file size (max 5MB)
-    if file_bytes.len() > 5 * 1024 * 1024 {
-        let error_msg = match locale {
-            Locale::Ru => "Файл слишком большой (максимум 5MB)",
-            Locale::En => "file-too-large-max-5mb",
-        };
-        return HttpResponse::BadRequest().json(json!({
-            "error": error_msg,
-        }));
-    }
-
-    // Validate it's an image
-    if !file_mime.starts_with("image/") {
-        let error_msg = match locale {
-            Locale::Ru => "Файл должен быть изображением",
-            Locale::En => "file-must-be-image",
-        };
-        return HttpResponse::BadRequest().json(json!({
-            "error": error_msg,
-        }));
+    if let Err(resp) = scan_upload(&state, &user_id, &file_name, &file_mime, &file_bytes, locale).await {
+        return resp;
     }
 
-    // Store file in files table
-    let file_id = Uuid::new_v4().to_string();
-    let file_size = file_bytes.len() as i64;
+    // The declared type is only a hint; the content has to decode as an image. Decoding and
+    // resizing are CPU-bound, so they run off the async workers.
+    let picture = match web::block(move || avatar::process(&file_bytes)).await {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Файл должен быть изображением",
+                Locale::En => "file-must-be-image",
+            };
+            return HttpResponse::BadRequest().json(json!({
+                "error": error_msg,
+            }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
 
-    let file_insert_result = sqlx::query(
-        "INSERT INTO files (id, filename, mime, size, bytes) VALUES (?, ?, ?, ?, ?)"
-    )
-    .bind(&file_id)
-    .bind(&file_name)
-    .bind(&file_mime)
-    .bind(file_size)
-    .bind(&file_bytes)
-    .execute(&state.pool)
-    .await;
+    if let Err(resp) = ensure_storage_quota(&state, &user_id, picture.large.bytes.len() + picture.thumb.bytes.len(), locale).await {
+        return resp;
+    }
 
-    if file_insert_result.is_err() {
-        let error_msg = match locale {
-            Locale::Ru => "Ошибка сохранения файла",
-            Locale::En => "file-save-failed",
-        };
-        return HttpResponse::InternalServerError().json(json!({
-            "error": error_msg,
-        }));
+    // Store both variants in files table
+    let mut file_ids = Vec::with_capacity(2);
+    for (size, variant) in [(avatar::LARGE_SIZE, picture.large), (avatar::THUMB_SIZE, picture.thumb)] {
+        let name = format!("avatar-{}.{}", size, variant.extension);
+        match store_file(&state.pool, name, variant.mime.to_string(), variant.bytes, None, Some(&user_id)).await {
+            Ok(att) => file_ids.push(att.id.unwrap_or_default()),
+            Err(_) => {
+                let error_msg = match locale {
+                    Locale::Ru => "Ошибка сохранения файла",
+                    Locale::En => "file-save-failed",
+                };
+                return HttpResponse::InternalServerError().json(json!({
+                    "error": error_msg,
+                }));
+            }
+        }
     }
 
     // Update user's profile_picture
     let update_result = sqlx::query(
-        "UPDATE users SET profile_picture = ? WHERE id = ?"
+        "UPDATE users SET profile_picture = ?, profile_picture_thumb = ? WHERE id = ?"
     )
-    .bind(&file_id)
+    .bind(&file_ids[0])
+    .bind(&file_ids[1])
     .bind(&user_id)
     .execute(&state.pool)
     .await;
@@ -355,7 +387,7 @@ pub async fn upload_profile_picture(
 
     // Return updated profile
     let row = sqlx::query(
-        "SELECT id, email, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, telegram_username
+        "SELECT id, email, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, profile_picture_thumb, telegram_username, analytics_opt_in, timezone, daily_digest
          FROM users
          WHERE id = ?
          LIMIT 1",
@@ -390,7 +422,11 @@ pub async fn upload_profile_picture(
         country: row.try_get::<Option<String>, _>("country").unwrap_or(None),
         gender: row.try_get::<Option<String>, _>("gender").unwrap_or(None),
         profile_picture: profile_picture_id,
+        profile_picture_thumb: row.try_get::<Option<String>, _>("profile_picture_thumb").unwrap_or(None),
         telegram_username: row.try_get::<Option<String>, _>("telegram_username").unwrap_or(None),
+        analytics_opt_in: row.try_get::<i64, _>("analytics_opt_in").unwrap_or(0) != 0,
+        timezone: row.try_get::<Option<String>, _>("timezone").unwrap_or(None),
+        daily_digest: row.try_get::<i64, _>("daily_digest").unwrap_or(0) != 0,
     };
 
     HttpResponse::Ok().json(profile)
@@ -398,27 +434,22 @@ pub async fn upload_profile_picture(
 
 pub async fn update_profile(
     req: HttpRequest,
-    query: web::Query<TokenCheck>,
+    user: AuthedUser,
     state: web::Data<AppState>,
     data: web::Json<UpdateUserData>,
 ) -> HttpResponse {
     let locale = i18n::detect_locale(&req);
-    let token = match &query.token {
-        Some(t) if !t.is_empty() => t,
-        _ => {
-            let error_msg = match locale {
-                Locale::Ru => "Токен не предоставлен",
-                Locale::En => "no-token",
-            };
-            return HttpResponse::Unauthorized().json(json!({
-                "error": error_msg,
-            }));
-        }
-    };
-
-    let now = chrono::Utc::now().to_rfc3339();
+    let user_id = user.id;
 
     let update = data.into_inner();
+    let country = match reference::validate_country(update.country.as_deref(), "country", locale) {
+        Ok(c) => c,
+        Err(resp) => return resp,
+    };
+    let timezone = match reference::validate_timezone(update.timezone.as_deref(), locale) {
+        Ok(tz) => tz,
+        Err(resp) => return resp,
+    };
     
     let profile_picture_was_provided = update.profile_picture.is_some();
     let profile_picture_value: Option<&str> = update.profile_picture.as_ref()
@@ -437,26 +468,33 @@ pub async fn update_profile(
             country = COALESCE(?, country),
             gender = COALESCE(?, gender),
             telegram_username = COALESCE(?, telegram_username),
+            analytics_opt_in = COALESCE(?, analytics_opt_in),
+            timezone = COALESCE(?, timezone),
+            daily_digest = COALESCE(?, daily_digest),
             profile_picture = CASE 
                 WHEN ? = 0 THEN profile_picture
                 ELSE ?
+            END,
+            profile_picture_thumb = CASE
+                WHEN ? = 0 THEN profile_picture_thumb
+                ELSE NULL
             END
-         WHERE id = (
-            SELECT user_id FROM sessions
-            WHERE token = ? AND (expires_at IS NULL OR expires_at > ?)
-         )",
+         WHERE id = ?",
     )
     .bind(update.business_type.as_deref())
     .bind(update.full_name.as_deref())
     .bind(update.nickname.as_deref())
     .bind(update.phone.as_deref())
-    .bind(update.country.as_deref())
+    .bind(country.as_deref())
     .bind(update.gender.as_deref())
     .bind(telegram_username_value)
+    .bind(update.analytics_opt_in)
+    .bind(timezone.as_deref())
+    .bind(update.daily_digest)
     .bind(if profile_picture_was_provided { 1 } else { 0 })
     .bind(profile_picture_value)
-    .bind(token)
-    .bind(&now)
+    .bind(if profile_picture_was_provided { 1 } else { 0 })
+    .bind(&user_id)
     .execute(&state.pool)
     .await;
 
@@ -475,23 +513,40 @@ pub async fn update_profile(
 
     if rows_affected == 0 {
         let error_msg = match locale {
-            Locale::Ru => "Недействительный или истекший токен",
-            Locale::En => "invalid-or-expired-token",
+            Locale::Ru => "Пользователь не найден",
+            Locale::En => "user-not-found",
         };
-        return HttpResponse::Unauthorized().json(json!({
+        return HttpResponse::NotFound().json(json!({
             "error": error_msg,
         }));
     }
 
+    // Opting out also withdraws the topics already derived from the user's conversations
+    if update.analytics_opt_in == Some(false) {
+        let cleared = sqlx::query(
+            "DELETE FROM conversation_topics WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)"
+        )
+        .bind(&user_id)
+        .execute(&state.pool)
+        .await;
+        if cleared.is_err() {
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка обновления",
+                Locale::En => "update-failed",
+            };
+            return HttpResponse::InternalServerError().json(json!({
+                "error": error_msg,
+            }));
+        }
+    }
+
     let row = sqlx::query(
-        "SELECT u.id, u.email, u.business_type, u.created_at, u.full_name, u.nickname, u.phone, u.country, u.gender, u.profile_picture, u.telegram_username
-         FROM sessions s
-         JOIN users u ON s.user_id = u.id
-         WHERE s.token = ? AND (s.expires_at IS NULL OR s.expires_at > ?)
+        "SELECT id, email, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, profile_picture_thumb, telegram_username, analytics_opt_in, timezone, daily_digest
+         FROM users
+         WHERE id = ?
          LIMIT 1",
     )
-    .bind(token)
-    .bind(&now)
+    .bind(&user_id)
     .fetch_optional(&state.pool)
     .await;
 
@@ -519,7 +574,11 @@ pub async fn update_profile(
         country: row.try_get::<Option<String>, _>("country").unwrap_or(None),
         gender: row.try_get::<Option<String>, _>("gender").unwrap_or(None),
         profile_picture: row.try_get::<Option<String>, _>("profile_picture").unwrap_or(None),
+        profile_picture_thumb: row.try_get::<Option<String>, _>("profile_picture_thumb").unwrap_or(None),
         telegram_username: row.try_get::<Option<String>, _>("telegram_username").unwrap_or(None),
+        analytics_opt_in: row.try_get::<i64, _>("analytics_opt_in").unwrap_or(0) != 0,
+        timezone: row.try_get::<Option<String>, _>("timezone").unwrap_or(None),
+        daily_digest: row.try_get::<i64, _>("daily_digest").unwrap_or(0) != 0,
     };
 
     HttpResponse::Ok().json(profile)
@@ -533,26 +592,56 @@ pub async fn register(
     let auth_req = data.into_inner();
     let pool = &state.pool;
     let locale = i18n::detect_locale(&req);
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string());
 
-    // check existing user
-    if let Ok(existing) = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(1) FROM users WHERE email = ?"
-    )
-    .bind(&auth_req.email)
-    .fetch_one(pool)
-    .await
-    {
-        if existing > 0 {
-            let error_msg = match locale {
-                Locale::Ru => "Пользователь уже существует",
-                Locale::En => "User already exists",
-            };
-            return HttpResponse::BadRequest().json(json!({
-                "error": error_msg
-            }));
-        }
+    if auth_req.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        let error_msg = match locale {
+            Locale::Ru => "Регистрация отклонена",
+            Locale::En => "registration-rejected",
+        };
+        return HttpResponse::BadRequest().json(json!({
+            "error": error_msg
+        }));
     }
-    
+
+    if !abuse::allow("register", client_ip.as_deref().unwrap_or("unknown"), REGISTER_PER_HOUR, Duration::from_secs(3600)) {
+        return too_many_requests(locale);
+    }
+
+    if !captcha::verify(auth_req.captcha_token.as_deref(), client_ip.as_deref()).await {
+        let error_msg = match locale {
+            Locale::Ru => "Проверка captcha не пройдена",
+            Locale::En => "captcha-failed",
+        };
+        return HttpResponse::BadRequest().json(json!({
+            "error": error_msg
+        }));
+    }
+
+    if abuse::is_disposable_email(&auth_req.email) {
+        let error_msg = match locale {
+            Locale::Ru => "Одноразовые адреса почты не поддерживаются",
+            Locale::En => "disposable-email-not-allowed",
+        };
+        return HttpResponse::BadRequest().json(json!({
+            "error": error_msg
+        }));
+    }
+
+    if let Err(resp) = enforce_password_policy(&state, &auth_req.password, locale).await {
+        return resp;
+    }
+    let country = match reference::validate_country(auth_req.country.as_deref(), "country", locale) {
+        Ok(c) => c,
+        Err(resp) => return resp,
+    };
+    // Clients that don't ask the user get the device's zone from X-Timezone
+    let timezone = match reference::validate_timezone(auth_req.timezone.as_deref(), locale) {
+        Ok(tz) => tz.or_else(|| timezone::detect(&req).map(|tz| tz.name().to_string())),
+        Err(resp) => return resp,
+    };
+
+    // Hashed before the lookup so both outcomes below take the same time
     let hashed_password = match bcrypt::hash(&auth_req.password, bcrypt::DEFAULT_COST) {
         Ok(hash) => hash,
         Err(_) => {
@@ -565,6 +654,19 @@ pub async fn register(
             }));
         }
     };
+
+    // check existing user
+    if let Ok(existing) = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(1) FROM users WHERE email = ?"
+    )
+    .bind(&auth_req.email)
+    .fetch_one(pool)
+    .await
+    {
+        if existing > 0 {
+            return decoy_registration(&state, &auth_req.email, auth_req.business_type.as_deref(), auth_req.remember_me.unwrap_or(true), locale);
+        }
+    }
     
     // Normalize empty profile_picture strings to None (NULL in DB)
     let profile_picture_value = auth_req.profile_picture.as_ref()
@@ -583,14 +685,15 @@ pub async fn register(
         full_name: auth_req.full_name.clone(),
         nickname: auth_req.nickname.clone(),
         phone: auth_req.phone.clone(),
-        country: auth_req.country.clone(),
+        country,
         gender: auth_req.gender.clone(),
         profile_picture: profile_picture_value.map(|s| s.to_string()),
         telegram_username: telegram_username_value.map(|s| s.to_string()),
+        timezone,
     };
 
     if let Err(_) = sqlx::query(
-        "INSERT INTO users (id, email, password, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, telegram_username) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        "INSERT INTO users (id, email, password, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, telegram_username, timezone) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
     )
     .bind(&user.id)
     .bind(&user.email)
@@ -604,6 +707,7 @@ pub async fn register(
     .bind(&user.gender)
     .bind(&user.profile_picture)
     .bind(&user.telegram_username)
+    .bind(&user.timezone)
     .execute(pool)
     .await
     {
@@ -615,20 +719,24 @@ pub async fn register(
     }
 
     // create session token
-    let token = Uuid::new_v4().to_string();
-    let created_at = chrono::Utc::now().to_rfc3339();
-    let expires_at = chrono::Utc::now() + chrono::Duration::days(30);
-    let expires_at_str = expires_at.to_rfc3339();
-
-    let _ = sqlx::query(
-        "INSERT INTO sessions (token, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)"
+    let remember_me = auth_req.remember_me.unwrap_or(true);
+    let session = match create_session(
+        &state,
+        &user.id,
+        remember_me,
+        auth_req.device_id.as_deref(),
+        auth_req.device_name.as_deref(),
+        &ClientInfo::from_request(&req),
+        locale,
     )
-    .bind(&token)
-    .bind(&user.id)
-    .bind(&created_at)
-    .bind(&expires_at_str)
-    .execute(pool)
-    .await;
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create session for {}: {}", user.id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
     
     let success_msg = match locale {
         Locale::Ru => "Пользователь успешно зарегистрирован",
@@ -641,7 +749,74 @@ pub async fn register(
             "email": user.email,
             "business_type": user.business_type
         },
-        "token": token
+        "token": session.token,
+        "token_expires_at": session.token_expires_at,
+        "refresh_token": session.refresh_token,
+        "expires_at": session.expires_at
+    }))
+}
+
+/// Answer for a registration with an address that already has an account: the same 201 a new
+/// account gets, with tokens for a session that doesn't exist, so registration can't be used to
+/// find out who has an account. The owner is told by email instead.
+fn decoy_registration(
+    state: &AppState,
+    email_address: &str,
+    business_type: Option<&str>,
+    remember_me: bool,
+    locale: Locale,
+) -> HttpResponse {
+    let to = email_address.to_string();
+    let notify = abuse::allow(
+        "signup-attempt-email",
+        &to.to_lowercase(),
+        SIGNUP_ATTEMPT_EMAILS_PER_DAY,
+        Duration::from_secs(24 * 60 * 60),
+    );
+    if notify {
+        actix_web::rt::spawn(async move {
+            let (subject, text) = match locale {
+                Locale::Ru => (
+                    "Попытка регистрации",
+                    "Кто-то пытался создать аккаунт с этим адресом почты. Аккаунт у вас уже есть: если это были вы, просто войдите или восстановите пароль. Если нет, ничего делать не нужно.",
+                ),
+                Locale::En => (
+                    "Sign-up attempt",
+                    "Someone tried to create an account with this email address. You already have one: if it was you, just sign in or reset your password. If it wasn't, there is nothing to do.",
+                ),
+            };
+            if let Err(e) = email::send(&to, subject, text).await {
+                eprintln!("Failed to tell an account owner about a sign-up attempt: {}", e);
+            }
+        });
+    }
+
+    let now = chrono::Utc::now();
+    let expires_at = if remember_me {
+        now + chrono::Duration::days(REMEMBER_ME_DAYS)
+    } else {
+        now + chrono::Duration::hours(SLIDING_SESSION_HOURS)
+    };
+    let user_id = Uuid::new_v4().to_string();
+    let (token, token_expires_at) = match access_token(state, &user_id, &Uuid::new_v4().to_string(), locale, expires_at) {
+        Some(t) => t,
+        None => return HttpResponse::InternalServerError().finish(),
+    };
+    let success_msg = match locale {
+        Locale::Ru => "Пользователь успешно зарегистрирован",
+        Locale::En => "User registered successfully",
+    };
+    HttpResponse::Created().json(json!({
+        "message": success_msg,
+        "user": {
+            "id": user_id,
+            "email": email_address,
+            "business_type": business_type.unwrap_or("general")
+        },
+        "token": token,
+        "token_expires_at": token_expires_at,
+        "refresh_token": new_refresh_token(),
+        "expires_at": expires_at.to_rfc3339()
     }))
 }
 
@@ -654,8 +829,13 @@ pub async fn login(
     let pool = &state.pool;
     let locale = i18n::detect_locale(&req);
 
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string());
+    if !abuse::allow("login", client_ip.as_deref().unwrap_or("unknown"), LOGIN_PER_15_MINUTES, Duration::from_secs(15 * 60)) {
+        return too_many_requests(locale);
+    }
+
     let row = sqlx::query(
-        "SELECT id, email, password, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, telegram_username FROM users WHERE email = ? LIMIT 1"
+        "SELECT id, email, password, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, telegram_username, timezone FROM users WHERE email = ? LIMIT 1"
     )
     .bind(&auth_req.email)
     .fetch_optional(pool)
@@ -664,6 +844,8 @@ pub async fn login(
     let row = match row {
         Ok(Some(r)) => r,
         _ => {
+            // Burn a bcrypt verification so unknown emails take as long as wrong passwords
+            let _ = bcrypt::verify(&auth_req.password, dummy_password_hash());
             let error_msg = match locale {
                 Locale::Ru => "Неверные учетные данные",
                 Locale::En => "Invalid credentials",
@@ -687,6 +869,7 @@ pub async fn login(
         gender: row.try_get::<Option<String>, _>("gender").unwrap_or(None),
         profile_picture: row.try_get::<Option<String>, _>("profile_picture").unwrap_or(None),
         telegram_username: row.try_get::<Option<String>, _>("telegram_username").unwrap_or(None),
+        timezone: row.try_get::<Option<String>, _>("timezone").unwrap_or(None),
     };
     
     let is_valid = match bcrypt::verify(&auth_req.password, &user.password) {
@@ -704,21 +887,63 @@ pub async fn login(
         }));
     }
 
+    // Accounts created before time zones were stored pick one up from the device
+    if user.timezone.is_none() {
+        if let Some(tz) = timezone::detect(&req) {
+            let _ = sqlx::query("UPDATE users SET timezone = ? WHERE id = ? AND timezone IS NULL")
+                .bind(tz.name())
+                .bind(&user.id)
+                .execute(pool)
+                .await;
+        }
+    }
+
     // create session token
-    let token = Uuid::new_v4().to_string();
-    let created_at = chrono::Utc::now().to_rfc3339();
-    let expires_at = chrono::Utc::now() + chrono::Duration::days(30);
-    let expires_at_str = expires_at.to_rfc3339();
+    let remember_me = auth_req.remember_me.unwrap_or(true);
+    let device_id = auth_req.device_id.as_deref().filter(|d| !d.is_empty());
+    let known_device = match device_id {
+        Some(d) => sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(1) FROM sessions WHERE user_id = ? AND device_id = ?"
+        )
+        .bind(&user.id)
+        .bind(d)
+        .fetch_one(pool)
+        .await
+        .map(|n| n > 0)
+        .unwrap_or(true),
+        None => true,
+    };
 
-    let _ = sqlx::query(
-        "INSERT INTO sessions (token, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)"
+    let session = match create_session(
+        &state,
+        &user.id,
+        remember_me,
+        device_id,
+        auth_req.device_name.as_deref(),
+        &ClientInfo::from_request(&req),
+        locale,
     )
-    .bind(&token)
-    .bind(&user.id)
-    .bind(&created_at)
-    .bind(&expires_at_str)
-    .execute(pool)
-    .await;
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create session for {}: {}", user.id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    // A sign-in from a device never seen before notifies the user's other devices
+    if !known_device {
+        let pool = pool.clone();
+        let user_id = user.id.clone();
+        let device_label = auth_req.device_name.clone().unwrap_or_else(|| match locale {
+            Locale::Ru => "новое устройство".to_string(),
+            Locale::En => "a new device".to_string(),
+        });
+        actix_web::rt::spawn(async move {
+            notify_new_device_login(&pool, &user_id, &device_label, locale).await;
+        });
+    }
 
     let success_msg = match locale {
         Locale::Ru => "Вход выполнен успешно",
@@ -731,6 +956,1087 @@ pub async fn login(
             "email": user.email,
             "business_type": user.business_type
         },
-        "token": token
+        "token": session.token,
+        "token_expires_at": session.token_expires_at,
+        "refresh_token": session.refresh_token,
+        "expires_at": session.expires_at
     }))
-}
\ No newline at end of file
+}
+
+/// Issues a session: remember-me sessions last 30 days, others slide on activity.
+/// The access token is a JWT naming the `sessions` row, which stays the source of truth for expiry
+/// and revocation; the refresh token keeps the session going past the access token's 15 minutes.
+/// Fails when the token can't be signed or the row can't be stored, since a token naming a missing
+/// session would be rejected on first use.
+pub(crate) async fn create_session(
+    state: &AppState,
+    user_id: &str,
+    remember_me: bool,
+    device_id: Option<&str>,
+    device_name: Option<&str>,
+    client: &ClientInfo,
+    locale: Locale,
+) -> Result<SessionTokens, Box<dyn std::error::Error>> {
+    let pool = &state.pool;
+    let session_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now();
+    let expires_at = if remember_me {
+        now + chrono::Duration::days(REMEMBER_ME_DAYS)
+    } else {
+        now + chrono::Duration::hours(SLIDING_SESSION_HOURS)
+    };
+    let refresh_token = new_refresh_token();
+    let (token, token_expires_at) =
+        access_token(state, user_id, &session_id, locale, expires_at).ok_or("failed to sign the access token")?;
+
+    sqlx::query(
+        "INSERT INTO sessions (token, user_id, created_at, last_used_at, expires_at, remember_me, device_id, device_name, refresh_token_hash,
+            user_agent, platform, created_ip, last_ip)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&session_id)
+    .bind(user_id)
+    .bind(now.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .bind(expires_at.to_rfc3339())
+    .bind(remember_me)
+    .bind(device_id)
+    .bind(device_name)
+    .bind(refresh_hash(&refresh_token))
+    .bind(&client.user_agent)
+    .bind(&client.platform)
+    .bind(&client.ip)
+    .bind(&client.ip)
+    .execute(pool)
+    .await?;
+
+    Ok(SessionTokens {
+        token,
+        token_expires_at,
+        refresh_token,
+        expires_at: expires_at.to_rfc3339(),
+    })
+}
+
+/// What login, registration and refresh hand back to the client
+#[derive(Serialize)]
+pub(crate) struct SessionTokens {
+    /// Access token for `?token=`
+    pub token: String,
+    pub token_expires_at: String,
+    /// Single-use token for `POST /api/auth/refresh`
+    pub refresh_token: String,
+    /// When the session ends; sliding sessions move it forward on every refresh
+    pub expires_at: String,
+}
+
+/// Access token for a session, never outliving the session itself
+fn access_token(
+    state: &AppState,
+    user_id: &str,
+    session_id: &str,
+    locale: Locale,
+    session_expires_at: chrono::DateTime<chrono::Utc>,
+) -> Option<(String, String)> {
+    let expires_at = session_expires_at.min(chrono::Utc::now() + chrono::Duration::minutes(ACCESS_TOKEN_MINUTES));
+    let token = jwt::issue(&state.jwt_secret, user_id, session_id, locale, expires_at)?;
+    Some((token, expires_at.to_rfc3339()))
+}
+
+fn new_refresh_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Only hashes of refresh tokens are stored
+fn refresh_hash(refresh_token: &str) -> String {
+    Sha256::digest(refresh_token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Device identifier sent by clients on every authenticated request
+pub(crate) fn request_device_id(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("X-Device-Id")
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty())
+}
+
+/// Longest user agent kept on a session
+const MAX_USER_AGENT: usize = 256;
+
+/// What the sessions listing shows about the client behind a session. Recorded at sign-in and
+/// refreshed by authenticated requests whenever it changes.
+pub(crate) struct ClientInfo {
+    pub user_agent: Option<String>,
+    /// `X-Platform` when the client sends it (`ios`, `android`, `web`, ...), else a guess from the user agent
+    pub platform: Option<String>,
+    pub ip: Option<String>,
+}
+
+impl ClientInfo {
+    pub(crate) fn from_request(req: &HttpRequest) -> Self {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let user_agent = header("User-Agent").map(|ua| ua.chars().take(MAX_USER_AGENT).collect::<String>());
+        let platform = header("X-Platform")
+            .map(|p| p.chars().take(32).collect::<String>().to_ascii_lowercase())
+            .or_else(|| user_agent.as_deref().and_then(guess_platform).map(str::to_string));
+        ClientInfo {
+            user_agent,
+            platform,
+            ip: geoip::client_ip(req).map(|ip| ip.to_string()),
+        }
+    }
+}
+
+fn guess_platform(user_agent: &str) -> Option<&'static str> {
+    let ua = user_agent.to_ascii_lowercase();
+    if ua.contains("iphone") || ua.contains("ipad") || ua.contains("cfnetwork") || ua.contains("darwin") {
+        Some("ios")
+    } else if ua.contains("android") || ua.contains("okhttp") || ua.contains("dalvik") {
+        Some("android")
+    } else if ua.contains("mozilla") {
+        Some("web")
+    } else {
+        None
+    }
+}
+
+/// `sessions` row behind a token: the `sid` claim of a JWT verified by the middleware, or the
+/// token itself for sessions issued before JWTs
+pub(crate) fn session_id(req: &HttpRequest, token: &str) -> Option<String> {
+    if jwt::is_jwt(token) {
+        jwt::request_claims(req, token).map(|c| c.sid)
+    } else {
+        Some(token.to_string())
+    }
+}
+
+/// Resolves a `?token=` to the owning user id. JWT sessions confirmed within the last minute
+/// skip the database; everything else goes through `session_user_id`. A bare session id is only
+/// accepted for sessions from before JWTs.
+pub(crate) async fn token_user_id(req: &HttpRequest, pool: &sqlx::SqlitePool, token: &str) -> Option<String> {
+    let device_id = request_device_id(req);
+    let client = ClientInfo::from_request(req);
+    if !jwt::is_jwt(token) {
+        let legacy: Option<i64> = sqlx::query_scalar("SELECT 1 FROM sessions WHERE token = ? AND legacy_bearer = 1")
+            .bind(token)
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten();
+        legacy?;
+        return session_user_id(pool, token, device_id, &client).await;
+    }
+    let claims = jwt::request_claims(req, token)?;
+    if let Some(user_id) = jwt::cached_session(&claims.sid, device_id) {
+        return Some(user_id);
+    }
+    let user_id = session_user_id(pool, &claims.sid, device_id, &client)
+        .await
+        .filter(|id| *id == claims.sub)?;
+    jwt::remember_session(&claims.sid, device_id, &user_id);
+    Some(user_id)
+}
+
+/// Resolves a session id to the owning user id, ignoring expired sessions.
+/// Every authenticated lookup goes through here, so this is also where sliding sessions renew,
+/// where the session's last use and client are recorded, and where device-bound tokens are
+/// rejected unless presented with their own `X-Device-Id`.
+pub(crate) async fn session_user_id(
+    pool: &sqlx::SqlitePool,
+    token: &str,
+    device_id: Option<&str>,
+    client: &ClientInfo,
+) -> Option<String> {
+    let now = chrono::Utc::now();
+    let row = sqlx::query(
+        "SELECT user_id, expires_at, remember_me, device_id, last_used_at, user_agent, platform, last_ip
+         FROM sessions WHERE token = ? AND (expires_at IS NULL OR expires_at > ?)"
+    )
+    .bind(token)
+    .bind(now.to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten()?;
+
+    // A device-bound token is only good with its own device id; leaving the header out doesn't skip the check
+    let bound_device: Option<String> = row.try_get("device_id").ok().flatten();
+    if bound_device.is_some() && device_id != bound_device.as_deref() {
+        eprintln!("Rejected session token presented without its bound device");
+        return None;
+    }
+
+    if row.try_get::<i64, _>("remember_me").unwrap_or(1) == 0 {
+        let renewed = now + chrono::Duration::hours(SLIDING_SESSION_HOURS);
+        let current = row
+            .try_get::<Option<String>, _>("expires_at")
+            .ok()
+            .flatten()
+            .and_then(|e| chrono::DateTime::parse_from_rfc3339(&e).ok());
+        // Skip the write unless the expiry moves noticeably
+        let stale = current.is_none_or(|exp| renewed.signed_duration_since(exp) > chrono::Duration::minutes(SESSION_RENEW_STEP_MINUTES));
+        if stale {
+            let _ = sqlx::query("UPDATE sessions SET expires_at = ? WHERE token = ?")
+                .bind(renewed.to_rfc3339())
+                .bind(token)
+                .execute(pool)
+                .await;
+        }
+    }
+
+    let last_used = row
+        .try_get::<Option<String>, _>("last_used_at")
+        .ok()
+        .flatten()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok());
+    // A new address or client is written at once; otherwise last use is only bumped every few minutes
+    let changed = |column: &str, seen: &Option<String>| {
+        seen.is_some() && row.try_get::<Option<String>, _>(column).ok().flatten() != *seen
+    };
+    let moved = changed("last_ip", &client.ip) || changed("user_agent", &client.user_agent) || changed("platform", &client.platform);
+    if moved || last_used.is_none_or(|t| now.signed_duration_since(t) > chrono::Duration::minutes(SESSION_RENEW_STEP_MINUTES)) {
+        let _ = sqlx::query(
+            "UPDATE sessions SET last_used_at = ?, last_ip = COALESCE(?, last_ip), user_agent = COALESCE(?, user_agent),
+                platform = COALESCE(?, platform)
+             WHERE token = ?"
+        )
+        .bind(now.to_rfc3339())
+        .bind(&client.ip)
+        .bind(&client.user_agent)
+        .bind(&client.platform)
+        .bind(token)
+        .execute(pool)
+        .await;
+    }
+
+    Some(row.get("user_id"))
+}
+
+/// Resolves `?token=` to a user id or builds the localized 401 response
+pub(crate) async fn authorize(
+    req: &HttpRequest,
+    pool: &sqlx::SqlitePool,
+    query: &TokenCheck,
+    locale: Locale,
+) -> Result<String, HttpResponse> {
+    let token = query.token.clone().filter(|t| !t.is_empty()).or_else(|| jwt::bearer_token(req));
+    authenticate(req, pool, token.as_deref(), locale).await.map(|user| user.id)
+}
+
+/// The signed-in user, resolved once from `?token=` or `Authorization: Bearer`. Handlers take it
+/// as an argument; requests without a valid session get the localized 401 before the handler runs.
+pub struct AuthedUser {
+    pub id: String,
+    /// `sessions` row behind the token
+    pub session_id: String,
+}
+
+impl FromRequest for AuthedUser {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            let locale = i18n::detect_locale(&req);
+            let state = match req.app_data::<web::Data<AppState>>() {
+                Some(s) => s.clone(),
+                None => return Err(actix_web::error::ErrorInternalServerError("app state missing")),
+            };
+            let token = jwt::request_token(&req);
+            authenticate(&req, &state.pool, token.as_deref(), locale)
+                .await
+                .map_err(|resp| InternalError::from_response("unauthorized", resp).into())
+        })
+    }
+}
+
+async fn authenticate(
+    req: &HttpRequest,
+    pool: &sqlx::SqlitePool,
+    token: Option<&str>,
+    locale: Locale,
+) -> Result<AuthedUser, HttpResponse> {
+    let token = match token {
+        Some(t) if !t.is_empty() => t,
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Токен не предоставлен",
+                Locale::En => "no-token",
+            };
+            return Err(HttpResponse::Unauthorized().json(json!({ "error": error_msg })));
+        }
+    };
+
+    match (token_user_id(req, pool, token).await, session_id(req, token)) {
+        (Some(id), Some(session_id)) => Ok(AuthedUser { id, session_id }),
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Недействительный или истекший токен",
+                Locale::En => "invalid-or-expired-token",
+            };
+            Err(HttpResponse::Unauthorized().json(json!({ "error": error_msg })))
+        }
+    }
+}
+
+async fn notify_new_device_login(pool: &sqlx::SqlitePool, user_id: &str, device_label: &str, locale: Locale) {
+    let tokens = fcm::security_tokens(pool, user_id).await;
+    if tokens.is_empty() {
+        return;
+    }
+    let fcm = match FcmService::new() {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+
+    let (title, body) = match locale {
+        Locale::Ru => ("Новый вход в аккаунт", format!("Выполнен вход с устройства: {}", device_label)),
+        Locale::En => ("New sign-in", format!("Your account was signed in on {}", device_label)),
+    };
+    let mut data = std::collections::HashMap::new();
+    data.insert("type".to_string(), "new_device_login".to_string());
+
+    if let Err(e) = fcm.send_notification(pool, tokens, title, &body, Some(data)).await {
+        eprintln!("Failed to send new-device alert: {}", e);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RenameDeviceRequest {
+    pub device_name: String,
+}
+
+/// Names the device behind the current session (and its other sessions on the same device)
+pub async fn rename_device(
+    req: HttpRequest,
+    user: AuthedUser,
+    data: web::Json<RenameDeviceRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = user.id;
+
+    let name = data.device_name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        let error_msg = match locale {
+            Locale::Ru => "Название устройства должно быть от 1 до 64 символов",
+            Locale::En => "invalid-device-name",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let token = user.session_id;
+    let result = sqlx::query(
+        "UPDATE sessions SET device_name = ?
+         WHERE user_id = ? AND (
+            token = ?
+            OR device_id = (SELECT device_id FROM sessions WHERE token = ? AND device_id IS NOT NULL)
+         )"
+    )
+    .bind(name)
+    .bind(&user_id)
+    .bind(&token)
+    .bind(&token)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({ "device_name": name })),
+        Err(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка обновления",
+                Locale::En => "update-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+/// Revokes the current session; its token stops working right away
+pub async fn logout(
+    user: AuthedUser,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pool = &state.pool;
+    let user_id = user.id;
+    let session_id = user.session_id;
+
+    let result = sqlx::query("DELETE FROM sessions WHERE token = ? AND user_id = ?")
+        .bind(&session_id)
+        .bind(&user_id)
+        .execute(pool)
+        .await;
+    jwt::forget_session(&session_id);
+
+    match result {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+fn invalid_refresh_token(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Недействительный или истекший токен обновления",
+        Locale::En => "invalid-or-expired-refresh-token",
+    };
+    HttpResponse::Unauthorized().json(json!({ "error": error_msg }))
+}
+
+/// Trades a refresh token for a new access token and a new refresh token. Each refresh token works
+/// once: presenting one that was already rotated ends the session, since someone else holds a copy.
+pub async fn refresh(
+    req: HttpRequest,
+    data: web::Json<RefreshRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let presented = refresh_hash(data.refresh_token.trim());
+    let now = chrono::Utc::now();
+
+    let row = match sqlx::query(
+        "SELECT token, user_id, expires_at, remember_me, device_id FROM sessions WHERE refresh_token_hash = ?"
+    )
+    .bind(&presented)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(row) => row,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let row = match row {
+        Some(r) => r,
+        None => {
+            let reused: Option<String> = sqlx::query_scalar("SELECT token FROM sessions WHERE refresh_previous_hash = ?")
+                .bind(&presented)
+                .fetch_optional(pool)
+                .await
+                .ok()
+                .flatten();
+            if let Some(session_id) = reused {
+                eprintln!("Rotated refresh token presented again; revoking its session");
+                let _ = sqlx::query("DELETE FROM sessions WHERE token = ?")
+                    .bind(&session_id)
+                    .execute(pool)
+                    .await;
+                jwt::forget_session(&session_id);
+            }
+            return invalid_refresh_token(locale);
+        }
+    };
+
+    let session_id: String = row.get("token");
+    let user_id: String = row.get("user_id");
+    let expires_at = row
+        .get::<Option<String>, _>("expires_at")
+        .and_then(|e| chrono::DateTime::parse_from_rfc3339(&e).ok())
+        .map(|e| e.with_timezone(&chrono::Utc))
+        .unwrap_or(now + chrono::Duration::days(REMEMBER_ME_DAYS));
+    if expires_at <= now {
+        return invalid_refresh_token(locale);
+    }
+    let bound_device: Option<String> = row.get("device_id");
+    if bound_device.is_some() && request_device_id(&req) != bound_device.as_deref() {
+        eprintln!("Rejected refresh token presented without its bound device");
+        return invalid_refresh_token(locale);
+    }
+    let expires_at = if row.get::<i64, _>("remember_me") == 0 {
+        now + chrono::Duration::hours(SLIDING_SESSION_HOURS)
+    } else {
+        expires_at
+    };
+
+    let (token, token_expires_at) = match access_token(&state, &user_id, &session_id, locale, expires_at) {
+        Some(t) => t,
+        None => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Compare-and-swap on the presented hash so two concurrent refreshes can't both succeed
+    let refresh_token = new_refresh_token();
+    let client = ClientInfo::from_request(&req);
+    let rotated = sqlx::query(
+        "UPDATE sessions SET refresh_previous_hash = refresh_token_hash, refresh_token_hash = ?, expires_at = ?, last_used_at = ?,
+            last_ip = COALESCE(?, last_ip), user_agent = COALESCE(?, user_agent), platform = COALESCE(?, platform)
+         WHERE token = ? AND refresh_token_hash = ?"
+    )
+    .bind(refresh_hash(&refresh_token))
+    .bind(expires_at.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .bind(&client.ip)
+    .bind(&client.user_agent)
+    .bind(&client.platform)
+    .bind(&session_id)
+    .bind(&presented)
+    .execute(pool)
+    .await;
+    match rotated {
+        Ok(r) if r.rows_affected() == 1 => {}
+        Ok(_) => return invalid_refresh_token(locale),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    HttpResponse::Ok().json(SessionTokens {
+        token,
+        token_expires_at,
+        refresh_token,
+        expires_at: expires_at.to_rfc3339(),
+    })
+}
+
+/// Public handle of a session. The session id itself is kept off the wire because sessions from
+/// before JWTs accept it as a bearer token.
+fn session_handle(session_id: &str) -> String {
+    Sha256::digest(session_id.as_bytes()).iter().take(8).map(|b| format!("{:02x}", b)).collect()
+}
+
+fn session_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Сессия не найдена",
+        Locale::En => "session-not-found",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+/// Ids of the user's unexpired sessions
+async fn active_session_ids(pool: &sqlx::SqlitePool, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT token FROM sessions WHERE user_id = ? AND (expires_at IS NULL OR expires_at > ?)")
+        .bind(user_id)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .fetch_all(pool)
+        .await
+}
+
+async fn delete_session(pool: &sqlx::SqlitePool, session_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sessions WHERE token = ?")
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    jwt::forget_session(session_id);
+    Ok(())
+}
+
+/// `GET /api/auth/sessions?token=` lists the user's active sessions, most recently used first
+pub async fn list_sessions(
+    user: AuthedUser,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pool = &state.pool;
+    let user_id = user.id;
+    let current = Some(user.session_id);
+
+    let rows = sqlx::query(
+        "SELECT token, device_id, device_name, created_at, last_used_at, expires_at, remember_me, user_agent, platform, created_ip, last_ip
+         FROM sessions WHERE user_id = ? AND (expires_at IS NULL OR expires_at > ?)
+         ORDER BY COALESCE(last_used_at, created_at) DESC"
+    )
+    .bind(&user_id)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let sessions: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|r| {
+                    let id: String = r.get("token");
+                    json!({
+                        "id": session_handle(&id),
+                        "device_id": r.get::<Option<String>, _>("device_id"),
+                        "device_name": r.get::<Option<String>, _>("device_name"),
+                        "platform": r.get::<Option<String>, _>("platform"),
+                        "user_agent": r.get::<Option<String>, _>("user_agent"),
+                        "ip": r.get::<Option<String>, _>("last_ip"),
+                        "created_ip": r.get::<Option<String>, _>("created_ip"),
+                        "created_at": r.get::<String, _>("created_at"),
+                        "last_used_at": r.get::<Option<String>, _>("last_used_at"),
+                        "expires_at": r.get::<Option<String>, _>("expires_at"),
+                        "remember_me": r.get::<i64, _>("remember_me") != 0,
+                        "current": current.as_deref() == Some(id.as_str()),
+                    })
+                })
+                .collect();
+            HttpResponse::Ok().json(json!({ "sessions": sessions }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `DELETE /api/auth/sessions/{id}?token=` signs one session out; it may be the current one
+pub async fn revoke_session(
+    req: HttpRequest,
+    path: web::Path<String>,
+    user: AuthedUser,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = user.id;
+    let handle = path.into_inner();
+
+    let sessions = match active_session_ids(pool, &user_id).await {
+        Ok(s) => s,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let session_id = match sessions.into_iter().find(|s| session_handle(s) == handle) {
+        Some(s) => s,
+        None => return session_not_found(locale),
+    };
+    match delete_session(pool, &session_id).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `DELETE /api/auth/sessions?token=` signs out every session except the current one
+pub async fn revoke_other_sessions(
+    user: AuthedUser,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pool = &state.pool;
+    let user_id = user.id;
+    let current = Some(user.session_id);
+
+    match revoke_sessions_except(pool, &user_id, current.as_deref()).await {
+        Ok(revoked) => HttpResponse::Ok().json(json!({ "revoked": revoked })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Deletes every active session of the user but `keep`; returns how many went
+async fn revoke_sessions_except(pool: &sqlx::SqlitePool, user_id: &str, keep: Option<&str>) -> Result<usize, sqlx::Error> {
+    let sessions = active_session_ids(pool, user_id).await?;
+    let mut revoked = 0;
+    for session_id in sessions.iter().filter(|s| keep != Some(s.as_str())) {
+        delete_session(pool, session_id).await?;
+        revoked += 1;
+    }
+    Ok(revoked)
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+/// `POST /api/auth/change-password?token=` sets a new password after checking the current one,
+/// then signs out every other session
+pub async fn change_password(
+    req: HttpRequest,
+    user: AuthedUser,
+    data: web::Json<ChangePasswordRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = user.id;
+    if !abuse::allow("change-password", &user_id, CHANGE_PASSWORD_PER_15_MINUTES, Duration::from_secs(15 * 60)) {
+        return too_many_requests(locale);
+    }
+
+    let stored: String = match sqlx::query_scalar("SELECT password FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(p) => p,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if !bcrypt::verify(&data.current_password, &stored).unwrap_or(false) {
+        let error_msg = match locale {
+            Locale::Ru => "Неверный текущий пароль",
+            Locale::En => "wrong-current-password",
+        };
+        return HttpResponse::Forbidden().json(json!({ "error": error_msg }));
+    }
+    if data.new_password == data.current_password {
+        let error_msg = match locale {
+            Locale::Ru => "Новый пароль совпадает с текущим",
+            Locale::En => "same-password",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+    if let Err(resp) = enforce_password_policy(&state, &data.new_password, locale).await {
+        return resp;
+    }
+
+    let hashed = match bcrypt::hash(&data.new_password, bcrypt::DEFAULT_COST) {
+        Ok(h) => h,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if sqlx::query("UPDATE users SET password = ? WHERE id = ?")
+        .bind(&hashed)
+        .bind(&user_id)
+        .execute(pool)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let current = Some(user.session_id);
+    let revoked = revoke_sessions_except(pool, &user_id, current.as_deref()).await.unwrap_or_else(|e| {
+        eprintln!("Failed to sign out other sessions after a password change: {}", e);
+        0
+    });
+    let success_msg = match locale {
+        Locale::Ru => "Пароль изменен",
+        Locale::En => "Password changed",
+    };
+    HttpResponse::Ok().json(json!({
+        "message": success_msg,
+        "revoked_sessions": revoked,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    pub new_email: String,
+    /// Required unless the account was created through Google, Apple or a phone code and has no password
+    pub password: Option<String>,
+}
+
+/// `POST /api/auth/change-email?token=` mails a confirmation link to the new address. The current
+/// email stays in use until the link is opened; a newer request replaces a pending one.
+pub async fn change_email(
+    req: HttpRequest,
+    user: AuthedUser,
+    data: web::Json<ChangeEmailRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = user.id;
+    if !abuse::allow("change-email", &user_id, EMAIL_CHANGES_PER_HOUR, Duration::from_secs(3600)) {
+        return too_many_requests(locale);
+    }
+
+    let new_email = data.new_email.trim().to_string();
+    if !email::is_valid(&new_email) {
+        let error_msg = match locale {
+            Locale::Ru => "Неверный адрес почты",
+            Locale::En => "invalid-email",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+    if abuse::is_disposable_email(&new_email) {
+        let error_msg = match locale {
+            Locale::Ru => "Одноразовые адреса почты не поддерживаются",
+            Locale::En => "disposable-email-not-allowed",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let row = match sqlx::query(
+        "SELECT email, password, has_password FROM users WHERE id = ?"
+    )
+    .bind(&user_id)
+    .fetch_one(pool)
+    .await
+    {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Same rule as deleting the account: a session alone can't move the account to another inbox
+    if row.get::<bool, _>("has_password") {
+        let stored: String = row.get("password");
+        let confirmed = data
+            .password
+            .as_deref()
+            .is_some_and(|p| bcrypt::verify(p, &stored).unwrap_or(false));
+        if !confirmed {
+            let error_msg = match locale {
+                Locale::Ru => "Неверный текущий пароль",
+                Locale::En => "wrong-current-password",
+            };
+            return HttpResponse::Forbidden().json(json!({ "error": error_msg }));
+        }
+    }
+    let current_email: String = row.get("email");
+    if current_email.eq_ignore_ascii_case(&new_email) {
+        let error_msg = match locale {
+            Locale::Ru => "Новый адрес совпадает с текущим",
+            Locale::En => "same-email",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+    match email_taken(pool, &new_email).await {
+        Ok(false) => {}
+        Ok(true) => {
+            let error_msg = match locale {
+                Locale::Ru => "Этот адрес уже занят",
+                Locale::En => "email-taken",
+            };
+            return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if !email::configured() {
+        let error_msg = match locale {
+            Locale::Ru => "Отправка писем недоступна",
+            Locale::En => "email-not-configured",
+        };
+        return HttpResponse::ServiceUnavailable().json(json!({ "error": error_msg }));
+    }
+
+    let code = new_refresh_token();
+    let now = chrono::Utc::now();
+    let expires_at = now + chrono::Duration::hours(EMAIL_CHANGE_TTL_HOURS);
+    let stored = sqlx::query(
+        "INSERT INTO email_changes (user_id, new_email, code_hash, created_at, expires_at) VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(user_id) DO UPDATE SET new_email = excluded.new_email, code_hash = excluded.code_hash,
+            created_at = excluded.created_at, expires_at = excluded.expires_at"
+    )
+    .bind(&user_id)
+    .bind(&new_email)
+    .bind(refresh_hash(&code))
+    .bind(now.to_rfc3339())
+    .bind(expires_at.to_rfc3339())
+    .execute(pool)
+    .await;
+    if stored.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let link = format!(
+        "{}/api/auth/change-email/confirm?code={}",
+        std::env::var("PUBLIC_BASE_URL").unwrap_or_default().trim_end_matches('/'),
+        code
+    );
+    let (subject, text) = match locale {
+        Locale::Ru => (
+            "Подтвердите новый адрес почты",
+            format!("Чтобы входить с этим адресом, откройте ссылку в течение {} часов:\n{}\n\nЕсли вы не меняли адрес, просто проигнорируйте письмо.", EMAIL_CHANGE_TTL_HOURS, link),
+        ),
+        Locale::En => (
+            "Confirm your new email address",
+            format!("To sign in with this address, open the link within {} hours:\n{}\n\nIf you didn't ask to change your email, ignore this message.", EMAIL_CHANGE_TTL_HOURS, link),
+        ),
+    };
+    if let Err(e) = email::send(&new_email, subject, &text).await {
+        eprintln!("Sending email change confirmation failed: {}", e);
+        let _ = sqlx::query("DELETE FROM email_changes WHERE user_id = ?").bind(&user_id).execute(pool).await;
+        let error_msg = match locale {
+            Locale::Ru => "Не удалось отправить письмо",
+            Locale::En => "email-send-failed",
+        };
+        return HttpResponse::BadGateway().json(json!({ "error": error_msg }));
+    }
+
+    HttpResponse::Accepted().json(json!({
+        "new_email": new_email,
+        "expires_at": expires_at.to_rfc3339(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmailQuery {
+    pub code: String,
+}
+
+/// `GET /api/auth/change-email/confirm?code=` is the link from the confirmation email; it switches
+/// the account to the new address and answers with a short page, since it opens in a browser
+pub async fn confirm_email_change(
+    req: HttpRequest,
+    query: web::Query<ConfirmEmailQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let page = |status: actix_web::http::StatusCode, ru: &'static str, en: &'static str| {
+        let body = match locale {
+            Locale::Ru => ru,
+            Locale::En => en,
+        };
+        HttpResponse::build(status).content_type("text/plain; charset=utf-8").body(body)
+    };
+
+    let pending = match sqlx::query(
+        "SELECT e.user_id, e.new_email, u.email AS old_email FROM email_changes e JOIN users u ON u.id = e.user_id
+         WHERE e.code_hash = ? AND e.expires_at > ?"
+    )
+    .bind(refresh_hash(query.code.trim()))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            return page(
+                actix_web::http::StatusCode::NOT_FOUND,
+                "Ссылка недействительна или устарела",
+                "This link is invalid or has expired",
+            )
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let user_id: String = pending.get("user_id");
+    let new_email: String = pending.get("new_email");
+    let old_email: String = pending.get("old_email");
+
+    // The address may have been registered by someone else since the link was sent
+    match email_taken(pool, &new_email).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return page(
+                actix_web::http::StatusCode::CONFLICT,
+                "Этот адрес уже занят другим аккаунтом",
+                "This address is already used by another account",
+            )
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let switched = sqlx::query("UPDATE users SET email = ? WHERE id = ?")
+        .bind(&new_email)
+        .bind(&user_id)
+        .execute(&mut tx)
+        .await;
+    if switched.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if sqlx::query("DELETE FROM email_changes WHERE user_id = ?")
+        .bind(&user_id)
+        .execute(&mut tx)
+        .await
+        .is_err()
+        || tx.commit().await.is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Tell the old inbox, so a change the owner didn't make doesn't go unnoticed
+    if !old_email.ends_with(".invalid") {
+        let (subject, text) = match locale {
+            Locale::Ru => ("Адрес почты изменен", format!("Адрес почты вашего аккаунта изменен на {}.", new_email)),
+            Locale::En => ("Your email address was changed", format!("The email address of your account was changed to {}.", new_email)),
+        };
+        if let Err(e) = email::send(&old_email, subject, &text).await {
+            eprintln!("Failed to notify the previous email address: {}", e);
+        }
+    }
+
+    page(
+        actix_web::http::StatusCode::OK,
+        "Адрес почты подтвержден. Теперь входите с новым адресом.",
+        "Your email address is confirmed. Sign in with the new address from now on.",
+    )
+}
+
+async fn email_taken(pool: &sqlx::SqlitePool, email: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE lower(email) = lower(?))")
+        .bind(email)
+        .fetch_one(pool)
+        .await
+}
+
+#[derive(Deserialize, Default)]
+pub struct DeleteAccountRequest {
+    /// Required unless the account was created through Google, Apple or a phone code and has no password
+    pub password: Option<String>,
+}
+
+/// `DELETE /api/auth/account?token=` erases the account with its conversations, messages, files,
+/// support history, business records and devices. Cannot be undone.
+pub async fn delete_account(
+    req: HttpRequest,
+    user: AuthedUser,
+    data: Option<web::Json<DeleteAccountRequest>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = user.id;
+    // Shares the change-password bucket: both let a stolen session test passwords
+    if !abuse::allow("change-password", &user_id, CHANGE_PASSWORD_PER_15_MINUTES, Duration::from_secs(15 * 60)) {
+        return too_many_requests(locale);
+    }
+    let data = data.map(|d| d.into_inner()).unwrap_or_default();
+
+    // A session alone isn't enough to destroy the account, except where the owner has no password to give.
+    // A linked provider doesn't count: password accounts get one linked just by signing in with it.
+    let (stored, has_password): (String, bool) = match sqlx::query_as("SELECT password, has_password FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if has_password {
+        let confirmed = data
+            .password
+            .as_deref()
+            .is_some_and(|p| bcrypt::verify(p, &stored).unwrap_or(false));
+        if !confirmed {
+            let error_msg = match locale {
+                Locale::Ru => "Неверный текущий пароль",
+                Locale::En => "wrong-current-password",
+            };
+            return HttpResponse::Forbidden().json(json!({ "error": error_msg }));
+        }
+    }
+
+    match account::erase(pool, &user_id).await {
+        Ok(sessions) => {
+            for session_id in &sessions {
+                jwt::forget_session(session_id);
+            }
+            println!("Account {} deleted on request", user_id);
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            eprintln!("Deleting account {} failed: {}", user_id, e);
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось удалить аккаунт",
+                Locale::En => "account-deletion-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+/// `GET /api/auth/export?token=` downloads a zip of all personal data held for the user
+pub async fn export_account(
+    req: HttpRequest,
+    user: AuthedUser,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = user.id;
+    if !abuse::allow("data-export", &user_id, DATA_EXPORTS_PER_HOUR, Duration::from_secs(3600)) {
+        return too_many_requests(locale);
+    }
+
+    match account::export(pool, &user_id).await {
+        Ok(bytes) => HttpResponse::Ok()
+            .content_type("application/zip")
+            .append_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"personal-data-{}.zip\"", chrono::Utc::now().format("%Y%m%d")),
+            ))
+            .body(bytes),
+        Err(e) => {
+            eprintln!("Personal data export for {} failed: {}", user_id, e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::files::store_file;
+use crate::services::timezone;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct CreateServiceRequest {
+    pub title: String,
+    pub duration_minutes: i64,
+    pub work_start: Option<String>, // "09:00" in the owner's time zone
+    pub work_end: Option<String>,   // "18:00" in the owner's time zone
+}
+
+#[derive(Serialize)]
+pub struct BookingService {
+    pub id: String,
+    pub user_id: String,
+    pub title: String,
+    pub duration_minutes: i64,
+    pub work_start: String,
+    pub work_end: String,
+    pub booking_url: String,
+}
+
+#[derive(Deserialize)]
+pub struct SlotsQuery {
+    pub date: String, // YYYY-MM-DD
+}
+
+#[derive(Deserialize)]
+pub struct CreateBookingRequest {
+    pub service_id: String,
+    pub starts_at: String, // RFC 3339
+    pub client_name: String,
+    pub client_email: Option<String>,
+    pub client_phone: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Booking {
+    pub id: String,
+    pub service_id: String,
+    pub service_title: String,
+    pub client_name: String,
+    pub client_email: Option<String>,
+    pub client_phone: Option<String>,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub status: String,
+    pub created_at: String,
+}
+
+/// Public link to a service's booking page, absolute when PUBLIC_BASE_URL is set
+pub(crate) fn booking_url(service_id: &str) -> String {
+    format!(
+        "{}/api/bookings/services/{}",
+        std::env::var("PUBLIC_BASE_URL").unwrap_or_default().trim_end_matches('/'),
+        service_id
+    )
+}
+
+fn service_from_row(r: &sqlx::sqlite::SqliteRow) -> BookingService {
+    let id: String = r.get("id");
+    BookingService {
+        booking_url: booking_url(&id),
+        id,
+        user_id: r.get("user_id"),
+        title: r.get("title"),
+        duration_minutes: r.get("duration_minutes"),
+        work_start: r.get("work_start"),
+        work_end: r.get("work_end"),
+    }
+}
+
+pub async fn create_service(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    body: web::Json<CreateServiceRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let data = body.into_inner();
+
+    let work_start = data.work_start.unwrap_or_else(|| "09:00".to_string());
+    let work_end = data.work_end.unwrap_or_else(|| "18:00".to_string());
+    let hours_valid = match (parse_hhmm(&work_start), parse_hhmm(&work_end)) {
+        (Some(s), Some(e)) => s < e,
+        _ => false,
+    };
+
+    if data.title.trim().is_empty() || data.duration_minutes <= 0 || !hours_valid {
+        let error_msg = match locale {
+            Locale::Ru => "Некорректные параметры услуги",
+            Locale::En => "invalid-service",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let result = sqlx::query(
+        "INSERT INTO booking_services (id, user_id, title, duration_minutes, work_start, work_end) VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&user_id)
+    .bind(data.title.trim())
+    .bind(data.duration_minutes)
+    .bind(&work_start)
+    .bind(&work_end)
+    .execute(pool)
+    .await;
+
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Created().json(BookingService {
+        booking_url: booking_url(&id),
+        id,
+        user_id,
+        title: data.title.trim().to_string(),
+        duration_minutes: data.duration_minutes,
+        work_start,
+        work_end,
+    })
+}
+
+pub async fn list_services(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(
+        "SELECT id, user_id, title, duration_minutes, work_start, work_end FROM booking_services WHERE user_id = ? ORDER BY title"
+    )
+    .bind(&user_id)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let services: Vec<BookingService> = rs.iter().map(service_from_row).collect();
+            HttpResponse::Ok().json(json!({ "services": services }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Public: the page behind a booking link, no token required
+pub async fn get_service(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let row = sqlx::query(
+        "SELECT id, user_id, title, duration_minutes, work_start, work_end FROM booking_services WHERE id = ?"
+    )
+    .bind(path.into_inner())
+    .fetch_optional(&state.pool)
+    .await;
+
+    match row {
+        Ok(Some(r)) => HttpResponse::Ok().json(service_from_row(&r)),
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Услуга не найдена",
+                Locale::En => "service-not-found",
+            };
+            HttpResponse::NotFound().json(json!({ "error": error_msg }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Public: free slots of a service on a given day
+pub async fn get_slots(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<SlotsQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let service_id = path.into_inner();
+
+    let date = match NaiveDate::parse_from_str(&query.date, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Дата должна быть в формате YYYY-MM-DD",
+                Locale::En => "invalid-date",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let service = match sqlx::query(
+        "SELECT user_id, duration_minutes, work_start, work_end FROM booking_services WHERE id = ?"
+    )
+    .bind(&service_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Услуга не найдена",
+                Locale::En => "service-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let duration = Duration::minutes(service.get::<i64, _>("duration_minutes"));
+    let tz = owner_timezone(pool, &service.get::<String, _>("user_id")).await;
+    let hours = match (
+        parse_hhmm(&service.get::<String, _>("work_start")),
+        parse_hhmm(&service.get::<String, _>("work_end")),
+    ) {
+        (Some(s), Some(e)) => (local_to_utc(tz, date, s), local_to_utc(tz, date, e)),
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+    let (work_start, work_end) = match hours {
+        (Some(s), Some(e)) => (s, e),
+        // The work day starts or ends inside a DST gap: nothing to offer that day
+        _ => {
+            return HttpResponse::Ok().json(json!({
+                "service_id": service_id,
+                "date": query.date,
+                "timezone": tz.name(),
+                "slots": Vec::<String>::new(),
+            }))
+        }
+    };
+
+    let taken = sqlx::query(
+        "SELECT starts_at, ends_at FROM bookings WHERE service_id = ? AND status = 'confirmed' AND starts_at < ? AND ends_at > ?"
+    )
+    .bind(&service_id)
+    .bind(work_end.to_rfc3339())
+    .bind(work_start.to_rfc3339())
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+    .into_iter()
+    .filter_map(|r| {
+        let s = DateTime::parse_from_rfc3339(&r.get::<String, _>("starts_at")).ok()?;
+        let e = DateTime::parse_from_rfc3339(&r.get::<String, _>("ends_at")).ok()?;
+        Some((s.with_timezone(&Utc), e.with_timezone(&Utc)))
+    })
+    .collect::<Vec<_>>();
+
+    let now = Utc::now();
+    let mut slots: Vec<String> = Vec::new();
+    let mut cursor = work_start;
+    while cursor + duration <= work_end {
+        let end = cursor + duration;
+        let overlaps = taken.iter().any(|(s, e)| cursor < *e && end > *s);
+        if !overlaps && cursor > now {
+            slots.push(cursor.with_timezone(&tz).to_rfc3339());
+        }
+        cursor = end;
+    }
+
+    HttpResponse::Ok().json(json!({
+        "service_id": service_id,
+        "date": query.date,
+        "timezone": tz.name(),
+        "slots": slots,
+    }))
+}
+
+/// Public: a client books a slot via the link the assistant generated
+pub async fn create_booking(
+    req: HttpRequest,
+    body: web::Json<CreateBookingRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let data = body.into_inner();
+
+    let starts_at = match DateTime::parse_from_rfc3339(&data.starts_at) {
+        Ok(dt) => dt.with_timezone(&Utc),
+        Err(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Некорректное время начала",
+                Locale::En => "invalid-starts-at",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    if data.client_name.trim().is_empty() || (data.client_email.is_none() && data.client_phone.is_none()) {
+        let error_msg = match locale {
+            Locale::Ru => "Требуются имя клиента и email или телефон",
+            Locale::En => "client-name-and-contact-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let service = match sqlx::query(
+        "SELECT user_id, title, duration_minutes, work_start, work_end FROM booking_services WHERE id = ?"
+    )
+    .bind(&data.service_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Услуга не найдена",
+                Locale::En => "service-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let service_title: String = service.get("title");
+    let ends_at = starts_at + Duration::minutes(service.get::<i64, _>("duration_minutes"));
+    let tz = owner_timezone(pool, &service.get::<String, _>("user_id")).await;
+    let (local_start, local_end) = (starts_at.with_timezone(&tz), ends_at.with_timezone(&tz));
+    let within_hours = match (
+        parse_hhmm(&service.get::<String, _>("work_start")),
+        parse_hhmm(&service.get::<String, _>("work_end")),
+    ) {
+        (Some(s), Some(e)) => {
+            local_start.date_naive() == local_end.date_naive() && local_start.time() >= s && local_end.time() <= e
+        }
+        _ => false,
+    };
+
+    if !within_hours || starts_at <= Utc::now() {
+        let error_msg = match locale {
+            Locale::Ru => "Выбранное время недоступно",
+            Locale::En => "slot-unavailable",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+    }
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let conflicts: i64 = sqlx::query_scalar(
+        "SELECT COUNT(1) FROM bookings WHERE service_id = ? AND status = 'confirmed' AND starts_at < ? AND ends_at > ?"
+    )
+    .bind(&data.service_id)
+    .bind(ends_at.to_rfc3339())
+    .bind(starts_at.to_rfc3339())
+    .fetch_one(&mut tx)
+    .await
+    .unwrap_or(1);
+
+    if conflicts > 0 {
+        let error_msg = match locale {
+            Locale::Ru => "Выбранное время недоступно",
+            Locale::En => "slot-unavailable",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let created_at = Utc::now().to_rfc3339();
+    let insert = sqlx::query(
+        "INSERT INTO bookings (id, service_id, owner_user_id, client_name, client_email, client_phone, starts_at, ends_at, status, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'confirmed', ?)"
+    )
+    .bind(&id)
+    .bind(&data.service_id)
+    .bind(service.get::<String, _>("user_id"))
+    .bind(data.client_name.trim())
+    .bind(&data.client_email)
+    .bind(&data.client_phone)
+    .bind(starts_at.to_rfc3339())
+    .bind(ends_at.to_rfc3339())
+    .bind(&created_at)
+    .execute(&mut tx)
+    .await;
+
+    if insert.is_err() || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let ics = build_ics(&id, &service_title, data.client_name.trim(), starts_at, ends_at);
+    let attachment = store_file(
+        pool,
+        format!("booking-{}.ics", &id[..8]),
+        "text/calendar".to_string(),
+        ics.into_bytes(),
+        None,
+        Some(&service.get::<String, _>("user_id")),
+    )
+    .await
+    .ok();
+
+    HttpResponse::Created().json(json!({
+        "booking": Booking {
+            id,
+            service_id: data.service_id,
+            service_title,
+            client_name: data.client_name.trim().to_string(),
+            client_email: data.client_email,
+            client_phone: data.client_phone,
+            starts_at: starts_at.to_rfc3339(),
+            ends_at: ends_at.to_rfc3339(),
+            status: "confirmed".to_string(),
+            created_at,
+        },
+        "files": attachment.map(|a| vec![a]),
+    }))
+}
+
+/// Owner's view of upcoming bookings across all their services
+pub async fn list_bookings(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(
+        "SELECT b.id, b.service_id, s.title AS service_title, b.client_name, b.client_email, b.client_phone,
+                b.starts_at, b.ends_at, b.status, b.created_at
+         FROM bookings b
+         JOIN booking_services s ON s.id = b.service_id
+         WHERE b.owner_user_id = ? AND b.ends_at > ?
+         ORDER BY b.starts_at ASC"
+    )
+    .bind(&user_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let bookings: Vec<Booking> = rs.into_iter().map(|r| Booking {
+                id: r.get("id"),
+                service_id: r.get("service_id"),
+                service_title: r.get("service_title"),
+                client_name: r.get("client_name"),
+                client_email: r.try_get("client_email").ok().flatten(),
+                client_phone: r.try_get("client_phone").ok().flatten(),
+                starts_at: r.get("starts_at"),
+                ends_at: r.get("ends_at"),
+                status: r.get("status"),
+                created_at: r.get("created_at"),
+            }).collect();
+            HttpResponse::Ok().json(json!({ "bookings": bookings }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+fn parse_hhmm(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M").ok()
+}
+
+/// Work hours are kept as the owner's wall-clock time, in the zone saved in their profile (UTC without one)
+async fn owner_timezone(pool: &SqlitePool, owner_id: &str) -> Tz {
+    timezone::user_timezone(pool, owner_id).await.unwrap_or(Tz::UTC)
+}
+
+/// `time` on `date` in `tz`; `None` when DST skips it
+fn local_to_utc(tz: Tz, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+    tz.from_local_datetime(&date.and_time(time)).earliest().map(|dt| dt.with_timezone(&Utc))
+}
+
+fn build_ics(id: &str, title: &str, client_name: &str, starts_at: DateTime<Utc>, ends_at: DateTime<Utc>) -> String {
+    let fmt = "%Y%m%dT%H%M%SZ";
+    let escape = |s: &str| s.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n");
+    [
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//business-assistant//bookings//EN".to_string(),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@business-assistant", id),
+        format!("DTSTAMP:{}", Utc::now().format(fmt)),
+        format!("DTSTART:{}", starts_at.format(fmt)),
+        format!("DTEND:{}", ends_at.format(fmt)),
+        format!("SUMMARY:{}", escape(title)),
+        format!("DESCRIPTION:{}", escape(client_name)),
+        "END:VEVENT".to_string(),
+        "END:VCALENDAR".to_string(),
+    ]
+    .join("\r\n")
+        + "\r\n"
+}
@@ -0,0 +1,127 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+
+use crate::handlers::admin::require_admin;
+use crate::services::openai;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Serialize)]
+pub struct CategoryModel {
+    pub category: String,
+    /// OpenRouter model id, e.g. `anthropic/claude-3.5-sonnet`
+    pub model: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// `GET /api/admin/category-models` lists the overrides and the model every other category gets
+pub async fn list_category_models(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let rows = sqlx::query("SELECT category, model, created_at, updated_at FROM category_models ORDER BY category")
+        .fetch_all(&state.pool)
+        .await;
+
+    match rows {
+        Ok(rs) => {
+            let routes: Vec<CategoryModel> = rs.iter().map(|r| CategoryModel {
+                category: r.get("category"),
+                model: r.get("model"),
+                created_at: r.get("created_at"),
+                updated_at: r.get("updated_at"),
+            }).collect();
+            HttpResponse::Ok().json(json!({
+                "category_models": routes,
+                "default_model": openai::current_model(&state),
+                "vision_model": openai::vision_model(&state),
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetCategoryModelRequest {
+    pub model: String,
+}
+
+/// `PUT /api/admin/category-models/{category}` routes the category to `model`, replacing any previous override
+pub async fn set_category_model(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<SetCategoryModelRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let category = path.into_inner().trim().to_lowercase();
+    let model = body.model.trim();
+    if category.is_empty() || model.is_empty() {
+        let error_msg = match locale {
+            Locale::Ru => "Требуются категория и модель",
+            Locale::En => "category-and-model-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        "INSERT INTO category_models (category, model, created_at, updated_at) VALUES (?, ?, ?, ?)
+         ON CONFLICT(category) DO UPDATE SET model = excluded.model, updated_at = excluded.updated_at
+         RETURNING created_at"
+    )
+    .bind(&category)
+    .bind(model)
+    .bind(&now)
+    .bind(&now)
+    .fetch_one(&state.pool)
+    .await;
+
+    match result {
+        Ok(row) => HttpResponse::Ok().json(CategoryModel {
+            category,
+            model: model.to_string(),
+            created_at: row.get("created_at"),
+            updated_at: now,
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `DELETE /api/admin/category-models/{category}` sends the category back to the default model
+pub async fn delete_category_model(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let category = path.into_inner().trim().to_lowercase();
+
+    let result = sqlx::query("DELETE FROM category_models WHERE category = ?")
+        .bind(&category)
+        .execute(&state.pool)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => {
+            let error_msg = match locale {
+                Locale::Ru => "Для этой категории модель не задана",
+                Locale::En => "category-model-not-found",
+            };
+            HttpResponse::NotFound().json(json!({ "error": error_msg }))
+        }
+        Ok(_) => HttpResponse::Ok().json(json!({ "status": "deleted", "category": category })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -1,63 +1,172 @@
 use actix_web::{web, HttpRequest, HttpResponse};
+use actix_multipart::Multipart;
+use futures_util::TryStreamExt;
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::models::{ChatRequest, ChatResponse, MessageRecord, ConversationSummary, FileAttachment, TableSpec, ConversationContext, ContextFilters, CreateConversationRequest};
+use crate::models::{ChatRequest, ChatResponse, Clarification, InlineImage, MessageRecord, ConversationSummary, FileAttachment, TableSpec, ConversationContext, ContextFilters, CreateConversationRequest};
 use crate::state::AppState;
-use crate::services::openai;
+use crate::services::{breaker, clarify, disclaimer, extract, geoip, knowledge, openai, preferences, storage, structured, summary, timezone, titles, tokens, transcription, tts};
+use crate::services::transcript::{self, Transcript, TranscriptFormat, TranscriptMessage};
+use crate::handlers::{drafts, files, inventory, limits, reads, reference, stats};
 use crate::i18n::{self, Locale};
+use crate::metrics::{self, LlmSignal};
 use sqlx::Row;
 use base64::engine::general_purpose::STANDARD as B64;
 use base64::Engine;
-use rust_xlsxwriter::Workbook;
+use rust_xlsxwriter::{DocProperties, ExcelDateTime, Workbook};
+use std::collections::HashMap;
 use std::io::Cursor;
-use serde::Deserialize;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
 
-pub async fn send_message(
-    req: HttpRequest,
-    data: web::Json<ChatRequest>,
-    state: web::Data<AppState>,
-) -> HttpResponse {
-    let chat_req = data.into_inner();
-    
-    let locale = if let Some(lang) = chat_req.language.as_ref() {
+/// Days a deleted conversation can still be restored before the scheduler purges it
+pub const CONVERSATION_RETENTION_DAYS: i64 = 30;
+
+/// Everything resolved before the model is called; shared by the blocking and streaming endpoints
+pub(crate) struct ChatTurn {
+    chat_req: ChatRequest,
+    locale: Locale,
+    resolved_user_id: String,
+    conversation_id: String,
+    category: String,
+    business_type: String,
+    history: Option<Vec<(String, String)>>,
+    context: ConversationContext,
+    /// Set when answering an already stored (edited) user message instead of a new one
+    resend_of: Option<String>,
+    /// Images attached to this message, for the vision model
+    images: Vec<openai::ImageInput>,
+    /// Clarifying questions already asked when the model may ask another one instead of answering
+    clarify: Option<u32>,
+    /// The conversation was waiting on answers to clarifying questions
+    clarifying: bool,
+    /// Model answering this turn, see `openai::chat_model`
+    model: String,
+    /// The conversation has no title yet; one is generated alongside the answer
+    untitled: bool,
+}
+
+pub(crate) async fn prepare_turn(
+    req: &HttpRequest,
+    chat_req: ChatRequest,
+    state: &AppState,
+) -> Result<ChatTurn, HttpResponse> {
+    build_turn(chat_req, i18n::detect_locale(req), geoip::request_country(req).await, state).await
+}
+
+/// `prepare_turn` without the HTTP request: `request_locale` applies unless the message names
+/// its language, `request_region` is the last resort for the user's region
+async fn build_turn(
+    mut chat_req: ChatRequest,
+    request_locale: Locale,
+    request_region: Option<String>,
+    state: &AppState,
+) -> Result<ChatTurn, HttpResponse> {
+    let mut locale = if let Some(lang) = chat_req.language.as_ref() {
         match lang.to_lowercase().as_str() {
             "ru" | "ru-ru" => Locale::Ru,
             _ => Locale::En,
         }
     } else {
-        i18n::detect_locale(&req)
+        request_locale
     };
     
-    if chat_req.message.is_empty() || chat_req.user_id.is_empty() {
+    if (chat_req.message.is_empty() && chat_req.attachment_ids.is_empty()) || chat_req.user_id.is_empty() {
         let error_msg = match locale {
             Locale::Ru => "Требуются сообщение и user_id",
             Locale::En => "Message and user_id are required",
         };
-        return HttpResponse::BadRequest().json(json!({
+        return Err(HttpResponse::BadRequest().json(json!({
             "error": error_msg
-        }));
+        })));
+    }
+    if let Some(schema) = chat_req.response_schema.as_ref() {
+        if let Err(problem) = structured::check_schema(schema) {
+            let error_msg = match locale {
+                Locale::Ru => "Некорректная схема ответа",
+                Locale::En => "invalid-response-schema",
+            };
+            return Err(HttpResponse::BadRequest().json(json!({ "error": error_msg, "detail": problem })));
+        }
+    }
+    if let Some(filters) = chat_req.context_filters.as_mut() {
+        filters.region = reference::validate_country(filters.region.as_deref(), "region", locale)?;
+    }
+    let requested_model = chat_req.model.as_deref().map(str::trim);
+    if let Some(model) = requested_model.filter(|m| !m.is_empty()) {
+        let allowed = openai::allowed_models(state);
+        if !allowed.iter().any(|m| m == model) {
+            let error_msg = match locale {
+                Locale::Ru => "Эта модель недоступна",
+                Locale::En => "model-not-allowed",
+            };
+            return Err(HttpResponse::BadRequest().json(json!({ "error": error_msg, "allowed_models": allowed })));
+        }
+    }
+    if chat_req.message.is_empty() {
+        chat_req.message = match locale {
+            Locale::Ru => "Проанализируй приложенные файлы",
+            Locale::En => "Please analyze the attached files",
+        }.to_string();
     }
-
-    let default_business_type = match locale {
-        Locale::Ru => "общий бизнес",
-        Locale::En => "general business",
-    };
-    
-    let error_message = match locale {
-        Locale::Ru => "Извините, произошла ошибка при обработке запроса",
-        Locale::En => "Sorry, an error occurred while processing your request",
-    };
 
     let pool = &state.pool;
     
     // Resolve user_id to main user_id for conversation synchronization
     let resolved_user_id = resolve_user_id_for_conversations(pool, &chat_req.user_id).await;
+
+    // Saved preferences fill in what the message leaves out
+    let prefs = preferences::load(pool, &resolved_user_id).await;
+    if chat_req.language.is_none() {
+        locale = prefs.locale().unwrap_or(locale);
+    }
+    if chat_req.category.is_none() {
+        chat_req.category = prefs.default_category;
+    }
+    if chat_req.output_format.is_none() {
+        chat_req.output_format = prefs.default_output_format;
+    }
+    let default_model = prefs.default_model;
+
+    // Checked before any inline image is stored, so an oversized request leaves no files behind
+    let mut distinct_ids: Vec<&String> = chat_req.attachment_ids.iter().collect();
+    distinct_ids.sort();
+    distinct_ids.dedup();
+    if distinct_ids.len() + chat_req.images.len() > MAX_ATTACHMENTS {
+        let error_msg = match locale {
+            Locale::Ru => "Слишком много вложений",
+            Locale::En => "too-many-attachments",
+        };
+        return Err(HttpResponse::BadRequest().json(json!({ "error": error_msg, "max_attachments": MAX_ATTACHMENTS })));
+    }
+
+    // Inline images become regular uploads so they show up in history and the file gallery
+    for image in std::mem::take(&mut chat_req.images) {
+        let file_id = store_inline_image(state, &resolved_user_id, image, locale).await?;
+        chat_req.attachment_ids.push(file_id);
+    }
+
+    let mut attachments = match load_attachments(pool, &resolved_user_id, &chat_req.attachment_ids).await {
+        Ok(Some(a)) => a,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Вложение не найдено или уже использовано",
+                Locale::En => "invalid-attachment",
+            };
+            return Err(HttpResponse::BadRequest().json(json!({ "error": error_msg, "max_attachments": MAX_ATTACHMENTS })));
+        }
+        Err(_) => return Err(HttpResponse::InternalServerError().finish()),
+    };
+    chat_req.attachment_ids = attachments.iter().map(|a| a.id.clone()).collect();
+    let images: Vec<openai::ImageInput> = attachments.iter_mut().filter_map(|a| a.image.take()).collect();
+    // A voice message's transcript is the message text already
+    attachments.retain(|a| !transcription::audio_mime_allowed(&a.mime));
     
     let conversation_id = if let Some(cid) = chat_req.conversation_id.clone() {
         // Validate conversation belongs to resolved user_id (all conversations use resolved_user_id)
         let exists: Option<i64> = sqlx::query_scalar(
-            "SELECT CASE WHEN EXISTS(SELECT 1 FROM conversations WHERE id = ? AND user_id = ?) THEN 1 ELSE 0 END"
+            "SELECT CASE WHEN EXISTS(SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL) THEN 1 ELSE 0 END"
         )
         .bind(&cid)
         .bind(&resolved_user_id)
@@ -109,116 +218,597 @@ pub async fn send_message(
         new_id
     };
     
+    // A model picked on a message sticks to the conversation; an empty one goes back to the default
+    if let Some(model) = requested_model {
+        let _ = sqlx::query("UPDATE conversations SET model = ? WHERE id = ?")
+            .bind(Some(model).filter(|m| !m.is_empty()))
+            .bind(&conversation_id)
+            .execute(pool)
+            .await;
+    }
+
+    // The conversation keeps the language of its first message, so prompts, titles and exports
+    // don't flip with each device's settings; a message naming its own language still wins
+    let conversation_language: Option<String> = sqlx::query_scalar("SELECT language FROM conversations WHERE id = ?")
+        .bind(&conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    match conversation_language.as_deref().and_then(i18n::parse_language) {
+        Some(stored) if chat_req.language.is_none() => locale = stored,
+        Some(_) => {}
+        None => {
+            let _ = sqlx::query("UPDATE conversations SET language = ? WHERE id = ? AND language IS NULL")
+                .bind(i18n::language_code(locale))
+                .bind(&conversation_id)
+                .execute(pool)
+                .await;
+        }
+    }
+    let default_business_type = match locale {
+        Locale::Ru => "общий бизнес",
+        Locale::En => "general business",
+    };
+
+    let conversation_model: Option<String> = sqlx::query_scalar("SELECT model FROM conversations WHERE id = ?")
+        .bind(&conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    let untitled: Option<i64> = sqlx::query_scalar("SELECT 1 FROM conversations WHERE id = ? AND (title IS NULL OR title = '')")
+        .bind(&conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let category = chat_req.category.clone().unwrap_or_else(|| "general".to_string());
+    let model = openai::chat_model(
+        state,
+        &category,
+        !images.is_empty(),
+        conversation_model.as_deref(),
+        default_model.as_deref(),
+    )
+    .await;
+
     // Получить контекст для использования в промпте
     let conversation_context = get_conversation_context(pool, &conversation_id).await;
     let user_base_context = get_user_base_context(pool, &resolved_user_id).await;
-    let final_context = merge_contexts(user_base_context, conversation_context, chat_req.context_filters.clone());
+    let mut final_context = merge_contexts(user_base_context, conversation_context, chat_req.context_filters.clone());
+    // Last resort: the country the request came from
+    if final_context.region.is_none() {
+        final_context.region = request_region.map(|c| crate::services::reference::country_code(&c).map(str::to_string).unwrap_or(c));
+    }
 
-    let conversation_history = {
+    let mut conversation_history: Option<Vec<(String, String)>> = {
         let history_rows = sqlx::query(
-            "SELECT role, content FROM messages WHERE conversation_id = ? ORDER BY datetime(timestamp) ASC"
+            "SELECT id, role, content FROM messages WHERE conversation_id = ? ORDER BY datetime(timestamp) ASC"
         )
         .bind(&conversation_id)
         .fetch_all(pool)
         .await
         .ok();
-        
+        let mut earlier_attachments = conversation_attachments(pool, &conversation_id).await;
+
         history_rows.map(|rows| {
-            rows.into_iter()
-                .map(|r| {
-                    let role: String = r.get("role");
-                    let content: String = r.get("content");
-                    (role, content)
-                })
-                .collect()
+            let mut history = Vec::new();
+            for r in rows {
+                let id: String = r.get("id");
+                history.push((r.get::<String, _>("role"), r.get::<String, _>("content")));
+                // Documents stay visible to the model for the rest of the conversation
+                if let Some(files) = earlier_attachments.remove(&id) {
+                    history.push(("system".to_string(), attachment_note(&files, locale)));
+                }
+            }
+            history
         })
     };
+    if let Some(history) = conversation_history.take() {
+        conversation_history = Some(summary::compact_history(state, &conversation_id, history, locale).await);
+    }
 
-    let raw_ai_response = match openai::generate_response(
-        &chat_req.message,
-        chat_req.category.as_deref().unwrap_or("general"),
-        chat_req.business_type.as_deref().unwrap_or(default_business_type),
-        &state,
-        &chat_req.user_id,
+    // Ground stock questions in the user's real inventory (runtime flag `inventory_grounding`)
+    if state.config.load().feature_enabled("inventory_grounding", true) {
+        if let Some(note) = inventory::inventory_prompt_note(pool, &resolved_user_id, locale).await {
+            conversation_history
+                .get_or_insert_with(Vec::new)
+                .push(("system".to_string(), note));
+        }
+    }
+
+    // Excerpts from the user's knowledge-base documents (runtime flag `knowledge_base`)
+    if state.config.load().feature_enabled("knowledge_base", true) {
+        let chunks = knowledge::relevant_chunks(pool, &resolved_user_id, &chat_req.message).await;
+        if !chunks.is_empty() {
+            conversation_history
+                .get_or_insert_with(Vec::new)
+                .push(("system".to_string(), knowledge::prompt_note(&chunks, locale)));
+        }
+    }
+
+    if !attachments.is_empty() {
+        conversation_history
+            .get_or_insert_with(Vec::new)
+            .push(("system".to_string(), attachment_note(&attachments, locale)));
+    }
+
+    // Vague questions get up to `max_questions` follow-ups before the full answer; once started,
+    // the conversation keeps clarifying unless the request opts out
+    let policy = state.config.load().clarification.clone();
+    let pending = clarify::pending(pool, &conversation_id).await;
+    let asked = pending.unwrap_or(0);
+    let wanted = chat_req.clarify.unwrap_or(policy.enabled || pending.is_some()) && chat_req.response_schema.is_none();
+    let clarify_turn = (wanted && asked < policy.max_questions).then_some(asked);
+    if clarify_turn.is_some() {
+        conversation_history
+            .get_or_insert_with(Vec::new)
+            .push(("system".to_string(), clarify::instruction(asked, policy.max_questions, locale)));
+    }
+
+    Ok(ChatTurn {
+        category,
+        business_type: chat_req.business_type.clone().unwrap_or_else(|| default_business_type.to_string()),
+        chat_req,
         locale,
-        conversation_history,
-        final_context,
-    ).await {
-        Ok(response) => response,
-        Err(_) => error_message.to_string()
+        resolved_user_id,
+        conversation_id,
+        history: conversation_history,
+        context: final_context,
+        resend_of: None,
+        images,
+        clarify: clarify_turn,
+        clarifying: pending.is_some(),
+        model,
+        untitled: untitled.is_some(),
+    })
+}
+
+/// Files a user may attach to a single message
+pub const MAX_ATTACHMENTS: usize = 5;
+
+/// An uploaded file attached to a user message, with the text the model gets to see
+struct MessageAttachment {
+    id: String,
+    filename: String,
+    mime: String,
+    text: Option<String>,
+    /// Only set for images attached to the current message
+    image: Option<openai::ImageInput>,
+}
+
+/// Largest inline (base64) image accepted on a message
+pub const MAX_INLINE_IMAGE: usize = 10 * 1024 * 1024;
+
+/// Decodes a base64 image from the request and stores it as the user's upload
+async fn store_inline_image(state: &AppState, user_id: &str, image: InlineImage, locale: Locale) -> Result<String, HttpResponse> {
+    // Accept both raw base64 and `data:image/png;base64,...` URLs
+    let data = image.data.rsplit_once("base64,").map(|(_, d)| d).unwrap_or(&image.data);
+    let bytes = match B64.decode(data.trim()) {
+        Ok(b) if !b.is_empty() && b.len() <= MAX_INLINE_IMAGE && image.mime.starts_with("image/") => b,
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Некорректное изображение (до 10MB, base64)",
+                Locale::En => "invalid-image",
+            };
+            return Err(HttpResponse::BadRequest().json(json!({ "error": error_msg })));
+        }
     };
 
-    let mut ai_response = String::new();
-    let mut title: Option<String> = None;
-    {
-        let mut lines = raw_ai_response.lines();
-        if let Some(first) = lines.next() {
-            let trimmed = first.trim();
-            if let Some(rest) = trimmed.strip_prefix("TITLE:") {
-                let t = rest.trim();
-                if !t.is_empty() {
-                    title = Some(t.chars().take(80).collect());
-                }
-                if let Some(second) = lines.next() {
-                    let second_trimmed = second.trim();
-                    if second_trimmed.is_empty() {
-                        ai_response = lines.collect::<Vec<_>>().join("\n");
-                    } else {
-                        let mut all = Vec::new();
-                        all.push(second);
-                        all.extend(lines);
-                        ai_response = all.join("\n");
-                    }
-                } else {
-                    ai_response.clear();
-                }
-            } else {
-                ai_response = raw_ai_response.clone();
+    files::ensure_storage_quota(state, user_id, bytes.len(), locale).await?;
+    let filename = image.filename.unwrap_or_else(|| {
+        let ext = image.mime.trim_start_matches("image/");
+        format!("image-{}.{}", chrono::Utc::now().format("%Y%m%d-%H%M%S"), ext)
+    });
+    files::scan_upload(state, user_id, &filename, &image.mime, &bytes, locale).await?;
+    match files::store_file(&state.pool, filename, image.mime, bytes, None, Some(user_id)).await {
+        Ok(att) => Ok(att.id.unwrap_or_default()),
+        Err(_) => Err(HttpResponse::InternalServerError().finish()),
+    }
+}
+
+/// Resolves `ids` to the user's own, not yet attached files, extracting their text on first use;
+/// `None` when any id is unusable or there are too many
+async fn load_attachments(
+    pool: &sqlx::SqlitePool,
+    user_id: &str,
+    ids: &[String],
+) -> Result<Option<Vec<MessageAttachment>>, sqlx::Error> {
+    let mut unique: Vec<&String> = Vec::new();
+    for id in ids {
+        if !unique.contains(&id) {
+            unique.push(id);
+        }
+    }
+    if unique.len() > MAX_ATTACHMENTS {
+        return Ok(None);
+    }
+
+    let mut attachments = Vec::new();
+    for id in unique {
+        let row = sqlx::query(
+            "SELECT f.filename, f.mime, f.extracted_text, COALESCE(b.bytes, f.bytes) AS bytes
+             FROM files f LEFT JOIN file_blobs b ON b.sha256 = f.sha256
+             WHERE f.id = ? AND f.user_id = ? AND f.message_id IS NULL
+               AND f.deleted_at IS NULL AND f.scan_status IS NOT 'quarantined'"
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        let row = match row {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+
+        let mime: String = row.get("mime");
+        let bytes: Vec<u8> = row.get("bytes");
+        let image = mime.starts_with("image/").then(|| openai::ImageInput {
+            mime: mime.clone(),
+            base64: B64.encode(&bytes),
+        });
+        let mut text: Option<String> = row.get("extracted_text");
+        if text.is_none() && image.is_none() {
+            let file_mime = mime.clone();
+            // PDF parsing is CPU-bound; keep it off the async workers
+            text = web::block(move || extract::extract_text(&file_mime, &bytes)).await.ok().flatten();
+            if text.is_some() {
+                sqlx::query("UPDATE files SET extracted_text = ? WHERE id = ?")
+                    .bind(&text)
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
             }
-        } else {
-            ai_response = raw_ai_response.clone();
         }
+
+        attachments.push(MessageAttachment {
+            id: id.clone(),
+            filename: row.get("filename"),
+            mime,
+            text,
+            image,
+        });
     }
+    Ok(Some(attachments))
+}
+
+/// Attachments of earlier user messages in a conversation, keyed by message id
+async fn conversation_attachments(pool: &sqlx::SqlitePool, conversation_id: &str) -> HashMap<String, Vec<MessageAttachment>> {
+    let rows = sqlx::query(
+        "SELECT f.id, f.message_id, f.filename, f.mime, f.extracted_text FROM files f
+         JOIN messages m ON m.id = f.message_id
+         WHERE m.conversation_id = ? AND m.role = 'user' AND f.deleted_at IS NULL
+         ORDER BY f.created_at"
+    )
+    .bind(conversation_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
 
-    if title.is_none() {
-        let first_line = ai_response
-            .lines()
-            .find(|line| !line.trim().is_empty())
-            .unwrap_or("")
-            .trim();
-        if !first_line.is_empty() {
-            title = Some(first_line.chars().take(80).collect());
+    let mut by_message: HashMap<String, Vec<MessageAttachment>> = HashMap::new();
+    for r in rows {
+        let mime: String = r.get("mime");
+        if transcription::audio_mime_allowed(&mime) {
+            continue;
         }
+        by_message.entry(r.get("message_id")).or_default().push(MessageAttachment {
+            id: r.get("id"),
+            filename: r.get("filename"),
+            mime,
+            text: r.get("extracted_text"),
+            image: None,
+        });
     }
+    by_message
+}
 
-    if let Some(ref title_str) = title {
-        let _ = sqlx::query(
-            "UPDATE conversations SET title = ? WHERE id = ? AND (title IS NULL OR title = '')"
-        )
-        .bind(title_str)
-        .bind(&conversation_id)
-        .execute(pool)
-        .await;
+fn attachment_note(files: &[MessageAttachment], locale: Locale) -> String {
+    let (header, no_text) = match locale {
+        Locale::Ru => ("Пользователь приложил к сообщению файлы:", "(текст из файла извлечь не удалось)"),
+        Locale::En => ("The user attached files to the message:", "(no text could be extracted)"),
+    };
+    let mut note = header.to_string();
+    for f in files {
+        note.push_str(&format!("\n\n--- {} ({}) ---\n{}", f.filename, f.mime, f.text.as_deref().unwrap_or(no_text)));
     }
+    note
+}
+
+/// Stores the user's message and links its attachments; returns the message id
+async fn store_user_message(
+    pool: &sqlx::SqlitePool,
+    chat_req: &ChatRequest,
+    resolved_user_id: &str,
+    conversation_id: &str,
+    locale: Locale,
+    category: &str,
+) -> String {
+    stats::record_message(pool, resolved_user_id, locale, category).await;
 
     let user_msg_id = Uuid::new_v4().to_string();
-    let now1 = chrono::Utc::now().to_rfc3339();
+    let now = chrono::Utc::now().to_rfc3339();
     let _ = sqlx::query(
         "INSERT INTO messages (id, conversation_id, user_id, role, content, timestamp) VALUES (?, ?, ?, ?, ?, ?)"
     )
     .bind(&user_msg_id)
-        .bind(&conversation_id)
-    .bind(&resolved_user_id)
+    .bind(conversation_id)
+    .bind(resolved_user_id)
     .bind("user")
     .bind(&chat_req.message)
-    .bind(&now1)
+    .bind(&now)
+    .execute(pool)
+    .await;
+    drafts::clear(pool, conversation_id, resolved_user_id).await;
+
+    for file_id in &chat_req.attachment_ids {
+        let _ = sqlx::query("UPDATE files SET message_id = ? WHERE id = ? AND message_id IS NULL")
+            .bind(&user_msg_id)
+            .bind(file_id)
+            .execute(pool)
+            .await;
+    }
+    user_msg_id
+}
+
+/// Ends a turn whose answer was cancelled: the question stays in the conversation, no answer is stored
+async fn cancel_turn(state: &AppState, turn: ChatTurn) -> serde_json::Value {
+    let ChatTurn { chat_req, locale, resolved_user_id, conversation_id, category, resend_of, .. } = turn;
+    if resend_of.is_none() {
+        store_user_message(&state.pool, &chat_req, &resolved_user_id, &conversation_id, locale, &category).await;
+    }
+    json!({ "status": "cancelled", "conversation_id": conversation_id })
+}
+
+/// Accepts a message while the LLM provider is down: it is stored as `pending` and answered
+/// from the retry queue once the circuit breaker closes, with a push when the answer is ready
+async fn queue_turn(state: &AppState, turn: ChatTurn, retry_after: Duration) -> serde_json::Value {
+    let ChatTurn { mut chat_req, locale, resolved_user_id, conversation_id, category, resend_of, context, .. } = turn;
+    let pool = &state.pool;
+    let message_id = match resend_of {
+        Some(id) => id,
+        None => store_user_message(pool, &chat_req, &resolved_user_id, &conversation_id, locale, &category).await,
+    };
+    let _ = sqlx::query("UPDATE messages SET status = 'pending' WHERE id = ?")
+        .bind(&message_id)
+        .execute(pool)
+        .await;
+
+    // Replayed against the stored message: attachments are linked to it already
+    chat_req.conversation_id = Some(conversation_id.clone());
+    chat_req.attachment_ids.clear();
+    let now = chrono::Utc::now();
+    let retry_at = (now + chrono::Duration::from_std(retry_after).unwrap_or_else(|_| chrono::Duration::zero())).to_rfc3339();
+    let _ = sqlx::query(
+        "INSERT INTO queued_turns (id, message_id, conversation_id, user_id, request, locale, region, created_at, next_attempt_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&message_id)
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .bind(serde_json::to_string(&chat_req).unwrap_or_default())
+    .bind(match locale { Locale::Ru => "ru", Locale::En => "en" })
+    .bind(context.region)
+    .bind(now.to_rfc3339())
+    .bind(&retry_at)
     .execute(pool)
     .await;
 
+    json!({
+        "status": "pending",
+        "conversation_id": conversation_id,
+        "message_id": message_id,
+        "retry_after_seconds": retry_after.as_secs().max(1),
+        "retry_at": retry_at,
+    })
+}
+
+/// Answers a message from the retry queue. `Ok(None)` when the provider failed again;
+/// `Err` when the turn can no longer be prepared, e.g. the conversation is gone.
+pub(crate) async fn answer_queued(
+    state: &AppState,
+    message_id: &str,
+    chat_req: ChatRequest,
+    locale: Locale,
+    region: Option<String>,
+) -> Result<Option<ChatResponse>, HttpResponse> {
+    // build_turn opens a fresh conversation for a missing one; a queued message must stay in its own
+    let pool = &state.pool;
+    let owner = resolve_user_id_for_conversations(pool, &chat_req.user_id).await;
+    let conversation_id = chat_req.conversation_id.clone().unwrap_or_default();
+    let live: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .bind(&owner)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    if live.is_none() {
+        return Err(HttpResponse::Gone().finish());
+    }
+
+    let mut turn = build_turn(chat_req, locale, region, state).await?;
+    // The stored message goes out as the current message, not as history
+    if let Some(history) = turn.history.as_mut() {
+        if let Some(pos) = history.iter().rposition(|(role, content)| role == "user" && *content == turn.chat_req.message) {
+            history.remove(pos);
+        }
+    }
+    turn.resend_of = Some(message_id.to_string());
+
+    let generation = openai::generate_response(
+        &turn.chat_req.message,
+        &turn.category,
+        &turn.business_type,
+        state,
+        &turn.model,
+        &turn.resolved_user_id,
+        turn.locale,
+        turn.history.take(),
+        turn.context.clone(),
+        &turn.images,
+        turn.chat_req.response_schema.as_ref(),
+    );
+    match generation.await {
+        Ok(output) => Ok(Some(complete_turn(state, turn, Some(output)).await)),
+        Err(e) => {
+            eprintln!("Queued message {} failed again: {}", message_id, e);
+            Ok(None)
+        }
+    }
+}
+
+/// Runs `generation` unless the conversation's answer is cancelled first; dropping the
+/// future aborts the OpenRouter request. `None` means cancelled.
+async fn until_cancelled<T>(state: &AppState, conversation_id: &str, generation: impl std::future::Future<Output = T>) -> Option<T> {
+    let (generation_id, mut cancelled) = state.begin_generation(conversation_id);
+    let outcome = tokio::select! {
+        output = generation => Some(output),
+        _ = cancelled.wait_for(|c| *c) => None,
+    };
+    state.finish_generation(conversation_id, &generation_id);
+    outcome
+}
+
+/// Files generated from markdown tables in a single answer, one per table
+const MAX_GENERATED_TABLES: usize = 5;
+
+/// Post-processes the model output (metrics, persistence, generated files) and starts naming an
+/// untitled conversation; `None` means the call failed
+async fn complete_turn(state: &AppState, turn: ChatTurn, llm_output: Option<openai::Completion>) -> ChatResponse {
+    // The model is kept on the answer so feedback can be broken down by the model that wrote it
+    let ChatTurn { chat_req, locale, resolved_user_id, conversation_id, category, resend_of, clarify: clarify_turn, clarifying, model, untitled, .. } = turn;
+    let pool = &state.pool;
+
+    // Named by a separate small-model call so the answer itself carries no title line
+    if untitled {
+        let (state, conversation_id, message) = (state.clone(), conversation_id.clone(), chat_req.message.clone());
+        actix_web::rt::spawn(async move {
+            titles::ensure_title(&state, &conversation_id, &message, locale).await;
+        });
+    }
+
+    let error_message = match locale {
+        Locale::Ru => "Извините, произошла ошибка при обработке запроса",
+        Locale::En => "Sorry, an error occurred while processing your request",
+    };
+    let mut llm_failed = false;
+    let (llm_output, mut sources) = match llm_output {
+        Some(c) => (Some(c.text), c.sources),
+        None => (None, Vec::new()),
+    };
+    let mut raw_ai_response = match llm_output {
+        Some(response) if !response.trim().is_empty() => response,
+        _ => {
+            metrics::record(LlmSignal::EmptyResponse, &model, locale);
+            llm_failed = true;
+            error_message.to_string()
+        }
+    };
+
+    // Structured answers are returned as parsed JSON once they match the client's schema
+    let mut structured_answer: Option<serde_json::Value> = None;
+    let mut schema_errors: Option<Vec<String>> = None;
+    if let (Some(schema), false) = (chat_req.response_schema.as_ref(), llm_failed) {
+        let errors = match structured::parse_answer(&raw_ai_response) {
+            Some(value) => {
+                let errors = structured::validate(&value, schema);
+                if errors.is_empty() {
+                    raw_ai_response = value.to_string();
+                    structured_answer = Some(value);
+                }
+                errors
+            }
+            None => vec!["$: not valid JSON".to_string()],
+        };
+        if !errors.is_empty() {
+            eprintln!("Structured answer rejected: {}", errors.join("; "));
+            metrics::record(LlmSignal::SchemaViolation, &model, locale);
+            llm_failed = true;
+            raw_ai_response = error_message.to_string();
+            schema_errors = Some(errors);
+        }
+    }
+
+    // A follow-up question instead of the answer; the conversation waits for the user's reply
+    let question = match (clarify_turn, llm_failed) {
+        (Some(_), false) => clarify::parse(&raw_ai_response),
+        _ => None,
+    };
+    let clarification = match (clarify_turn, question.as_ref()) {
+        (Some(asked), Some(_)) => {
+            clarify::set_pending(pool, &conversation_id, Some(asked + 1)).await;
+            Some(Clarification { question: asked + 1, max_questions: state.config.load().clarification.max_questions })
+        }
+        _ => {
+            if clarifying && !llm_failed {
+                clarify::set_pending(pool, &conversation_id, None).await;
+            }
+            None
+        }
+    };
+
+    // A failed answer or a clarifying question isn't based on what the tools returned
+    if llm_failed || clarification.is_some() {
+        sources.clear();
+    }
+    let (mut ai_response, suggested_replies) = match question {
+        Some(q) => (q.text, q.options),
+        None => (raw_ai_response, Vec::new()),
+    };
+    if is_refusal(&ai_response) {
+        metrics::record(LlmSignal::Refusal, &model, locale);
+    }
+
+    // Compliance text comes from the disclaimers table rather than the model, and the answer
+    // records which version it carried
+    let disclaimer = if llm_failed || clarification.is_some() { None } else { disclaimer::current(pool, &category, locale).await };
+    if let (Some(d), None) = (disclaimer.as_ref(), structured_answer.as_ref()) {
+        ai_response.push_str(disclaimer::SEPARATOR);
+        ai_response.push_str(&d.text);
+    }
+
+    // A resent message is already stored and counted
+    let resent = resend_of.is_some();
+    let user_msg_id = match resend_of {
+        Some(id) => id,
+        None => store_user_message(pool, &chat_req, &resolved_user_id, &conversation_id, locale, &category).await,
+    };
+    // Nothing is stored for a failed answer: the question is marked `failed` and can be answered
+    // again through `retry_message` without retyping it
+    let status = if llm_failed { Some("failed") } else { None };
+    if llm_failed || resent {
+        let _ = sqlx::query("UPDATE messages SET status = ? WHERE id = ?")
+            .bind(status)
+            .bind(&user_msg_id)
+            .execute(pool)
+            .await;
+    }
+    if llm_failed {
+        return ChatResponse {
+            response: ai_response,
+            message_id: user_msg_id.clone(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            conversation_id,
+            files: None,
+            structured: None,
+            schema_errors,
+            disclaimer: None,
+            clarification: None,
+            sources: Vec::new(),
+            suggested_replies: Vec::new(),
+            retry_message_id: Some(user_msg_id),
+        };
+    }
+
     let asst_msg_id = Uuid::new_v4().to_string();
     let now2 = chrono::Utc::now().to_rfc3339();
     let _ = sqlx::query(
-        "INSERT INTO messages (id, conversation_id, user_id, role, content, timestamp) VALUES (?, ?, ?, ?, ?, ?)"
+        "INSERT INTO messages (id, conversation_id, user_id, role, content, timestamp, category, model, disclaimer_id, prompt_version) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
     )
     .bind(&asst_msg_id)
         .bind(&conversation_id)
@@ -226,68 +816,609 @@ pub async fn send_message(
     .bind("assistant")
     .bind(&ai_response)
     .bind(&now2)
+    .bind(&category)
+    .bind(&model)
+    .bind(disclaimer.as_ref().map(|d| &d.id))
+    .bind(openai::PROMPT_VERSION)
     .execute(pool)
     .await;
 
     let mut files: Vec<FileAttachment> = Vec::new();
     let (mut fmt_opt, mut table_opt) = (chat_req.output_format.clone(), chat_req.table.clone());
+    let mut tables: Vec<TableSpec> = Vec::new();
     
-    if fmt_opt.is_none() || table_opt.is_none() {
+    // A structured answer is the client's own format, not a file intent or a markdown table
+    let freeform = structured_answer.is_none() && clarification.is_none();
+    if freeform && (fmt_opt.is_none() || table_opt.is_none()) {
         if let Some((f, t)) = extract_file_intent(&ai_response) {
             fmt_opt = Some(f);
             table_opt = Some(t);
+        } else if ai_response.contains("\"output_format\"") {
+            metrics::record(LlmSignal::FileIntentParseFailure, &model, locale);
         }
     }
     
-    if table_opt.is_none() {
-        if let Some(table) = parse_markdown_table(&ai_response) {
-            table_opt = Some(table);
+    match table_opt.take() {
+        Some(table) => tables.push(table),
+        None if freeform => {
+            tables = parse_markdown_tables(&ai_response);
+            tables.truncate(MAX_GENERATED_TABLES);
             // Detect format from user message if not already set
-            if fmt_opt.is_none() {
+            if !tables.is_empty() && fmt_opt.is_none() {
                 fmt_opt = Some(detect_format_from_message(&chat_req.message));
             }
         }
+        None => {}
     }
     
-    if let (Some(fmt), Some(table)) = (fmt_opt.as_deref(), table_opt.as_ref()) {
-        match generate_file_and_store(pool, fmt, table, Some(&asst_msg_id)).await {
-            Ok(att) => files.push(att),
-            Err(_) => { /* ignore file errors to not break chat */ }
+    // Over-quota users still get the answer, just without generated files
+    let has_room = storage::has_room(pool, &state.config.load().storage, &resolved_user_id, 0).await;
+    if let (Some(fmt), true) = (fmt_opt.as_deref(), has_room) {
+        for table in &tables {
+            match generate_file_and_store(pool, fmt, table, Some(&asst_msg_id), Some(&resolved_user_id)).await {
+                Ok(att) => files.push(att),
+                Err(_) => { /* ignore file errors to not break chat */ }
+            }
+        }
+    }
+
+    // Read-out copy for hands-free use; when synthesis fails the answer goes out without it
+    if chat_req.tts && structured_answer.is_none() && !llm_failed && has_room && tts::is_configured() {
+        match tts::synthesize(&ai_response).await {
+            Ok(audio) => {
+                let filename = format!("answer-{}.mp3", &asst_msg_id[..8]);
+                match files::store_file(pool, filename, tts::MIME.to_string(), audio, Some(&asst_msg_id), Some(&resolved_user_id)).await {
+                    Ok(att) => files.push(att),
+                    Err(e) => eprintln!("Failed to store spoken answer: {}", e),
+                }
+            }
+            Err(e) => eprintln!("Speech synthesis failed: {}", e),
         }
     }
 
-    HttpResponse::Ok().json(ChatResponse {
+    ChatResponse {
         response: ai_response,
-        message_id: Uuid::new_v4().to_string(),
+        message_id: asst_msg_id,
         timestamp: chrono::Utc::now().to_rfc3339(),
         conversation_id,
         files: if files.is_empty() { None } else { Some(files) },
-    })
+        structured: structured_answer,
+        schema_errors,
+        disclaimer: disclaimer.map(|d| d.text),
+        clarification,
+        sources,
+        suggested_replies,
+        retry_message_id: None,
+    }
 }
 
 
-pub async fn create_conversation(
-    _req: HttpRequest,
-    data: web::Json<CreateConversationRequest>,
+pub async fn send_message(
+    req: HttpRequest,
+    data: web::Json<ChatRequest>,
     state: web::Data<AppState>,
 ) -> HttpResponse {
-    let pool = &state.pool;
-    
-    // Resolve user_id to main user_id for conversation synchronization
-    let resolved_user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
-    
-    let conversation_id = Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
-    
-    // Создать беседу
-    let _ = sqlx::query(
-        "INSERT INTO conversations (id, user_id, title, created_at) VALUES (?, ?, ?, ?)"
-    )
-    .bind(&conversation_id)
-    .bind(&resolved_user_id)
-    .bind(&data.title)
-    .bind(&now)
-    .execute(pool)
+    let mut turn = match prepare_turn(&req, data.into_inner(), &state).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    if let Some(wait) = breaker::retry_after() {
+        return HttpResponse::Accepted().json(queue_turn(&state, turn, wait).await);
+    }
+
+    let generation = openai::generate_response(
+        &turn.chat_req.message,
+        &turn.category,
+        &turn.business_type,
+        &state,
+        &turn.model,
+        &turn.resolved_user_id,
+        turn.locale,
+        turn.history.take(),
+        turn.context.clone(),
+        &turn.images,
+        turn.chat_req.response_schema.as_ref(),
+    );
+    match until_cancelled(&state, &turn.conversation_id, generation).await {
+        Some(llm_output) => {
+            let (identity, owner_id) = (turn.chat_req.user_id.clone(), turn.resolved_user_id.clone());
+            let reply = complete_turn(&state, turn, llm_output.ok()).await;
+            reads::record_bot_delivery(&state.pool, &identity, &owner_id, &reply).await;
+            HttpResponse::Ok().json(reply)
+        }
+        None => HttpResponse::Ok().json(cancel_turn(&state, turn).await),
+    }
+}
+
+/// Largest file accepted directly on a chat message; bigger ones go through `/api/uploads`
+const MAX_INLINE_ATTACHMENT: usize = 20 * 1024 * 1024;
+
+fn attachment_mime_allowed(mime: &str) -> bool {
+    mime == "application/pdf" || mime.starts_with("text/") || mime.starts_with("image/")
+}
+
+/// Multipart variant of `send_message`: a `request` field with the usual JSON body plus
+/// one or more `file` fields, stored and attached to the user message
+pub async fn send_message_with_files(
+    req: HttpRequest,
+    mut payload: Multipart,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let mut chat_req: Option<ChatRequest> = None;
+    let mut uploads: Vec<(String, String, Vec<u8>)> = Vec::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let name = field.name().to_string();
+        let filename = field.content_disposition().get_filename().map(|f| f.to_string());
+        let mime = field.content_type().map(|m| m.to_string()).unwrap_or_default();
+
+        if name == "file" && uploads.len() >= MAX_ATTACHMENTS {
+            return limits::too_many_files(locale, MAX_ATTACHMENTS);
+        }
+        let limit = match name.as_str() {
+            "file" => MAX_INLINE_ATTACHMENT,
+            "request" => limits::MAX_JSON_BODY,
+            _ => limits::MAX_FORM_FIELD,
+        };
+        let bytes = match limits::read_field(&mut field, limit).await {
+            Some(b) => b,
+            None if name == "file" => {
+                let error_msg = match locale {
+                    Locale::Ru => "Файл слишком большой (максимум 20MB)",
+                    Locale::En => "file-too-large-max-20mb",
+                };
+                return HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg, "upload_url": "/api/uploads" }));
+            }
+            None => return limits::payload_too_large(locale, limit),
+        };
+
+        match name.as_str() {
+            "request" => match serde_json::from_slice::<ChatRequest>(&bytes) {
+                Ok(r) => chat_req = Some(r),
+                Err(_) => {
+                    let error_msg = match locale {
+                        Locale::Ru => "Некорректный формат запроса",
+                        Locale::En => "invalid-request",
+                    };
+                    return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+                }
+            },
+            "file" if !bytes.is_empty() => {
+                if !attachment_mime_allowed(&mime) {
+                    let error_msg = match locale {
+                        Locale::Ru => "Поддерживаются PDF, CSV, текстовые файлы и изображения",
+                        Locale::En => "unsupported-attachment-type",
+                    };
+                    return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+                }
+                let filename = filename.unwrap_or_else(|| format!("attachment-{}", uploads.len() + 1));
+                uploads.push((filename, mime, bytes));
+            }
+            _ => {}
+        }
+    }
+
+    let mut chat_req = match chat_req {
+        Some(r) => r,
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Требуются сообщение и user_id",
+                Locale::En => "Message and user_id are required",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+    if uploads.len() + chat_req.attachment_ids.len() > MAX_ATTACHMENTS {
+        let error_msg = match locale {
+            Locale::Ru => "Слишком много вложений",
+            Locale::En => "too-many-attachments",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "max_attachments": MAX_ATTACHMENTS }));
+    }
+
+    let pool = &state.pool;
+    let user_id = resolve_user_id_for_conversations(pool, &chat_req.user_id).await;
+    let incoming: usize = uploads.iter().map(|(_, _, b)| b.len()).sum();
+    if let Err(resp) = files::ensure_storage_quota(&state, &user_id, incoming, locale).await {
+        return resp;
+    }
+    for (filename, mime, bytes) in uploads {
+        if let Err(resp) = files::scan_upload(&state, &user_id, &filename, &mime, &bytes, locale).await {
+            return resp;
+        }
+        match files::store_file(pool, filename, mime, bytes, None, Some(&user_id)).await {
+            Ok(att) => chat_req.attachment_ids.extend(att.id),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+
+    let mut turn = match prepare_turn(&req, chat_req, &state).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    if let Some(wait) = breaker::retry_after() {
+        return HttpResponse::Accepted().json(queue_turn(&state, turn, wait).await);
+    }
+
+    let generation = openai::generate_response(
+        &turn.chat_req.message,
+        &turn.category,
+        &turn.business_type,
+        &state,
+        &turn.model,
+        &turn.resolved_user_id,
+        turn.locale,
+        turn.history.take(),
+        turn.context.clone(),
+        &turn.images,
+        turn.chat_req.response_schema.as_ref(),
+    );
+    match until_cancelled(&state, &turn.conversation_id, generation).await {
+        Some(llm_output) => {
+            let (identity, owner_id) = (turn.chat_req.user_id.clone(), turn.resolved_user_id.clone());
+            let reply = complete_turn(&state, turn, llm_output.ok()).await;
+            reads::record_bot_delivery(&state.pool, &identity, &owner_id, &reply).await;
+            HttpResponse::Ok().json(reply)
+        }
+        None => HttpResponse::Ok().json(cancel_turn(&state, turn).await),
+    }
+}
+
+/// Voice message: an `audio` field with the recording and a `request` field with the usual JSON
+/// body, whose `message` may be left out. The transcript becomes the user message and the
+/// recording stays attached to it; the reply carries the `transcript` next to the answer.
+pub async fn send_voice_message(
+    req: HttpRequest,
+    mut payload: Multipart,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let mut request: Option<serde_json::Value> = None;
+    let mut audio: Option<(String, String, Vec<u8>)> = None;
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let name = field.name().to_string();
+        let filename = field.content_disposition().get_filename().map(|f| f.to_string());
+        let mime = field.content_type().map(|m| m.to_string()).unwrap_or_default();
+
+        let limit = match name.as_str() {
+            "audio" => transcription::MAX_AUDIO_BYTES,
+            "request" => limits::MAX_JSON_BODY,
+            _ => limits::MAX_FORM_FIELD,
+        };
+        let bytes = match limits::read_field(&mut field, limit).await {
+            Some(b) => b,
+            None if name == "audio" => {
+                let error_msg = match locale {
+                    Locale::Ru => "Аудиофайл слишком большой (максимум 25MB)",
+                    Locale::En => "audio-too-large-max-25mb",
+                };
+                return HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg }));
+            }
+            None => return limits::payload_too_large(locale, limit),
+        };
+
+        match name.as_str() {
+            "request" => match serde_json::from_slice::<serde_json::Value>(&bytes) {
+                Ok(v) if v.is_object() => request = Some(v),
+                _ => {
+                    let error_msg = match locale {
+                        Locale::Ru => "Некорректный формат запроса",
+                        Locale::En => "invalid-request",
+                    };
+                    return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+                }
+            },
+            "audio" if !bytes.is_empty() => {
+                if !transcription::audio_mime_allowed(&mime) {
+                    let error_msg = match locale {
+                        Locale::Ru => "Поддерживаются только аудиофайлы",
+                        Locale::En => "unsupported-audio-type",
+                    };
+                    return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+                }
+                audio = Some((filename.unwrap_or_else(|| "voice-message".to_string()), mime, bytes));
+            }
+            _ => {}
+        }
+    }
+
+    // `message` is optional here: the transcript fills it in
+    let mut request = request.unwrap_or_else(|| json!({}));
+    if request.get("message").is_none() {
+        request["message"] = json!("");
+    }
+    let (mut chat_req, (filename, mime, bytes)) = match (serde_json::from_value::<ChatRequest>(request), audio) {
+        (Ok(r), Some(a)) if !r.user_id.is_empty() => (r, a),
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Требуются аудиозапись и user_id",
+                Locale::En => "audio-and-user-id-required",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+    if !transcription::is_configured() {
+        let error_msg = match locale {
+            Locale::Ru => "Голосовые сообщения временно недоступны",
+            Locale::En => "transcription-unavailable",
+        };
+        return HttpResponse::ServiceUnavailable().json(json!({ "error": error_msg }));
+    }
+
+    let pool = &state.pool;
+    let user_id = resolve_user_id_for_conversations(pool, &chat_req.user_id).await;
+    if let Err(resp) = files::ensure_storage_quota(&state, &user_id, bytes.len(), locale).await {
+        return resp;
+    }
+    if let Err(resp) = files::scan_upload(&state, &user_id, &filename, &mime, &bytes, locale).await {
+        return resp;
+    }
+
+    // The recording's language follows the message's, like the answer does
+    let speech_locale = match chat_req.language.as_deref().map(str::to_lowercase).as_deref() {
+        Some("ru") | Some("ru-ru") => Locale::Ru,
+        Some(_) => Locale::En,
+        None => locale,
+    };
+    let transcript = match transcription::transcribe(bytes.clone(), &filename, &mime, speech_locale).await {
+        Ok(t) if !t.is_empty() => t,
+        Ok(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось распознать речь",
+                Locale::En => "no-speech-detected",
+            };
+            return HttpResponse::UnprocessableEntity().json(json!({ "error": error_msg }));
+        }
+        Err(e) => {
+            eprintln!("Transcription failed: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось расшифровать голосовое сообщение",
+                Locale::En => "transcription-failed",
+            };
+            return HttpResponse::BadGateway().json(json!({ "error": error_msg }));
+        }
+    };
+
+    // Kept as the transcript's source so the app can play it back; its text is the transcript
+    let audio_id = match files::store_file(pool, filename, mime, bytes, None, Some(&user_id)).await {
+        Ok(att) => att.id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if let Some(id) = &audio_id {
+        let _ = sqlx::query("UPDATE files SET extracted_text = ? WHERE id = ?")
+            .bind(&transcript)
+            .bind(id)
+            .execute(pool)
+            .await;
+    }
+    chat_req.attachment_ids.extend(audio_id);
+    chat_req.message = match chat_req.message.trim() {
+        "" => transcript.clone(),
+        caption => format!("{}\n\n{}", caption, transcript),
+    };
+
+    let mut turn = match prepare_turn(&req, chat_req, &state).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    if let Some(wait) = breaker::retry_after() {
+        let mut queued = queue_turn(&state, turn, wait).await;
+        queued["transcript"] = json!(transcript);
+        return HttpResponse::Accepted().json(queued);
+    }
+
+    let generation = openai::generate_response(
+        &turn.chat_req.message,
+        &turn.category,
+        &turn.business_type,
+        &state,
+        &turn.model,
+        &turn.resolved_user_id,
+        turn.locale,
+        turn.history.take(),
+        turn.context.clone(),
+        &turn.images,
+        turn.chat_req.response_schema.as_ref(),
+    );
+    let mut body = match until_cancelled(&state, &turn.conversation_id, generation).await {
+        Some(llm_output) => {
+            let (identity, owner_id) = (turn.chat_req.user_id.clone(), turn.resolved_user_id.clone());
+            let reply = complete_turn(&state, turn, llm_output.ok()).await;
+            reads::record_bot_delivery(&state.pool, &identity, &owner_id, &reply).await;
+            serde_json::to_value(&reply).unwrap_or_default()
+        }
+        None => cancel_turn(&state, turn).await,
+    };
+    body["transcript"] = json!(transcript);
+    HttpResponse::Ok().json(body)
+}
+
+fn sse_event(event: &str, data: &serde_json::Value) -> web::Bytes {
+    web::Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Strips the clarification marker from streamed text before it reaches the client, when the
+/// turn may ask a clarifying question, and holds back the suggested replies after the question
+struct ClarifyFilter {
+    pending: String,
+    header_done: bool,
+    /// The reply is a clarifying question; only its first line is shown
+    asking: bool,
+    question_done: bool,
+}
+
+impl ClarifyFilter {
+    fn new(clarify: bool) -> Self {
+        ClarifyFilter { pending: String::new(), header_done: !clarify, asking: false, question_done: false }
+    }
+
+    /// The part of a clarifying question's `text` still on the question line
+    fn question_line(&mut self, text: &str) -> String {
+        if self.question_done {
+            return String::new();
+        }
+        match text.split_once('\n') {
+            Some((line, _)) => {
+                self.question_done = true;
+                line.to_string()
+            }
+            None => text.to_string(),
+        }
+    }
+
+    /// Text to forward for `delta`
+    fn push(&mut self, delta: &str) -> String {
+        if self.asking {
+            return self.question_line(delta);
+        }
+        if self.header_done {
+            return delta.to_string();
+        }
+        self.pending.push_str(delta);
+        let head = self.pending.trim_start();
+        if let Some(question) = head.strip_prefix(clarify::MARKER) {
+            let question = question.trim_start().to_string();
+            self.pending.clear();
+            self.header_done = true;
+            self.asking = true;
+            self.question_line(&question)
+        } else if clarify::MARKER.starts_with(head) {
+            // Could still turn into a clarifying question
+            String::new()
+        } else {
+            self.header_done = true;
+            std::mem::take(&mut self.pending)
+        }
+    }
+}
+
+/// Runs one turn against the streaming API, reporting progress through `emit(event, data)`:
+/// `meta`, then `delta` while generating, `title` once a new conversation is named, one `file` per
+/// generated attachment, and `done` (or `cancelled` instead of the files and `done` when the
+/// answer is cancelled). While the
+/// provider is down only `meta` and `pending` are sent, see `queue_turn`.
+pub(crate) async fn stream_turn(state: &AppState, mut turn: ChatTurn, emit: impl Fn(&str, serde_json::Value)) {
+    emit("meta", json!({ "conversation_id": turn.conversation_id }));
+    if let Some(wait) = breaker::retry_after() {
+        emit("pending", queue_turn(state, turn, wait).await);
+        return;
+    }
+
+    // The title call runs alongside the answer and is reported before `done`
+    let title = turn.untitled.then(|| {
+        let (state, conversation_id, message, locale) =
+            (state.clone(), turn.conversation_id.clone(), turn.chat_req.message.clone(), turn.locale);
+        actix_web::rt::spawn(async move { titles::ensure_title(&state, &conversation_id, &message, locale).await })
+    });
+    turn.untitled = false;
+
+    let mut filter = ClarifyFilter::new(turn.clarify.is_some());
+    let generation = openai::stream_response(
+        &turn.chat_req.message,
+        &turn.category,
+        &turn.business_type,
+        state,
+        &turn.model,
+        &turn.resolved_user_id,
+        turn.locale,
+        turn.history.take(),
+        turn.context.clone(),
+        &turn.images,
+        turn.chat_req.response_schema.as_ref(),
+        |delta| {
+            let text = filter.push(delta);
+            if !text.is_empty() {
+                emit("delta", json!({ "content": text }));
+            }
+        },
+    );
+    let conversation_id = turn.conversation_id.clone();
+    let llm_output = match until_cancelled(state, &conversation_id, generation).await {
+        Some(Ok(content)) => Some(content),
+        Some(Err(e)) => {
+            eprintln!("OpenRouter streaming failed: {}", e);
+            None
+        }
+        None => {
+            emit("cancelled", cancel_turn(state, turn).await);
+            return;
+        }
+    };
+    let response = complete_turn(state, turn, llm_output).await;
+    if let Some(handle) = title {
+        if let Ok(Some(title)) = handle.await {
+            emit("title", json!({ "title": title }));
+        }
+    }
+    // The disclaimer is added after generation, so the streamed text needs it too
+    if let (Some(text), None) = (response.disclaimer.as_ref(), response.structured.as_ref()) {
+        emit("delta", json!({ "content": format!("{}{}", disclaimer::SEPARATOR, text) }));
+    }
+    for file in response.files.iter().flatten() {
+        emit("file", json!(file));
+    }
+    emit("done", json!(response));
+}
+
+/// SSE variant of `send_message`; the `done` event carries the same body `send_message` returns
+/// and its `response` is authoritative
+pub async fn send_message_stream(
+    req: HttpRequest,
+    data: web::Json<ChatRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let turn = match prepare_turn(&req, data.into_inner(), &state).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    if let Some(wait) = breaker::retry_after() {
+        return HttpResponse::Accepted().json(queue_turn(&state, turn, wait).await);
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<web::Bytes>();
+
+    // Generation runs detached so the answer is still saved if the client disconnects
+    actix_web::rt::spawn(async move {
+        stream_turn(&state, turn, |event, data| {
+            let _ = tx.send(sse_event(event, &data));
+        }).await;
+    });
+
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (Ok::<_, actix_web::Error>(chunk), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body)
+}
+
+pub async fn create_conversation(
+    _req: HttpRequest,
+    data: web::Json<CreateConversationRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pool = &state.pool;
+    
+    // Resolve user_id to main user_id for conversation synchronization
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
+    
+    let conversation_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    
+    // Создать беседу
+    let _ = sqlx::query(
+        "INSERT INTO conversations (id, user_id, title, created_at) VALUES (?, ?, ?, ?)"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .bind(&data.title)
+    .bind(&now)
+    .execute(pool)
     .await;
     
     // Сохранить контекст беседы, если передан
@@ -302,13 +1433,18 @@ pub async fn create_conversation(
 }
 
 pub async fn update_conversation_context(
-    _req: HttpRequest,
+    req: HttpRequest,
     path: web::Path<String>,
     data: web::Json<ContextFilters>,
     state: web::Data<AppState>,
 ) -> HttpResponse {
     let conversation_id = path.into_inner();
     let pool = &state.pool;
+    let mut filters = data.into_inner();
+    filters.region = match reference::validate_country(filters.region.as_deref(), "region", i18n::detect_locale(&req)) {
+        Ok(r) => r,
+        Err(resp) => return resp,
+    };
     
     // Проверить существование беседы
     let exists: Option<i64> = sqlx::query_scalar(
@@ -322,7 +1458,7 @@ pub async fn update_conversation_context(
     
     match exists {
         Some(1) => {
-            let result = save_conversation_context(pool, &conversation_id, &data.into_inner()).await;
+            let result = save_conversation_context(pool, &conversation_id, &filters).await;
             match result {
                 Ok(_) => HttpResponse::Ok().json(json!({"status": "ok"})),
                 Err(_) => HttpResponse::InternalServerError().json(json!({"error": "Failed to update context"})),
@@ -332,9 +1468,19 @@ pub async fn update_conversation_context(
     }
 }
 
+#[derive(Deserialize)]
+pub struct ListConversationsQuery {
+    /// Omitted returns every conversation
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
 pub async fn list_conversations(
     _req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<ListConversationsQuery>,
     state: web::Data<AppState>,
 ) -> HttpResponse {
     let user_id = path.into_inner();
@@ -343,20 +1489,45 @@ pub async fn list_conversations(
     // Resolve to main user_id - all conversations are stored with main user_id
     let resolved_user_id = resolve_user_id_for_conversations(pool, &user_id).await;
     
+    // Deleted conversations only come back through restore
+    let visible = if query.include_archived {
+        "c.deleted_at IS NULL"
+    } else {
+        "c.deleted_at IS NULL AND c.archived_at IS NULL"
+    };
+    let total: i64 = match sqlx::query_scalar(&format!("SELECT COUNT(*) FROM conversations c WHERE c.user_id = ? AND {}", visible))
+        .bind(&resolved_user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let offset = query.offset.unwrap_or(0).max(0);
+
     // Show conversations for the resolved user_id
     // Since all conversations are created with resolved_user_id, they will be synced between platforms
-    let rows = sqlx::query(
+    let sql = format!(
         r#"
         SELECT 
-            c.id, c.user_id, c.title, c.created_at,
-            ctx.user_role, ctx.business_stage, ctx.goal, ctx.urgency, ctx.region, ctx.business_niche
+            c.id, c.user_id, c.title, c.created_at, c.archived_at, c.pinned, c.last_message_at, c.model, c.language,
+            ctx.user_role, ctx.business_stage, ctx.goal, ctx.urgency, ctx.region, ctx.business_niche,
+            {} AS last_read_message_id, {} AS unread_count
         FROM conversations c
         LEFT JOIN conversation_context ctx ON c.id = ctx.conversation_id
-        WHERE c.user_id = ? 
-        ORDER BY datetime(c.created_at) DESC
-        "#
-    )
+        WHERE c.user_id = ? AND {}
+        ORDER BY c.pinned DESC, julianday(COALESCE(c.last_message_at, c.created_at)) DESC
+        LIMIT ? OFFSET ?
+        "#,
+        reads::LAST_READ_MESSAGE_SQL,
+        reads::UNREAD_COUNT_SQL,
+        visible
+    );
+    let rows = sqlx::query(&sql)
     .bind(&resolved_user_id)
+    // A negative LIMIT means no limit in SQLite
+    .bind(query.limit.map(|l| l.clamp(1, 200)).unwrap_or(-1))
+    .bind(offset)
     .fetch_all(pool)
     .await;
 
@@ -382,162 +1553,1323 @@ pub async fn list_conversations(
                     title: r.try_get("title").ok().flatten(),
                     created_at: r.get("created_at"),
                     context,
+                    archived_at: r.get("archived_at"),
+                    pinned: r.get::<i64, _>("pinned") != 0,
+                    last_message_at: r.get("last_message_at"),
+                    model: r.get("model"),
+                    language: r.get("language"),
+                    unread_count: r.get("unread_count"),
+                    last_read_message_id: r.get("last_read_message_id"),
                 }
             }).collect();
-            HttpResponse::Ok().json(json!({"user_id": user_id, "conversations": list}))
+            let has_more = offset + (list.len() as i64) < total;
+            HttpResponse::Ok().json(json!({
+                "user_id": user_id,
+                "conversations": list,
+                "total": total,
+                "has_more": has_more,
+            }))
         }
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    /// Only messages strictly older than this RFC 3339 timestamp
+    pub before: Option<String>,
+    /// Only messages strictly newer than this RFC 3339 timestamp
+    pub after: Option<String>,
+    /// Returns a window of messages centred on this one (deep links)
+    pub around_message_id: Option<String>,
+    pub limit: Option<i64>,
+    /// Without cursors: how many of the newest messages to skip
+    pub offset: Option<i64>,
+}
+
+struct HistoryPage {
+    messages: Vec<MessageRecord>,
+    /// None when the page wasn't cut in that direction
+    has_more_before: Option<bool>,
+    has_more_after: Option<bool>,
+}
+
+fn message_record(r: &sqlx::sqlite::SqliteRow) -> MessageRecord {
+    MessageRecord {
+        id: r.get::<String, _>("id"),
+        role: r.get::<String, _>("role"),
+        content: r.get::<String, _>("content"),
+        timestamp: r.get::<String, _>("timestamp"),
+        local_timestamp: None,
+        status: r.get("status"),
+    }
+}
+
+/// Loads the requested slice of a conversation; `Ok(None)` means the anchor message doesn't exist
+async fn load_history_page(
+    pool: &sqlx::SqlitePool,
+    conversation_id: &str,
+    query: &HistoryQuery,
+) -> Result<Option<HistoryPage>, sqlx::Error> {
+    let has_cursor = query.before.is_some() || query.after.is_some() || query.around_message_id.is_some();
+
+    // Without any cursor or page size the whole conversation is returned, as before
+    if !has_cursor && query.limit.is_none() && query.offset.is_none() {
+        let rows = sqlx::query(
+            "SELECT id, role, content, timestamp, status FROM messages WHERE conversation_id = ? ORDER BY julianday(timestamp), rowid"
+        )
+        .bind(conversation_id)
+        .fetch_all(pool)
+        .await?;
+        return Ok(Some(HistoryPage {
+            messages: rows.iter().map(message_record).collect(),
+            has_more_before: Some(false),
+            has_more_after: Some(false),
+        }));
+    }
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+
+    // Offset paging counts back from the newest message, pages stay in chronological order
+    if !has_cursor {
+        let offset = query.offset.unwrap_or(0).max(0);
+        let mut rows = sqlx::query(
+            "SELECT id, role, content, timestamp, status FROM messages WHERE conversation_id = ?
+             ORDER BY julianday(timestamp) DESC, rowid DESC
+             LIMIT ? OFFSET ?"
+        )
+        .bind(conversation_id)
+        .bind(limit + 1)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+        let has_more = rows.len() as i64 > limit;
+        rows.truncate(limit as usize);
+        rows.reverse();
+        return Ok(Some(HistoryPage {
+            messages: rows.iter().map(message_record).collect(),
+            has_more_before: Some(has_more),
+            has_more_after: Some(offset > 0),
+        }));
+    }
+
+    if let Some(ref anchor_id) = query.around_message_id {
+        let anchor = sqlx::query(
+            "SELECT rowid, id, role, content, timestamp, status FROM messages WHERE id = ? AND conversation_id = ?"
+        )
+        .bind(anchor_id)
+        .bind(conversation_id)
+        .fetch_optional(pool)
+        .await?;
+        let anchor = match anchor {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let anchor_rowid: i64 = anchor.get("rowid");
+        let anchor_ts: String = anchor.get("timestamp");
+        let older_count = (limit - 1) / 2;
+        let newer_count = limit - 1 - older_count;
+
+        // julianday keeps sub-second precision; rowid breaks ties between equal timestamps
+        let mut older = sqlx::query(
+            "SELECT id, role, content, timestamp, status FROM messages
+             WHERE conversation_id = ?
+               AND (julianday(timestamp) < julianday(?) OR (julianday(timestamp) = julianday(?) AND rowid < ?))
+             ORDER BY julianday(timestamp) DESC, rowid DESC
+             LIMIT ?"
+        )
+        .bind(conversation_id)
+        .bind(&anchor_ts)
+        .bind(&anchor_ts)
+        .bind(anchor_rowid)
+        .bind(older_count + 1)
+        .fetch_all(pool)
+        .await?;
+        let mut newer = sqlx::query(
+            "SELECT id, role, content, timestamp, status FROM messages
+             WHERE conversation_id = ?
+               AND (julianday(timestamp) > julianday(?) OR (julianday(timestamp) = julianday(?) AND rowid > ?))
+             ORDER BY julianday(timestamp) ASC, rowid ASC
+             LIMIT ?"
+        )
+        .bind(conversation_id)
+        .bind(&anchor_ts)
+        .bind(&anchor_ts)
+        .bind(anchor_rowid)
+        .bind(newer_count + 1)
+        .fetch_all(pool)
+        .await?;
+
+        let has_more_before = older.len() as i64 > older_count;
+        older.truncate(older_count as usize);
+        let has_more_after = newer.len() as i64 > newer_count;
+        newer.truncate(newer_count as usize);
+
+        let mut messages: Vec<MessageRecord> = older.iter().rev().map(message_record).collect();
+        messages.push(message_record(&anchor));
+        messages.extend(newer.iter().map(message_record));
+        return Ok(Some(HistoryPage {
+            messages,
+            has_more_before: Some(has_more_before),
+            has_more_after: Some(has_more_after),
+        }));
+    }
+
+    // With `before` the page hugs that cursor (scrolling up); otherwise it hugs `after`
+    let mut sql = String::from("SELECT id, role, content, timestamp, status FROM messages WHERE conversation_id = ?");
+    if query.before.is_some() {
+        sql.push_str(" AND julianday(timestamp) < julianday(?)");
+    }
+    if query.after.is_some() {
+        sql.push_str(" AND julianday(timestamp) > julianday(?)");
+    }
+    if query.before.is_some() {
+        sql.push_str(" ORDER BY julianday(timestamp) DESC, rowid DESC LIMIT ?");
+    } else {
+        sql.push_str(" ORDER BY julianday(timestamp) ASC, rowid ASC LIMIT ?");
+    }
+
+    let mut q = sqlx::query(&sql).bind(conversation_id);
+    if let Some(ref before) = query.before {
+        q = q.bind(before);
+    }
+    if let Some(ref after) = query.after {
+        q = q.bind(after);
+    }
+    let mut rows = q.bind(limit + 1).fetch_all(pool).await?;
+
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    if query.before.is_some() {
+        rows.reverse();
+        Ok(Some(HistoryPage {
+            messages: rows.iter().map(message_record).collect(),
+            has_more_before: Some(has_more),
+            has_more_after: None,
+        }))
+    } else {
+        Ok(Some(HistoryPage {
+            messages: rows.iter().map(message_record).collect(),
+            has_more_before: None,
+            has_more_after: Some(has_more),
+        }))
+    }
+}
+
 pub async fn get_conversation_history(
-    _req: HttpRequest,
+    req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<HistoryQuery>,
     state: web::Data<AppState>,
 ) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
     let conversation_id = path.into_inner();
     let pool = &state.pool;
-    let rows = sqlx::query(
-        "SELECT id, role, content, timestamp FROM messages WHERE conversation_id = ? ORDER BY datetime(timestamp) ASC"
-    )
-    .bind(&conversation_id)
-    .fetch_all(pool)
-    .await;
 
-    match rows {
-        Ok(rs) => {
-            let messages: Vec<MessageRecord> = rs
-                .into_iter()
-                .map(|r| MessageRecord {
-                id: r.get::<String, _>("id"),
-                role: r.get::<String, _>("role"),
-                content: r.get::<String, _>("content"),
-                timestamp: r.get::<String, _>("timestamp"),
-                })
-                .collect();
+    let invalid_cursor = [&query.before, &query.after]
+        .into_iter()
+        .flatten()
+        .any(|ts| chrono::DateTime::parse_from_rfc3339(ts).is_err());
+    if invalid_cursor {
+        let error_msg = match locale {
+            Locale::Ru => "Неверный формат даты, ожидается RFC 3339",
+            Locale::En => "invalid-timestamp",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
 
-            // For each message, load associated files (if any)
-            let mut files_by_message: Vec<serde_json::Value> = Vec::new();
-            for msg in &messages {
-                let file_rows = sqlx::query(
-                    "SELECT id, filename, mime, size, bytes FROM files WHERE message_id = ?"
-                )
-                .bind(&msg.id)
-                .fetch_all(pool)
-                .await;
+    if conversation_deleted(pool, &conversation_id).await {
+        return conversation_not_found(locale);
+    }
+
+    let total: i64 = match sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE conversation_id = ?")
+        .bind(&conversation_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let owner: Option<String> = sqlx::query_scalar("SELECT user_id FROM conversations WHERE id = ?")
+        .bind(&conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let tz = match &owner {
+        Some(user_id) => timezone::for_request(&req, pool, user_id).await,
+        None => timezone::detect(&req).unwrap_or(chrono_tz::Tz::UTC),
+    };
+
+    match load_history_page(pool, &conversation_id, &query).await {
+        Ok(Some(page)) => {
+            let mut messages = page.messages;
+            for msg in &mut messages {
+                msg.local_timestamp = timezone::to_local(&msg.timestamp, tz);
+            }
+
+            // For each message, load associated files (if any)
+            let mut files_by_message: Vec<serde_json::Value> = Vec::new();
+            for msg in &messages {
+                let file_rows = sqlx::query(
+                    "SELECT f.id, f.filename, f.mime, f.size, COALESCE(b.bytes, f.bytes) AS bytes
+                     FROM files f LEFT JOIN file_blobs b ON b.sha256 = f.sha256
+                     WHERE f.message_id = ? AND f.deleted_at IS NULL"
+                )
+                .bind(&msg.id)
+                .fetch_all(pool)
+                .await;
+
+                if let Ok(frs) = file_rows {
+                    if frs.is_empty() {
+                        continue;
+                    }
+
+                    let mut attachments: Vec<FileAttachment> = Vec::new();
+                    for fr in frs {
+                        let id = fr.get::<String, _>("id");
+                        let filename = fr.get::<String, _>("filename");
+                        let mime = fr.get::<String, _>("mime");
+                        let size = fr.get::<i64, _>("size") as usize;
+                        let bytes: Vec<u8> = fr.get("bytes");
+
+                        let content_base64 = if size <= 1024 * 1024 {
+                            Some(B64.encode(&bytes))
+                        } else {
+                            None
+                        };
+                        let download_url = Some(format!("/api/files/{}", id));
+
+                        attachments.push(FileAttachment {
+                            id: Some(id),
+                            filename,
+                            mime,
+                            size,
+                            content_base64,
+                            download_url,
+                        });
+                    }
+
+                    if !attachments.is_empty() {
+                        files_by_message.push(json!({
+                            "message_id": msg.id,
+                            "files": attachments,
+                        }));
+                    }
+                }
+            }
+
+            HttpResponse::Ok().json(json!({
+                "conversation_id": conversation_id,
+                "timezone": tz.name(),
+                "messages": messages,
+                "count": messages.len(),
+                "total": total,
+                "attachments": files_by_message,
+                "has_more_before": page.has_more_before,
+                "has_more_after": page.has_more_after,
+            }))
+        }
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Сообщение не найдено",
+                Locale::En => "message-not-found",
+            };
+            HttpResponse::NotFound().json(json!({ "error": error_msg }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConversationOwner {
+    pub user_id: String,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateConversationTitle {
+    pub user_id: String,
+    pub title: Option<String>,
+}
+
+pub async fn delete_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<ConversationOwner>,
+) -> HttpResponse {
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+
+    // Resolve user_id to main user_id
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    // Rows stay until the scheduler purges them, so an accidental delete can be restored
+    let now = chrono::Utc::now();
+    let result = sqlx::query(
+        "UPDATE conversations SET deleted_at = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(now.to_rfc3339())
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().json(json!({
+            "status": "deleted",
+            "conversation_id": conversation_id,
+            "purge_at": (now + chrono::Duration::days(CONVERSATION_RETENTION_DAYS)).to_rfc3339(),
+        })),
+        Ok(_) => conversation_not_found(i18n::detect_locale(&req)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Most conversations one bulk delete accepts
+const MAX_BULK_DELETE: usize = 200;
+
+#[derive(Deserialize)]
+pub struct BulkDeleteRequest {
+    pub user_id: String,
+    pub conversation_ids: Vec<String>,
+}
+
+/// `POST /api/chat/conversations/bulk-delete` deletes many conversations in one transaction.
+/// Ids that don't exist, aren't the user's or are already deleted come back in `not_found`.
+pub async fn bulk_delete_conversations(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<BulkDeleteRequest>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let mut ids: Vec<&str> = body.conversation_ids.iter().map(String::as_str).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() || ids.len() > MAX_BULK_DELETE {
+        let error_msg = match locale {
+            Locale::Ru => "Передайте от 1 до 200 идентификаторов разговоров",
+            Locale::En => "invalid-conversation-ids",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "max": MAX_BULK_DELETE }));
+    }
+
+    let now = chrono::Utc::now();
+    let mut deleted: Vec<&str> = Vec::new();
+    let mut not_found: Vec<&str> = Vec::new();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    for id in ids {
+        let result = sqlx::query(
+            "UPDATE conversations SET deleted_at = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+        )
+        .bind(now.to_rfc3339())
+        .bind(id)
+        .bind(&resolved_user_id)
+        .execute(&mut tx)
+        .await;
+        match result {
+            Ok(r) if r.rows_affected() > 0 => deleted.push(id),
+            Ok(_) => not_found.push(id),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok().json(json!({
+        "status": "deleted",
+        "deleted": deleted,
+        "not_found": not_found,
+        "purge_at": (now + chrono::Duration::days(CONVERSATION_RETENTION_DAYS)).to_rfc3339(),
+    }))
+}
+
+pub async fn restore_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<ConversationOwner>,
+) -> HttpResponse {
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let result = sqlx::query(
+        "UPDATE conversations SET deleted_at = NULL WHERE id = ? AND user_id = ? AND deleted_at IS NOT NULL"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().json(json!({
+            "status": "restored",
+            "conversation_id": conversation_id,
+        })),
+        Ok(_) => conversation_not_found(i18n::detect_locale(&req)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Stops the answer currently being generated; the question is kept, nothing partial is stored
+pub async fn cancel_generation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<ConversationOwner>,
+) -> HttpResponse {
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let owned: Result<Option<i64>, _> = sqlx::query_scalar("SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL")
+        .bind(&conversation_id)
+        .bind(&resolved_user_id)
+        .fetch_optional(pool)
+        .await;
+    match owned {
+        Ok(Some(_)) => {}
+        Ok(None) => return conversation_not_found(i18n::detect_locale(&req)),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let cancelled = state.cancel_generation(&conversation_id);
+    HttpResponse::Ok().json(json!({
+        "status": if cancelled { "cancelled" } else { "idle" },
+        "conversation_id": conversation_id,
+    }))
+}
+
+/// Models that can be picked with `model` on a chat message; an empty list means picking is off
+pub async fn list_models(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "default_model": openai::current_model(&state),
+        "allowed_models": openai::allowed_models(&state),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveConversationRequest {
+    pub user_id: String,
+    /// `false` moves the conversation back to the main list
+    #[serde(default = "default_archived")]
+    pub archived: bool,
+}
+
+fn default_archived() -> bool {
+    true
+}
+
+pub async fn archive_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<ArchiveConversationRequest>,
+) -> HttpResponse {
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    // Archiving twice keeps the original timestamp
+    let archived_at = body.archived.then(|| chrono::Utc::now().to_rfc3339());
+    let result = sqlx::query(
+        "UPDATE conversations SET archived_at = CASE WHEN ? IS NULL THEN NULL ELSE COALESCE(archived_at, ?) END
+         WHERE id = ? AND user_id = ? AND deleted_at IS NULL
+         RETURNING archived_at"
+    )
+    .bind(&archived_at)
+    .bind(&archived_at)
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await;
+
+    match result {
+        Ok(Some(row)) => HttpResponse::Ok().json(json!({
+            "status": if body.archived { "archived" } else { "unarchived" },
+            "conversation_id": conversation_id,
+            "archived_at": row.get::<Option<String>, _>("archived_at"),
+        })),
+        Ok(None) => conversation_not_found(i18n::detect_locale(&req)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PinConversationRequest {
+    pub user_id: String,
+    /// `false` unpins
+    #[serde(default = "default_pinned")]
+    pub pinned: bool,
+}
+
+fn default_pinned() -> bool {
+    true
+}
+
+pub async fn pin_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<PinConversationRequest>,
+) -> HttpResponse {
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let result = sqlx::query(
+        "UPDATE conversations SET pinned = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(body.pinned as i64)
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "pinned": body.pinned,
+        })),
+        Ok(_) => conversation_not_found(i18n::detect_locale(&req)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConversationLanguageRequest {
+    pub user_id: String,
+    /// `ru` or `en`
+    pub language: String,
+}
+
+/// Switches the language later answers, titles and exports of the conversation use
+pub async fn update_conversation_language(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<ConversationLanguageRequest>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+
+    let language = match i18n::parse_language(&body.language) {
+        Some(l) => i18n::language_code(l),
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Поддерживаются языки ru и en",
+                Locale::En => "unsupported-language",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg, "supported": ["ru", "en"] }));
+        }
+    };
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let result = sqlx::query(
+        "UPDATE conversations SET language = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(language)
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "language": language,
+        })),
+        Ok(_) => conversation_not_found(locale),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+fn conversation_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Разговор не найден или не принадлежит пользователю",
+        Locale::En => "conversation-not-found-or-not-owned",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+/// True for conversations sitting in the deleted state; their content is hidden until restored
+pub(crate) async fn conversation_deleted(pool: &sqlx::SqlitePool, conversation_id: &str) -> bool {
+    sqlx::query_scalar::<_, i64>("SELECT 1 FROM conversations WHERE id = ? AND deleted_at IS NOT NULL")
+        .bind(conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .is_some()
+}
+
+#[derive(Deserialize)]
+pub struct ForkConversationRequest {
+    pub user_id: String,
+    /// Last message carried over into the fork
+    pub from_message_id: String,
+    /// Defaults to the original title
+    pub title: Option<String>,
+}
+
+/// Starts a new conversation holding a copy of the thread up to `from_message_id`, with the
+/// same context and attachments; the original conversation is left untouched
+pub async fn fork_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ForkConversationRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let data = body.into_inner();
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
+
+    let source = sqlx::query(
+        "SELECT c.title, c.language, m.rowid, m.timestamp FROM conversations c
+         JOIN messages m ON m.conversation_id = c.id
+         WHERE c.id = ? AND c.user_id = ? AND c.deleted_at IS NULL AND m.id = ?"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .bind(&data.from_message_id)
+    .fetch_optional(pool)
+    .await;
+    let source = match source {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Разговор или сообщение не найдены",
+                Locale::En => "conversation-or-message-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let rowid: i64 = source.get("rowid");
+    let timestamp: String = source.get("timestamp");
+    let title = data
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .or_else(|| source.get("title"));
+
+    let fork_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let copied = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO conversations (id, user_id, title, created_at, forked_from, forked_from_message_id, language) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&fork_id)
+        .bind(&resolved_user_id)
+        .bind(&title)
+        .bind(&now)
+        .bind(&conversation_id)
+        .bind(&data.from_message_id)
+        .bind(source.get::<Option<String>, _>("language"))
+        .execute(&mut tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO conversation_context (conversation_id, user_role, business_stage, goal, urgency, region, business_niche, updated_at)
+             SELECT ?, user_role, business_stage, goal, urgency, region, business_niche, ? FROM conversation_context WHERE conversation_id = ?"
+        )
+        .bind(&fork_id)
+        .bind(&now)
+        .bind(&conversation_id)
+        .execute(&mut tx)
+        .await?;
+
+        // Same ordering as history paging: timestamp, then insertion order for ties
+        let ids: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM messages
+             WHERE conversation_id = ? AND (julianday(timestamp) < julianday(?) OR (julianday(timestamp) = julianday(?) AND rowid <= ?))
+             ORDER BY julianday(timestamp), rowid"
+        )
+        .bind(&conversation_id)
+        .bind(&timestamp)
+        .bind(&timestamp)
+        .bind(rowid)
+        .fetch_all(&mut tx)
+        .await?;
+
+        for old_id in &ids {
+            let new_id = Uuid::new_v4().to_string();
+            sqlx::query(
+                "INSERT INTO messages (id, conversation_id, user_id, role, content, timestamp, edited_at, category, model)
+                 SELECT ?, ?, user_id, role, content, timestamp, edited_at, category, model FROM messages WHERE id = ?"
+            )
+            .bind(&new_id)
+            .bind(&fork_id)
+            .bind(old_id)
+            .execute(&mut tx)
+            .await?;
+
+            // Attachments are new rows sharing the stored content
+            let file_ids: Vec<String> = sqlx::query_scalar(
+                "SELECT id FROM files WHERE message_id = ? AND deleted_at IS NULL AND scan_status IS NOT 'quarantined'"
+            )
+            .bind(old_id)
+            .fetch_all(&mut tx)
+            .await?;
+            for file_id in file_ids {
+                sqlx::query(
+                    "UPDATE file_blobs SET ref_count = ref_count + 1
+                     WHERE sha256 = (SELECT sha256 FROM files WHERE id = ?)"
+                )
+                .bind(&file_id)
+                .execute(&mut tx)
+                .await?;
+                sqlx::query(
+                    "INSERT INTO files (id, filename, mime, size, bytes, message_id, user_id, sha256, scan_status, scan_signature, extracted_text, created_at)
+                     SELECT ?, filename, mime, size, bytes, ?, user_id, sha256, scan_status, scan_signature, extracted_text, created_at
+                     FROM files WHERE id = ?"
+                )
+                .bind(Uuid::new_v4().to_string())
+                .bind(&new_id)
+                .bind(&file_id)
+                .execute(&mut tx)
+                .await?;
+            }
+        }
+
+        // Copied messages keep their times; the fork itself counts as fresh activity
+        sqlx::query("UPDATE conversations SET last_message_at = ? WHERE id = ?")
+            .bind(&now)
+            .bind(&fork_id)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok::<usize, sqlx::Error>(ids.len())
+    }
+    .await;
+
+    match copied {
+        Ok(messages) => HttpResponse::Created().json(json!({
+            "conversation_id": fork_id,
+            "title": title,
+            "forked_from": conversation_id,
+            "forked_from_message_id": data.from_message_id,
+            "messages_copied": messages,
+            "created_at": now,
+        })),
+        Err(e) => {
+            eprintln!("Conversation fork failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportConversationQuery {
+    pub user_id: String,
+    /// `pdf`, `md` or `txt`
+    pub format: String,
+}
+
+/// Renders the whole conversation into a file in the owner's storage and returns it for download
+pub async fn export_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ExportConversationQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+
+    let format = match TranscriptFormat::parse(query.format.trim()) {
+        Some(f) => f,
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Формат должен быть pdf, md или txt",
+                Locale::En => "invalid-export-format",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+    let conversation = match sqlx::query(
+        "SELECT title, created_at, language FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => return conversation_not_found(locale),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // Headings and labels of the file follow the conversation rather than the downloading device
+    let locale = conversation
+        .get::<Option<String>, _>("language")
+        .as_deref()
+        .and_then(i18n::parse_language)
+        .unwrap_or(locale);
+
+    let doc = match load_transcript(pool, &conversation_id, conversation.get("title"), conversation.get("created_at"), locale).await {
+        Ok(d) => d,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let bytes = match transcript::render(&doc, format, locale) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Conversation export failed: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if let Err(resp) = files::ensure_storage_quota(&state, &resolved_user_id, bytes.len(), locale).await {
+        return resp;
+    }
+    let filename = format!("{}.{}", export_file_stem(&doc.title), format.extension());
+    match files::store_file(pool, filename, format.mime().to_string(), bytes, None, Some(&resolved_user_id)).await {
+        Ok(attachment) => HttpResponse::Ok().json(json!({ "conversation_id": conversation_id, "file": attachment })),
+        Err(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка сохранения файла",
+                Locale::En => "file-save-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ConversationStatsQuery {
+    pub user_id: String,
+}
+
+/// Summary card of a conversation: message counts, first and last activity, tokens and files.
+/// Tokens are counted with the prompt tokenizer over the stored messages, so they estimate the
+/// text exchanged rather than what the provider billed (system prompts and tool calls aren't stored).
+pub async fn conversation_stats(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ConversationStatsQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    let owned: Option<i64> = match sqlx::query_scalar(
+        "SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(o) => o,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if owned.is_none() {
+        return conversation_not_found(locale);
+    }
+
+    let messages = match sqlx::query(
+        "SELECT role, content, timestamp FROM messages WHERE conversation_id = ? ORDER BY julianday(timestamp), rowid"
+    )
+    .bind(&conversation_id)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let files = sqlx::query(
+        "SELECT
+            COALESCE(SUM(CASE WHEN m.role = 'assistant' THEN 1 ELSE 0 END), 0) AS generated,
+            COALESCE(SUM(CASE WHEN m.role = 'user' THEN 1 ELSE 0 END), 0) AS attached
+         FROM files f JOIN messages m ON m.id = f.message_id
+         WHERE m.conversation_id = ? AND f.deleted_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .fetch_one(pool)
+    .await;
+    let files = match files {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let (mut user_messages, mut assistant_messages) = (0, 0);
+    let (mut user_tokens, mut assistant_tokens) = (0, 0);
+    for m in &messages {
+        let content: String = m.get("content");
+        match m.get::<String, _>("role").as_str() {
+            "user" => {
+                user_messages += 1;
+                user_tokens += tokens::count(&content);
+            }
+            "assistant" => {
+                assistant_messages += 1;
+                assistant_tokens += tokens::count(&content);
+            }
+            _ => {}
+        }
+    }
+
+    HttpResponse::Ok().json(json!({
+        "conversation_id": conversation_id,
+        "messages": {
+            "total": messages.len(),
+            "user": user_messages,
+            "assistant": assistant_messages,
+        },
+        "first_activity_at": messages.first().map(|m| m.get::<String, _>("timestamp")),
+        "last_activity_at": messages.last().map(|m| m.get::<String, _>("timestamp")),
+        "tokens": {
+            "total": user_tokens + assistant_tokens,
+            "user": user_tokens,
+            "assistant": assistant_tokens,
+        },
+        "files": {
+            "generated": files.get::<i64, _>("generated"),
+            "attached": files.get::<i64, _>("attached"),
+        },
+    }))
+}
+
+/// Every message of a conversation in order, with the names of attached files
+pub(crate) async fn load_transcript(
+    pool: &sqlx::SqlitePool,
+    conversation_id: &str,
+    title: Option<String>,
+    created_at: String,
+    locale: Locale,
+) -> Result<Transcript, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT m.role, m.content, m.timestamp,
+                (SELECT json_group_array(f.filename) FROM files f WHERE f.message_id = m.id AND f.deleted_at IS NULL) AS attachments
+         FROM messages m
+         WHERE m.conversation_id = ?
+         ORDER BY julianday(m.timestamp), m.rowid"
+    )
+    .bind(conversation_id)
+    .fetch_all(pool)
+    .await?;
+
+    let messages = rows.iter().map(|r| TranscriptMessage {
+        role: r.get("role"),
+        content: r.get("content"),
+        timestamp: r.get("timestamp"),
+        attachments: serde_json::from_str(&r.get::<String, _>("attachments")).unwrap_or_default(),
+    }).collect();
+    let title = title.filter(|t| !t.trim().is_empty()).unwrap_or_else(|| match locale {
+        Locale::Ru => "Разговор".to_string(),
+        Locale::En => "Conversation".to_string(),
+    });
+    Ok(Transcript { title, created_at, messages })
+}
+
+/// Conversation title made safe for a file name
+fn export_file_stem(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .take(60)
+        .collect();
+    let stem = stem.trim_matches('_');
+    if stem.is_empty() { "conversation".to_string() } else { stem.to_string() }
+}
+
+#[derive(Deserialize)]
+pub struct EditMessageRequest {
+    pub user_id: String,
+    pub content: String,
+    /// Generate a fresh answer from the edited point
+    #[serde(default)]
+    pub regenerate: bool,
+    pub category: Option<String>,
+    pub business_type: Option<String>,
+    pub language: Option<String>,
+}
+
+/// Edits a user message; everything after it in the conversation is dropped since it answered the old text
+pub async fn edit_message(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    body: web::Json<EditMessageRequest>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let message_id = path.into_inner();
+    let pool = &state.pool;
+    let data = body.into_inner();
+
+    if data.content.trim().is_empty() {
+        let error_msg = match locale {
+            Locale::Ru => "Сообщение не может быть пустым",
+            Locale::En => "content-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
 
-                if let Ok(frs) = file_rows {
-                    if frs.is_empty() {
-                        continue;
-                    }
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
+    let row = sqlx::query(
+        "SELECT m.rowid, m.conversation_id, m.role, m.timestamp FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE m.id = ? AND c.user_id = ? AND c.deleted_at IS NULL"
+    )
+    .bind(&message_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await;
+    let row = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Сообщение не найдено или не принадлежит пользователю",
+                Locale::En => "message-not-found-or-not-owned",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if row.get::<String, _>("role") != "user" {
+        let error_msg = match locale {
+            Locale::Ru => "Редактировать можно только свои сообщения",
+            Locale::En => "only-user-messages-editable",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+    let conversation_id: String = row.get("conversation_id");
+    let rowid: i64 = row.get("rowid");
+    let timestamp: String = row.get("timestamp");
+    let edited_at = chrono::Utc::now().to_rfc3339();
 
-                    let mut attachments: Vec<FileAttachment> = Vec::new();
-                    for fr in frs {
-                        let id = fr.get::<String, _>("id");
-                        let filename = fr.get::<String, _>("filename");
-                        let mime = fr.get::<String, _>("mime");
-                        let size = fr.get::<i64, _>("size") as usize;
-                        let bytes: Vec<u8> = fr.get("bytes");
+    // Same ordering as history paging: timestamp, then insertion order for ties
+    const DOWNSTREAM: &str = "conversation_id = ? AND (julianday(timestamp) > julianday(?) OR (julianday(timestamp) = julianday(?) AND rowid > ?))";
 
-                        let content_base64 = if size <= 1024 * 1024 {
-                            Some(B64.encode(&bytes))
-                        } else {
-                            None
-                        };
-                        let download_url = Some(format!("/api/files/{}", id));
+    let removed = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("UPDATE messages SET content = ?, edited_at = ? WHERE id = ?")
+            .bind(&data.content)
+            .bind(&edited_at)
+            .bind(&message_id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query(&format!("DELETE FROM files WHERE message_id IN (SELECT id FROM messages WHERE {})", DOWNSTREAM))
+            .bind(&conversation_id)
+            .bind(&timestamp)
+            .bind(&timestamp)
+            .bind(rowid)
+            .execute(&mut tx)
+            .await?;
+        let removed = sqlx::query(&format!("DELETE FROM messages WHERE {}", DOWNSTREAM))
+            .bind(&conversation_id)
+            .bind(&timestamp)
+            .bind(&timestamp)
+            .bind(rowid)
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(removed)
+    }
+    .await;
+    let removed = match removed {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
 
-                        attachments.push(FileAttachment {
-                            id: Some(id),
-                            filename,
-                            mime,
-                            size,
-                            content_base64,
-                            download_url,
-                        });
-                    }
+    let mut result = json!({
+        "message": {
+            "id": message_id,
+            "role": "user",
+            "content": data.content,
+            "timestamp": timestamp,
+            "edited_at": edited_at,
+        },
+        "conversation_id": conversation_id,
+        "removed_messages": removed,
+    });
 
-                    if !attachments.is_empty() {
-                        files_by_message.push(json!({
-                            "message_id": msg.id,
-                            "files": attachments,
-                        }));
-                    }
-                }
+    if data.regenerate {
+        let chat_req = ChatRequest {
+            message: data.content.clone(),
+            category: data.category,
+            user_id: data.user_id,
+            business_type: data.business_type,
+            conversation_id: Some(conversation_id),
+            output_format: None,
+            table: None,
+            language: data.language,
+            context_filters: None,
+            attachment_ids: Vec::new(),
+            images: Vec::new(),
+            response_schema: None,
+            clarify: None,
+            model: None,
+            tts: false,
+        };
+        let mut turn = match prepare_turn(&req, chat_req, &state).await {
+            Ok(t) => t,
+            Err(resp) => return resp,
+        };
+        // The edited text goes out as the current message, not as history
+        if let Some(history) = turn.history.as_mut() {
+            if let Some(pos) = history.iter().rposition(|(role, content)| role == "user" && *content == data.content) {
+                history.remove(pos);
             }
-
-            HttpResponse::Ok().json(json!({
-                "conversation_id": conversation_id,
-                "messages": messages,
-                "count": messages.len(),
-                "attachments": files_by_message,
-            }))
         }
-        Err(_) => HttpResponse::InternalServerError().finish(),
+        turn.resend_of = Some(message_id);
+        if let Some(wait) = breaker::retry_after() {
+            result["reply"] = queue_turn(&state, turn, wait).await;
+            return HttpResponse::Accepted().json(result);
+        }
+
+        let generation = openai::generate_response(
+            &turn.chat_req.message,
+            &turn.category,
+            &turn.business_type,
+            &state,
+            &turn.model,
+            &turn.resolved_user_id,
+            turn.locale,
+            turn.history.take(),
+            turn.context.clone(),
+            &turn.images,
+            turn.chat_req.response_schema.as_ref(),
+        );
+        result["reply"] = match until_cancelled(&state, &turn.conversation_id, generation).await {
+            Some(llm_output) => json!(complete_turn(&state, turn, llm_output.ok()).await),
+            None => cancel_turn(&state, turn).await,
+        };
     }
-}
 
-#[derive(Deserialize)]
-pub struct ConversationOwner {
-    pub user_id: String,
+    HttpResponse::Ok().json(result)
 }
 
 #[derive(Deserialize)]
-pub struct UpdateConversationTitle {
+pub struct RetryMessageRequest {
     pub user_id: String,
-    pub title: Option<String>,
+    pub category: Option<String>,
+    pub business_type: Option<String>,
+    pub language: Option<String>,
 }
 
-pub async fn delete_conversation(
+/// Answers a user message whose answer failed (`status` `failed`) again, as if it had just been
+/// sent. Only the latest message of the conversation can be retried, so the answer stays in order.
+pub async fn retry_message(
     req: HttpRequest,
     path: web::Path<String>,
     state: web::Data<AppState>,
-    body: web::Json<ConversationOwner>,
+    body: web::Json<RetryMessageRequest>,
 ) -> HttpResponse {
-    let conversation_id = path.into_inner();
+    let locale = i18n::detect_locale(&req);
+    let message_id = path.into_inner();
     let pool = &state.pool;
+    let data = body.into_inner();
 
-    // Resolve user_id to main user_id
-    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
-    
-    // Check if conversation belongs to resolved user_id
-    let exists: Option<i64> = sqlx::query_scalar(
-        "SELECT CASE WHEN EXISTS(SELECT 1 FROM conversations WHERE id = ? AND user_id = ?) THEN 1 ELSE 0 END"
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
+    let row = sqlx::query(
+        "SELECT m.conversation_id, m.role, m.content, m.status,
+                (SELECT l.id FROM messages l WHERE l.conversation_id = m.conversation_id
+                 ORDER BY julianday(l.timestamp) DESC, l.rowid DESC LIMIT 1) AS latest_id
+         FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE m.id = ? AND c.user_id = ? AND c.deleted_at IS NULL"
     )
-    .bind(&conversation_id)
+    .bind(&message_id)
     .bind(&resolved_user_id)
     .fetch_optional(pool)
-    .await
-    .ok()
-    .flatten();
-
-    let locale = i18n::detect_locale(&req);
-    let error_msg = match locale {
-        Locale::Ru => "Разговор не найден или не принадлежит пользователю",
-        Locale::En => "conversation-not-found-or-not-owned",
+    .await;
+    let row = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Сообщение не найдено или не принадлежит пользователю",
+                Locale::En => "message-not-found-or-not-owned",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
     };
+    let failed = row.get::<String, _>("role") == "user" && row.get::<Option<String>, _>("status").as_deref() == Some("failed");
+    if !failed {
+        let error_msg = match locale {
+            Locale::Ru => "Повторить можно только сообщение, на которое не удалось ответить",
+            Locale::En => "message-not-failed",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+    }
+    if row.get::<Option<String>, _>("latest_id").as_deref() != Some(message_id.as_str()) {
+        let error_msg = match locale {
+            Locale::Ru => "После этого сообщения в разговоре уже есть другие",
+            Locale::En => "message-not-latest",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+    }
+    let content: String = row.get("content");
 
-    match exists {
-        Some(1) => {
-            // Delete messages first due to FK
-            let _ = sqlx::query("DELETE FROM messages WHERE conversation_id = ?")
-                .bind(&conversation_id)
-                .execute(pool)
-                .await;
-
-            let _ = sqlx::query("DELETE FROM conversations WHERE id = ? AND user_id = ?")
-                .bind(&conversation_id)
-                .bind(&resolved_user_id)
-                .execute(pool)
-                .await;
-
-            HttpResponse::Ok().json(json!({
-                "status": "deleted",
-                "conversation_id": conversation_id,
-            }))
+    let chat_req = ChatRequest {
+        message: content.clone(),
+        category: data.category,
+        user_id: data.user_id,
+        business_type: data.business_type,
+        conversation_id: Some(row.get("conversation_id")),
+        output_format: None,
+        table: None,
+        language: data.language,
+        context_filters: None,
+        // Attachments are linked to the stored message already
+        attachment_ids: Vec::new(),
+        images: Vec::new(),
+        response_schema: None,
+        clarify: None,
+        model: None,
+        tts: false,
+    };
+    let mut turn = match prepare_turn(&req, chat_req, &state).await {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    // The stored message goes out as the current message, not as history
+    if let Some(history) = turn.history.as_mut() {
+        if let Some(pos) = history.iter().rposition(|(role, c)| role == "user" && *c == content) {
+            history.remove(pos);
         }
-        _ => HttpResponse::NotFound().json(json!({
-            "error": error_msg,
-        })),
+    }
+    turn.resend_of = Some(message_id);
+    if let Some(wait) = breaker::retry_after() {
+        return HttpResponse::Accepted().json(queue_turn(&state, turn, wait).await);
+    }
+
+    let generation = openai::generate_response(
+        &turn.chat_req.message,
+        &turn.category,
+        &turn.business_type,
+        &state,
+        &turn.model,
+        &turn.resolved_user_id,
+        turn.locale,
+        turn.history.take(),
+        turn.context.clone(),
+        &turn.images,
+        turn.chat_req.response_schema.as_ref(),
+    );
+    match until_cancelled(&state, &turn.conversation_id, generation).await {
+        Some(llm_output) => HttpResponse::Ok().json(complete_turn(&state, turn, llm_output.ok()).await),
+        None => HttpResponse::Ok().json(cancel_turn(&state, turn).await),
     }
 }
 
@@ -599,6 +2931,16 @@ struct FileIntent {
     table: TableSpec,
 }
 
+/// Heuristic for answers where the model declined instead of helping
+fn is_refusal(text: &str) -> bool {
+    const MARKERS: &[&str] = &[
+        "i can't help with", "i cannot help with", "i can't assist", "i cannot assist", "i'm unable to help",
+        "не могу помочь с", "не могу с этим помочь", "я не могу ответить",
+    ];
+    let head: String = text.chars().take(300).collect::<String>().to_lowercase();
+    MARKERS.iter().any(|m| head.contains(m))
+}
+
 fn extract_file_intent(text: &str) -> Option<(String, TableSpec)> {
     // First, try to extract JSON from code blocks (```json ... ``` or ``` ... ```)
     let json_block_markers = ["```json", "```"];
@@ -628,68 +2970,79 @@ fn extract_file_intent(text: &str) -> Option<(String, TableSpec)> {
     None
 }
 
-fn parse_markdown_table(text: &str) -> Option<TableSpec> {
-    let lines: Vec<&str> = text.lines().collect();
-    let mut table_lines: Vec<&str> = Vec::new();
-    let mut in_table = false;
-    
-    // Find table boundaries (lines starting with |)
-    for line in &lines {
-        let trimmed = line.trim();
-        if trimmed.starts_with('|') && trimmed.ends_with('|') {
-            in_table = true;
-            table_lines.push(trimmed);
-        } else if in_table && !trimmed.starts_with('|') {
-            // End of table
-            break;
+/// Every GFM table in `text`, in order. A table is a header row, an alignment row (`---`,
+/// `:--`, `--:`, `:-:`) with as many cells, then body rows up to the first line without a pipe.
+/// Outer pipes are optional and `\|` is a literal pipe inside a cell.
+fn parse_markdown_tables(text: &str) -> Vec<TableSpec> {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    let mut tables = Vec::new();
+    let mut i = 0;
+    while i + 1 < lines.len() {
+        let headers = match split_table_row(lines[i]) {
+            Some(h) if h.iter().any(|c| !c.is_empty()) && is_alignment_row(lines[i + 1], h.len()) => h,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        let mut next = i + 2;
+        while let Some(mut cells) = lines.get(next).and_then(|l| split_table_row(l)) {
+            // Short rows are padded, long ones cut, as GFM renders them
+            cells.resize(headers.len(), String::new());
+            rows.push(cells);
+            next += 1;
         }
+        if !rows.is_empty() {
+            tables.push(TableSpec { headers, rows });
+        }
+        i = next;
     }
-    
-    if table_lines.len() < 2 {
-        return None;
-    }
-    
-    // Parse header (first line)
-    let header_line = table_lines[0];
-    let headers: Vec<String> = header_line
-        .split('|')
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect();
-    
-    if headers.is_empty() {
-        return None;
-    }
-    
-    // Skip separator line (second line, usually |---|---|)
-    let mut rows: Vec<Vec<String>> = Vec::new();
-    
-    // Parse data rows (starting from third line)
-    for line in table_lines.iter().skip(2) {
-        let cells: Vec<String> = line
-            .split('|')
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
-        
-        if cells.len() == headers.len() {
-            rows.push(cells);
-        } else if cells.len() > 0 {
-            // Try to pad or truncate to match header count
-            let mut adjusted_cells = cells;
-            while adjusted_cells.len() < headers.len() {
-                adjusted_cells.push(String::new());
+    tables
+}
+
+/// Trimmed cells of a table row, `None` when the line has no unescaped pipe
+fn split_table_row(line: &str) -> Option<Vec<String>> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut pipes = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => {
+                pipes += 1;
+                cells.push(std::mem::take(&mut cell));
             }
-            adjusted_cells.truncate(headers.len());
-            rows.push(adjusted_cells);
+            _ => cell.push(c),
         }
     }
-    
-    if rows.is_empty() {
+    if pipes == 0 {
         return None;
     }
-    
-    Some(TableSpec { headers, rows })
+    // Whatever follows the last pipe is a cell unless the row is closed by it
+    if !cell.trim().is_empty() {
+        cells.push(cell);
+    }
+    if line.starts_with('|') {
+        cells.remove(0);
+    }
+    Some(cells.into_iter().map(|c| c.trim().to_string()).collect())
+}
+
+fn is_alignment_row(line: &str, columns: usize) -> bool {
+    match split_table_row(line) {
+        Some(cells) => cells.len() == columns && cells.iter().all(|c| {
+            let dashes = c.strip_prefix(':').unwrap_or(c);
+            let dashes = dashes.strip_suffix(':').unwrap_or(dashes);
+            !dashes.is_empty() && dashes.chars().all(|ch| ch == '-')
+        }),
+        None => false,
+    }
 }
 
 fn detect_format_from_message(message: &str) -> String {
@@ -703,15 +3056,18 @@ fn detect_format_from_message(message: &str) -> String {
     }
 }
 
-async fn generate_file_and_store(
+pub(crate) async fn generate_file_and_store(
     pool: &sqlx::SqlitePool,
     fmt: &str,
     table: &TableSpec,
     message_id: Option<&str>,
+    user_id: Option<&str>,
 ) -> Result<FileAttachment, Box<dyn std::error::Error>> {
     let (filename, mime, bytes) = match fmt.to_ascii_lowercase().as_str() {
         "xlsx" => {
             let mut wb = Workbook::new();
+            // Fixed creation date keeps identical tables byte-identical, so re-exports dedupe
+            wb.set_properties(&DocProperties::new().set_creation_datetime(&ExcelDateTime::from_ymd(2000, 1, 1)?));
             let ws = wb.add_worksheet();
             for (c, h) in table.headers.iter().enumerate() {
                 ws.write_string(0, c as u16, h)?;
@@ -746,35 +3102,15 @@ async fn generate_file_and_store(
         _ => return Err("unsupported_format".into()),
     };
 
-    let size = bytes.len();
-    let id = Uuid::new_v4().to_string();
-    sqlx::query(
-        "INSERT INTO files (id, filename, mime, size, bytes, message_id) VALUES (?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&id)
-    .bind(&filename)
-    .bind(&mime)
-    .bind(size as i64)
-    .bind(bytes.clone())
-    .bind(message_id)
-    .execute(pool)
-    .await?;
-
-    let content_base64 = if size <= 1024 * 1024 {
+    let content_base64 = if bytes.len() <= 1024 * 1024 {
         Some(B64.encode(&bytes))
     } else {
         None
     };
-    let download_url = Some(format!("/api/files/{}", id));
-
-    Ok(FileAttachment {
-        id: Some(id),
-        filename,
-        mime,
-        size,
-        content_base64,
-        download_url,
-    })
+
+    let mut attachment = files::store_file(pool, filename, mime, bytes, message_id, user_id).await?;
+    attachment.content_base64 = content_base64;
+    Ok(attachment)
 }
 
 // ========== USER ID RESOLUTION ==========
@@ -791,7 +3127,7 @@ fn normalize_telegram_username(username: &str) -> String {
 ///    - Direct link through telegram_users.user_id
 ///    - Link through matching telegram_username (normalized, case-insensitive)
 /// 3. If telegram_username is provided - finds main user by telegram_username
-async fn resolve_user_id_for_conversations(
+pub(crate) async fn resolve_user_id_for_conversations(
     pool: &sqlx::SqlitePool,
     user_id: &str,
 ) -> String {
@@ -987,7 +3323,7 @@ async fn get_conversation_context(
     })
 }
 
-async fn get_user_base_context(
+pub(crate) async fn get_user_base_context(
     pool: &sqlx::SqlitePool,
     user_id: &str,
 ) -> ConversationContext {
@@ -1105,4 +3441,265 @@ async fn save_conversation_context(
     .await?;
     
     Ok(())
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct ConversationSearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+/// Character offsets of every case-insensitive occurrence of `needle` in `haystack`
+fn match_offsets(haystack: &str, needle: &str) -> Vec<(usize, usize)> {
+    let fold = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let hay: Vec<char> = haystack.chars().map(fold).collect();
+    let pat: Vec<char> = needle.chars().map(fold).collect();
+    if pat.is_empty() || pat.len() > hay.len() {
+        return Vec::new();
+    }
+
+    (0..=hay.len() - pat.len())
+        .filter(|&i| hay[i..i + pat.len()] == pat[..])
+        .map(|i| (i, pat.len()))
+        .collect()
+}
+
+/// Builds an FTS5 query matching every term as a prefix, with user input quoted
+fn fts_query(q: &str) -> Option<String> {
+    let terms: Vec<String> = q
+        .split_whitespace()
+        .map(|t| format!("\"{}\"*", t.replace('"', "\"\"")))
+        .collect();
+    if terms.is_empty() { None } else { Some(terms.join(" ")) }
+}
+
+/// User input as a literal LIKE pattern, for use with `ESCAPE '\'`
+fn like_literal(q: &str) -> String {
+    q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+pub async fn search_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ConversationSearchQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let q = query.q.trim();
+
+    let fts = match fts_query(q) {
+        Some(f) if q.chars().count() <= 200 => f,
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Укажите поисковый запрос",
+                Locale::En => "query-required",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+
+    if conversation_deleted(pool, &conversation_id).await {
+        return conversation_not_found(locale);
+    }
+
+    // FTS catches word/prefix matches in any case; LIKE adds substrings inside words
+    let rows = sqlx::query(
+        "SELECT m.id, m.role, m.content, m.timestamp FROM messages m
+         WHERE m.conversation_id = ?
+           AND (m.rowid IN (SELECT rowid FROM messages_fts WHERE messages_fts MATCH ?)
+                OR m.content LIKE '%' || ? || '%' ESCAPE '\\')
+         ORDER BY datetime(m.timestamp) ASC
+         LIMIT ?"
+    )
+    .bind(&conversation_id)
+    .bind(&fts)
+    .bind(like_literal(q))
+    .bind(limit)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let results: Vec<serde_json::Value> = rs.into_iter().filter_map(|r| {
+                let content: String = r.get("content");
+                let mut offsets = match_offsets(&content, q);
+                if offsets.is_empty() {
+                    // Matched per term through FTS: highlight each term instead
+                    for term in q.split_whitespace() {
+                        offsets.extend(match_offsets(&content, term));
+                    }
+                    offsets.sort_unstable();
+                }
+                if offsets.is_empty() {
+                    return None;
+                }
+                Some(json!({
+                    "message_id": r.get::<String, _>("id"),
+                    "role": r.get::<String, _>("role"),
+                    "timestamp": r.get::<String, _>("timestamp"),
+                    "matches": offsets.into_iter().map(|(start, length)| json!({ "offset": start, "length": length })).collect::<Vec<_>>(),
+                }))
+            }).collect();
+
+            HttpResponse::Ok().json(json!({
+                "conversation_id": conversation_id,
+                "query": q,
+                "results": results,
+            }))
+        }
+        Err(e) => {
+            eprintln!("Conversation search failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchSnippet {
+    pub message_id: String,
+    pub role: String,
+    pub timestamp: String,
+    pub snippet: String,
+}
+
+#[derive(Serialize)]
+pub struct ConversationHit {
+    pub conversation_id: String,
+    pub title: Option<String>,
+    pub title_highlight: Option<String>,
+    pub created_at: String,
+    pub match_count: i64,
+    /// Up to three best-ranked message excerpts
+    pub snippets: Vec<SearchSnippet>,
+    #[serde(skip)]
+    best_rank: f64,
+}
+
+#[derive(Deserialize)]
+pub struct GlobalSearchQuery {
+    pub user_id: String,
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+/// Snippets wrap matched terms in `<mark>`…`</mark>`
+pub async fn search_conversations(
+    req: HttpRequest,
+    query: web::Query<GlobalSearchQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let q = query.q.trim();
+
+    let fts = match fts_query(q) {
+        Some(f) if q.chars().count() <= 200 => f,
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Укажите поисковый запрос",
+                Locale::En => "query-required",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    let title_rows = sqlx::query(
+        "SELECT c.id, c.title, c.created_at,
+                highlight(conversations_fts, 0, '<mark>', '</mark>') AS title_highlight,
+                bm25(conversations_fts) AS rank
+         FROM conversations_fts JOIN conversations c ON c.rowid = conversations_fts.rowid
+         WHERE conversations_fts MATCH ? AND c.user_id = ? AND c.deleted_at IS NULL
+         ORDER BY rank
+         LIMIT ?"
+    )
+    .bind(&fts)
+    .bind(&resolved_user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await;
+
+    // A generous slice so each conversation gets a few snippets
+    let message_rows = sqlx::query(
+        "SELECT c.id AS conversation_id, c.title, c.created_at, m.id AS message_id, m.role, m.timestamp,
+                snippet(messages_fts, 0, '<mark>', '</mark>', '…', 12) AS snippet,
+                bm25(messages_fts) AS rank
+         FROM messages_fts
+         JOIN messages m ON m.rowid = messages_fts.rowid
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE messages_fts MATCH ? AND c.user_id = ? AND c.deleted_at IS NULL
+         ORDER BY rank
+         LIMIT ?"
+    )
+    .bind(&fts)
+    .bind(&resolved_user_id)
+    .bind(limit * 10)
+    .fetch_all(pool)
+    .await;
+
+    let (title_rows, message_rows) = match (title_rows, message_rows) {
+        (Ok(t), Ok(m)) => (t, m),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Conversation search failed: {}", e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let mut hits: Vec<ConversationHit> = Vec::new();
+    let mut index: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    let mut hit_for = |r: &sqlx::sqlite::SqliteRow, id_column: &str| -> usize {
+        let id: String = r.get(id_column);
+        let rank: f64 = r.get("rank");
+        let i = *index.entry(id.clone()).or_insert_with(|| {
+            hits.push(ConversationHit {
+                conversation_id: id,
+                title: r.try_get("title").ok().flatten(),
+                title_highlight: None,
+                created_at: r.get("created_at"),
+                match_count: 0,
+                snippets: Vec::new(),
+                best_rank: rank,
+            });
+            hits.len() - 1
+        });
+        hits[i].best_rank = hits[i].best_rank.min(rank);
+        i
+    };
+
+    let mut title_hits = Vec::new();
+    for r in &title_rows {
+        title_hits.push((hit_for(r, "id"), r.get::<String, _>("title_highlight")));
+    }
+    let mut message_hits = Vec::new();
+    for r in &message_rows {
+        message_hits.push((hit_for(r, "conversation_id"), SearchSnippet {
+            message_id: r.get("message_id"),
+            role: r.get("role"),
+            timestamp: r.get("timestamp"),
+            snippet: r.get("snippet"),
+        }));
+    }
+    for (i, highlight) in title_hits {
+        hits[i].title_highlight = Some(highlight);
+    }
+    for (i, snippet) in message_hits {
+        hits[i].match_count += 1;
+        if hits[i].snippets.len() < 3 {
+            hits[i].snippets.push(snippet);
+        }
+    }
+
+    // bm25 is negative and lower is better
+    hits.sort_by(|a, b| a.best_rank.total_cmp(&b.best_rank));
+    hits.truncate(limit as usize);
+
+    HttpResponse::Ok().json(json!({
+        "user_id": resolved_user_id,
+        "query": q,
+        "results": hits,
+    }))
+}
@@ -0,0 +1,149 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Longest draft kept; anything longer is rejected rather than cut
+const MAX_DRAFT_CHARS: usize = 20_000;
+
+/// Drops the owner's draft once a message is sent in the conversation, from any channel
+pub(crate) async fn clear(pool: &sqlx::SqlitePool, conversation_id: &str, owner_id: &str) {
+    let _ = sqlx::query("DELETE FROM conversation_drafts WHERE conversation_id = ? AND user_id = ?")
+        .bind(conversation_id)
+        .bind(owner_id)
+        .execute(pool)
+        .await;
+}
+
+/// Owner of the conversation for `user_id` (an account id or a linked Telegram user id), or the
+/// 404 response when the conversation isn't theirs or is deleted
+async fn owner(
+    pool: &sqlx::SqlitePool,
+    conversation_id: &str,
+    user_id: &str,
+    locale: Locale,
+) -> Result<String, HttpResponse> {
+    let resolved_user_id = resolve_user_id_for_conversations(pool, user_id).await;
+    let owned: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(conversation_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    if owned.is_none() {
+        let error_msg = match locale {
+            Locale::Ru => "Разговор не найден или не принадлежит пользователю",
+            Locale::En => "conversation-not-found-or-not-owned",
+        };
+        return Err(HttpResponse::NotFound().json(json!({ "error": error_msg })));
+    }
+    Ok(resolved_user_id)
+}
+
+#[derive(Deserialize)]
+pub struct DraftQuery {
+    pub user_id: String,
+}
+
+/// `GET /api/chat/conversations/{id}/draft?user_id=` returns the unsent text, `null` when there is none
+pub async fn get_draft(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DraftQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let owner_id = match owner(pool, &conversation_id, &query.user_id, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let row = sqlx::query("SELECT content, updated_at FROM conversation_drafts WHERE conversation_id = ? AND user_id = ?")
+        .bind(&conversation_id)
+        .bind(&owner_id)
+        .fetch_optional(pool)
+        .await;
+    match row {
+        Ok(Some(r)) => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "content": r.get::<String, _>("content"),
+            "updated_at": r.get::<String, _>("updated_at"),
+        })),
+        Ok(None) => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "content": null,
+            "updated_at": null,
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SaveDraftRequest {
+    pub user_id: String,
+    /// Empty or blank clears the draft
+    #[serde(default)]
+    pub content: String,
+}
+
+/// `PUT /api/chat/conversations/{id}/draft` replaces the unsent text. Drafts belong to the
+/// account owning the conversation, so one started in the app is there in Telegram and back.
+pub async fn save_draft(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<SaveDraftRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+
+    if body.content.chars().count() > MAX_DRAFT_CHARS {
+        let error_msg = match locale {
+            Locale::Ru => "Черновик слишком длинный",
+            Locale::En => "draft-too-long",
+        };
+        return HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg }));
+    }
+    let owner_id = match owner(pool, &conversation_id, &body.user_id, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    if body.content.trim().is_empty() {
+        clear(pool, &conversation_id, &owner_id).await;
+        return HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "content": null,
+            "updated_at": null,
+        }));
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        "INSERT INTO conversation_drafts (conversation_id, user_id, content, updated_at) VALUES (?, ?, ?, ?)
+         ON CONFLICT(conversation_id, user_id) DO UPDATE SET content = excluded.content, updated_at = excluded.updated_at"
+    )
+    .bind(&conversation_id)
+    .bind(&owner_id)
+    .bind(&body.content)
+    .bind(&now)
+    .execute(pool)
+    .await;
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "content": body.content,
+            "updated_at": now,
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,325 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::admin::require_admin;
+use crate::services::faq::USAGE_JOIN;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Serialize)]
+pub struct FaqEntry {
+    pub id: String,
+    pub question: String,
+    pub answer: String,
+    /// `ru` or `en`
+    pub locale: String,
+    pub tags: Vec<String>,
+    pub active: bool,
+    /// Automatic support replies drawn from this entry
+    pub times_answered: i64,
+    /// Of those, replies after which the user did not ask for a human
+    pub times_resolved: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+const FAQ_COLUMNS: &str = "f.id, f.question, f.answer, f.locale, f.tags, f.active, f.created_at, f.updated_at,
+    COALESCE(u.answered, 0) AS answered, COALESCE(u.answered - u.handed_off, 0) AS resolved";
+
+fn faq_from_row(r: &sqlx::sqlite::SqliteRow) -> FaqEntry {
+    FaqEntry {
+        id: r.get("id"),
+        question: r.get("question"),
+        answer: r.get("answer"),
+        locale: r.get("locale"),
+        tags: parse_tags(r.get("tags")),
+        active: r.get::<i64, _>("active") != 0,
+        times_answered: r.get("answered"),
+        times_resolved: r.get("resolved"),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    }
+}
+
+/// Tags are stored as a JSON array
+fn parse_tags(raw: String) -> Vec<String> {
+    serde_json::from_str(&raw).unwrap_or_default()
+}
+
+/// Trimmed, lowercased and deduplicated so filtering by tag is predictable
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !out.contains(&tag) {
+            out.push(tag);
+        }
+    }
+    out
+}
+
+#[derive(Deserialize)]
+pub struct CreateFaqRequest {
+    pub question: String,
+    pub answer: String,
+    pub locale: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateFaqRequest {
+    pub question: Option<String>,
+    pub answer: Option<String>,
+    pub locale: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub active: Option<bool>,
+}
+
+fn invalid_faq(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Нужны вопрос, ответ и язык (ru или en)",
+        Locale::En => "question-answer-and-locale-required",
+    };
+    HttpResponse::BadRequest().json(json!({ "error": error_msg }))
+}
+
+fn faq_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Запись FAQ не найдена",
+        Locale::En => "faq-not-found",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+async fn fetch_faq(pool: &sqlx::SqlitePool, id: &str) -> Result<Option<FaqEntry>, sqlx::Error> {
+    let row = sqlx::query(&format!("SELECT {} FROM faqs f {} WHERE f.id = ?", FAQ_COLUMNS, USAGE_JOIN))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.as_ref().map(faq_from_row))
+}
+
+/// `POST /api/admin/support/faqs` adds an entry the support auto-answer can draw on
+pub async fn create_faq(
+    req: HttpRequest,
+    body: web::Json<CreateFaqRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let question = body.question.trim();
+    let answer = body.answer.trim();
+    let entry_locale = body.locale.trim().to_lowercase();
+    if question.is_empty() || answer.is_empty() || !matches!(entry_locale.as_str(), "ru" | "en") {
+        return invalid_faq(locale);
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let entry = FaqEntry {
+        id: Uuid::new_v4().to_string(),
+        question: question.to_string(),
+        answer: answer.to_string(),
+        locale: entry_locale,
+        tags: normalize_tags(&body.tags),
+        active: true,
+        times_answered: 0,
+        times_resolved: 0,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    let result = sqlx::query(
+        "INSERT INTO faqs (id, question, answer, locale, tags, active, created_at, updated_at) VALUES (?, ?, ?, ?, ?, 1, ?, ?)"
+    )
+    .bind(&entry.id)
+    .bind(&entry.question)
+    .bind(&entry.answer)
+    .bind(&entry.locale)
+    .bind(serde_json::to_string(&entry.tags).unwrap_or_else(|_| "[]".to_string()))
+    .bind(&entry.created_at)
+    .bind(&entry.updated_at)
+    .execute(&state.pool)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Created().json(entry),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `PUT /api/admin/support/faqs/{id}` changes only the fields that are present
+pub async fn update_faq(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<UpdateFaqRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let faq_id = path.into_inner();
+    let data = body.into_inner();
+
+    let question = data.question.as_deref().map(str::trim);
+    let answer = data.answer.as_deref().map(str::trim);
+    let entry_locale = data.locale.as_deref().map(|l| l.trim().to_lowercase());
+    if question.is_some_and(str::is_empty)
+        || answer.is_some_and(str::is_empty)
+        || entry_locale.as_deref().is_some_and(|l| !matches!(l, "ru" | "en"))
+    {
+        return invalid_faq(locale);
+    }
+    let tags = data
+        .tags
+        .as_deref()
+        .map(|t| serde_json::to_string(&normalize_tags(t)).unwrap_or_else(|_| "[]".to_string()));
+
+    let result = sqlx::query(
+        "UPDATE faqs SET
+            question = COALESCE(?, question),
+            answer = COALESCE(?, answer),
+            locale = COALESCE(?, locale),
+            tags = COALESCE(?, tags),
+            active = COALESCE(?, active),
+            updated_at = ?
+         WHERE id = ?"
+    )
+    .bind(question)
+    .bind(answer)
+    .bind(entry_locale)
+    .bind(tags)
+    .bind(data.active.map(|a| a as i64))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&faq_id)
+    .execute(&state.pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => return faq_not_found(locale),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    match fetch_faq(&state.pool, &faq_id).await {
+        Ok(Some(entry)) => HttpResponse::Ok().json(entry),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `DELETE /api/admin/support/faqs/{id}`; past auto replies keep their `faq_id` for the record
+pub async fn delete_faq(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let faq_id = path.into_inner();
+
+    let result = sqlx::query("DELETE FROM faqs WHERE id = ?")
+        .bind(&faq_id)
+        .execute(&state.pool)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => faq_not_found(locale),
+        Ok(_) => HttpResponse::Ok().json(json!({ "status": "deleted", "id": faq_id })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FaqListQuery {
+    /// Filters by entry language; all languages when omitted
+    pub locale: Option<String>,
+}
+
+/// `GET /api/admin/support/faqs` lists all entries, inactive ones included
+pub async fn list_faqs(
+    req: HttpRequest,
+    query: web::Query<FaqListQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM faqs f {}
+         WHERE (? IS NULL OR f.locale = ?)
+         ORDER BY f.locale, f.created_at",
+        FAQ_COLUMNS, USAGE_JOIN
+    ))
+    .bind(&query.locale)
+    .bind(&query.locale)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let faqs: Vec<FaqEntry> = rs.iter().map(faq_from_row).collect();
+            HttpResponse::Ok().json(json!({ "faqs": faqs }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Serialize)]
+pub struct PublicFaq {
+    pub id: String,
+    pub question: String,
+    pub answer: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PublicFaqQuery {
+    pub tag: Option<String>,
+}
+
+/// `GET /api/support/faq?lang=` lists active entries in the request language,
+/// the ones that resolve the most tickets first
+pub async fn list_public_faqs(
+    req: HttpRequest,
+    query: web::Query<PublicFaqQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let tag = query.tag.as_deref().map(|t| t.trim().to_lowercase()).filter(|t| !t.is_empty());
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM faqs f {}
+         WHERE f.locale = ? AND f.active = 1
+           AND (? IS NULL OR EXISTS (SELECT 1 FROM json_each(f.tags) WHERE json_each.value = ?))
+         ORDER BY resolved DESC, f.created_at",
+        FAQ_COLUMNS, USAGE_JOIN
+    ))
+    .bind(match locale { Locale::Ru => "ru", Locale::En => "en" })
+    .bind(&tag)
+    .bind(&tag)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let faqs: Vec<PublicFaq> = rs.iter().map(faq_from_row).map(|f| PublicFaq {
+                id: f.id,
+                question: f.question,
+                answer: f.answer,
+                tags: f.tags,
+            }).collect();
+            HttpResponse::Ok().json(json!({ "faqs": faqs }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,168 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::admin::require_admin;
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Longest comment kept with a rating
+const MAX_COMMENT_CHARS: usize = 2000;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+#[derive(Deserialize)]
+pub struct FeedbackRequest {
+    pub user_id: String,
+    pub rating: Rating,
+    pub comment: Option<String>,
+}
+
+/// `POST /api/chat/messages/{message_id}/feedback` rates an assistant answer;
+/// rating the same answer again replaces the earlier rating
+pub async fn submit_feedback(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FeedbackRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let message_id = path.into_inner();
+    let pool = &state.pool;
+    let data = body.into_inner();
+
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
+    let answer = match sqlx::query(
+        "SELECT m.role, m.category, m.model FROM messages m
+         JOIN conversations c ON c.id = m.conversation_id
+         WHERE m.id = ? AND c.user_id = ? AND c.deleted_at IS NULL"
+    )
+    .bind(&message_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Сообщение не найдено или не принадлежит пользователю",
+                Locale::En => "message-not-found-or-not-owned",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if answer.get::<String, _>("role") != "assistant" {
+        let error_msg = match locale {
+            Locale::Ru => "Оценить можно только ответ ассистента",
+            Locale::En => "only-assistant-messages-rateable",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+    let category: Option<String> = answer.get("category");
+    let model: Option<String> = answer.get("model");
+
+    let rating: i64 = match data.rating {
+        Rating::Up => 1,
+        Rating::Down => -1,
+    };
+    let comment: Option<String> = data
+        .comment
+        .as_deref()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| c.chars().take(MAX_COMMENT_CHARS).collect());
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let result = sqlx::query(
+        "INSERT INTO message_feedback (id, message_id, user_id, rating, comment, category, model, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(message_id) DO UPDATE SET
+            rating = excluded.rating,
+            comment = excluded.comment,
+            updated_at = excluded.updated_at"
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&message_id)
+    .bind(&resolved_user_id)
+    .bind(rating)
+    .bind(&comment)
+    .bind(&category)
+    .bind(&model)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(_) => HttpResponse::Ok().json(json!({
+            "message_id": message_id,
+            "rating": if rating > 0 { "up" } else { "down" },
+            "comment": comment,
+            "updated_at": now,
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FeedbackReportQuery {
+    pub days: Option<i64>,
+}
+
+/// `GET /api/admin/analytics/feedback` counts ratings per answer category and model
+pub async fn feedback_report(
+    req: HttpRequest,
+    query: web::Query<FeedbackReportQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    let since = (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+
+    // Answers stored before category and model were recorded are grouped as 'unknown'
+    let rows = sqlx::query(
+        "SELECT COALESCE(category, 'unknown') AS category, COALESCE(model, 'unknown') AS model,
+                SUM(CASE WHEN rating > 0 THEN 1 ELSE 0 END) AS up,
+                SUM(CASE WHEN rating < 0 THEN 1 ELSE 0 END) AS down,
+                SUM(CASE WHEN comment IS NOT NULL THEN 1 ELSE 0 END) AS comments
+         FROM message_feedback
+         WHERE julianday(updated_at) >= julianday(?)
+         GROUP BY 1, 2
+         ORDER BY COUNT(*) DESC"
+    )
+    .bind(&since)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let buckets: Vec<_> = rs.iter().map(|r| {
+                let up: i64 = r.get("up");
+                let down: i64 = r.get("down");
+                json!({
+                    "category": r.get::<String, _>("category"),
+                    "model": r.get::<String, _>("model"),
+                    "up": up,
+                    "down": down,
+                    "comments": r.get::<i64, _>("comments"),
+                    "satisfaction": up as f64 / (up + down).max(1) as f64,
+                })
+            }).collect();
+            HttpResponse::Ok().json(json!({ "days": days, "feedback": buckets }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
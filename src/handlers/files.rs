@@ -1,12 +1,75 @@
-use actix_web::{HttpResponse, web};
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use sqlx::Row;
+use crate::handlers::admin::require_admin;
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::chat::{conversation_deleted, resolve_user_id_for_conversations};
+use crate::models::FileAttachment;
+use crate::services::antivirus::{self, ScanVerdict};
+use crate::services::{bundle, storage};
 use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Days a trashed file can still be restored before the scheduler purges it
+pub const TRASH_RETENTION_DAYS: i64 = 30;
+
+const FILE_KINDS: [&str; 6] = ["image", "pdf", "spreadsheet", "document", "audio", "other"];
+
+/// Single source for mapping a mime type to the gallery `kind`
+const FILE_KIND_SQL: &str = "CASE
+    WHEN f.mime LIKE 'image/%' THEN 'image'
+    WHEN f.mime = 'application/pdf' THEN 'pdf'
+    WHEN f.mime IN ('text/csv', 'application/vnd.ms-excel')
+      OR f.mime LIKE 'application/vnd.openxmlformats-officedocument.spreadsheetml%' THEN 'spreadsheet'
+    WHEN f.mime = 'application/msword'
+      OR f.mime LIKE 'application/vnd.openxmlformats-officedocument.wordprocessingml%'
+      OR f.mime LIKE 'text/%' THEN 'document'
+    WHEN f.mime LIKE 'audio/%' THEN 'audio'
+    ELSE 'other' END";
+
+#[derive(Deserialize)]
+pub struct ConversationFilesQuery {
+    /// Comma-separated kinds, e.g. `image,pdf`
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// Lists the conversation's trash instead of its live files
+    #[serde(default)]
+    pub trashed: bool,
+}
+
+#[derive(Deserialize)]
+pub struct FileOwner {
+    pub user_id: String,
+}
+
+#[derive(Serialize)]
+pub struct ConversationFile {
+    pub id: String,
+    pub filename: String,
+    pub mime: String,
+    pub kind: String,
+    pub size: i64,
+    /// `generated` for assistant output, `uploaded` for files attached by the user
+    pub source: String,
+    pub message_id: String,
+    pub created_at: String,
+    pub download_url: String,
+    pub deleted_at: Option<String>,
+}
 
 pub async fn download_file(path: web::Path<String>, state: web::Data<AppState>) -> HttpResponse {
     let id = path.into_inner();
     let pool = &state.pool;
 
-    let row = sqlx::query("SELECT filename, mime, bytes FROM files WHERE id = ?")
+    let row = sqlx::query(
+        "SELECT f.filename, f.mime, COALESCE(b.bytes, f.bytes) AS bytes
+         FROM files f LEFT JOIN file_blobs b ON b.sha256 = f.sha256
+         WHERE f.id = ? AND f.deleted_at IS NULL AND f.scan_status IS NOT 'quarantined'"
+    )
         .bind(&id)
         .fetch_optional(pool)
         .await;
@@ -25,3 +88,541 @@ pub async fn download_file(path: web::Path<String>, state: web::Data<AppState>)
         Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
+
+/// Persists a generated blob and returns it as an attachment with a download link.
+/// Identical content is stored once in `file_blobs` and shared by reference count.
+pub(crate) async fn store_file(
+    pool: &sqlx::SqlitePool,
+    filename: String,
+    mime: String,
+    bytes: Vec<u8>,
+    message_id: Option<&str>,
+    user_id: Option<&str>,
+) -> Result<FileAttachment, sqlx::Error> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let size = bytes.len();
+    let sha256: String = Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO file_blobs (sha256, bytes, size, ref_count) VALUES (?, ?, ?, 1)
+         ON CONFLICT(sha256) DO UPDATE SET ref_count = file_blobs.ref_count + 1"
+    )
+    .bind(&sha256)
+    .bind(&bytes)
+    .bind(size as i64)
+    .execute(&mut tx)
+    .await?;
+
+    // The row keeps an empty inline blob; content is read through its sha256
+    sqlx::query(
+        "INSERT INTO files (id, filename, mime, size, bytes, message_id, user_id, sha256) VALUES (?, ?, ?, ?, x'', ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&filename)
+    .bind(&mime)
+    .bind(size as i64)
+    .bind(message_id)
+    .bind(user_id)
+    .bind(&sha256)
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(FileAttachment {
+        download_url: Some(format!("/api/files/{}", id)),
+        id: Some(id),
+        filename,
+        mime,
+        size,
+        content_base64: None,
+    })
+}
+
+/// Rejects a write of `incoming` bytes that would push the user over their plan's quota
+pub(crate) async fn ensure_storage_quota(state: &AppState, user_id: &str, incoming: usize, locale: Locale) -> Result<(), HttpResponse> {
+    let policy = state.config.load().storage.clone();
+    if storage::has_room(&state.pool, &policy, user_id, incoming).await {
+        return Ok(());
+    }
+
+    let error_msg = match locale {
+        Locale::Ru => "Превышен лимит хранилища, удалите ненужные файлы",
+        Locale::En => "storage-quota-exceeded",
+    };
+    Err(HttpResponse::InsufficientStorage().json(json!({
+        "error": error_msg,
+        "usage_url": format!("/api/files/usage/{}", user_id),
+    })))
+}
+
+/// Runs an upload through ClamAV before it is stored or parsed. Infected content is kept
+/// as a quarantined file for review and the upload is rejected; when clamd is configured
+/// but unreachable the upload is rejected too rather than let through unscanned.
+pub(crate) async fn scan_upload(
+    state: &AppState,
+    user_id: &str,
+    filename: &str,
+    mime: &str,
+    bytes: &[u8],
+    locale: Locale,
+) -> Result<(), HttpResponse> {
+    let signature = match antivirus::scan(bytes).await {
+        Ok(ScanVerdict::Clean) => return Ok(()),
+        Ok(ScanVerdict::Infected(signature)) => signature,
+        Err(e) => {
+            eprintln!("Antivirus scan failed: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Проверка файла временно недоступна, попробуйте позже",
+                Locale::En => "antivirus-unavailable",
+            };
+            return Err(HttpResponse::ServiceUnavailable().json(json!({ "error": error_msg })));
+        }
+    };
+
+    eprintln!("Antivirus: quarantined upload from user {} ({})", user_id, signature);
+    if let Ok(att) = store_file(&state.pool, filename.to_string(), mime.to_string(), bytes.to_vec(), None, Some(user_id)).await {
+        let _ = sqlx::query("UPDATE files SET scan_status = 'quarantined', scan_signature = ? WHERE id = ?")
+            .bind(&signature)
+            .bind(att.id)
+            .execute(&state.pool)
+            .await;
+    }
+
+    let error_msg = match locale {
+        Locale::Ru => "Файл отклонён: обнаружено вредоносное содержимое",
+        Locale::En => "file-infected",
+    };
+    Err(HttpResponse::UnprocessableEntity().json(json!({ "error": error_msg })))
+}
+
+pub async fn list_conversation_files(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ConversationFilesQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+
+    let kinds: Vec<String> = query
+        .kind
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(|k| k.trim().to_lowercase())
+        .filter(|k| !k.is_empty())
+        .collect();
+    if kinds.iter().any(|k| !FILE_KINDS.contains(&k.as_str())) {
+        let error_msg = match locale {
+            Locale::Ru => "Неизвестный тип файла",
+            Locale::En => "invalid-file-type",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "allowed": FILE_KINDS }));
+    }
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    if conversation_deleted(pool, &conversation_id).await {
+        let error_msg = match locale {
+            Locale::Ru => "Разговор не найден",
+            Locale::En => "conversation-not-found",
+        };
+        return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+    }
+
+    let mut filtered = format!(
+        "SELECT f.id, f.filename, f.mime, f.size, f.created_at, f.deleted_at, f.message_id, m.role, {} AS kind
+         FROM files f JOIN messages m ON m.id = f.message_id
+         WHERE m.conversation_id = ? AND f.deleted_at {}",
+        FILE_KIND_SQL,
+        if query.trashed { "IS NOT NULL" } else { "IS NULL" }
+    );
+    if !kinds.is_empty() {
+        filtered.push_str(&format!(" AND {} IN ({})", FILE_KIND_SQL, vec!["?"; kinds.len()].join(", ")));
+    }
+
+    let count_sql = format!("SELECT COUNT(*) FROM ({})", filtered);
+    let mut count_q = sqlx::query_scalar::<_, i64>(&count_sql).bind(&conversation_id);
+    for k in &kinds {
+        count_q = count_q.bind(k);
+    }
+    let total = match count_q.fetch_one(pool).await {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let page_sql = format!("{} ORDER BY f.created_at DESC, f.id LIMIT ? OFFSET ?", filtered);
+    let mut page_q = sqlx::query(&page_sql).bind(&conversation_id);
+    for k in &kinds {
+        page_q = page_q.bind(k);
+    }
+    let rows = page_q.bind(limit).bind(offset).fetch_all(pool).await;
+
+    match rows {
+        Ok(rs) => {
+            let files: Vec<ConversationFile> = rs.into_iter().map(|r| {
+                let id: String = r.get("id");
+                let role: String = r.get("role");
+                ConversationFile {
+                    download_url: format!("/api/files/{}", id),
+                    id,
+                    filename: r.get("filename"),
+                    mime: r.get("mime"),
+                    kind: r.get("kind"),
+                    size: r.get("size"),
+                    source: if role == "user" { "uploaded" } else { "generated" }.to_string(),
+                    message_id: r.get("message_id"),
+                    created_at: r.get("created_at"),
+                    deleted_at: r.try_get("deleted_at").ok().flatten(),
+                }
+            }).collect();
+            HttpResponse::Ok().json(json!({
+                "conversation_id": conversation_id,
+                "files": files,
+                "total": total,
+                "limit": limit,
+                "offset": offset,
+                "has_more": offset + (files.len() as i64) < total,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Owner of a file: `files.user_id`, or for rows stored before it was recorded, through the
+/// conversation for chat attachments and through the invoice for invoice PDFs
+async fn file_owner(pool: &sqlx::SqlitePool, file_id: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT user_id FROM files WHERE id = ? AND user_id IS NOT NULL
+         UNION ALL
+         SELECT * FROM (
+             SELECT c.user_id FROM files f
+             JOIN messages m ON m.id = f.message_id
+             JOIN conversations c ON c.id = m.conversation_id
+             WHERE f.id = ? AND f.user_id IS NULL
+             UNION
+             SELECT i.user_id FROM invoices i JOIN files f ON f.id = i.file_id
+             WHERE i.file_id = ? AND f.user_id IS NULL
+         )
+         LIMIT 1"
+    )
+    .bind(file_id)
+    .bind(file_id)
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Moves a file to the trash (`trash = true`) or back out of it
+async fn set_trashed(req: HttpRequest, file_id: String, body: FileOwner, state: &AppState, trash: bool) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+    match file_owner(pool, &file_id).await {
+        Ok(Some(owner)) if owner == resolved_user_id => {}
+        Ok(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Файл не найден или не принадлежит пользователю",
+                Locale::En => "file-not-found-or-not-owned",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let result = if trash {
+        sqlx::query("UPDATE files SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(&file_id)
+            .execute(pool)
+            .await
+    } else {
+        sqlx::query("UPDATE files SET deleted_at = NULL WHERE id = ?")
+            .bind(&file_id)
+            .execute(pool)
+            .await
+    };
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let deleted_at: Option<String> = sqlx::query_scalar("SELECT deleted_at FROM files WHERE id = ?")
+        .bind(&file_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    let purge_at = deleted_at.as_deref()
+        .and_then(|d| chrono::DateTime::parse_from_rfc3339(d).ok())
+        .map(|d| (d + chrono::Duration::days(TRASH_RETENTION_DAYS)).to_rfc3339());
+
+    HttpResponse::Ok().json(json!({
+        "status": if trash { "trashed" } else { "restored" },
+        "file_id": file_id,
+        "deleted_at": deleted_at,
+        "purge_at": purge_at,
+    }))
+}
+
+pub async fn delete_file(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FileOwner>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    set_trashed(req, path.into_inner(), body.into_inner(), &state, true).await
+}
+
+pub async fn restore_file(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FileOwner>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    set_trashed(req, path.into_inner(), body.into_inner(), &state, false).await
+}
+
+#[derive(Serialize)]
+pub struct CleanupCandidate {
+    pub id: String,
+    pub filename: String,
+    pub size: i64,
+    pub created_at: String,
+    pub download_url: String,
+}
+
+async fn cleanup_candidates(pool: &sqlx::SqlitePool, user_id: &str, order: &str) -> Result<Vec<CleanupCandidate>, sqlx::Error> {
+    // The current profile picture is never suggested for removal
+    let sql = format!(
+        "SELECT f.id, f.filename, f.size, f.created_at FROM files f
+         LEFT JOIN users u ON u.id = f.user_id
+         WHERE f.user_id = ? AND f.deleted_at IS NULL AND f.scan_status IS NOT 'quarantined' AND f.id IS NOT u.profile_picture
+         ORDER BY {} LIMIT 5",
+        order
+    );
+    let rows = sqlx::query(&sql).bind(user_id).fetch_all(pool).await?;
+    Ok(rows.into_iter().map(|r| {
+        let id: String = r.get("id");
+        CleanupCandidate {
+            download_url: format!("/api/files/{}", id),
+            id,
+            filename: r.get("filename"),
+            size: r.get("size"),
+            created_at: r.get("created_at"),
+        }
+    }).collect())
+}
+
+pub async fn get_storage_usage(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let caller = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let user_id = resolve_user_id_for_conversations(pool, &path.into_inner()).await;
+    if user_id != caller {
+        let error_msg = match locale {
+            Locale::Ru => "Доступ запрещен",
+            Locale::En => "forbidden",
+        };
+        return HttpResponse::Forbidden().json(json!({ "error": error_msg }));
+    }
+
+    let (used, breakdown, largest, oldest) = match (
+        storage::used_bytes(pool, &user_id).await,
+        storage::breakdown(pool, &user_id).await,
+        cleanup_candidates(pool, &user_id, "f.size DESC").await,
+        cleanup_candidates(pool, &user_id, "f.created_at ASC").await,
+    ) {
+        (Ok(u), Ok(b), Ok(l), Ok(o)) => (u, b, l, o),
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+    let plan = storage::user_plan(pool, &user_id).await;
+    let quota = state.config.load().storage.quota_bytes(&plan);
+
+    HttpResponse::Ok().json(json!({
+        "user_id": user_id,
+        "plan": plan,
+        "used_bytes": used,
+        "quota_bytes": quota,
+        "available_bytes": (quota - used).max(0),
+        "breakdown": breakdown,
+        "suggestions": {
+            "largest": largest,
+            "oldest": oldest,
+        },
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct OrphanPurgeQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// `POST /api/admin/storage/orphans/purge[?dry_run=true]` removes files whose message is gone and
+/// blobs no file references any more
+pub async fn purge_orphaned_files(
+    req: HttpRequest,
+    query: web::Query<OrphanPurgeQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    match storage::purge_orphans(&state.pool, query.dry_run).await {
+        Ok(report) => HttpResponse::Ok().json(json!({ "dry_run": query.dry_run, "purged": report })),
+        Err(e) => {
+            eprintln!("Orphaned file purge failed: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Upper bound on files per bundle so one request can't pin a worker for long
+const MAX_BUNDLE_FILES: usize = 50;
+
+#[derive(Deserialize)]
+pub struct BundleRequest {
+    pub user_id: String,
+    pub file_ids: Vec<String>,
+}
+
+/// Queues a ZIP of the given files; the archive is built in the background and polled via `get_bundle`
+pub async fn create_bundle(
+    req: HttpRequest,
+    body: web::Json<BundleRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let body = body.into_inner();
+
+    let mut file_ids: Vec<String> = Vec::new();
+    for id in body.file_ids {
+        if !file_ids.contains(&id) {
+            file_ids.push(id);
+        }
+    }
+    if file_ids.is_empty() || file_ids.len() > MAX_BUNDLE_FILES {
+        let error_msg = match locale {
+            Locale::Ru => "Укажите от 1 до 50 файлов",
+            Locale::En => "invalid-file-count",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "max_files": MAX_BUNDLE_FILES }));
+    }
+
+    let user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+    let mut total_size: i64 = 0;
+    for id in &file_ids {
+        let size: Option<i64> = match sqlx::query_scalar(
+            "SELECT size FROM files WHERE id = ? AND user_id = ? AND deleted_at IS NULL AND scan_status IS NOT 'quarantined'"
+        )
+        .bind(id)
+        .bind(&user_id)
+        .fetch_optional(pool)
+        .await
+        {
+            Ok(s) => s,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        match size {
+            Some(s) => total_size += s,
+            None => {
+                let error_msg = match locale {
+                    Locale::Ru => "Файл не найден или не принадлежит пользователю",
+                    Locale::En => "file-not-found-or-not-owned",
+                };
+                return HttpResponse::NotFound().json(json!({ "error": error_msg, "file_id": id }));
+            }
+        }
+    }
+
+    // Compression rarely helps much for xlsx/pdf, so budget for the uncompressed size
+    if let Err(resp) = ensure_storage_quota(&state, &user_id, total_size as usize, locale).await {
+        return resp;
+    }
+
+    let job_id = uuid::Uuid::new_v4().to_string();
+    let inserted = sqlx::query(
+        "INSERT INTO bundle_jobs (id, user_id, file_ids, status, created_at) VALUES (?, ?, ?, 'pending', ?)"
+    )
+    .bind(&job_id)
+    .bind(&user_id)
+    .bind(serde_json::to_string(&file_ids).unwrap_or_default())
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // The scheduler retries the job if this task dies before finishing
+    let job_pool = pool.clone();
+    let spawned_id = job_id.clone();
+    actix_web::rt::spawn(async move {
+        bundle::process(&job_pool, &spawned_id).await;
+    });
+
+    HttpResponse::Accepted().json(json!({
+        "job_id": job_id,
+        "status": "pending",
+        "file_count": file_ids.len(),
+        "status_url": format!("/api/files/bundle/{}?user_id={}", job_id, user_id),
+    }))
+}
+
+pub async fn get_bundle(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<FileOwner>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let job_id = path.into_inner();
+    let user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    let row = match sqlx::query(
+        "SELECT status, file_id, error, created_at, completed_at FROM bundle_jobs WHERE id = ? AND user_id = ?"
+    )
+    .bind(&job_id)
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Задача не найдена",
+                Locale::En => "bundle-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let file_id: Option<String> = row.get("file_id");
+    HttpResponse::Ok().json(json!({
+        "job_id": job_id,
+        "status": row.get::<String, _>("status"),
+        "file_id": file_id,
+        "download_url": file_id.as_ref().map(|id| format!("/api/files/{}", id)),
+        "error": row.get::<Option<String>, _>("error"),
+        "created_at": row.get::<String, _>("created_at"),
+        "completed_at": row.get::<Option<String>, _>("completed_at"),
+    }))
+}
@@ -0,0 +1,296 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct InventoryItemUpsert {
+    pub name: Option<String>,
+    pub sku: Option<String>,
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+    pub low_stock_threshold: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct InventoryItem {
+    pub id: String,
+    pub name: String,
+    pub sku: Option<String>,
+    pub quantity: f64,
+    pub unit: Option<String>,
+    pub low_stock_threshold: Option<f64>,
+    pub is_low: bool,
+    pub updated_at: String,
+}
+
+const ITEM_COLUMNS: &str = "id, name, sku, quantity, unit, low_stock_threshold, updated_at";
+
+fn item_from_row(r: &sqlx::sqlite::SqliteRow) -> InventoryItem {
+    let quantity: f64 = r.get("quantity");
+    let threshold: Option<f64> = r.try_get("low_stock_threshold").ok().flatten();
+    InventoryItem {
+        id: r.get("id"),
+        name: r.get("name"),
+        sku: r.try_get("sku").ok().flatten(),
+        quantity,
+        unit: r.try_get("unit").ok().flatten(),
+        low_stock_threshold: threshold,
+        is_low: threshold.is_some_and(|t| quantity <= t),
+        updated_at: r.get("updated_at"),
+    }
+}
+
+fn item_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Товар не найден",
+        Locale::En => "item-not-found",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+pub async fn list_items(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM inventory_items WHERE user_id = ? ORDER BY name COLLATE NOCASE",
+        ITEM_COLUMNS
+    ))
+    .bind(&user_id)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let items: Vec<InventoryItem> = rs.iter().map(item_from_row).collect();
+            let low_stock = items.iter().filter(|i| i.is_low).count();
+            HttpResponse::Ok().json(json!({ "items": items, "low_stock": low_stock }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn create_item(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    body: web::Json<InventoryItemUpsert>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let data = body.into_inner();
+
+    let name = match data.name.as_deref().map(str::trim) {
+        Some(n) if !n.is_empty() => n.to_string(),
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Требуется название товара",
+                Locale::En => "name-required",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        "INSERT INTO inventory_items (id, user_id, name, sku, quantity, unit, low_stock_threshold, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&user_id)
+    .bind(&name)
+    .bind(&data.sku)
+    .bind(data.quantity.unwrap_or(0.0))
+    .bind(&data.unit)
+    .bind(data.low_stock_threshold)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await;
+
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let quantity = data.quantity.unwrap_or(0.0);
+    HttpResponse::Created().json(InventoryItem {
+        id,
+        name,
+        sku: data.sku,
+        quantity,
+        unit: data.unit,
+        low_stock_threshold: data.low_stock_threshold,
+        is_low: data.low_stock_threshold.is_some_and(|t| quantity <= t),
+        updated_at: now,
+    })
+}
+
+pub async fn update_item(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    body: web::Json<InventoryItemUpsert>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let item_id = path.into_inner();
+    let data = body.into_inner();
+
+    // Restocking above the threshold re-arms the low-stock alert
+    let result = sqlx::query(
+        "UPDATE inventory_items SET
+            name = COALESCE(?, name),
+            sku = COALESCE(?, sku),
+            quantity = COALESCE(?, quantity),
+            unit = COALESCE(?, unit),
+            low_stock_threshold = COALESCE(?, low_stock_threshold),
+            updated_at = ?
+         WHERE id = ? AND user_id = ?"
+    )
+    .bind(data.name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(&data.sku)
+    .bind(data.quantity)
+    .bind(&data.unit)
+    .bind(data.low_stock_threshold)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&item_id)
+    .bind(&user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => return item_not_found(locale),
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let _ = sqlx::query(
+        "UPDATE inventory_items SET low_stock_notified_at = NULL
+         WHERE id = ? AND (low_stock_threshold IS NULL OR quantity > low_stock_threshold)"
+    )
+    .bind(&item_id)
+    .execute(pool)
+    .await;
+
+    let row = sqlx::query(&format!("SELECT {} FROM inventory_items WHERE id = ?", ITEM_COLUMNS))
+        .bind(&item_id)
+        .fetch_optional(pool)
+        .await;
+
+    match row {
+        Ok(Some(r)) => HttpResponse::Ok().json(item_from_row(&r)),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn delete_item(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let item_id = path.into_inner();
+
+    let result = sqlx::query("DELETE FROM inventory_items WHERE id = ? AND user_id = ?")
+        .bind(&item_id)
+        .bind(&user_id)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => item_not_found(locale),
+        Ok(_) => HttpResponse::Ok().json(json!({ "status": "deleted", "id": item_id })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// Builds a system note with the user's real stock levels so the assistant
+/// can answer "what's running low?" without guessing. None if the user has no inventory.
+pub(crate) async fn inventory_prompt_note(
+    pool: &sqlx::SqlitePool,
+    user_id: &str,
+    locale: Locale,
+) -> Option<String> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM inventory_items WHERE user_id = ? ORDER BY name COLLATE NOCASE LIMIT 200",
+        ITEM_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .ok()?;
+
+    if rows.is_empty() {
+        return None;
+    }
+
+    let items: Vec<InventoryItem> = rows.iter().map(item_from_row).collect();
+    let low: Vec<String> = items
+        .iter()
+        .filter(|i| i.is_low)
+        .map(|i| {
+            format!(
+                "- {}: {} {} (threshold {})",
+                i.name,
+                i.quantity,
+                i.unit.as_deref().unwrap_or(""),
+                i.low_stock_threshold.unwrap_or_default()
+            )
+        })
+        .collect();
+
+    let mut note = match locale {
+        Locale::Ru => format!(
+            "Данные склада пользователя (реальные, используй их при вопросах об остатках). Всего позиций: {}. ",
+            items.len()
+        ),
+        Locale::En => format!(
+            "The user's real inventory data (use it for any stock questions). Total items: {}. ",
+            items.len()
+        ),
+    };
+    if low.is_empty() {
+        note.push_str(match locale {
+            Locale::Ru => "Ни одна позиция не ниже порога.",
+            Locale::En => "No items are below their low-stock threshold.",
+        });
+    } else {
+        note.push_str(match locale {
+            Locale::Ru => "Заканчиваются:\n",
+            Locale::En => "Running low:\n",
+        });
+        note.push_str(&low.join("\n"));
+    }
+
+    Some(note)
+}
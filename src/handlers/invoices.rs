@@ -0,0 +1,287 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::files::{ensure_storage_quota, store_file};
+use crate::services::pdf::{self, PdfLine};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct InvoiceLineItem {
+    pub description: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateInvoiceRequest {
+    pub buyer_name: String,
+    pub buyer_details: Option<String>,
+    pub currency: Option<String>,
+    pub items: Vec<InvoiceLineItem>,
+    pub due_date: Option<String>,
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct InvoiceSummary {
+    pub id: String,
+    pub number: i64,
+    pub buyer_name: String,
+    pub currency: String,
+    pub total: f64,
+    pub issued_at: String,
+    pub status: String,
+    pub paid_at: Option<String>,
+    pub download_url: Option<String>,
+}
+
+struct Seller {
+    name: String,
+    business_type: String,
+    email: String,
+    phone: Option<String>,
+    country: Option<String>,
+}
+
+pub async fn create_invoice(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    body: web::Json<CreateInvoiceRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let data = body.into_inner();
+
+    let items_valid = !data.items.is_empty()
+        && data.items.iter().all(|i| !i.description.trim().is_empty() && i.quantity > 0.0 && i.unit_price >= 0.0);
+    if data.buyer_name.trim().is_empty() || !items_valid {
+        let error_msg = match locale {
+            Locale::Ru => "Требуются покупатель и хотя бы одна корректная позиция",
+            Locale::En => "buyer-and-line-items-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    // Seller details come from the business profile
+    let seller = match sqlx::query(
+        "SELECT email, business_type, full_name, phone, country FROM users WHERE id = ?"
+    )
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => {
+            let email: String = r.get("email");
+            Seller {
+                name: r.try_get::<Option<String>, _>("full_name").unwrap_or(None).unwrap_or_else(|| email.clone()),
+                business_type: r.get("business_type"),
+                email,
+                phone: r.try_get("phone").ok().flatten(),
+                country: r.try_get("country").ok().flatten(),
+            }
+        }
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let currency = data.currency.clone().unwrap_or_else(|| match locale {
+        Locale::Ru => "RUB".to_string(),
+        Locale::En => "USD".to_string(),
+    });
+    let total: f64 = data.items.iter().map(|i| i.quantity * i.unit_price).sum();
+
+    // Reserve the next sequential number; UNIQUE(user_id, number) guards concurrent issues
+    let invoice_id = Uuid::new_v4().to_string();
+    let issued_at = chrono::Utc::now().to_rfc3339();
+    let number: i64 = {
+        let mut tx = match pool.begin().await {
+            Ok(tx) => tx,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        let next: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(number), 0) + 1 FROM invoices WHERE user_id = ?")
+            .bind(&user_id)
+            .fetch_one(&mut tx)
+            .await
+            .unwrap_or(1);
+        let inserted = sqlx::query(
+            "INSERT INTO invoices (id, user_id, number, buyer_name, currency, total, issued_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&invoice_id)
+        .bind(&user_id)
+        .bind(next)
+        .bind(data.buyer_name.trim())
+        .bind(&currency)
+        .bind(total)
+        .bind(&issued_at)
+        .execute(&mut tx)
+        .await;
+        if inserted.is_err() || tx.commit().await.is_err() {
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось выставить счет",
+                Locale::En => "invoice-create-failed",
+            };
+            return HttpResponse::InternalServerError().json(json!({ "error": error_msg }));
+        }
+        next
+    };
+
+    let lines = invoice_lines(locale, number, &seller, &data, &currency, total, &issued_at[..10]);
+    let title = match locale {
+        Locale::Ru => format!("Счет № {}", number),
+        Locale::En => format!("Invoice #{}", number),
+    };
+    let bytes = match pdf::render_lines(&title, &lines) {
+        Ok(b) => b,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Err(resp) = ensure_storage_quota(&state, &user_id, bytes.len(), locale).await {
+        return resp;
+    }
+
+    let attachment = match store_file(pool, format!("invoice-{:05}.pdf", number), "application/pdf".to_string(), bytes, None, Some(&user_id)).await {
+        Ok(att) => att,
+        Err(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка сохранения файла",
+                Locale::En => "file-save-failed",
+            };
+            return HttpResponse::InternalServerError().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let _ = sqlx::query("UPDATE invoices SET file_id = ? WHERE id = ?")
+        .bind(&attachment.id)
+        .bind(&invoice_id)
+        .execute(pool)
+        .await;
+
+    HttpResponse::Created().json(json!({
+        "invoice": InvoiceSummary {
+            id: invoice_id,
+            number,
+            buyer_name: data.buyer_name.trim().to_string(),
+            currency,
+            total,
+            issued_at,
+            status: "issued".to_string(),
+            paid_at: None,
+            download_url: attachment.download_url.clone(),
+        },
+        "file": attachment,
+    }))
+}
+
+pub async fn list_invoices(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(
+        "SELECT id, number, buyer_name, currency, total, issued_at, status, paid_at, file_id FROM invoices WHERE user_id = ? ORDER BY number DESC"
+    )
+    .bind(&user_id)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let invoices: Vec<InvoiceSummary> = rs.into_iter().map(|r| InvoiceSummary {
+                id: r.get("id"),
+                number: r.get("number"),
+                buyer_name: r.get("buyer_name"),
+                currency: r.get("currency"),
+                total: r.get("total"),
+                issued_at: r.get("issued_at"),
+                status: r.get("status"),
+                paid_at: r.try_get("paid_at").ok().flatten(),
+                download_url: r
+                    .try_get::<Option<String>, _>("file_id")
+                    .unwrap_or(None)
+                    .map(|id| format!("/api/files/{}", id)),
+            }).collect();
+            HttpResponse::Ok().json(json!({ "invoices": invoices }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+fn invoice_lines(
+    locale: Locale,
+    number: i64,
+    seller: &Seller,
+    data: &CreateInvoiceRequest,
+    currency: &str,
+    total: f64,
+    issued_on: &str,
+) -> Vec<PdfLine> {
+    let (l_title, l_date, l_due, l_seller, l_buyer, l_items, l_total, l_notes) = match locale {
+        Locale::Ru => ("Счет №", "Дата", "Оплатить до", "Продавец", "Покупатель", "Позиции", "Итого", "Примечание"),
+        Locale::En => ("Invoice #", "Date", "Due date", "Seller", "Bill to", "Items", "Total", "Notes"),
+    };
+
+    let mut lines = vec![
+        PdfLine::heading(format!("{} {}", l_title, number)),
+        PdfLine::text(format!("{}: {}", l_date, issued_on)),
+    ];
+    if let Some(ref due) = data.due_date {
+        lines.push(PdfLine::text(format!("{}: {}", l_due, due)));
+    }
+
+    lines.push(PdfLine::blank());
+    lines.push(PdfLine::text(format!("{}: {} ({})", l_seller, seller.name, seller.business_type)));
+    let contacts: Vec<&str> = [Some(seller.email.as_str()), seller.phone.as_deref(), seller.country.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect();
+    lines.push(PdfLine { text: contacts.join(", "), size: 10.0, indent: 5.0 });
+
+    lines.push(PdfLine::text(format!("{}: {}", l_buyer, data.buyer_name.trim())));
+    if let Some(ref details) = data.buyer_details {
+        lines.push(PdfLine { text: details.clone(), size: 10.0, indent: 5.0 });
+    }
+
+    lines.push(PdfLine::blank());
+    lines.push(PdfLine::text(format!("{}:", l_items)));
+    for (idx, item) in data.items.iter().enumerate() {
+        lines.push(PdfLine {
+            text: format!(
+                "{}. {} — {} × {:.2} = {:.2} {}",
+                idx + 1,
+                item.description.trim(),
+                item.quantity,
+                item.unit_price,
+                item.quantity * item.unit_price,
+                currency
+            ),
+            size: 10.0,
+            indent: 5.0,
+        });
+    }
+
+    lines.push(PdfLine::blank());
+    lines.push(PdfLine { text: format!("{}: {:.2} {}", l_total, total, currency), size: 12.0, indent: 0.0 });
+
+    if let Some(ref notes) = data.notes {
+        lines.push(PdfLine::blank());
+        lines.push(PdfLine::text(format!("{}: {}", l_notes, notes)));
+    }
+
+    lines
+}
@@ -0,0 +1,200 @@
+use actix_multipart::Multipart;
+use actix_web::{HttpRequest, HttpResponse, web};
+use futures_util::TryStreamExt;
+use serde_json::json;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::handlers::{files, limits};
+use crate::services::{extract, knowledge};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Largest document accepted into the knowledge base
+const MAX_DOCUMENT_SIZE: usize = 20 * 1024 * 1024;
+/// Text indexed per document; the rest of a very long document is ignored
+const MAX_DOCUMENT_CHARS: usize = 300_000;
+
+async fn caller(req: &HttpRequest, state: &AppState, query: &TokenCheck, locale: Locale) -> Result<String, HttpResponse> {
+    let user_id = authorize(req, &state.pool, query, locale).await?;
+    Ok(resolve_user_id_for_conversations(&state.pool, &user_id).await)
+}
+
+/// `POST /api/knowledge/documents` stores a PDF or text document and indexes it for chat answers
+pub async fn upload_document(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    mut payload: Multipart,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let mut upload: Option<(String, String, Vec<u8>)> = None;
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let name = field.name().to_string();
+        if name == "file" && upload.is_some() {
+            return limits::too_many_files(locale, 1);
+        }
+        let filename = field.content_disposition().get_filename().map(|f| f.to_string());
+        let mime = field.content_type().map(|m| m.to_string()).unwrap_or_default();
+        let limit = if name == "file" { MAX_DOCUMENT_SIZE } else { limits::MAX_FORM_FIELD };
+        let bytes = match limits::read_field(&mut field, limit).await {
+            Some(b) => b,
+            None if name == "file" => {
+                let error_msg = match locale {
+                    Locale::Ru => "Файл слишком большой (максимум 20MB)",
+                    Locale::En => "file-too-large-max-20mb",
+                };
+                return HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg }));
+            }
+            None => return limits::payload_too_large(locale, limit),
+        };
+        if name == "file" && !bytes.is_empty() {
+            upload = Some((filename.unwrap_or_else(|| "document".to_string()), mime, bytes));
+        }
+    }
+
+    let (filename, mime, bytes) = match upload {
+        Some(u) => u,
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Файл не предоставлен",
+                Locale::En => "no-file-provided",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    if let Err(resp) = files::ensure_storage_quota(&state, &user_id, bytes.len(), locale).await {
+        return resp;
+    }
+    if let Err(resp) = files::scan_upload(&state, &user_id, &filename, &mime, &bytes, locale).await {
+        return resp;
+    }
+
+    // PDF parsing is CPU-bound; keep it off the async workers
+    let text = {
+        let (mime, bytes) = (mime.clone(), bytes.clone());
+        web::block(move || extract::extract_text_up_to(&mime, &bytes, MAX_DOCUMENT_CHARS)).await.ok().flatten()
+    };
+    let text = match text {
+        Some(t) => t,
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Поддерживаются PDF и текстовые документы с извлекаемым текстом",
+                Locale::En => "document-has-no-text",
+            };
+            return HttpResponse::UnprocessableEntity().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let file_id = match files::store_file(&state.pool, filename.clone(), mime.clone(), bytes, None, Some(&user_id)).await {
+        Ok(att) => att.id.unwrap_or_default(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match knowledge::index_document(&state.pool, &user_id, &file_id, &filename, &mime, &text).await {
+        Ok(doc) => HttpResponse::Created().json(doc),
+        Err(e) => {
+            eprintln!("Knowledge base: indexing {} failed: {}", file_id, e);
+            // The stored file would otherwise count against the quota without being searchable
+            let _ = sqlx::query("UPDATE files SET deleted_at = ? WHERE id = ?")
+                .bind(chrono::Utc::now().to_rfc3339())
+                .bind(&file_id)
+                .execute(&state.pool)
+                .await;
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось проиндексировать документ, попробуйте позже",
+                Locale::En => "indexing-failed",
+            };
+            HttpResponse::BadGateway().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+/// `GET /api/knowledge/documents` lists the caller's indexed documents, newest first
+pub async fn list_documents(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(
+        "SELECT id, file_id, filename, mime, chunk_count, embedding_model, created_at
+         FROM knowledge_documents WHERE user_id = ? ORDER BY created_at DESC"
+    )
+    .bind(&user_id)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let documents: Vec<_> = rs.iter().map(knowledge::from_row).collect();
+            HttpResponse::Ok().json(json!({ "documents": documents }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `DELETE /api/knowledge/documents/{id}` removes the document from search and moves its file to the trash
+pub async fn delete_document(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let document_id = path.into_inner();
+    let pool = &state.pool;
+
+    let result: Result<Option<String>, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM embeddings WHERE document_id = ? AND user_id = ?")
+            .bind(&document_id)
+            .bind(&user_id)
+            .execute(&mut tx)
+            .await?;
+        let file_id: Option<String> = sqlx::query_scalar(
+            "DELETE FROM knowledge_documents WHERE id = ? AND user_id = ? RETURNING file_id"
+        )
+        .bind(&document_id)
+        .bind(&user_id)
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some(file_id) = file_id.as_ref() {
+            sqlx::query("UPDATE files SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL")
+                .bind(chrono::Utc::now().to_rfc3339())
+                .bind(file_id)
+                .execute(&mut tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(file_id)
+    }
+    .await;
+
+    match result {
+        Ok(Some(_)) => HttpResponse::Ok().json(json!({ "status": "deleted", "document_id": document_id })),
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Документ не найден",
+                Locale::En => "document-not-found",
+            };
+            HttpResponse::NotFound().json(json!({ "error": error_msg }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,378 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_multipart::Multipart;
+use futures_util::TryStreamExt;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::Row;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::chat::generate_file_and_store;
+use crate::handlers::files::{ensure_storage_quota, scan_upload};
+use crate::handlers::limits;
+use crate::models::TableSpec;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+const LEAD_FIELDS: [&str; 5] = ["name", "email", "phone", "company", "notes"];
+const MAX_IMPORT_SIZE: usize = 5 * 1024 * 1024;
+
+#[derive(Serialize)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub error: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    pub total_rows: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+#[derive(Default)]
+struct LeadRow {
+    name: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    company: Option<String>,
+    notes: Option<String>,
+}
+
+pub async fn import_leads(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    mut payload: Multipart,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    // "file" carries the CSV, optional "mapping" is a JSON object {lead_field: csv_column}
+    let mut file_data: Option<Vec<u8>> = None;
+    let mut mapping: HashMap<String, String> = HashMap::new();
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let name = field.name().to_string();
+        if name == "file" && file_data.is_some() {
+            return limits::too_many_files(locale, 1);
+        }
+        let limit = if name == "file" { MAX_IMPORT_SIZE } else { limits::MAX_FORM_FIELD };
+        let bytes = match limits::read_field(&mut field, limit).await {
+            Some(b) => b,
+            None if name == "file" => {
+                let error_msg = match locale {
+                    Locale::Ru => "Файл слишком большой (максимум 5MB)",
+                    Locale::En => "file-too-large-max-5mb",
+                };
+                return HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg }));
+            }
+            None => return limits::payload_too_large(locale, limit),
+        };
+
+        match name.as_str() {
+            "file" if !bytes.is_empty() => file_data = Some(bytes),
+            "mapping" => {
+                match serde_json::from_slice::<HashMap<String, String>>(&bytes) {
+                    Ok(m) => mapping = m,
+                    Err(_) => {
+                        let error_msg = match locale {
+                            Locale::Ru => "Некорректное сопоставление колонок",
+                            Locale::En => "invalid-column-mapping",
+                        };
+                        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(bytes) = &file_data {
+        if let Err(resp) = scan_upload(&state, &user_id, "leads-import.csv", "text/csv", bytes, locale).await {
+            return resp;
+        }
+    }
+
+    let text = match file_data.map(String::from_utf8) {
+        Some(Ok(t)) => t,
+        Some(Err(_)) => {
+            let error_msg = match locale {
+                Locale::Ru => "Файл должен быть в кодировке UTF-8",
+                Locale::En => "file-must-be-utf8",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Файл не предоставлен",
+                Locale::En => "no-file-provided",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let mut records = parse_csv(text.trim_start_matches('\u{feff}')).into_iter();
+    let headers: Vec<String> = match records.next() {
+        Some(h) => h.into_iter().map(|s| s.trim().to_lowercase()).collect(),
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Пустой CSV файл",
+                Locale::En => "empty-csv",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    // Map each lead field to a column index: explicit mapping first, then same-named header
+    let mut columns: HashMap<&str, usize> = HashMap::new();
+    for field in LEAD_FIELDS {
+        let source = mapping
+            .get(field)
+            .map(|s| s.trim().to_lowercase())
+            .unwrap_or_else(|| field.to_string());
+        if let Some(idx) = headers.iter().position(|h| *h == source) {
+            columns.insert(field, idx);
+        }
+    }
+
+    if !columns.contains_key("email") && !columns.contains_key("phone") {
+        let error_msg = match locale {
+            Locale::Ru => "В файле нет колонок email или phone",
+            Locale::En => "missing-email-or-phone-column",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    // Existing contacts of this user, used for deduplication
+    let existing = sqlx::query("SELECT email, phone FROM leads WHERE user_id = ?")
+        .bind(&user_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let mut seen_emails: HashSet<String> = HashSet::new();
+    let mut seen_phones: HashSet<String> = HashSet::new();
+    for r in existing {
+        if let Some(e) = r.try_get::<Option<String>, _>("email").unwrap_or(None) {
+            seen_emails.insert(e);
+        }
+        if let Some(p) = r.try_get::<Option<String>, _>("phone").unwrap_or(None) {
+            seen_phones.insert(p);
+        }
+    }
+
+    let mut report = ImportReport { total_rows: 0, imported: 0, duplicates: 0, errors: Vec::new() };
+
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    for (idx, record) in records.enumerate() {
+        // Row numbers are 1-based and count the header line
+        let row_no = idx + 2;
+        if record.iter().all(|c| c.trim().is_empty()) {
+            continue;
+        }
+        report.total_rows += 1;
+
+        let cell = |field: &str| -> Option<String> {
+            columns
+                .get(field)
+                .and_then(|&i| record.get(i))
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+
+        let lead = LeadRow {
+            name: cell("name"),
+            email: cell("email").map(|e| e.to_lowercase()),
+            phone: cell("phone").map(|p| normalize_phone(&p)).filter(|p| !p.is_empty()),
+            company: cell("company"),
+            notes: cell("notes"),
+        };
+
+        if lead.email.is_none() && lead.phone.is_none() {
+            report.errors.push(ImportRowError { row: row_no, error: "missing-email-and-phone" });
+            continue;
+        }
+        if let Some(ref email) = lead.email {
+            if !is_valid_email(email) {
+                report.errors.push(ImportRowError { row: row_no, error: "invalid-email" });
+                continue;
+            }
+        }
+
+        let is_duplicate = lead.email.as_ref().is_some_and(|e| seen_emails.contains(e))
+            || lead.phone.as_ref().is_some_and(|p| seen_phones.contains(p));
+        if is_duplicate {
+            report.duplicates += 1;
+            continue;
+        }
+
+        let result = sqlx::query(
+            "INSERT INTO leads (id, user_id, name, email, phone, company, notes) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&user_id)
+        .bind(&lead.name)
+        .bind(&lead.email)
+        .bind(&lead.phone)
+        .bind(&lead.company)
+        .bind(&lead.notes)
+        .execute(&mut tx)
+        .await;
+
+        if result.is_err() {
+            report.errors.push(ImportRowError { row: row_no, error: "insert-failed" });
+            continue;
+        }
+
+        if let Some(e) = lead.email {
+            seen_emails.insert(e);
+        }
+        if let Some(p) = lead.phone {
+            seen_phones.insert(p);
+        }
+        report.imported += 1;
+    }
+
+    if tx.commit().await.is_err() {
+        let error_msg = match locale {
+            Locale::Ru => "Ошибка сохранения лидов",
+            Locale::En => "leads-save-failed",
+        };
+        return HttpResponse::InternalServerError().json(json!({ "error": error_msg }));
+    }
+
+    HttpResponse::Ok().json(report)
+}
+
+pub async fn export_leads(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = match sqlx::query(
+        "SELECT name, email, phone, company, notes, created_at FROM leads WHERE user_id = ? ORDER BY datetime(created_at) ASC"
+    )
+    .bind(&user_id)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(rs) => rs,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let headers: Vec<String> = match locale {
+        Locale::Ru => vec!["Имя", "Email", "Телефон", "Компания", "Заметки", "Создан"],
+        Locale::En => vec!["Name", "Email", "Phone", "Company", "Notes", "Created at"],
+    }
+    .into_iter()
+    .map(String::from)
+    .collect();
+
+    let table_rows: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|r| {
+            let mut row: Vec<String> = LEAD_FIELDS
+                .iter()
+                .map(|f| r.try_get::<Option<String>, _>(*f).unwrap_or(None).unwrap_or_default())
+                .collect();
+            row.push(r.get::<String, _>("created_at"));
+            row
+        })
+        .collect();
+
+    if let Err(resp) = ensure_storage_quota(&state, &user_id, 0, locale).await {
+        return resp;
+    }
+
+    let table = TableSpec { headers, rows: table_rows };
+    match generate_file_and_store(pool, "xlsx", &table, None, Some(&user_id)).await {
+        Ok(att) => HttpResponse::Ok().json(json!({
+            "count": table.rows.len(),
+            "file": att,
+        })),
+        Err(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка сохранения файла",
+                Locale::En => "file-save-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
+
+/// Minimal RFC 4180 reader: quoted fields, escaped quotes, CRLF, and `;` exports from Excel
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let first_line = text.lines().next().unwrap_or("");
+    let delimiter = if first_line.matches(';').count() > first_line.matches(',').count() { ';' } else { ',' };
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+/// Keeps digits and a leading `+` so "+7 (999) 123-45-67" and "+79991234567" dedupe together
+fn normalize_phone(phone: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in phone.trim().chars().enumerate() {
+        if c.is_ascii_digit() || (i == 0 && c == '+') {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn is_valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.') && !domain.starts_with('.'),
+        None => false,
+    }
+}
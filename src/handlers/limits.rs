@@ -0,0 +1,58 @@
+use actix_multipart::Field;
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::TryStreamExt;
+use serde_json::json;
+
+use crate::handlers::chat::{MAX_ATTACHMENTS, MAX_INLINE_IMAGE};
+use crate::i18n::{self, Locale};
+
+/// JSON bodies everywhere except the chat message routes
+pub const MAX_JSON_BODY: usize = 2 * 1024 * 1024;
+
+/// Chat messages may carry `MAX_ATTACHMENTS` base64 images, a third larger than the bytes
+pub const MAX_CHAT_JSON_BODY: usize = MAX_ATTACHMENTS * MAX_INLINE_IMAGE / 3 * 4 + 256 * 1024;
+
+/// Non-file multipart fields (JSON options, mappings) are small
+pub const MAX_FORM_FIELD: usize = 64 * 1024;
+
+pub fn payload_too_large(locale: Locale, limit: usize) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Слишком большой запрос",
+        Locale::En => "payload-too-large",
+    };
+    HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg, "max_bytes": limit }))
+}
+
+pub fn too_many_files(locale: Locale, max: usize) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Слишком много файлов в запросе",
+        Locale::En => "too-many-files",
+    };
+    HttpResponse::PayloadTooLarge().json(json!({ "error": error_msg, "max_files": max }))
+}
+
+/// Localized 413 instead of actix's plain-text one when a JSON body is over the limit
+fn json_error(err: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    if let JsonPayloadError::Overflow { limit } | JsonPayloadError::OverflowKnownLength { limit, .. } = err {
+        let resp = payload_too_large(i18n::detect_locale(req), limit);
+        return InternalError::from_response(err, resp).into();
+    }
+    err.into()
+}
+
+pub fn json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default().limit(limit).error_handler(json_error)
+}
+
+/// Reads a multipart field into memory, giving up with `None` as soon as it grows past `limit`
+pub async fn read_field(field: &mut Field, limit: usize) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    while let Ok(Some(chunk)) = field.try_next().await {
+        if bytes.len() + chunk.len() > limit {
+            return None;
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Some(bytes)
+}
@@ -5,10 +5,38 @@ pub mod analytics;
 pub mod legal;
 pub mod files;
 pub mod telegram;
+pub mod leads;
+pub mod bookings;
+pub mod inventory;
+pub mod invoices;
+pub mod payments;
+pub mod admin;
+pub mod stats;
+pub mod ws;
+pub mod uploads;
+pub mod support;
+pub mod faq;
+pub mod feedback;
+pub mod share;
+pub mod limits;
+pub mod category_models;
+pub mod quality;
+pub mod knowledge;
+pub mod reference;
+pub mod reads;
+pub mod drafts;
+pub mod oauth;
+pub mod otp;
+pub mod onboarding;
+pub mod preferences;
+pub mod widgets;
+pub mod roadmap;
 
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use serde_json::json;
 
+use crate::state::AppState;
+
 pub async fn main() -> HttpResponse {
     HttpResponse::Ok()
         .content_type("text/html")
@@ -21,4 +49,14 @@ pub async fn health_check() -> HttpResponse {
         "timestamp": chrono::Utc::now().to_rfc3339(),
         "version": "1.0.0"
     }))
-}
\ No newline at end of file
+}
+
+/// Startup self-check result; 503 while the schema or required env vars are off
+pub async fn readiness(state: web::Data<AppState>) -> HttpResponse {
+    let report = state.readiness.as_ref();
+    if report.ready {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::ServiceUnavailable().json(report)
+    }
+}
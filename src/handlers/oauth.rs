@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::auth::{create_session, too_many_requests, ClientInfo};
+use crate::i18n::{self, Locale};
+use crate::services::oauth::{self, OAuthError, Provider};
+use crate::services::{abuse, geoip, timezone};
+use crate::state::AppState;
+
+const OAUTH_PER_15_MINUTES: usize = 20;
+
+#[derive(Deserialize)]
+pub struct OAuthRequest {
+    /// ID token from the provider's SDK
+    pub id_token: String,
+    /// Nonce the client passed to the provider, if any
+    pub nonce: Option<String>,
+    /// Apple shares the name only on the first sign-in, and only with the app
+    pub full_name: Option<String>,
+    /// Used only when the sign-in creates the account
+    pub business_type: Option<String>,
+    pub remember_me: Option<bool>,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+}
+
+/// `POST /api/auth/oauth/{provider}` (`google` or `apple`) signs in with the provider's ID token.
+/// A known identity signs into its account; otherwise a verified email links to the account
+/// registered with it, and failing that a new account is created.
+pub async fn sign_in(
+    req: HttpRequest,
+    path: web::Path<String>,
+    data: web::Json<OAuthRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let provider = match Provider::parse(&path.into_inner()) {
+        Some(p) => p,
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string());
+    if !abuse::allow("oauth", client_ip.as_deref().unwrap_or("unknown"), OAUTH_PER_15_MINUTES, Duration::from_secs(15 * 60)) {
+        return too_many_requests(locale);
+    }
+
+    let identity = match oauth::verify(provider, &data.id_token, data.nonce.as_deref()).await {
+        Ok(i) => i,
+        Err(OAuthError::NotConfigured) => {
+            let error_msg = match locale {
+                Locale::Ru => "Вход через этого провайдера не настроен",
+                Locale::En => "oauth-provider-not-configured",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(OAuthError::InvalidToken) => {
+            let error_msg = match locale {
+                Locale::Ru => "Недействительный токен провайдера",
+                Locale::En => "invalid-id-token",
+            };
+            return HttpResponse::Unauthorized().json(json!({ "error": error_msg }));
+        }
+        Err(OAuthError::Unavailable) => {
+            let error_msg = match locale {
+                Locale::Ru => "Провайдер временно недоступен",
+                Locale::En => "oauth-provider-unavailable",
+            };
+            return HttpResponse::ServiceUnavailable().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let linked: Option<String> = match sqlx::query_scalar(
+        "SELECT user_id FROM oauth_identities WHERE provider = ? AND subject = ?"
+    )
+    .bind(provider.name())
+    .bind(&identity.subject)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let mut created = false;
+    let user_id = match linked {
+        Some(id) => id,
+        None => {
+            // Only a provider-verified address may be linked to, or claim, an account
+            let email = match identity.email.as_deref().filter(|_| identity.email_verified) {
+                Some(e) => e.to_string(),
+                None => {
+                    let error_msg = match locale {
+                        Locale::Ru => "Провайдер не подтвердил адрес почты",
+                        Locale::En => "verified-email-required",
+                    };
+                    return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+                }
+            };
+            let existing: Option<String> = match sqlx::query_scalar("SELECT id FROM users WHERE lower(email) = lower(?) LIMIT 1")
+                .bind(&email)
+                .fetch_optional(pool)
+                .await
+            {
+                Ok(id) => id,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            let user_id = match existing {
+                Some(id) => id,
+                None => match create_user(&req, pool, &email, &data).await {
+                    Ok(id) => {
+                        created = true;
+                        id
+                    }
+                    Err(_) => return HttpResponse::InternalServerError().finish(),
+                },
+            };
+            let linked = sqlx::query(
+                "INSERT INTO oauth_identities (provider, subject, user_id, email, created_at) VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT(provider, subject) DO NOTHING"
+            )
+            .bind(provider.name())
+            .bind(&identity.subject)
+            .bind(&user_id)
+            .bind(&email)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(pool)
+            .await;
+            if linked.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            user_id
+        }
+    };
+
+    let user = match sqlx::query("SELECT id, email, business_type FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let session = match create_session(
+        &state,
+        &user_id,
+        data.remember_me.unwrap_or(true),
+        data.device_id.as_deref().filter(|d| !d.is_empty()),
+        data.device_name.as_deref(),
+        &ClientInfo::from_request(&req),
+        locale,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create session for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let success_msg = match locale {
+        Locale::Ru => "Вход выполнен успешно",
+        Locale::En => "Login successful",
+    };
+    let body = json!({
+        "message": success_msg,
+        "user": {
+            "id": user.get::<String, _>("id"),
+            "email": user.get::<String, _>("email"),
+            "business_type": user.get::<String, _>("business_type")
+        },
+        "created": created,
+        "token": session.token,
+        "token_expires_at": session.token_expires_at,
+        "refresh_token": session.refresh_token,
+        "expires_at": session.expires_at
+    });
+    if created {
+        HttpResponse::Created().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
+
+/// Account for a first OAuth sign-in. Its password is a hash of a random value nobody knows, so
+/// the account opens only through the provider.
+async fn create_user(req: &HttpRequest, pool: &sqlx::SqlitePool, email: &str, data: &OAuthRequest) -> Result<String, sqlx::Error> {
+    let id = Uuid::new_v4().to_string();
+    let password = bcrypt::hash(Uuid::new_v4().to_string(), bcrypt::DEFAULT_COST).unwrap_or_default();
+    sqlx::query(
+        "INSERT INTO users (id, email, password, has_password, business_type, created_at, full_name, timezone) VALUES (?, ?, ?, 0, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(email)
+    .bind(&password)
+    .bind(data.business_type.as_deref().filter(|b| !b.is_empty()).unwrap_or("general"))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(data.full_name.as_deref().map(str::trim).filter(|n| !n.is_empty()))
+    .bind(timezone::detect(req).map(|tz| tz.name().to_string()))
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
@@ -0,0 +1,52 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::auth::AuthedUser;
+use crate::i18n::{self, Locale};
+use crate::services::onboarding;
+use crate::state::AppState;
+
+/// `GET /api/auth/onboarding` lists the onboarding steps and which are complete
+pub async fn get_onboarding(user: AuthedUser, state: web::Data<AppState>) -> HttpResponse {
+    match onboarding::state(&state.pool, &user.id).await {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MarkStepRequest {
+    pub step: String,
+    /// `false` clears an earlier mark
+    pub completed: Option<bool>,
+}
+
+/// `POST /api/auth/onboarding` marks a step done or skipped; returns the updated steps
+pub async fn mark_step(
+    req: HttpRequest,
+    user: AuthedUser,
+    data: web::Json<MarkStepRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    if !onboarding::is_step(&data.step) {
+        let error_msg = match locale {
+            Locale::Ru => "Неизвестный шаг онбординга",
+            Locale::En => "unknown-onboarding-step",
+        };
+        return HttpResponse::BadRequest().json(json!({
+            "error": error_msg,
+            "steps": onboarding::STEPS,
+        }));
+    }
+
+    if onboarding::mark(pool, &user.id, &data.step, data.completed.unwrap_or(true)).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    match onboarding::state(pool, &user.id).await {
+        Ok(s) => HttpResponse::Ok().json(s),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
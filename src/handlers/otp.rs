@@ -0,0 +1,265 @@
+use std::time::Duration;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::auth::{create_session, too_many_requests, ClientInfo};
+use crate::i18n::{self, Locale};
+use crate::services::{abuse, captcha, geoip, sms, timezone};
+use crate::state::AppState;
+
+const CODE_TTL_MINUTES: i64 = 5;
+/// Wrong guesses before the code is burned and a new one has to be requested
+const MAX_ATTEMPTS: i64 = 5;
+/// Minimum gap between two codes to the same number
+const RESEND_SECONDS: i64 = 60;
+const CODES_PER_PHONE_PER_HOUR: usize = 5;
+const CODES_PER_IP_PER_HOUR: usize = 10;
+const VERIFY_PER_IP_PER_15_MINUTES: usize = 20;
+
+fn code_hash(phone: &str, code: &str) -> String {
+    Sha256::digest(format!("{}:{}", phone, code).as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn invalid_phone(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Неверный номер телефона",
+        Locale::En => "invalid-phone",
+    };
+    HttpResponse::BadRequest().json(json!({ "error": error_msg }))
+}
+
+fn invalid_code(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Неверный или истекший код",
+        Locale::En => "invalid-or-expired-code",
+    };
+    HttpResponse::Unauthorized().json(json!({ "error": error_msg }))
+}
+
+#[derive(Deserialize)]
+pub struct OtpRequest {
+    pub phone: String,
+    pub captcha_token: Option<String>,
+}
+
+/// `POST /api/auth/otp/request` texts a 6-digit sign-in code to the phone number
+pub async fn request_code(
+    req: HttpRequest,
+    data: web::Json<OtpRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let phone = match sms::normalize_phone(&data.phone) {
+        Some(p) => p,
+        None => return invalid_phone(locale),
+    };
+    if !sms::configured() {
+        let error_msg = match locale {
+            Locale::Ru => "Вход по SMS недоступен",
+            Locale::En => "sms-not-configured",
+        };
+        return HttpResponse::ServiceUnavailable().json(json!({ "error": error_msg }));
+    }
+
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string());
+    if !abuse::allow("otp-ip", client_ip.as_deref().unwrap_or("unknown"), CODES_PER_IP_PER_HOUR, Duration::from_secs(3600))
+        || !abuse::allow("otp-phone", &phone, CODES_PER_PHONE_PER_HOUR, Duration::from_secs(3600))
+    {
+        return too_many_requests(locale);
+    }
+    if !captcha::verify(data.captcha_token.as_deref(), client_ip.as_deref()).await {
+        let error_msg = match locale {
+            Locale::Ru => "Проверка captcha не пройдена",
+            Locale::En => "captcha-failed",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let now = chrono::Utc::now();
+    let last_sent: Option<String> = sqlx::query_scalar("SELECT created_at FROM otp_codes WHERE phone = ?")
+        .bind(&phone)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let too_soon = last_sent
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(&t).ok())
+        .is_some_and(|t| now.signed_duration_since(t) < chrono::Duration::seconds(RESEND_SECONDS));
+    if too_soon {
+        return too_many_requests(locale);
+    }
+
+    let code = format!("{:06}", rand::random_range(0..1_000_000u32));
+    let stored = sqlx::query(
+        "INSERT INTO otp_codes (phone, code_hash, attempts, created_at, expires_at) VALUES (?, ?, 0, ?, ?)
+         ON CONFLICT(phone) DO UPDATE SET code_hash = excluded.code_hash, attempts = 0,
+            created_at = excluded.created_at, expires_at = excluded.expires_at"
+    )
+    .bind(&phone)
+    .bind(code_hash(&phone, &code))
+    .bind(now.to_rfc3339())
+    .bind((now + chrono::Duration::minutes(CODE_TTL_MINUTES)).to_rfc3339())
+    .execute(pool)
+    .await;
+    if stored.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let text = match locale {
+        Locale::Ru => format!("Код для входа: {}. Никому его не сообщайте.", code),
+        Locale::En => format!("Your sign-in code: {}. Don't share it with anyone.", code),
+    };
+    if let Err(e) = sms::send(&phone, &text).await {
+        eprintln!("Sending sign-in code failed: {}", e);
+        let _ = sqlx::query("DELETE FROM otp_codes WHERE phone = ?").bind(&phone).execute(pool).await;
+        let error_msg = match locale {
+            Locale::Ru => "Не удалось отправить SMS",
+            Locale::En => "sms-send-failed",
+        };
+        return HttpResponse::BadGateway().json(json!({ "error": error_msg }));
+    }
+
+    HttpResponse::Accepted().json(json!({
+        "phone": phone,
+        "expires_in": CODE_TTL_MINUTES * 60,
+        "resend_in": RESEND_SECONDS,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct OtpVerifyRequest {
+    pub phone: String,
+    pub code: String,
+    /// Used only when the code creates the account
+    pub business_type: Option<String>,
+    pub remember_me: Option<bool>,
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+}
+
+/// `POST /api/auth/otp/verify` trades the code for a session, creating the account on first use.
+/// Phone accounts get a placeholder email under the reserved `.invalid` domain.
+pub async fn verify_code(
+    req: HttpRequest,
+    data: web::Json<OtpVerifyRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let phone = match sms::normalize_phone(&data.phone) {
+        Some(p) => p,
+        None => return invalid_phone(locale),
+    };
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string());
+    if !abuse::allow("otp-verify", client_ip.as_deref().unwrap_or("unknown"), VERIFY_PER_IP_PER_15_MINUTES, Duration::from_secs(15 * 60)) {
+        return too_many_requests(locale);
+    }
+
+    let now = chrono::Utc::now();
+    // Counting the attempt before comparing keeps parallel guesses within MAX_ATTEMPTS
+    let counted = sqlx::query(
+        "UPDATE otp_codes SET attempts = attempts + 1 WHERE phone = ? AND attempts < ? AND expires_at > ?"
+    )
+    .bind(&phone)
+    .bind(MAX_ATTEMPTS)
+    .bind(now.to_rfc3339())
+    .execute(pool)
+    .await;
+    match counted {
+        Ok(r) if r.rows_affected() == 1 => {}
+        Ok(_) => return invalid_code(locale),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let consumed = sqlx::query("DELETE FROM otp_codes WHERE phone = ? AND code_hash = ?")
+        .bind(&phone)
+        .bind(code_hash(&phone, data.code.trim()))
+        .execute(pool)
+        .await;
+    match consumed {
+        Ok(r) if r.rows_affected() == 1 => {}
+        Ok(_) => return invalid_code(locale),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let existing = match sqlx::query("SELECT id, email, business_type FROM users WHERE phone = ? ORDER BY created_at LIMIT 1")
+        .bind(&phone)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(u) => u,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let (user_id, email, business_type, created) = match existing {
+        Some(u) => (u.get::<String, _>("id"), u.get::<String, _>("email"), u.get::<String, _>("business_type"), false),
+        None => {
+            let id = Uuid::new_v4().to_string();
+            let email = format!("phone{}@users.invalid", phone.trim_start_matches('+'));
+            let business_type = data.business_type.clone().filter(|b| !b.is_empty()).unwrap_or_else(|| "general".to_string());
+            // Nobody knows this password; the account opens with a code
+            let password = bcrypt::hash(Uuid::new_v4().to_string(), bcrypt::DEFAULT_COST).unwrap_or_default();
+            let inserted = sqlx::query(
+                "INSERT INTO users (id, email, password, has_password, business_type, created_at, phone, timezone) VALUES (?, ?, ?, 0, ?, ?, ?, ?)"
+            )
+            .bind(&id)
+            .bind(&email)
+            .bind(&password)
+            .bind(&business_type)
+            .bind(now.to_rfc3339())
+            .bind(&phone)
+            .bind(timezone::detect(&req).map(|tz| tz.name().to_string()))
+            .execute(pool)
+            .await;
+            if inserted.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            (id, email, business_type, true)
+        }
+    };
+
+    let session = match create_session(
+        &state,
+        &user_id,
+        data.remember_me.unwrap_or(true),
+        data.device_id.as_deref().filter(|d| !d.is_empty()),
+        data.device_name.as_deref(),
+        &ClientInfo::from_request(&req),
+        locale,
+    )
+    .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Failed to create session for {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let success_msg = match locale {
+        Locale::Ru => "Вход выполнен успешно",
+        Locale::En => "Login successful",
+    };
+    let body = json!({
+        "message": success_msg,
+        "user": {
+            "id": user_id,
+            "email": email,
+            "business_type": business_type
+        },
+        "created": created,
+        "token": session.token,
+        "token_expires_at": session.token_expires_at,
+        "refresh_token": session.refresh_token,
+        "expires_at": session.expires_at
+    });
+    if created {
+        HttpResponse::Created().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
@@ -0,0 +1,297 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::services::payments::YooKassaClient;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct CreatePaymentLinkRequest {
+    pub invoice_id: Option<String>,
+    pub amount: Option<f64>,
+    pub currency: Option<String>,
+    pub description: Option<String>,
+    pub return_url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct PaymentLink {
+    pub id: String,
+    pub invoice_id: Option<String>,
+    pub provider: String,
+    pub amount: f64,
+    pub currency: String,
+    pub description: String,
+    pub status: String,
+    pub url: Option<String>,
+    pub created_at: String,
+    pub paid_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct YooKassaNotification {
+    pub event: String,
+    pub object: YooKassaNotificationObject,
+}
+
+#[derive(Deserialize)]
+pub struct YooKassaNotificationObject {
+    pub id: String,
+}
+
+pub async fn create_payment_link(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    body: web::Json<CreatePaymentLinkRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let data = body.into_inner();
+
+    let provider = match YooKassaClient::from_env() {
+        Some(p) => p,
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Платежный провайдер не настроен",
+                Locale::En => "payment-provider-not-configured",
+            };
+            return HttpResponse::ServiceUnavailable().json(json!({ "error": error_msg }));
+        }
+    };
+
+    // A link for an invoice always charges the invoice total so it can be reconciled
+    let (amount, currency, description) = if let Some(ref invoice_id) = data.invoice_id {
+        let row = sqlx::query("SELECT number, currency, total, status FROM invoices WHERE id = ? AND user_id = ?")
+            .bind(invoice_id)
+            .bind(&user_id)
+            .fetch_optional(pool)
+            .await;
+        match row {
+            Ok(Some(r)) => {
+                let status: String = r.get("status");
+                if status == "paid" {
+                    let error_msg = match locale {
+                        Locale::Ru => "Счет уже оплачен",
+                        Locale::En => "invoice-already-paid",
+                    };
+                    return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+                }
+                let number: i64 = r.get("number");
+                let description = data.description.clone().unwrap_or_else(|| match locale {
+                    Locale::Ru => format!("Оплата счета № {}", number),
+                    Locale::En => format!("Payment for invoice #{}", number),
+                });
+                (r.get::<f64, _>("total"), r.get::<String, _>("currency"), description)
+            }
+            Ok(None) => {
+                let error_msg = match locale {
+                    Locale::Ru => "Счет не найден",
+                    Locale::En => "invoice-not-found",
+                };
+                return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+            }
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    } else {
+        match (data.amount, data.description.clone()) {
+            (Some(amount), Some(description)) if amount > 0.0 && !description.trim().is_empty() => {
+                (amount, data.currency.clone().unwrap_or_else(|| "RUB".to_string()), description)
+            }
+            _ => {
+                let error_msg = match locale {
+                    Locale::Ru => "Укажите счет или сумму с описанием",
+                    Locale::En => "invoice-or-amount-required",
+                };
+                return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+            }
+        }
+    };
+
+    let return_url = match data.return_url.clone().or_else(|| std::env::var("PAYMENT_RETURN_URL").ok()) {
+        Some(url) => url,
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Не указан адрес возврата",
+                Locale::En => "return-url-required",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let link_id = Uuid::new_v4().to_string();
+    let payment = match provider
+        .create_payment(amount, &currency, &description, &return_url, &link_id, &link_id)
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Failed to create YooKassa payment: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Не удалось создать ссылку на оплату",
+                Locale::En => "payment-link-create-failed",
+            };
+            return HttpResponse::BadGateway().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let created_at = chrono::Utc::now().to_rfc3339();
+    let url = payment.confirmation_url();
+    let inserted = sqlx::query(
+        "INSERT INTO payment_links (id, user_id, invoice_id, provider, provider_payment_id, amount, currency, description, status, confirmation_url, created_at) VALUES (?, ?, ?, 'yookassa', ?, ?, ?, ?, 'pending', ?, ?)"
+    )
+    .bind(&link_id)
+    .bind(&user_id)
+    .bind(&data.invoice_id)
+    .bind(&payment.id)
+    .bind(amount)
+    .bind(&currency)
+    .bind(&description)
+    .bind(&url)
+    .bind(&created_at)
+    .execute(pool)
+    .await;
+
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Created().json(PaymentLink {
+        id: link_id,
+        invoice_id: data.invoice_id,
+        provider: "yookassa".to_string(),
+        amount,
+        currency,
+        description,
+        status: "pending".to_string(),
+        url,
+        created_at,
+        paid_at: None,
+    })
+}
+
+pub async fn list_payment_links(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let user_id = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(
+        "SELECT id, invoice_id, provider, amount, currency, description, status, confirmation_url, created_at, paid_at FROM payment_links WHERE user_id = ? ORDER BY created_at DESC"
+    )
+    .bind(&user_id)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let links: Vec<PaymentLink> = rs.into_iter().map(|r| PaymentLink {
+                id: r.get("id"),
+                invoice_id: r.try_get("invoice_id").ok().flatten(),
+                provider: r.get("provider"),
+                amount: r.get("amount"),
+                currency: r.get("currency"),
+                description: r.get("description"),
+                status: r.get("status"),
+                url: r.try_get("confirmation_url").ok().flatten(),
+                created_at: r.get("created_at"),
+                paid_at: r.try_get("paid_at").ok().flatten(),
+            }).collect();
+            HttpResponse::Ok().json(json!({ "links": links }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// YooKassa HTTP notification; always acknowledged unless we failed to persist the new status
+pub async fn yookassa_webhook(
+    body: web::Json<YooKassaNotification>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pool = &state.pool;
+    let notification = body.into_inner();
+
+    let provider = match YooKassaClient::from_env() {
+        Some(p) => p,
+        None => return HttpResponse::ServiceUnavailable().finish(),
+    };
+
+    let link = sqlx::query("SELECT id, invoice_id, status FROM payment_links WHERE provider = 'yookassa' AND provider_payment_id = ?")
+        .bind(&notification.object.id)
+        .fetch_optional(pool)
+        .await;
+    let (link_id, invoice_id, current_status): (String, Option<String>, String) = match link {
+        Ok(Some(r)) => (r.get("id"), r.try_get("invoice_id").ok().flatten(), r.get("status")),
+        Ok(None) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let payment = match provider.get_payment(&notification.object.id).await {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("Failed to verify YooKassa payment {} ({}): {}", notification.object.id, notification.event, e);
+            return HttpResponse::BadGateway().finish();
+        }
+    };
+
+    let status = match payment.status.as_str() {
+        "succeeded" => "succeeded",
+        "canceled" => "canceled",
+        _ => "pending",
+    };
+    if status == current_status {
+        return HttpResponse::Ok().finish();
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let updated = sqlx::query(
+        "UPDATE payment_links SET status = ?, paid_at = CASE WHEN ? = 'succeeded' THEN ? ELSE paid_at END WHERE id = ?"
+    )
+    .bind(status)
+    .bind(status)
+    .bind(&now)
+    .bind(&link_id)
+    .execute(&mut tx)
+    .await;
+    if updated.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Reconcile: a successful payment settles the linked invoice
+    if status == "succeeded" {
+        if let Some(ref invoice_id) = invoice_id {
+            let settled = sqlx::query("UPDATE invoices SET status = 'paid', paid_at = ? WHERE id = ? AND status != 'paid'")
+                .bind(&now)
+                .bind(invoice_id)
+                .execute(&mut tx)
+                .await;
+            if settled.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+        }
+    }
+
+    match tx.commit().await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::auth::AuthedUser;
+use crate::i18n::{self, Locale};
+use crate::services::openai;
+use crate::services::preferences::{self, OUTPUT_FORMATS};
+use crate::state::AppState;
+
+/// `GET /api/auth/preferences` returns the user's settings, defaults included
+pub async fn get_preferences(user: AuthedUser, state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(preferences::load(&state.pool, &user.id).await)
+}
+
+/// Omitted fields keep their value; an empty string clears `locale`, `default_category`, `default_output_format`
+/// or `default_model`
+#[derive(Deserialize)]
+pub struct UpdatePreferences {
+    pub locale: Option<String>,
+    pub push_notifications: Option<bool>,
+    pub default_category: Option<String>,
+    pub default_output_format: Option<String>,
+    pub default_model: Option<String>,
+}
+
+fn invalid_preference(locale: Locale, field: &str) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Недопустимое значение настройки",
+        Locale::En => "invalid-preference",
+    };
+    HttpResponse::BadRequest().json(json!({ "error": error_msg, "field": field }))
+}
+
+/// `PUT /api/auth/preferences` updates the given settings and returns all of them
+pub async fn update_preferences(
+    req: HttpRequest,
+    user: AuthedUser,
+    data: web::Json<UpdatePreferences>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let data = data.into_inner();
+    let mut prefs = preferences::load(pool, &user.id).await;
+
+    if let Some(lang) = data.locale.as_deref().map(str::trim) {
+        prefs.locale = match lang {
+            "" => None,
+            _ => match i18n::parse_language(lang) {
+                Some(l) => Some(i18n::language_code(l).to_string()),
+                None => return invalid_preference(locale, "locale"),
+            },
+        };
+    }
+    if let Some(category) = data.default_category.as_deref() {
+        prefs.default_category = match category.trim() {
+            "" => None,
+            _ => match preferences::normalize_category(category) {
+                Some(c) => Some(c),
+                None => return invalid_preference(locale, "default_category"),
+            },
+        };
+    }
+    if let Some(format) = data.default_output_format.as_deref() {
+        let format = format.trim().to_ascii_lowercase();
+        prefs.default_output_format = match format.as_str() {
+            "" => None,
+            f if OUTPUT_FORMATS.contains(&f) => Some(format),
+            _ => return invalid_preference(locale, "default_output_format"),
+        };
+    }
+    if let Some(model) = data.default_model.as_deref().map(str::trim) {
+        prefs.default_model = match model {
+            "" => None,
+            m if openai::allowed_models(&state).iter().any(|a| a == m) => Some(m.to_string()),
+            _ => return invalid_preference(locale, "default_model"),
+        };
+    }
+    if let Some(push) = data.push_notifications {
+        prefs.push_notifications = push;
+    }
+    prefs.updated_at = Some(chrono::Utc::now().to_rfc3339());
+
+    match preferences::save(pool, &user.id, &prefs).await {
+        Ok(()) => HttpResponse::Ok().json(prefs),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,111 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::handlers::admin::require_admin;
+use crate::services::quality;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct QualityReportQuery {
+    pub days: Option<i64>,
+    /// `prompt_version` (default) or `category`
+    pub group_by: Option<String>,
+}
+
+/// `GET /api/admin/analytics/quality` averages judge scores per day and prompt version or category,
+/// plus totals per group over the whole window
+pub async fn quality_report(
+    req: HttpRequest,
+    query: web::Query<QualityReportQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    let since = (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+    // Only ever one of two fixed column names goes into the SQL
+    let group_by = match query.group_by.as_deref() {
+        None | Some("prompt_version") => "prompt_version",
+        Some("category") => "category",
+        Some(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "group_by: prompt_version или category",
+                Locale::En => "invalid-group-by",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let averages = "COUNT(*) AS answers, AVG(correctness) AS correctness, AVG(actionability) AS actionability,
+                    AVG(locale_compliance) AS locale_compliance, AVG(overall) AS overall";
+    // Answers stored before prompt versions were recorded are grouped as 'unknown'
+    let trend = sqlx::query(&format!(
+        "SELECT substr(scored_at, 1, 10) AS day, COALESCE({group}, 'unknown') AS grp, {averages}
+         FROM answer_scores WHERE julianday(scored_at) >= julianday(?)
+         GROUP BY 1, 2 ORDER BY 1, 2",
+        group = group_by,
+        averages = averages,
+    ))
+    .bind(&since)
+    .fetch_all(&state.pool)
+    .await;
+    let totals = sqlx::query(&format!(
+        "SELECT COALESCE({group}, 'unknown') AS grp, {averages}
+         FROM answer_scores WHERE julianday(scored_at) >= julianday(?)
+         GROUP BY 1 ORDER BY 1",
+        group = group_by,
+        averages = averages,
+    ))
+    .bind(&since)
+    .fetch_all(&state.pool)
+    .await;
+
+    let scores = |r: &sqlx::sqlite::SqliteRow| json!({
+        group_by: r.get::<String, _>("grp"),
+        "answers": r.get::<i64, _>("answers"),
+        "correctness": r.get::<f64, _>("correctness"),
+        "actionability": r.get::<f64, _>("actionability"),
+        "locale_compliance": r.get::<f64, _>("locale_compliance"),
+        "overall": r.get::<f64, _>("overall"),
+    });
+
+    match (trend, totals) {
+        (Ok(trend), Ok(totals)) => {
+            let trend: Vec<_> = trend.iter().map(|r| {
+                let mut point = scores(r);
+                point["day"] = json!(r.get::<String, _>("day"));
+                point
+            }).collect();
+            let totals: Vec<_> = totals.iter().map(scores).collect();
+            HttpResponse::Ok().json(json!({ "days": days, "group_by": group_by, "totals": totals, "trend": trend }))
+        }
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `POST /api/admin/analytics/quality/run` scores a sample now, even when the scheduled run is off
+pub async fn run_quality_eval(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+
+    let policy = state.config.load().quality_eval.clone();
+    match quality::run(&state, &policy).await {
+        Ok(scored) => HttpResponse::Ok().json(json!({ "scored": scored })),
+        Err(e) => {
+            eprintln!("Quality evaluation failed: {}", e);
+            let error_msg = match locale {
+                Locale::Ru => "Ошибка оценки ответов",
+                Locale::En => "quality-eval-failed",
+            };
+            HttpResponse::InternalServerError().json(json!({ "error": error_msg }))
+        }
+    }
+}
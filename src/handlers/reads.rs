@@ -0,0 +1,256 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::models::ChatResponse;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Unread count of the conversation aliased `c`: assistant messages newer than both the newest
+/// read marker of any of the owner's identities and the owner's last message, since writing in a
+/// conversation (from the app or the bot) means everything before was seen
+pub(crate) const UNREAD_COUNT_SQL: &str = "(SELECT COUNT(*) FROM messages m
+    WHERE m.conversation_id = c.id AND m.role = 'assistant'
+      AND julianday(m.timestamp) > MAX(
+        COALESCE((SELECT MAX(julianday(cr.last_read_at)) FROM conversation_reads cr WHERE cr.conversation_id = c.id AND cr.user_id = c.user_id), 0),
+        COALESCE((SELECT MAX(julianday(u.timestamp)) FROM messages u WHERE u.conversation_id = c.id AND u.role = 'user'), 0)))";
+
+/// Newest read message of the conversation aliased `c` across the owner's identities
+pub(crate) const LAST_READ_MESSAGE_SQL: &str = "(SELECT cr.last_read_message_id FROM conversation_reads cr
+    WHERE cr.conversation_id = c.id AND cr.user_id = c.user_id
+    ORDER BY julianday(cr.last_read_at) DESC LIMIT 1)";
+
+/// Channel an identity reads through: Telegram user ids are numeric, account ids are UUIDs
+fn channel(identity: &str) -> &'static str {
+    if identity.parse::<i64>().is_ok() { "telegram" } else { "app" }
+}
+
+/// Moves `identity`'s marker forward to a message read at `read_at`; older messages are ignored
+async fn save_marker(
+    pool: &sqlx::SqlitePool,
+    identity: &str,
+    owner_id: &str,
+    conversation_id: &str,
+    message_id: &str,
+    read_at: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO conversation_reads (identity, conversation_id, user_id, channel, last_read_message_id, last_read_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(identity, conversation_id) DO UPDATE SET
+            user_id = excluded.user_id,
+            last_read_message_id = excluded.last_read_message_id,
+            last_read_at = excluded.last_read_at,
+            updated_at = excluded.updated_at
+         WHERE julianday(excluded.last_read_at) >= julianday(conversation_reads.last_read_at)"
+    )
+    .bind(identity)
+    .bind(conversation_id)
+    .bind(owner_id)
+    .bind(channel(identity))
+    .bind(message_id)
+    .bind(read_at)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// An answer returned to the Telegram bot is shown in the chat right away, so it counts as read
+/// for that identity and the app's badge drops without the bot calling the read endpoint
+pub(crate) async fn record_bot_delivery(pool: &sqlx::SqlitePool, identity: &str, owner_id: &str, reply: &ChatResponse) {
+    if channel(identity) != "telegram" {
+        return;
+    }
+    if let Err(e) = save_marker(pool, identity, owner_id, &reply.conversation_id, &reply.message_id, &reply.timestamp).await {
+        eprintln!("Failed to record Telegram read for {}: {}", reply.conversation_id, e);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MarkReadRequest {
+    pub user_id: String,
+    /// Newest message the user has seen; defaults to the latest message of the conversation
+    pub message_id: Option<String>,
+}
+
+/// `POST /api/chat/conversations/{id}/read` moves the read marker of the calling identity (the
+/// `user_id` as sent: an account id or a Telegram user id) forward. Marking a message older than
+/// the current marker changes nothing, so devices reporting out of order are safe.
+pub async fn mark_read(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<MarkReadRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let owned: Option<i64> = match sqlx::query_scalar(
+        "SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(o) => o,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if owned.is_none() {
+        let error_msg = match locale {
+            Locale::Ru => "Разговор не найден или не принадлежит пользователю",
+            Locale::En => "conversation-not-found-or-not-owned",
+        };
+        return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+    }
+
+    let message = match &body.message_id {
+        Some(id) => {
+            sqlx::query("SELECT id, timestamp FROM messages WHERE id = ? AND conversation_id = ?")
+                .bind(id)
+                .bind(&conversation_id)
+                .fetch_optional(pool)
+                .await
+        }
+        None => {
+            sqlx::query(
+                "SELECT id, timestamp FROM messages WHERE conversation_id = ?
+                 ORDER BY julianday(timestamp) DESC, rowid DESC LIMIT 1"
+            )
+            .bind(&conversation_id)
+            .fetch_optional(pool)
+            .await
+        }
+    };
+    let (message_id, read_at) = match message {
+        Ok(Some(r)) => (r.get::<String, _>("id"), r.get::<String, _>("timestamp")),
+        Ok(None) if body.message_id.is_some() => {
+            let error_msg = match locale {
+                Locale::Ru => "Сообщение не найдено",
+                Locale::En => "message-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        // Nothing to read yet
+        Ok(None) => {
+            return HttpResponse::Ok().json(json!({
+                "conversation_id": conversation_id,
+                "last_read_message_id": null,
+                "unread": 0,
+            }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if save_marker(pool, &body.user_id, &resolved_user_id, &conversation_id, &message_id, &read_at).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let row = sqlx::query(&format!(
+        "SELECT {} AS last_read_message_id, {} AS unread FROM conversations c WHERE c.id = ?",
+        LAST_READ_MESSAGE_SQL, UNREAD_COUNT_SQL
+    ))
+    .bind(&conversation_id)
+    .fetch_one(pool)
+    .await;
+
+    match row {
+        Ok(r) => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "last_read_message_id": r.get::<String, _>("last_read_message_id"),
+            "unread": r.get::<i64, _>("unread"),
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `GET /api/chat/unread/{user_id}` lists conversations with unread answers, e.g. ones
+/// answered from the retry queue while the app was closed, for badges
+pub async fn unread_counts(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let user_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &user_id).await;
+
+    let rows = sqlx::query(&format!(
+        "SELECT id, unread FROM (
+            SELECT c.id, c.last_message_at, c.created_at, {} AS unread FROM conversations c
+            WHERE c.user_id = ? AND c.deleted_at IS NULL AND c.archived_at IS NULL
+         ) WHERE unread > 0
+         ORDER BY julianday(COALESCE(last_message_at, created_at)) DESC",
+        UNREAD_COUNT_SQL
+    ))
+    .bind(&resolved_user_id)
+    .fetch_all(pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let conversations: Vec<serde_json::Value> = rs
+                .iter()
+                .map(|r| json!({
+                    "conversation_id": r.get::<String, _>("id"),
+                    "unread": r.get::<i64, _>("unread"),
+                }))
+                .collect();
+            let total: i64 = rs.iter().map(|r| r.get::<i64, _>("unread")).sum();
+            HttpResponse::Ok().json(json!({
+                "user_id": user_id,
+                "total": total,
+                "conversations": conversations,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MarkAllReadRequest {
+    pub user_id: String,
+}
+
+/// `POST /api/chat/read-all` marks every conversation of the user as read up to its latest message
+pub async fn mark_all_read(
+    body: web::Json<MarkAllReadRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let result = sqlx::query(
+        "INSERT INTO conversation_reads (identity, conversation_id, user_id, channel, last_read_message_id, last_read_at, updated_at)
+         SELECT ?, c.id, c.user_id, ?, m.id, m.timestamp, ?
+         FROM conversations c
+         JOIN messages m ON m.id = (
+            SELECT id FROM messages WHERE conversation_id = c.id ORDER BY julianday(timestamp) DESC, rowid DESC LIMIT 1
+         )
+         WHERE c.user_id = ? AND c.deleted_at IS NULL
+         ON CONFLICT(identity, conversation_id) DO UPDATE SET
+            user_id = excluded.user_id,
+            last_read_message_id = excluded.last_read_message_id,
+            last_read_at = excluded.last_read_at,
+            updated_at = excluded.updated_at
+         WHERE julianday(excluded.last_read_at) >= julianday(conversation_reads.last_read_at)"
+    )
+    .bind(&body.user_id)
+    .bind(channel(&body.user_id))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&resolved_user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) => HttpResponse::Ok().json(json!({
+            "user_id": body.user_id,
+            "marked": r.rows_affected(),
+        })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,75 @@
+use actix_web::{HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::services::reference::{self, Entry};
+use crate::services::timezone;
+use crate::i18n::{self, Locale};
+
+/// The datasets only change with a deploy
+fn reference_response(req: &HttpRequest, key: &str, entries: &'static [Entry]) -> HttpResponse {
+    let locale = i18n::detect_locale(req);
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=86400"))
+        .json(json!({ key: reference::localized(entries, locale) }))
+}
+
+/// `GET /api/reference/countries`: ISO 3166-1 codes accepted for `country` and `region`
+pub async fn countries(req: HttpRequest) -> HttpResponse {
+    reference_response(&req, "countries", reference::countries())
+}
+
+/// `GET /api/reference/currencies`: ISO 4217 codes
+pub async fn currencies(req: HttpRequest) -> HttpResponse {
+    reference_response(&req, "currencies", reference::currencies())
+}
+
+/// `GET /api/reference/timezones`: IANA time zone ids
+pub async fn timezones(req: HttpRequest) -> HttpResponse {
+    reference_response(&req, "timezones", reference::timezones())
+}
+
+/// ISO code for a country sent by a client as a code or a name; blank means not given.
+/// Anything else is rejected so region-aware prompts and analytics only see known countries.
+pub(crate) fn validate_country(value: Option<&str>, field: &str, locale: Locale) -> Result<Option<String>, HttpResponse> {
+    let value = match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    match reference::country_code(value) {
+        Some(code) => Ok(Some(code.to_string())),
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Неизвестная страна, см. /api/reference/countries",
+                Locale::En => "unknown-country",
+            };
+            Err(HttpResponse::BadRequest().json(json!({
+                "error": error_msg,
+                "field": field,
+                "reference": "/api/reference/countries",
+            })))
+        }
+    }
+}
+
+/// IANA zone id sent by a client; blank means not given. Any id the tz database knows is
+/// accepted, not just the ones listed by `/api/reference/timezones`.
+pub(crate) fn validate_timezone(value: Option<&str>, locale: Locale) -> Result<Option<String>, HttpResponse> {
+    let value = match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    match timezone::parse(value) {
+        Some(tz) => Ok(Some(tz.name().to_string())),
+        None => {
+            let error_msg = match locale {
+                Locale::Ru => "Неизвестный часовой пояс, см. /api/reference/timezones",
+                Locale::En => "unknown-timezone",
+            };
+            Err(HttpResponse::BadRequest().json(json!({
+                "error": error_msg,
+                "field": "timezone",
+                "reference": "/api/reference/timezones",
+            })))
+        }
+    }
+}
@@ -0,0 +1,456 @@
+use std::time::Duration;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::admin::require_admin;
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::services::abuse;
+use crate::services::fcm::{self, FcmService};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_DESCRIPTION_CHARS: usize = 5000;
+/// Items one user may post per hour
+const ITEMS_PER_HOUR: usize = 5;
+const STATUSES: [&str; 5] = ["open", "planned", "in_progress", "done", "declined"];
+
+fn kind_label(kind: &str, locale: Locale) -> &'static str {
+    match (kind, locale) {
+        ("bug", Locale::Ru) => "Ошибка",
+        ("bug", Locale::En) => "Bug",
+        (_, Locale::Ru) => "Предложение",
+        (_, Locale::En) => "Feature request",
+    }
+}
+
+fn status_label(status: &str, locale: Locale) -> &'static str {
+    match (status, locale) {
+        ("planned", Locale::Ru) => "Запланировано",
+        ("planned", Locale::En) => "Planned",
+        ("in_progress", Locale::Ru) => "В работе",
+        ("in_progress", Locale::En) => "In progress",
+        ("done", Locale::Ru) => "Готово",
+        ("done", Locale::En) => "Done",
+        ("declined", Locale::Ru) => "Отклонено",
+        ("declined", Locale::En) => "Declined",
+        (_, Locale::Ru) => "Открыто",
+        (_, Locale::En) => "Open",
+    }
+}
+
+#[derive(Serialize)]
+pub struct FeedbackItem {
+    pub id: String,
+    /// `feature` or `bug`
+    pub kind: String,
+    pub kind_label: &'static str,
+    pub title: String,
+    pub description: String,
+    pub screenshot_url: Option<String>,
+    pub status: String,
+    pub status_label: &'static str,
+    /// Team's answer shown under the item
+    pub admin_note: Option<String>,
+    pub votes: i64,
+    /// Whether the caller has voted; always false for anonymous listings
+    pub voted: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Columns for `feedback_items i`; the single `?` is the caller's user id, or NULL
+const ITEM_COLUMNS: &str = "i.id, i.kind, i.title, i.description, i.screenshot_file_id, i.status, i.admin_note,
+    i.created_at, i.updated_at,
+    (SELECT COUNT(*) FROM feedback_votes v WHERE v.item_id = i.id) AS votes,
+    EXISTS (SELECT 1 FROM feedback_votes v WHERE v.item_id = i.id AND v.user_id = ?) AS voted";
+
+fn item_from_row(r: &sqlx::sqlite::SqliteRow, locale: Locale) -> FeedbackItem {
+    let kind: String = r.get("kind");
+    let status: String = r.get("status");
+    FeedbackItem {
+        id: r.get("id"),
+        kind_label: kind_label(&kind, locale),
+        kind,
+        title: r.get("title"),
+        description: r.get("description"),
+        screenshot_url: r
+            .get::<Option<String>, _>("screenshot_file_id")
+            .map(|id| format!("/api/files/{}", id)),
+        status_label: status_label(&status, locale),
+        status,
+        admin_note: r.get("admin_note"),
+        votes: r.get("votes"),
+        voted: r.get::<i64, _>("voted") != 0,
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    }
+}
+
+async fn fetch_item(pool: &sqlx::SqlitePool, id: &str, user_id: Option<&str>, locale: Locale) -> Result<Option<FeedbackItem>, sqlx::Error> {
+    let row = sqlx::query(&format!("SELECT {} FROM feedback_items i WHERE i.id = ?", ITEM_COLUMNS))
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|r| item_from_row(&r, locale)))
+}
+
+fn locale_code(locale: Locale) -> &'static str {
+    match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    }
+}
+
+async fn caller(req: &HttpRequest, state: &AppState, query: &TokenCheck, locale: Locale) -> Result<String, HttpResponse> {
+    let user_id = authorize(req, &state.pool, query, locale).await?;
+    Ok(resolve_user_id_for_conversations(&state.pool, &user_id).await)
+}
+
+fn item_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Запись не найдена",
+        Locale::En => "feedback-not-found",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+#[derive(Deserialize)]
+pub struct CreateFeedbackRequest {
+    /// `feature` or `bug`
+    pub kind: String,
+    pub title: String,
+    pub description: String,
+    /// An image uploaded through `/api/uploads` beforehand
+    pub screenshot_file_id: Option<String>,
+}
+
+/// `POST /api/feedback` posts a feature request or bug report to the board in the request's
+/// language; the author's vote is counted right away
+pub async fn create_item(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    body: web::Json<CreateFeedbackRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let pool = &state.pool;
+
+    let kind = body.kind.trim().to_lowercase();
+    let title = body.title.trim();
+    let description = body.description.trim();
+    if !matches!(kind.as_str(), "feature" | "bug")
+        || title.is_empty()
+        || title.chars().count() > MAX_TITLE_CHARS
+        || description.is_empty()
+        || description.chars().count() > MAX_DESCRIPTION_CHARS
+    {
+        let error_msg = match locale {
+            Locale::Ru => "Укажите тип (feature или bug), заголовок до 200 и описание до 5000 символов",
+            Locale::En => "invalid-feedback",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    if let Some(file_id) = &body.screenshot_file_id {
+        let image: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM files WHERE id = ? AND user_id = ? AND mime LIKE 'image/%'
+               AND deleted_at IS NULL AND scan_status IS NOT 'quarantined'"
+        )
+        .bind(file_id)
+        .bind(&user_id)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None);
+        if image.is_none() {
+            let error_msg = match locale {
+                Locale::Ru => "Скриншот не найден",
+                Locale::En => "screenshot-not-found",
+            };
+            return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+        }
+    }
+
+    if !abuse::allow("feedback", &user_id, ITEMS_PER_HOUR, Duration::from_secs(3600)) {
+        let error_msg = match locale {
+            Locale::Ru => "Слишком много публикаций, попробуйте позже",
+            Locale::En => "too-many-requests",
+        };
+        return HttpResponse::TooManyRequests().json(json!({ "error": error_msg }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let created = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO feedback_items (id, user_id, kind, title, description, screenshot_file_id, locale, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&id)
+        .bind(&user_id)
+        .bind(&kind)
+        .bind(title)
+        .bind(description)
+        .bind(&body.screenshot_file_id)
+        .bind(locale_code(locale))
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut tx)
+        .await?;
+        sqlx::query("INSERT INTO feedback_votes (item_id, user_id, created_at) VALUES (?, ?, ?)")
+            .bind(&id)
+            .bind(&user_id)
+            .bind(&now)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await
+    }
+    .await;
+    if created.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match fetch_item(pool, &id, Some(&user_id), locale).await {
+        Ok(Some(item)) => HttpResponse::Created().json(item),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListFeedbackQuery {
+    /// Optional; with it `voted` is filled in
+    pub token: Option<String>,
+    pub kind: Option<String>,
+    pub status: Option<String>,
+    /// `top` (default) or `new`
+    pub sort: Option<String>,
+}
+
+/// `GET /api/feedback` lists the board in the request's language, most voted first
+pub async fn list_items(
+    req: HttpRequest,
+    query: web::Query<ListFeedbackQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match &query.token {
+        Some(token) => {
+            let check = TokenCheck { token: Some(token.clone()) };
+            match caller(&req, &state, &check, locale).await {
+                Ok(id) => Some(id),
+                Err(resp) => return resp,
+            }
+        }
+        None => None,
+    };
+
+    let kind = query.kind.as_deref().map(|k| k.trim().to_lowercase()).filter(|k| !k.is_empty());
+    let status = query.status.as_deref().map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty());
+    let order = match query.sort.as_deref() {
+        Some("new") => "i.created_at DESC",
+        _ => "votes DESC, i.created_at DESC",
+    };
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM feedback_items i
+         WHERE i.locale = ? AND (? IS NULL OR i.kind = ?) AND (? IS NULL OR i.status = ?)
+         ORDER BY {} LIMIT 200",
+        ITEM_COLUMNS, order
+    ))
+    .bind(&user_id)
+    .bind(locale_code(locale))
+    .bind(&kind)
+    .bind(&kind)
+    .bind(&status)
+    .bind(&status)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let items: Vec<FeedbackItem> = rs.iter().map(|r| item_from_row(r, locale)).collect();
+            HttpResponse::Ok().json(json!({ "items": items }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `POST /api/feedback/{id}/vote` adds the caller's vote; voting twice counts once
+pub async fn vote(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    set_vote(req, path.into_inner(), query.into_inner(), &state, true).await
+}
+
+/// `DELETE /api/feedback/{id}/vote` takes the caller's vote back
+pub async fn unvote(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    set_vote(req, path.into_inner(), query.into_inner(), &state, false).await
+}
+
+async fn set_vote(req: HttpRequest, item_id: String, query: TokenCheck, state: &AppState, voted: bool) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let pool = &state.pool;
+
+    let exists: Option<i64> = match sqlx::query_scalar("SELECT 1 FROM feedback_items WHERE id = ?")
+        .bind(&item_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(e) => e,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if exists.is_none() {
+        return item_not_found(locale);
+    }
+
+    let result = if voted {
+        sqlx::query("INSERT OR IGNORE INTO feedback_votes (item_id, user_id, created_at) VALUES (?, ?, ?)")
+            .bind(&item_id)
+            .bind(&user_id)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .execute(pool)
+            .await
+    } else {
+        sqlx::query("DELETE FROM feedback_votes WHERE item_id = ? AND user_id = ?")
+            .bind(&item_id)
+            .bind(&user_id)
+            .execute(pool)
+            .await
+    };
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let votes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM feedback_votes WHERE item_id = ?")
+        .bind(&item_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    HttpResponse::Ok().json(json!({ "id": item_id, "votes": votes, "voted": voted }))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateFeedbackStatusRequest {
+    pub status: Option<String>,
+    /// Empty string clears the note
+    pub admin_note: Option<String>,
+}
+
+/// `PUT /api/admin/feedback/{id}` moves an item along the roadmap; the author gets a push when
+/// the status changes
+pub async fn update_item(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<UpdateFeedbackStatusRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let pool = &state.pool;
+    let item_id = path.into_inner();
+
+    let status = body.status.as_deref().map(|s| s.trim().to_lowercase());
+    if status.as_deref().is_some_and(|s| !STATUSES.contains(&s)) {
+        let error_msg = match locale {
+            Locale::Ru => "Неизвестный статус",
+            Locale::En => "invalid-status",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "allowed": STATUSES }));
+    }
+
+    let current = match sqlx::query("SELECT user_id, status, title, locale FROM feedback_items WHERE id = ?")
+        .bind(&item_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => return item_not_found(locale),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let result = sqlx::query(
+        "UPDATE feedback_items SET
+            status = COALESCE(?, status),
+            admin_note = CASE WHEN ? IS NULL THEN admin_note ELSE NULLIF(?, '') END,
+            updated_at = ?
+         WHERE id = ?"
+    )
+    .bind(&status)
+    .bind(&body.admin_note)
+    .bind(body.admin_note.as_deref().map(str::trim))
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&item_id)
+    .execute(pool)
+    .await;
+    if result.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if let Some(new_status) = status.as_deref().filter(|s| *s != current.get::<String, _>("status")) {
+        let author_locale = if current.get::<String, _>("locale") == "ru" { Locale::Ru } else { Locale::En };
+        notify_status_change(
+            pool,
+            &current.get::<String, _>("user_id"),
+            &item_id,
+            &current.get::<String, _>("title"),
+            new_status,
+            author_locale,
+        )
+        .await;
+    }
+
+    match fetch_item(pool, &item_id, None, locale).await {
+        Ok(Some(item)) => HttpResponse::Ok().json(item),
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+async fn notify_status_change(pool: &sqlx::SqlitePool, user_id: &str, item_id: &str, title: &str, status: &str, locale: Locale) {
+    let tokens = fcm::user_tokens(pool, user_id).await;
+    if tokens.is_empty() {
+        return;
+    }
+    let fcm = match FcmService::new() {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+
+    let heading = match locale {
+        Locale::Ru => "Статус вашего предложения изменен",
+        Locale::En => "Your feedback has an update",
+    };
+    let body = format!("{}: {}", title, status_label(status, locale));
+    let mut data = std::collections::HashMap::new();
+    data.insert("type".to_string(), "feedback_status".to_string());
+    data.insert("feedback_id".to_string(), item_id.to_string());
+    data.insert("status".to_string(), status.to_string());
+
+    if let Err(e) = fcm.send_notification(pool, tokens, heading, &body, Some(data)).await {
+        eprintln!("Failed to send feedback status push: {}", e);
+    }
+}
@@ -0,0 +1,164 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::chat::{load_transcript, resolve_user_id_for_conversations};
+use crate::services::transcript;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct ShareRequest {
+    pub user_id: String,
+}
+
+fn share_url(token: &str) -> String {
+    format!("/share/{}", token)
+}
+
+/// `POST /api/chat/conversations/{id}/share` returns the conversation's active share link,
+/// creating one if there is none
+pub async fn share_conversation(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ShareRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let owned: Option<i64> = match sqlx::query_scalar(
+        "SELECT 1 FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(o) => o,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if owned.is_none() {
+        let error_msg = match locale {
+            Locale::Ru => "Разговор не найден или не принадлежит пользователю",
+            Locale::En => "conversation-not-found-or-not-owned",
+        };
+        return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+    }
+
+    let existing = sqlx::query(
+        "SELECT token, created_at FROM conversation_shares WHERE conversation_id = ? AND revoked_at IS NULL"
+    )
+    .bind(&conversation_id)
+    .fetch_optional(pool)
+    .await;
+    let (token, created_at) = match existing {
+        Ok(Some(r)) => (r.get::<String, _>("token"), r.get::<String, _>("created_at")),
+        Ok(None) => {
+            let token = Uuid::new_v4().simple().to_string();
+            let created_at = chrono::Utc::now().to_rfc3339();
+            let inserted = sqlx::query(
+                "INSERT INTO conversation_shares (token, conversation_id, user_id, created_at) VALUES (?, ?, ?, ?)"
+            )
+            .bind(&token)
+            .bind(&conversation_id)
+            .bind(&resolved_user_id)
+            .bind(&created_at)
+            .execute(pool)
+            .await;
+            if inserted.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            (token, created_at)
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    HttpResponse::Ok().json(json!({
+        "conversation_id": conversation_id,
+        "token": token,
+        "url": share_url(&token),
+        "created_at": created_at,
+    }))
+}
+
+/// `DELETE /api/chat/conversations/{id}/share` revokes the active link; a new share gets a new token
+pub async fn revoke_share(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ShareRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let conversation_id = path.into_inner();
+    let pool = &state.pool;
+    let resolved_user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    let result = sqlx::query(
+        "UPDATE conversation_shares SET revoked_at = ?
+         WHERE conversation_id = ? AND user_id = ? AND revoked_at IS NULL"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&conversation_id)
+    .bind(&resolved_user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().json(json!({
+            "conversation_id": conversation_id,
+            "status": "revoked",
+        })),
+        Ok(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Активная ссылка не найдена",
+                Locale::En => "share-not-found",
+            };
+            HttpResponse::NotFound().json(json!({ "error": error_msg }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `GET /share/{token}` renders the shared conversation as a read-only page, no account needed.
+/// Revoked links and deleted conversations look the same as unknown tokens.
+pub async fn view_shared(
+    req: HttpRequest,
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+
+    let row = sqlx::query(
+        "SELECT c.id, c.title, c.created_at FROM conversation_shares s
+         JOIN conversations c ON c.id = s.conversation_id
+         WHERE s.token = ? AND s.revoked_at IS NULL AND c.deleted_at IS NULL"
+    )
+    .bind(path.into_inner())
+    .fetch_optional(pool)
+    .await;
+    let row = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let body = match locale {
+                Locale::Ru => "Ссылка недействительна или была отозвана",
+                Locale::En => "This link is invalid or has been revoked",
+            };
+            return HttpResponse::NotFound().content_type("text/plain; charset=utf-8").body(body);
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let conversation_id: String = row.get("id");
+    match load_transcript(pool, &conversation_id, row.get("title"), row.get("created_at"), locale).await {
+        Ok(doc) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(transcript::render_html(&doc, locale)),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
@@ -0,0 +1,117 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Serialize)]
+pub struct StatBucket {
+    pub key: String,
+    pub count: i64,
+}
+
+#[derive(Serialize)]
+pub struct UserStats {
+    pub user_id: String,
+    pub total_messages: i64,
+    pub languages: Vec<StatBucket>,
+    pub categories: Vec<StatBucket>,
+    pub average_rating: Option<f64>,
+    pub ratings_count: i64,
+}
+
+async fn bump(pool: &sqlx::SqlitePool, user_id: &str, dimension: &str, key: &str, amount: f64) {
+    let _ = sqlx::query(
+        "INSERT INTO user_stats (user_id, dimension, key, count, total, updated_at) VALUES (?, ?, ?, 1, ?, ?)
+         ON CONFLICT(user_id, dimension, key) DO UPDATE SET
+            count = user_stats.count + 1,
+            total = user_stats.total + excluded.total,
+            updated_at = excluded.updated_at"
+    )
+    .bind(user_id)
+    .bind(dimension)
+    .bind(key)
+    .bind(amount)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+}
+
+/// Counts one user message towards the language and category aggregates
+pub(crate) async fn record_message(pool: &sqlx::SqlitePool, user_id: &str, locale: Locale, category: &str) {
+    let language = match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    };
+    bump(pool, user_id, "language", language, 0.0).await;
+    bump(pool, user_id, "category", category, 0.0).await;
+}
+
+pub async fn get_user_stats(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let caller = match authorize(&req, pool, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    // Telegram-linked ids share stats with their main account
+    let user_id = resolve_user_id_for_conversations(pool, &path.into_inner()).await;
+    if user_id != caller {
+        let error_msg = match locale {
+            Locale::Ru => "Доступ запрещен",
+            Locale::En => "forbidden",
+        };
+        return HttpResponse::Forbidden().json(json!({ "error": error_msg }));
+    }
+
+    let rows = match sqlx::query(
+        "SELECT dimension, key, count, total FROM user_stats WHERE user_id = ? ORDER BY count DESC"
+    )
+    .bind(&user_id)
+    .fetch_all(pool)
+    .await
+    {
+        Ok(rs) => rs,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let mut stats = UserStats {
+        user_id,
+        total_messages: 0,
+        languages: Vec::new(),
+        categories: Vec::new(),
+        average_rating: None,
+        ratings_count: 0,
+    };
+    for r in rows {
+        let dimension: String = r.get("dimension");
+        let bucket = StatBucket { key: r.get("key"), count: r.get("count") };
+        match dimension.as_str() {
+            "language" => {
+                stats.total_messages += bucket.count;
+                stats.languages.push(bucket);
+            }
+            "category" => stats.categories.push(bucket),
+            "rating" => {
+                let total: f64 = r.get("total");
+                stats.ratings_count = bucket.count;
+                if bucket.count > 0 {
+                    stats.average_rating = Some(total / bucket.count as f64);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    HttpResponse::Ok().json(stats)
+}
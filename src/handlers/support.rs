@@ -0,0 +1,620 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::admin::require_admin;
+use crate::handlers::auth::AuthedUser;
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::services::{dead_letters, faq, timezone};
+use crate::services::telegram::TelegramBot;
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+#[derive(Deserialize)]
+pub struct SupportHistoryQuery {
+    /// Id of the oldest message the client already has; the page holds messages before it
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct SupportMessage {
+    pub id: String,
+    pub message: String,
+    pub photo_url: Option<String>,
+    /// `user` or `support`
+    pub direction: String,
+    /// `public` or `internal`; only present in the agent view
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// Set on automatic FAQ answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub faq_id: Option<String>,
+    /// For automatic answers, the user message they answer; pass it to the "talk to a human" endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<String>,
+    pub created_at: String,
+    /// `created_at` in the user's zone; only present in the user's history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_created_at: Option<String>,
+}
+
+/// Agent notes start with this command, matching what agents type when replying in Telegram
+const NOTE_COMMAND: &str = "/note";
+
+/// `GET /api/support/history/{user_id}` pages the caller's own support thread newest-first;
+/// pass the last returned id as `before` to load older messages
+pub async fn get_support_history(
+    req: HttpRequest,
+    user: AuthedUser,
+    path: web::Path<String>,
+    query: web::Query<SupportHistoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    // Telegram-linked ids share the thread of their main account
+    let user_id = resolve_user_id_for_conversations(pool, &path.into_inner()).await;
+    if user_id != user.id {
+        let error_msg = match locale {
+            Locale::Ru => "Доступ запрещен",
+            Locale::En => "forbidden",
+        };
+        return HttpResponse::Forbidden().json(json!({ "error": error_msg }));
+    }
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+
+    let total: i64 = match sqlx::query_scalar("SELECT COUNT(*) FROM support_messages WHERE user_id = ? AND visibility = 'public'")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // created_at only has second precision, so rowid breaks ties within the same second
+    let cursor: Option<(String, i64)> = match &query.before {
+        Some(id) => {
+            let anchor = sqlx::query("SELECT created_at, rowid FROM support_messages WHERE id = ? AND user_id = ? AND visibility = 'public'")
+                .bind(id)
+                .bind(&user_id)
+                .fetch_optional(pool)
+                .await;
+            match anchor {
+                Ok(Some(r)) => Some((r.get("created_at"), r.get("rowid"))),
+                Ok(None) => {
+                    let error_msg = match locale {
+                        Locale::Ru => "Сообщение не найдено",
+                        Locale::En => "message-not-found",
+                    };
+                    return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+                }
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            }
+        }
+        None => None,
+    };
+
+    // One extra row tells whether an older page exists
+    let rows = match &cursor {
+        Some((created_at, rowid)) => {
+            sqlx::query(
+                "SELECT id, message, photo_url, direction, faq_id, in_reply_to, created_at FROM support_messages
+                 WHERE user_id = ? AND visibility = 'public' AND (julianday(created_at) < julianday(?) OR (created_at = ? AND rowid < ?))
+                 ORDER BY julianday(created_at) DESC, rowid DESC
+                 LIMIT ?"
+            )
+            .bind(&user_id)
+            .bind(created_at)
+            .bind(created_at)
+            .bind(rowid)
+            .bind(limit + 1)
+            .fetch_all(pool)
+            .await
+        }
+        None => {
+            sqlx::query(
+                "SELECT id, message, photo_url, direction, faq_id, in_reply_to, created_at FROM support_messages
+                 WHERE user_id = ? AND visibility = 'public'
+                 ORDER BY julianday(created_at) DESC, rowid DESC
+                 LIMIT ?"
+            )
+            .bind(&user_id)
+            .bind(limit + 1)
+            .fetch_all(pool)
+            .await
+        }
+    };
+
+    let tz = timezone::for_request(&req, pool, &user_id).await;
+
+    match rows {
+        Ok(mut rs) => {
+            let has_more = rs.len() as i64 > limit;
+            rs.truncate(limit as usize);
+            let messages: Vec<SupportMessage> = rs.into_iter().map(|r| SupportMessage {
+                id: r.get("id"),
+                message: r.get("message"),
+                photo_url: r.get("photo_url"),
+                direction: r.get("direction"),
+                visibility: None,
+                faq_id: r.get("faq_id"),
+                in_reply_to: r.get("in_reply_to"),
+                local_created_at: timezone::to_local(&r.get::<String, _>("created_at"), tz),
+                created_at: r.get("created_at"),
+            }).collect();
+            let next_before = if has_more { messages.last().map(|m| m.id.clone()) } else { None };
+
+            // The greeting heads the thread, so only the first page carries it
+            let greeting = query.before.is_none().then(|| {
+                let settings = &state.config.load().support_greeting;
+                let now = chrono::Utc::now();
+                json!({
+                    "message": settings.render(locale, now),
+                    "in_business_hours": settings.in_business_hours(now),
+                    "reply_within_hours": settings.reply_within_hours,
+                })
+            });
+
+            HttpResponse::Ok().json(json!({
+                "user_id": user_id,
+                "timezone": tz.name(),
+                "messages": messages,
+                "total": total,
+                "has_more": has_more,
+                "next_before": next_before,
+                "greeting": greeting,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AgentMessageRequest {
+    pub message: String,
+    /// `public` (default) or `internal`; a message starting with `/note` is always internal
+    pub visibility: Option<String>,
+}
+
+/// `POST /api/admin/support/{user_id}/messages` records an agent reply or, for
+/// `/note ...` and `visibility: internal`, a note the user never sees
+pub async fn post_agent_message(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<AgentMessageRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let pool = &state.pool;
+    let user_id = resolve_user_id_for_conversations(pool, &path.into_inner()).await;
+    let body = body.into_inner();
+
+    let trimmed = body.message.trim();
+    let (text, visibility) = match trimmed.strip_prefix(NOTE_COMMAND) {
+        Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => (rest.trim(), "internal"),
+        _ => match body.visibility.as_deref() {
+            None | Some("public") => (trimmed, "public"),
+            Some("internal") => (trimmed, "internal"),
+            Some(_) => {
+                let error_msg = match locale {
+                    Locale::Ru => "Некорректная видимость сообщения",
+                    Locale::En => "invalid-visibility",
+                };
+                return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+            }
+        },
+    };
+    if text.is_empty() {
+        let error_msg = match locale {
+            Locale::Ru => "Сообщение не может быть пустым",
+            Locale::En => "message-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let user_exists: i64 = match sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if user_exists == 0 {
+        let error_msg = match locale {
+            Locale::Ru => "Пользователь не найден",
+            Locale::En => "user-not-found",
+        };
+        return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let row = sqlx::query(
+        "INSERT INTO support_messages (id, user_id, message, direction, visibility)
+         VALUES (?, ?, ?, 'support', ?)
+         RETURNING created_at"
+    )
+    .bind(&id)
+    .bind(&user_id)
+    .bind(text)
+    .bind(visibility)
+    .fetch_one(pool)
+    .await;
+
+    match row {
+        Ok(r) => HttpResponse::Created().json(SupportMessage {
+            id,
+            message: text.to_string(),
+            photo_url: None,
+            direction: "support".to_string(),
+            visibility: Some(visibility.to_string()),
+            faq_id: None,
+            in_reply_to: None,
+            created_at: r.get("created_at"),
+            local_created_at: None,
+        }),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AgentThreadQuery {
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/admin/support/{user_id}/thread` is the agent view of a thread,
+/// internal notes and escalation events included, newest first
+pub async fn get_agent_thread(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<AgentThreadQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    if let Err(resp) = require_admin(&req, locale) {
+        return resp;
+    }
+    let pool = &state.pool;
+    let user_id = resolve_user_id_for_conversations(pool, &path.into_inner()).await;
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+
+    let rows = sqlx::query(
+        "SELECT id, message, photo_url, direction, visibility, faq_id, in_reply_to, created_at FROM support_messages
+         WHERE user_id = ?
+         ORDER BY julianday(created_at) DESC, rowid DESC
+         LIMIT ?"
+    )
+    .bind(&user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await;
+
+    let escalations = sqlx::query(
+        "SELECT support_message_id, level, chat_id, waited_minutes, created_at FROM support_escalations
+         WHERE user_id = ? ORDER BY created_at DESC LIMIT ?"
+    )
+    .bind(&user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await;
+
+    match (rows, escalations) {
+        (Ok(rs), Ok(es)) => {
+            let messages: Vec<SupportMessage> = rs.into_iter().map(|r| SupportMessage {
+                id: r.get("id"),
+                message: r.get("message"),
+                photo_url: r.get("photo_url"),
+                direction: r.get("direction"),
+                visibility: Some(r.get("visibility")),
+                faq_id: r.get("faq_id"),
+                in_reply_to: r.get("in_reply_to"),
+                created_at: r.get("created_at"),
+                local_created_at: None,
+            }).collect();
+            let escalations: Vec<serde_json::Value> = es.into_iter().map(|r| json!({
+                "support_message_id": r.get::<String, _>("support_message_id"),
+                "level": r.get::<i64, _>("level"),
+                "chat_id": r.get::<i64, _>("chat_id"),
+                "waited_minutes": r.get::<i64, _>("waited_minutes"),
+                "created_at": r.get::<String, _>("created_at"),
+            })).collect();
+            HttpResponse::Ok().json(json!({ "user_id": user_id, "messages": messages, "escalations": escalations }))
+        }
+        _ => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SendSupportMessageRequest {
+    pub user_id: String,
+    #[serde(default)]
+    pub message: String,
+    pub photo_url: Option<String>,
+}
+
+fn support_timestamp() -> String {
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Posts the message to the support group, retrying a few times; a message that still can't
+/// be delivered goes to the dead-letter queue for an admin to replay
+async fn forward_to_telegram(pool: &sqlx::SqlitePool, user_id: &str, message_id: &str, text: &str, photo_url: Option<&str>) -> bool {
+    let sent = dead_letters::with_retries(|| send_support_to_telegram(pool, user_id, message_id, text, photo_url)).await;
+    match sent {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Support: forwarding message {} to Telegram failed: {}", message_id, e);
+            let payload = json!({
+                "user_id": user_id,
+                "support_message_id": message_id,
+                "text": text,
+                "photo_url": photo_url,
+            });
+            dead_letters::record(pool, dead_letters::CHANNEL_SUPPORT_TELEGRAM, &payload, &e, dead_letters::MAX_ATTEMPTS).await;
+            false
+        }
+    }
+}
+
+/// One attempt at posting the message to the support group; remembers the Telegram id so agent replies can be matched
+pub(crate) async fn send_support_to_telegram(
+    pool: &sqlx::SqlitePool,
+    user_id: &str,
+    message_id: &str,
+    text: &str,
+    photo_url: Option<&str>,
+) -> Result<(), String> {
+    let bot = TelegramBot::new().map_err(|e| format!("Telegram unavailable: {}", e))?;
+    let user_name: Option<String> = sqlx::query_scalar("SELECT COALESCE(full_name, email) FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+
+    let sent = match photo_url {
+        Some(url) => bot.send_photo(url, Some(text).filter(|t| !t.is_empty()), user_name.as_deref()).await,
+        None => bot.send_message(text, user_name.as_deref()).await,
+    };
+    let telegram_message_id = sent.map_err(|e| e.to_string())?;
+
+    let _ = sqlx::query("UPDATE support_messages SET telegram_message_id = ? WHERE id = ?")
+        .bind(telegram_message_id)
+        .bind(message_id)
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("INSERT INTO message_mapping (id, telegram_message_id, user_id, support_message_id) VALUES (?, ?, ?, ?)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(telegram_message_id)
+        .bind(user_id)
+        .bind(message_id)
+        .execute(pool)
+        .await;
+    Ok(())
+}
+
+fn talk_to_human_action(locale: Locale, message_id: &str) -> serde_json::Value {
+    let label = match locale {
+        Locale::Ru => "Связаться с оператором",
+        Locale::En => "Talk to a human",
+    };
+    json!({
+        "type": "talk_to_human",
+        "label": label,
+        "url": format!("/api/support/messages/{}/human", message_id),
+    })
+}
+
+/// `POST /api/support/message` stores a user's support message. Text questions are first
+/// matched against the FAQ; a confident match is answered instantly and the ticket marked
+/// auto-resolved, anything else is forwarded to the support group in Telegram.
+pub async fn send_support_message(
+    req: HttpRequest,
+    body: web::Json<SendSupportMessageRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let body = body.into_inner();
+    let text = body.message.trim().to_string();
+    let photo_url = body.photo_url.filter(|u| !u.trim().is_empty());
+
+    if text.is_empty() && photo_url.is_none() {
+        let error_msg = match locale {
+            Locale::Ru => "Сообщение не может быть пустым",
+            Locale::En => "message-required",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+    let user_exists: i64 = match sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE id = ?")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(n) => n,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if user_exists == 0 {
+        let error_msg = match locale {
+            Locale::Ru => "Пользователь не найден",
+            Locale::En => "user-not-found",
+        };
+        return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let created_at = support_timestamp();
+    let inserted = sqlx::query(
+        "INSERT INTO support_messages (id, user_id, message, photo_url, direction, created_at) VALUES (?, ?, ?, ?, 'user', ?)"
+    )
+    .bind(&id)
+    .bind(&user_id)
+    .bind(&text)
+    .bind(&photo_url)
+    .bind(&created_at)
+    .execute(pool)
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let message = SupportMessage {
+        id: id.clone(),
+        message: text.clone(),
+        photo_url: photo_url.clone(),
+        direction: "user".to_string(),
+        visibility: None,
+        faq_id: None,
+        in_reply_to: None,
+        created_at,
+        local_created_at: None,
+    };
+
+    // Photos need a human eye, so only text questions are tried against the FAQ
+    let auto = match photo_url {
+        None => faq::try_answer(&state, locale, &text).await,
+        Some(_) => None,
+    };
+    if let Some(auto) = auto {
+        let reply_id = Uuid::new_v4().to_string();
+        let replied_at = support_timestamp();
+        let mut tx = match pool.begin().await {
+            Ok(tx) => tx,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        let stored = sqlx::query(
+            "INSERT INTO support_messages (id, user_id, message, direction, faq_id, in_reply_to, created_at)
+             VALUES (?, ?, ?, 'support', ?, ?, ?)"
+        )
+        .bind(&reply_id)
+        .bind(&user_id)
+        .bind(&auto.answer)
+        .bind(&auto.faq_id)
+        .bind(&id)
+        .bind(&replied_at)
+        .execute(&mut tx)
+        .await;
+        let resolved = sqlx::query("UPDATE support_messages SET auto_resolved_at = ? WHERE id = ?")
+            .bind(&replied_at)
+            .bind(&id)
+            .execute(&mut tx)
+            .await;
+        if stored.is_ok() && resolved.is_ok() && tx.commit().await.is_ok() {
+            return HttpResponse::Created().json(json!({
+                "message": message,
+                "auto_answer": {
+                    "message": SupportMessage {
+                        id: reply_id,
+                        message: auto.answer,
+                        photo_url: None,
+                        direction: "support".to_string(),
+                        visibility: None,
+                        faq_id: Some(auto.faq_id),
+                        in_reply_to: Some(id.clone()),
+                        created_at: replied_at,
+                        local_created_at: None,
+                    },
+                    "confidence": auto.confidence,
+                },
+                "status": "auto_resolved",
+                "actions": [talk_to_human_action(locale, &id)],
+            }));
+        }
+        // Storing the answer failed: fall through so a human still sees the question
+    }
+
+    let forwarded = forward_to_telegram(pool, &user_id, &id, &text, photo_url.as_deref()).await;
+    HttpResponse::Created().json(json!({
+        "message": message,
+        "auto_answer": null,
+        "status": if forwarded { "forwarded" } else { "queued" },
+        "actions": [],
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct TalkToHumanRequest {
+    pub user_id: String,
+}
+
+/// `POST /api/support/messages/{id}/human` reopens an auto-resolved ticket and forwards it to
+/// Telegram; the SLA for escalation starts counting from here
+pub async fn request_human(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<TalkToHumanRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let message_id = path.into_inner();
+    let user_id = resolve_user_id_for_conversations(pool, &body.user_id).await;
+
+    let row = sqlx::query(
+        "SELECT message, photo_url, telegram_message_id, auto_resolved_at, human_requested_at FROM support_messages
+         WHERE id = ? AND user_id = ? AND direction = 'user'"
+    )
+    .bind(&message_id)
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await;
+    let row = match row {
+        Ok(Some(r)) => r,
+        Ok(None) => {
+            let error_msg = match locale {
+                Locale::Ru => "Сообщение не найдено",
+                Locale::En => "message-not-found",
+            };
+            return HttpResponse::NotFound().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Some(requested_at) = row.get::<Option<String>, _>("human_requested_at") {
+        let forwarded = row.get::<Option<i64>, _>("telegram_message_id").is_some();
+        return HttpResponse::Ok().json(json!({
+            "id": message_id,
+            "status": if forwarded { "forwarded" } else { "queued" },
+            "human_requested_at": requested_at,
+        }));
+    }
+    if row.get::<Option<String>, _>("auto_resolved_at").is_none() {
+        let error_msg = match locale {
+            Locale::Ru => "Сообщение уже передано оператору",
+            Locale::En => "message-not-auto-resolved",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+    }
+
+    let requested_at = support_timestamp();
+    let claimed = sqlx::query(
+        "UPDATE support_messages SET auto_resolved_at = NULL, human_requested_at = ?
+         WHERE id = ? AND auto_resolved_at IS NOT NULL"
+    )
+    .bind(&requested_at)
+    .bind(&message_id)
+    .execute(pool)
+    .await;
+    match claimed {
+        Ok(r) if r.rows_affected() == 1 => {}
+        Ok(_) => return HttpResponse::Ok().json(json!({ "id": message_id, "status": "forwarded" })),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let text = format!("🤖 Автоответ не помог\n\n{}", row.get::<String, _>("message"));
+    let photo_url: Option<String> = row.get("photo_url");
+    let forwarded = forward_to_telegram(pool, &user_id, &message_id, &text, photo_url.as_deref()).await;
+    HttpResponse::Ok().json(json!({
+        "id": message_id,
+        "status": if forwarded { "forwarded" } else { "queued" },
+        "human_requested_at": requested_at,
+    }))
+}
@@ -0,0 +1,382 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::Row;
+
+use crate::handlers::chat::resolve_user_id_for_conversations;
+use crate::handlers::files::{ensure_storage_quota, scan_upload, store_file, FileOwner};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Largest file accepted through a resumable upload
+pub const MAX_UPLOAD_SIZE: i64 = 50 * 1024 * 1024;
+/// Largest single chunk; also the payload limit on the chunk route
+pub const MAX_CHUNK_SIZE: usize = 5 * 1024 * 1024;
+/// Unfinished sessions older than this are dropped by the scheduler
+pub const UPLOAD_SESSION_HOURS: i64 = 24;
+
+/// Resumable uploads are meant for documents and voice/audio attachments
+fn upload_mime_allowed(mime: &str) -> bool {
+    mime.starts_with("audio/")
+        || mime.starts_with("text/")
+        || mime == "application/pdf"
+        || mime == "application/msword"
+        || mime == "application/vnd.ms-excel"
+        || mime.starts_with("application/vnd.openxmlformats-officedocument.")
+}
+
+#[derive(Deserialize)]
+pub struct CreateUploadRequest {
+    pub user_id: String,
+    pub filename: String,
+    pub mime: String,
+    /// Total size in bytes, known up front so progress can be resumed by offset
+    pub size: i64,
+}
+
+#[derive(Deserialize)]
+pub struct ChunkQuery {
+    pub user_id: String,
+    /// Byte offset this chunk starts at; must match the bytes received so far
+    pub offset: i64,
+}
+
+fn upload_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Загрузка не найдена",
+        Locale::En => "upload-not-found",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+/// `POST /api/uploads` opens a session; chunks are then sent with `PATCH /api/uploads/{id}`
+pub async fn create_upload(
+    req: HttpRequest,
+    body: web::Json<CreateUploadRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let data = body.into_inner();
+
+    if data.filename.trim().is_empty() || !upload_mime_allowed(&data.mime) {
+        let error_msg = match locale {
+            Locale::Ru => "Поддерживаются только документы и аудио",
+            Locale::En => "unsupported-upload-type",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+    if data.size <= 0 || data.size > MAX_UPLOAD_SIZE {
+        let error_msg = match locale {
+            Locale::Ru => "Недопустимый размер файла (максимум 50MB)",
+            Locale::En => "invalid-upload-size",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "max_size": MAX_UPLOAD_SIZE }));
+    }
+
+    let user_id = resolve_user_id_for_conversations(pool, &data.user_id).await;
+    if let Err(resp) = ensure_storage_quota(&state, &user_id, data.size as usize, locale).await {
+        return resp;
+    }
+
+    let upload_id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        "INSERT INTO upload_sessions (id, user_id, filename, mime, total_size, received_size, status, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, 0, 'open', ?, ?)"
+    )
+    .bind(&upload_id)
+    .bind(&user_id)
+    .bind(data.filename.trim())
+    .bind(&data.mime)
+    .bind(data.size)
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Created().json(json!({
+        "upload_id": upload_id,
+        "offset": 0,
+        "size": data.size,
+        "max_chunk_size": MAX_CHUNK_SIZE,
+        "upload_url": format!("/api/uploads/{}", upload_id),
+    }))
+}
+
+/// Appends one chunk. The last chunk turns the session into a stored file; if that step
+/// fails (e.g. the virus scanner is down) resending an empty chunk at the final offset retries it.
+pub async fn upload_chunk(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ChunkQuery>,
+    body: web::Bytes,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let upload_id = path.into_inner();
+    let user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    let session = match sqlx::query(
+        "SELECT total_size, received_size, status FROM upload_sessions WHERE id = ? AND user_id = ?"
+    )
+    .bind(&upload_id)
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => r,
+        Ok(None) => return upload_not_found(locale),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let total: i64 = session.get("total_size");
+    let received: i64 = session.get("received_size");
+
+    if session.get::<String, _>("status") != "open" {
+        let error_msg = match locale {
+            Locale::Ru => "Загрузка уже завершена",
+            Locale::En => "upload-already-finished",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+    }
+    // The client lost track of progress: tell it where to resume from
+    if query.offset != received {
+        let error_msg = match locale {
+            Locale::Ru => "Неверное смещение, продолжите с указанного",
+            Locale::En => "offset-mismatch",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg, "offset": received }));
+    }
+    if received + body.len() as i64 > total {
+        let error_msg = match locale {
+            Locale::Ru => "Данные превышают заявленный размер файла",
+            Locale::En => "chunk-exceeds-size",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg }));
+    }
+
+    let received = if body.is_empty() {
+        received
+    } else {
+        match append_chunk(pool, &upload_id, received, &body).await {
+            Ok(true) => received + body.len() as i64,
+            Ok(false) => {
+                // Another request for the same offset won the race
+                let error_msg = match locale {
+                    Locale::Ru => "Неверное смещение, продолжите с указанного",
+                    Locale::En => "offset-mismatch",
+                };
+                return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+            }
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        }
+    };
+
+    if received < total {
+        return HttpResponse::Ok().json(json!({
+            "upload_id": upload_id,
+            "offset": received,
+            "size": total,
+            "status": "open",
+        }));
+    }
+
+    finalize_upload(&state, &upload_id, &user_id, locale).await
+}
+
+/// Stores a chunk and advances the session in one step; `false` when the offset moved meanwhile
+async fn append_chunk(pool: &sqlx::SqlitePool, upload_id: &str, offset: i64, bytes: &[u8]) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let advanced = sqlx::query(
+        "UPDATE upload_sessions SET received_size = received_size + ?, updated_at = ?
+         WHERE id = ? AND received_size = ? AND status = 'open'"
+    )
+    .bind(bytes.len() as i64)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(upload_id)
+    .bind(offset)
+    .execute(&mut tx)
+    .await?;
+    if advanced.rows_affected() == 0 {
+        return Ok(false);
+    }
+    sqlx::query("INSERT INTO upload_chunks (upload_id, position, bytes) VALUES (?, ?, ?)")
+        .bind(upload_id)
+        .bind(offset)
+        .bind(bytes)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+async fn finalize_upload(state: &AppState, upload_id: &str, user_id: &str, locale: Locale) -> HttpResponse {
+    let pool = &state.pool;
+
+    // Only one request assembles the file even if the last chunk is retried concurrently
+    let claimed = sqlx::query("UPDATE upload_sessions SET status = 'finalizing' WHERE id = ? AND status = 'open'")
+        .bind(upload_id)
+        .execute(pool)
+        .await;
+    match claimed {
+        Ok(r) if r.rows_affected() == 1 => {}
+        Ok(_) => {
+            let error_msg = match locale {
+                Locale::Ru => "Загрузка уже завершена",
+                Locale::En => "upload-already-finished",
+            };
+            return HttpResponse::Conflict().json(json!({ "error": error_msg }));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    let session = match sqlx::query("SELECT filename, mime FROM upload_sessions WHERE id = ?")
+        .bind(upload_id)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(r) => r,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let filename: String = session.get("filename");
+    let mime: String = session.get("mime");
+
+    let chunks: Vec<Vec<u8>> = match sqlx::query_scalar("SELECT bytes FROM upload_chunks WHERE upload_id = ? ORDER BY position")
+        .bind(upload_id)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(c) => c,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let bytes = chunks.concat();
+
+    if let Err(resp) = scan_upload(state, user_id, &filename, &mime, &bytes, locale).await {
+        // Infected uploads are final; a scanner outage leaves the session open for a retry
+        if resp.status() == actix_web::http::StatusCode::UNPROCESSABLE_ENTITY {
+            let _ = discard_upload(pool, upload_id, "rejected").await;
+        } else {
+            reopen_upload(pool, upload_id).await;
+        }
+        return resp;
+    }
+
+    let attachment = match store_file(pool, filename, mime, bytes, None, Some(user_id)).await {
+        Ok(a) => a,
+        Err(_) => {
+            reopen_upload(pool, upload_id).await;
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let _ = sqlx::query("UPDATE upload_sessions SET file_id = ? WHERE id = ?")
+        .bind(&attachment.id)
+        .bind(upload_id)
+        .execute(pool)
+        .await;
+    let _ = discard_upload(pool, upload_id, "complete").await;
+
+    HttpResponse::Ok().json(json!({
+        "upload_id": upload_id,
+        "status": "complete",
+        "file": attachment,
+    }))
+}
+
+async fn reopen_upload(pool: &sqlx::SqlitePool, upload_id: &str) {
+    let _ = sqlx::query("UPDATE upload_sessions SET status = 'open' WHERE id = ? AND status = 'finalizing'")
+        .bind(upload_id)
+        .execute(pool)
+        .await;
+}
+
+/// Drops buffered chunks and closes the session with the given status
+async fn discard_upload(pool: &sqlx::SqlitePool, upload_id: &str, status: &str) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM upload_chunks WHERE upload_id = ?")
+        .bind(upload_id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("UPDATE upload_sessions SET status = ?, updated_at = ? WHERE id = ?")
+        .bind(status)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(upload_id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await
+}
+
+/// Progress of a session, used by clients to find the offset to resume from
+pub async fn get_upload(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<FileOwner>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let upload_id = path.into_inner();
+    let user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    match sqlx::query(
+        "SELECT filename, mime, total_size, received_size, status, file_id, created_at, updated_at
+         FROM upload_sessions WHERE id = ? AND user_id = ?"
+    )
+    .bind(&upload_id)
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(Some(r)) => {
+            let file_id: Option<String> = r.get("file_id");
+            HttpResponse::Ok().json(json!({
+                "upload_id": upload_id,
+                "filename": r.get::<String, _>("filename"),
+                "mime": r.get::<String, _>("mime"),
+                "size": r.get::<i64, _>("total_size"),
+                "offset": r.get::<i64, _>("received_size"),
+                "status": r.get::<String, _>("status"),
+                "file_id": file_id,
+                "download_url": file_id.as_ref().map(|id| format!("/api/files/{}", id)),
+                "created_at": r.get::<String, _>("created_at"),
+                "updated_at": r.get::<String, _>("updated_at"),
+            }))
+        }
+        Ok(None) => upload_not_found(locale),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+pub async fn cancel_upload(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<FileOwner>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let upload_id = path.into_inner();
+    let user_id = resolve_user_id_for_conversations(pool, &query.user_id).await;
+
+    let open: Option<i64> = match sqlx::query_scalar(
+        "SELECT 1 FROM upload_sessions WHERE id = ? AND user_id = ? AND status = 'open'"
+    )
+    .bind(&upload_id)
+    .bind(&user_id)
+    .fetch_optional(pool)
+    .await
+    {
+        Ok(o) => o,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if open.is_none() {
+        return upload_not_found(locale);
+    }
+
+    match discard_upload(pool, &upload_id, "cancelled").await {
+        Ok(_) => HttpResponse::Ok().json(json!({ "upload_id": upload_id, "status": "cancelled" })),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
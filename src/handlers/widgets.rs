@@ -0,0 +1,632 @@
+use std::time::Duration;
+
+use actix_web::{HttpRequest, HttpResponse, web};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::handlers::auth::{authorize, TokenCheck};
+use crate::handlers::chat::{get_user_base_context, resolve_user_id_for_conversations};
+use crate::services::widget::{self, WidgetTheme};
+use crate::services::{abuse, breaker, geoip, knowledge, openai};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+/// Widgets one account can have
+const MAX_WIDGETS: i64 = 10;
+/// Sites a single widget may be embedded on
+const MAX_ORIGINS: usize = 20;
+/// Longest visitor message, in characters
+const MAX_VISITOR_MESSAGE: usize = 2000;
+/// Earlier messages of the visitor session sent to the model
+const VISITOR_HISTORY: i64 = 20;
+/// Messages one visitor IP may send to one widget per minute
+const VISITOR_MESSAGES_PER_MINUTE: usize = 10;
+
+#[derive(Serialize)]
+pub struct Widget {
+    pub id: String,
+    pub name: String,
+    pub public_key: String,
+    pub allowed_origins: Vec<String>,
+    pub theme: WidgetTheme,
+    pub embed_code: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+fn widget_from_row(r: &sqlx::sqlite::SqliteRow) -> Widget {
+    let public_key: String = r.get("public_key");
+    Widget {
+        id: r.get("id"),
+        name: r.get("name"),
+        embed_code: widget::embed_code(&public_key),
+        public_key,
+        allowed_origins: serde_json::from_str(&r.get::<String, _>("allowed_origins")).unwrap_or_default(),
+        theme: serde_json::from_str(&r.get::<String, _>("theme")).unwrap_or_default(),
+        created_at: r.get("created_at"),
+        updated_at: r.get("updated_at"),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WidgetRequest {
+    pub name: Option<String>,
+    /// Sites the widget runs on, e.g. `https://shop.example.com`; requests from others are refused
+    pub allowed_origins: Option<Vec<String>>,
+    pub theme: Option<WidgetTheme>,
+}
+
+async fn caller(req: &HttpRequest, state: &AppState, query: &TokenCheck, locale: Locale) -> Result<String, HttpResponse> {
+    let user_id = authorize(req, &state.pool, query, locale).await?;
+    Ok(resolve_user_id_for_conversations(&state.pool, &user_id).await)
+}
+
+fn widget_not_found(locale: Locale) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Виджет не найден",
+        Locale::En => "widget-not-found",
+    };
+    HttpResponse::NotFound().json(json!({ "error": error_msg }))
+}
+
+fn invalid_widget(locale: Locale, field: &str) -> HttpResponse {
+    let error_msg = match locale {
+        Locale::Ru => "Неверные параметры виджета",
+        Locale::En => "invalid-widget",
+    };
+    HttpResponse::BadRequest().json(json!({ "error": error_msg, "field": field }))
+}
+
+/// Normalized, deduplicated origins; `Err` names the offending field
+fn validate_origins(origins: &[String]) -> Result<Vec<String>, &'static str> {
+    if origins.is_empty() || origins.len() > MAX_ORIGINS {
+        return Err("allowed_origins");
+    }
+    let mut out: Vec<String> = Vec::new();
+    for origin in origins {
+        let normalized = widget::normalize_origin(origin).ok_or("allowed_origins")?;
+        if !out.contains(&normalized) {
+            out.push(normalized);
+        }
+    }
+    Ok(out)
+}
+
+/// `POST /api/widgets` creates a chat widget answering on behalf of the caller's business
+pub async fn create_widget(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    body: web::Json<WidgetRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let pool = &state.pool;
+    let body = body.into_inner();
+
+    let name = match body.name.as_deref().map(str::trim).filter(|n| !n.is_empty() && n.chars().count() <= 100) {
+        Some(n) => n.to_string(),
+        None => return invalid_widget(locale, "name"),
+    };
+    let origins = match validate_origins(body.allowed_origins.as_deref().unwrap_or_default()) {
+        Ok(o) => o,
+        Err(field) => return invalid_widget(locale, field),
+    };
+    let theme = body.theme.unwrap_or_default();
+    if !theme.is_valid() {
+        return invalid_widget(locale, "theme");
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM widgets WHERE user_id = ? AND revoked_at IS NULL")
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0);
+    if count >= MAX_WIDGETS {
+        let error_msg = match locale {
+            Locale::Ru => "Достигнуто максимальное число виджетов",
+            Locale::En => "widget-limit-reached",
+        };
+        return HttpResponse::Conflict().json(json!({ "error": error_msg, "max": MAX_WIDGETS }));
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        "INSERT INTO widgets (id, user_id, name, public_key, allowed_origins, theme, created_at, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(&user_id)
+    .bind(&name)
+    .bind(widget::new_key())
+    .bind(serde_json::to_string(&origins).unwrap_or_default())
+    .bind(serde_json::to_string(&theme).unwrap_or_default())
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await;
+    if inserted.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    match load_widget(pool, &id, &user_id).await {
+        Some(w) => HttpResponse::Created().json(w),
+        None => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+async fn load_widget(pool: &sqlx::SqlitePool, id: &str, user_id: &str) -> Option<Widget> {
+    sqlx::query("SELECT * FROM widgets WHERE id = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|r| widget_from_row(&r))
+}
+
+/// `GET /api/widgets` lists the caller's widgets with their total usage
+pub async fn list_widgets(
+    req: HttpRequest,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+
+    let rows = sqlx::query(
+        "SELECT w.*, COALESCE(SUM(u.messages), 0) AS total_messages, COALESCE(SUM(u.sessions), 0) AS total_sessions
+         FROM widgets w LEFT JOIN widget_usage u ON u.widget_id = w.id
+         WHERE w.user_id = ? AND w.revoked_at IS NULL
+         GROUP BY w.id ORDER BY w.created_at"
+    )
+    .bind(&user_id)
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows {
+        Ok(rs) => {
+            let widgets: Vec<serde_json::Value> = rs
+                .iter()
+                .map(|r| json!({
+                    "widget": widget_from_row(r),
+                    "messages": r.get::<i64, _>("total_messages"),
+                    "sessions": r.get::<i64, _>("total_sessions"),
+                }))
+                .collect();
+            HttpResponse::Ok().json(json!({ "widgets": widgets }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `PUT /api/widgets/{id}` changes the name, allowed sites or theme; omitted fields stay
+pub async fn update_widget(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    body: web::Json<WidgetRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let pool = &state.pool;
+    let id = path.into_inner();
+    let body = body.into_inner();
+
+    let name = match body.name.as_deref().map(str::trim) {
+        Some(n) if n.is_empty() || n.chars().count() > 100 => return invalid_widget(locale, "name"),
+        other => other.map(str::to_string),
+    };
+    let origins = match body.allowed_origins.as_deref().map(validate_origins) {
+        Some(Err(field)) => return invalid_widget(locale, field),
+        Some(Ok(o)) => Some(serde_json::to_string(&o).unwrap_or_default()),
+        None => None,
+    };
+    let theme = match body.theme {
+        Some(t) if !t.is_valid() => return invalid_widget(locale, "theme"),
+        Some(t) => Some(serde_json::to_string(&t).unwrap_or_default()),
+        None => None,
+    };
+
+    let result = sqlx::query(
+        "UPDATE widgets SET
+            name = COALESCE(?, name),
+            allowed_origins = COALESCE(?, allowed_origins),
+            theme = COALESCE(?, theme),
+            updated_at = ?
+         WHERE id = ? AND user_id = ? AND revoked_at IS NULL"
+    )
+    .bind(name)
+    .bind(origins)
+    .bind(theme)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(&id)
+    .bind(&user_id)
+    .execute(pool)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => match load_widget(pool, &id, &user_id).await {
+            Some(w) => HttpResponse::Ok().json(w),
+            None => HttpResponse::InternalServerError().finish(),
+        },
+        Ok(_) => widget_not_found(locale),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `POST /api/widgets/{id}/rotate-key` issues a new public key; the old snippet stops working
+pub async fn rotate_widget_key(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let pool = &state.pool;
+    let id = path.into_inner();
+
+    let result = sqlx::query("UPDATE widgets SET public_key = ?, updated_at = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(widget::new_key())
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(&id)
+        .bind(&user_id)
+        .execute(pool)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => match load_widget(pool, &id, &user_id).await {
+            Some(w) => HttpResponse::Ok().json(w),
+            None => HttpResponse::InternalServerError().finish(),
+        },
+        Ok(_) => widget_not_found(locale),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `DELETE /api/widgets/{id}` turns the widget off; its usage history is kept
+pub async fn delete_widget(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<TokenCheck>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let user_id = match caller(&req, &state, &query, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let id = path.into_inner();
+
+    let result = sqlx::query("UPDATE widgets SET revoked_at = ? WHERE id = ? AND user_id = ? AND revoked_at IS NULL")
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(&id)
+        .bind(&user_id)
+        .execute(&state.pool)
+        .await;
+
+    match result {
+        Ok(r) if r.rows_affected() > 0 => HttpResponse::Ok().json(json!({ "status": "deleted", "widget_id": id })),
+        Ok(_) => widget_not_found(locale),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    pub token: Option<String>,
+    pub days: Option<i64>,
+}
+
+/// `GET /api/widgets/{id}/usage?days=30` returns daily visitor messages and new sessions
+pub async fn widget_usage(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<UsageQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let token = TokenCheck { token: query.token.clone() };
+    let user_id = match caller(&req, &state, &token, locale).await {
+        Ok(id) => id,
+        Err(resp) => return resp,
+    };
+    let pool = &state.pool;
+    let id = path.into_inner();
+    if load_widget(pool, &id, &user_id).await.is_none() {
+        return widget_not_found(locale);
+    }
+
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    let since = (chrono::Utc::now() - chrono::Duration::days(days - 1)).format("%Y-%m-%d").to_string();
+    let rows = sqlx::query("SELECT day, messages, sessions FROM widget_usage WHERE widget_id = ? AND day >= ? ORDER BY day")
+        .bind(&id)
+        .bind(&since)
+        .fetch_all(pool)
+        .await;
+
+    match rows {
+        Ok(rs) => {
+            let daily: Vec<serde_json::Value> = rs
+                .iter()
+                .map(|r| json!({
+                    "day": r.get::<String, _>("day"),
+                    "messages": r.get::<i64, _>("messages"),
+                    "sessions": r.get::<i64, _>("sessions"),
+                }))
+                .collect();
+            HttpResponse::Ok().json(json!({
+                "widget_id": id,
+                "since": since,
+                "messages": rs.iter().map(|r| r.get::<i64, _>("messages")).sum::<i64>(),
+                "sessions": rs.iter().map(|r| r.get::<i64, _>("sessions")).sum::<i64>(),
+                "daily": daily,
+            }))
+        }
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// ========== PUBLIC, CALLED FROM VISITORS' BROWSERS ==========
+
+/// `GET /widget.js`: the embed script; it reads its key from `data-widget`
+pub async fn widget_script() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/javascript; charset=utf-8")
+        .insert_header(("Cache-Control", "public, max-age=3600"))
+        .body(include_str!("../../assets/widget.js"))
+}
+
+/// An active widget by public key, as `(id, owner, allowed origins, theme)`
+async fn widget_by_key(pool: &sqlx::SqlitePool, key: &str) -> Option<(String, String, Vec<String>, WidgetTheme)> {
+    let r = sqlx::query("SELECT id, user_id, allowed_origins, theme FROM widgets WHERE public_key = ? AND revoked_at IS NULL")
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()?;
+    Some((
+        r.get("id"),
+        r.get("user_id"),
+        serde_json::from_str(&r.get::<String, _>("allowed_origins")).unwrap_or_default(),
+        serde_json::from_str(&r.get::<String, _>("theme")).unwrap_or_default(),
+    ))
+}
+
+/// The page's origin if the widget may run there. Browsers always send `Origin` on these
+/// cross-site calls, so a missing header is refused as well.
+fn allowed_origin(req: &HttpRequest, allowed: &[String], locale: Locale) -> Result<String, HttpResponse> {
+    let origin = req
+        .headers()
+        .get("Origin")
+        .and_then(|v| v.to_str().ok())
+        .and_then(widget::normalize_origin);
+    match origin {
+        Some(o) if allowed.contains(&o) => Ok(o),
+        _ => {
+            let error_msg = match locale {
+                Locale::Ru => "Виджет не разрешен на этом сайте",
+                Locale::En => "origin-not-allowed",
+            };
+            Err(HttpResponse::Forbidden().json(json!({ "error": error_msg })))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WidgetConfigQuery {
+    pub key: String,
+}
+
+/// `GET /api/widget/config?key=` returns the theme the embed script renders
+pub async fn widget_config(
+    req: HttpRequest,
+    query: web::Query<WidgetConfigQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let (_, _, allowed, theme) = match widget_by_key(&state.pool, &query.key).await {
+        Some(w) => w,
+        None => return widget_not_found(locale),
+    };
+    if let Err(resp) = allowed_origin(&req, &allowed, locale) {
+        return resp;
+    }
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-cache"))
+        .json(json!({ "theme": theme }))
+}
+
+#[derive(Deserialize)]
+pub struct VisitorMessage {
+    pub key: String,
+    /// Returned by the first reply; omitted to start a new visitor session
+    pub session_id: Option<String>,
+    pub message: String,
+}
+
+/// `POST /api/widget/messages` answers a site visitor on behalf of the widget owner's business.
+/// No cookies or account tokens are involved: the public key only reaches this widget, and only
+/// from its allowed sites.
+pub async fn widget_message(
+    req: HttpRequest,
+    body: web::Json<VisitorMessage>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let locale = i18n::detect_locale(&req);
+    let pool = &state.pool;
+    let body = body.into_inner();
+
+    let (widget_id, owner_id, allowed, _) = match widget_by_key(pool, &body.key).await {
+        Some(w) => w,
+        None => return widget_not_found(locale),
+    };
+    let origin = match allowed_origin(&req, &allowed, locale) {
+        Ok(o) => o,
+        Err(resp) => return resp,
+    };
+
+    let message = body.message.trim();
+    if message.is_empty() || message.chars().count() > MAX_VISITOR_MESSAGE {
+        let error_msg = match locale {
+            Locale::Ru => "Сообщение пустое или слишком длинное",
+            Locale::En => "invalid-message",
+        };
+        return HttpResponse::BadRequest().json(json!({ "error": error_msg, "max_chars": MAX_VISITOR_MESSAGE }));
+    }
+
+    let client_ip = geoip::client_ip(&req).map(|ip| ip.to_string()).unwrap_or_else(|| "unknown".to_string());
+    if !abuse::allow("widget", &format!("{}:{}", widget_id, client_ip), VISITOR_MESSAGES_PER_MINUTE, Duration::from_secs(60)) {
+        let error_msg = match locale {
+            Locale::Ru => "Слишком много сообщений, попробуйте позже",
+            Locale::En => "too-many-requests",
+        };
+        return HttpResponse::TooManyRequests().json(json!({ "error": error_msg }));
+    }
+    if let Some(wait) = breaker::retry_after() {
+        let error_msg = match locale {
+            Locale::Ru => "Ассистент временно недоступен",
+            Locale::En => "assistant-unavailable",
+        };
+        return HttpResponse::ServiceUnavailable()
+            .insert_header(("Retry-After", wait.as_secs().max(1).to_string()))
+            .json(json!({ "error": error_msg, "retry_after_seconds": wait.as_secs().max(1) }));
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let existing: Option<String> = match &body.session_id {
+        Some(sid) => sqlx::query_scalar("SELECT id FROM widget_sessions WHERE id = ? AND widget_id = ?")
+            .bind(sid)
+            .bind(&widget_id)
+            .fetch_optional(pool)
+            .await
+            .ok()
+            .flatten(),
+        None => None,
+    };
+    let new_session = existing.is_none();
+    let session_id = match existing {
+        Some(sid) => sid,
+        None => {
+            let sid = Uuid::new_v4().to_string();
+            let created = sqlx::query("INSERT INTO widget_sessions (id, widget_id, origin, created_at, last_seen_at) VALUES (?, ?, ?, ?, ?)")
+                .bind(&sid)
+                .bind(&widget_id)
+                .bind(&origin)
+                .bind(&now)
+                .bind(&now)
+                .execute(pool)
+                .await;
+            if created.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            sid
+        }
+    };
+
+    // Newest first from the query, oldest first for the model
+    let mut history: Vec<(String, String)> = sqlx::query(
+        "SELECT role, content FROM widget_messages WHERE session_id = ? ORDER BY created_at DESC, rowid DESC LIMIT ?"
+    )
+    .bind(&session_id)
+    .bind(VISITOR_HISTORY)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+    .iter()
+    .map(|r| (r.get::<String, _>("role"), r.get::<String, _>("content")))
+    .collect();
+    history.reverse();
+
+    let business_type: String = sqlx::query_scalar("SELECT business_type FROM users WHERE id = ?")
+        .bind(&owner_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "general".to_string());
+    history.insert(0, ("system".to_string(), widget::visitor_instruction(&business_type, locale)));
+    if state.config.load().feature_enabled("knowledge_base", true) {
+        let chunks = knowledge::relevant_chunks(pool, &owner_id, message).await;
+        if !chunks.is_empty() {
+            history.push(("system".to_string(), knowledge::prompt_note(&chunks, locale)));
+        }
+    }
+
+    let model = openai::chat_model(&state, "general", false, None, None).await;
+    let context = get_user_base_context(pool, &owner_id).await;
+    let reply = match openai::generate_response(
+        message, "general", &business_type, &state, &model, &owner_id, locale, Some(history), context, &[], None,
+    )
+    .await
+    {
+        Ok(r) => r.text,
+        Err(e) => {
+            eprintln!("Widget {}: generation failed: {}", widget_id, e);
+            let error_msg = match locale {
+                Locale::Ru => "Ассистент временно недоступен",
+                Locale::En => "assistant-unavailable",
+            };
+            return HttpResponse::BadGateway().json(json!({ "error": error_msg }));
+        }
+    };
+
+    let reply_id = Uuid::new_v4().to_string();
+    let replied_at = chrono::Utc::now().to_rfc3339();
+    let mut tx = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    for (id, role, content, at) in [
+        (Uuid::new_v4().to_string(), "user", message, &now),
+        (reply_id.clone(), "assistant", reply.as_str(), &replied_at),
+    ] {
+        let saved = sqlx::query("INSERT INTO widget_messages (id, session_id, role, content, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(id)
+            .bind(&session_id)
+            .bind(role)
+            .bind(content)
+            .bind(at)
+            .execute(&mut tx)
+            .await;
+        if saved.is_err() {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    let touched = sqlx::query("UPDATE widget_sessions SET last_seen_at = ? WHERE id = ?")
+        .bind(&replied_at)
+        .bind(&session_id)
+        .execute(&mut tx)
+        .await;
+    if touched.is_err() || tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if let Err(e) = widget::record_usage(pool, &widget_id, new_session).await {
+        eprintln!("Widget {}: usage not recorded: {}", widget_id, e);
+    }
+
+    HttpResponse::Ok().json(json!({
+        "session_id": session_id,
+        "message_id": reply_id,
+        "reply": reply,
+        "timestamp": replied_at,
+    }))
+}
@@ -0,0 +1,223 @@
+use std::time::{Duration, Instant};
+
+use actix::{Actor, ActorContext, AsyncContext, Handler, Message, StreamHandler};
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web_actors::ws;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::handlers::chat::{prepare_turn, stream_turn};
+use crate::models::{ChatRequest, ContextFilters, InlineImage, TableSpec};
+use crate::state::AppState;
+use crate::i18n::{self, Locale};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+pub struct ChatSocketQuery {
+    pub user_id: String,
+    /// Omitted to start a new conversation on the first message
+    pub conversation_id: Option<String>,
+}
+
+/// A message sent by the client; everything except `message` is optional
+#[derive(Deserialize)]
+struct ClientFrame {
+    message: String,
+    category: Option<String>,
+    business_type: Option<String>,
+    output_format: Option<String>,
+    table: Option<TableSpec>,
+    language: Option<String>,
+    context_filters: Option<ContextFilters>,
+    #[serde(default)]
+    attachment_ids: Vec<String>,
+    #[serde(default)]
+    images: Vec<InlineImage>,
+    response_schema: Option<serde_json::Value>,
+    clarify: Option<bool>,
+    model: Option<String>,
+    #[serde(default)]
+    tts: bool,
+}
+
+/// Event pushed to the client as `{"type": event, "data": ...}`
+#[derive(Message)]
+#[rtype(result = "()")]
+struct ServerEvent {
+    event: String,
+    data: serde_json::Value,
+}
+
+/// Frees the connection for the next message when a turn ends without a `done` event
+#[derive(Message)]
+#[rtype(result = "()")]
+struct TurnFinished;
+
+/// One live connection bound to a single conversation
+pub struct ChatSocket {
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    user_id: String,
+    conversation_id: Option<String>,
+    /// Only one answer is generated at a time per connection
+    busy: bool,
+    last_heartbeat: Instant,
+}
+
+impl ChatSocket {
+    fn send_event(ctx: &mut ws::WebsocketContext<Self>, event: &str, data: serde_json::Value) {
+        ctx.text(json!({ "type": event, "data": data }).to_string());
+    }
+
+    fn send_error(&self, ctx: &mut ws::WebsocketContext<Self>, ru: &str, en: &str) {
+        let error_msg = match i18n::detect_locale(&self.req) {
+            Locale::Ru => ru,
+            Locale::En => en,
+        };
+        Self::send_event(ctx, "error", json!({ "error": error_msg }));
+    }
+
+    fn start_turn(&mut self, frame: ClientFrame, ctx: &mut ws::WebsocketContext<Self>) {
+        if self.busy {
+            self.send_error(ctx, "Дождитесь окончания текущего ответа", "turn-in-progress");
+            return;
+        }
+        self.busy = true;
+
+        let chat_req = ChatRequest {
+            message: frame.message,
+            category: frame.category,
+            user_id: self.user_id.clone(),
+            business_type: frame.business_type,
+            conversation_id: self.conversation_id.clone(),
+            output_format: frame.output_format,
+            table: frame.table,
+            language: frame.language,
+            context_filters: frame.context_filters,
+            attachment_ids: frame.attachment_ids,
+            images: frame.images,
+            response_schema: frame.response_schema,
+            clarify: frame.clarify,
+            model: frame.model,
+            tts: frame.tts,
+        };
+        let state = self.state.clone();
+        let req = self.req.clone();
+        let addr = ctx.address();
+
+        // Detached like the SSE endpoint: a dropped socket doesn't lose the answer
+        actix_web::rt::spawn(async move {
+            let turn = match prepare_turn(&req, chat_req, &state).await {
+                Ok(t) => t,
+                Err(resp) => {
+                    let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap_or_default();
+                    let data = serde_json::from_slice(&body).unwrap_or_else(|_| json!({}));
+                    addr.do_send(ServerEvent { event: "error".to_string(), data });
+                    addr.do_send(TurnFinished);
+                    return;
+                }
+            };
+            stream_turn(&state, turn, |event, data| {
+                addr.do_send(ServerEvent { event: event.to_string(), data });
+            }).await;
+        });
+    }
+}
+
+impl Actor for ChatSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if Instant::now().duration_since(act.last_heartbeat) > CLIENT_TIMEOUT {
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+}
+
+impl Handler<ServerEvent> for ChatSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: ServerEvent, ctx: &mut Self::Context) {
+        match msg.event.as_str() {
+            // Later messages on this socket continue the conversation the first one created
+            "meta" => {
+                if let Some(id) = msg.data.get("conversation_id").and_then(|v| v.as_str()) {
+                    self.conversation_id = Some(id.to_string());
+                }
+            }
+            // `pending`: accepted while the provider is down, the answer arrives later by push
+            "done" | "cancelled" | "pending" => self.busy = false,
+            _ => {}
+        }
+        Self::send_event(ctx, &msg.event, msg.data);
+    }
+}
+
+impl Handler<TurnFinished> for ChatSocket {
+    type Result = ();
+
+    fn handle(&mut self, _msg: TurnFinished, _ctx: &mut Self::Context) {
+        self.busy = false;
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for ChatSocket {
+    fn handle(&mut self, item: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let msg = match item {
+            Ok(m) => m,
+            Err(_) => {
+                ctx.stop();
+                return;
+            }
+        };
+        self.last_heartbeat = Instant::now();
+
+        match msg {
+            ws::Message::Ping(bytes) => ctx.pong(&bytes),
+            ws::Message::Pong(_) => {}
+            ws::Message::Text(text) => match serde_json::from_str::<ClientFrame>(&text) {
+                Ok(frame) if !frame.message.trim().is_empty() => self.start_turn(frame, ctx),
+                _ => self.send_error(ctx, "Неверный формат сообщения", "invalid-message"),
+            },
+            ws::Message::Binary(_) => self.send_error(ctx, "Неверный формат сообщения", "invalid-message"),
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `GET /ws/chat?user_id=&conversation_id=` upgrades to a live chat session
+pub async fn chat_socket(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<ChatSocketQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if query.user_id.trim().is_empty() {
+        let error_msg = match i18n::detect_locale(&req) {
+            Locale::Ru => "Требуется user_id",
+            Locale::En => "user_id is required",
+        };
+        return Ok(HttpResponse::BadRequest().json(json!({ "error": error_msg })));
+    }
+
+    let query = query.into_inner();
+    let socket = ChatSocket {
+        state,
+        req: req.clone(),
+        user_id: query.user_id,
+        conversation_id: query.conversation_id,
+        busy: false,
+        last_heartbeat: Instant::now(),
+    };
+    ws::start(socket, &req, stream)
+}
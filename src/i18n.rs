@@ -25,6 +25,23 @@ pub fn detect_locale(req: &HttpRequest) -> Locale {
     Locale::En
 }
 
+/// Locale for a language code such as `ru` or `en-US`; `None` for languages we don't answer in
+pub fn parse_language(code: &str) -> Option<Locale> {
+    let code = code.trim().to_ascii_lowercase();
+    match code.split(['-', '_']).next() {
+        Some("ru") => Some(Locale::Ru),
+        Some("en") => Some(Locale::En),
+        _ => None,
+    }
+}
+
+pub fn language_code(locale: Locale) -> &'static str {
+    match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    }
+}
+
 pub fn direction_label(locale: Locale, dir: &str) -> Cow<'static, str> {
     match (locale, dir) {
         (Locale::Ru, "growing") => Cow::Borrowed("рост"),
@@ -4,9 +4,12 @@ mod services;
 mod state;
 mod db;
 mod i18n;
+mod scheduler;
+mod config;
+mod metrics;
 
 use actix_web::{web, App, HttpServer};
-use actix_web::middleware::NormalizePath;
+use actix_web::middleware::{from_fn, NormalizePath};
 use actix_cors::Cors;
 use state::AppState;
 
@@ -45,23 +48,86 @@ async fn main() -> std::io::Result<()> {
     let pool = db::init_pool(&database_url)
         .await
         .expect("Failed to initialize SQLite pool");
-    let app_state = web::Data::new(AppState::new(pool));
+
+    match services::reference::normalize_stored(&pool).await {
+        Ok(0) => {}
+        Ok(n) => println!("Normalized {} stored country values to ISO codes", n),
+        Err(e) => eprintln!("Normalizing stored country values failed: {}", e),
+    }
+
+    let readiness = services::selfcheck::run(&pool).await;
+    readiness.log();
+
+    let runtime_config = config::load().expect("Failed to read config file");
+    let shared_config: config::SharedConfig = std::sync::Arc::new(arc_swap::ArcSwap::from_pointee(runtime_config));
+    config::watch_sighup(shared_config.clone());
+    scheduler::spawn(pool.clone(), shared_config.clone());
+
+    let app_state = web::Data::new(AppState::new(pool, shared_config, readiness));
+    services::quality::spawn(app_state.get_ref().clone());
+    services::retry_queue::spawn(app_state.get_ref().clone());
+    services::daily_digest::spawn(app_state.get_ref().clone());
+    services::analytics_refresh::spawn(app_state.get_ref().clone());
+    services::trends::spawn(app_state.get_ref().clone());
+
+    let tls = services::tls::TlsSettings::from_env();
+    let hsts_max_age = tls.as_ref().map(|t| t.hsts_max_age);
     
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(NormalizePath::trim())
             .wrap(Cors::permissive())
+            .wrap(from_fn(services::jwt::verify_request))
+            .wrap(services::tls::secure_headers(hsts_max_age))
             .app_data(app_state.clone())
+            .app_data(handlers::limits::json_config(handlers::limits::MAX_JSON_BODY))
             .route("/", web::get().to(handlers::main))
             .route("/health", web::get().to(handlers::health_check))
+            .route("/ready", web::get().to(handlers::readiness))
+            .route("/metrics", web::get().to(metrics::metrics))
             
-            .route("/api/chat/message", web::post().to(handlers::chat::send_message))
+            .service(
+                web::resource("/api/chat/message")
+                    .app_data(handlers::limits::json_config(handlers::limits::MAX_CHAT_JSON_BODY))
+                    .route(web::post().to(handlers::chat::send_message))
+            )
+            .route("/api/chat/message/upload", web::post().to(handlers::chat::send_message_with_files))
+            .route("/api/chat/message/audio", web::post().to(handlers::chat::send_voice_message))
+            .service(
+                web::resource("/api/chat/message/stream")
+                    .app_data(handlers::limits::json_config(handlers::limits::MAX_CHAT_JSON_BODY))
+                    .route(web::post().to(handlers::chat::send_message_stream))
+            )
             .route("/api/chat/conversations", web::post().to(handlers::chat::create_conversation))
+            .route("/api/chat/conversations/bulk-delete", web::post().to(handlers::chat::bulk_delete_conversations))
             .route("/api/chat/conversations/{user_id}", web::get().to(handlers::chat::list_conversations))
             .route("/api/chat/conversations/{conversation_id}", web::delete().to(handlers::chat::delete_conversation))
+            .route("/api/chat/conversations/{conversation_id}/pin", web::put().to(handlers::chat::pin_conversation))
+            .route("/api/chat/conversations/{conversation_id}/archive", web::put().to(handlers::chat::archive_conversation))
+            .route("/api/chat/conversations/{conversation_id}/restore", web::post().to(handlers::chat::restore_conversation))
+            .route("/api/chat/conversations/{conversation_id}/cancel", web::post().to(handlers::chat::cancel_generation))
             .route("/api/chat/conversations/{conversation_id}/title", web::put().to(handlers::chat::update_conversation_title))
+            .route("/api/chat/conversations/{conversation_id}/language", web::put().to(handlers::chat::update_conversation_language))
             .route("/api/chat/conversations/{conversation_id}/context", web::put().to(handlers::chat::update_conversation_context))
+            .route("/api/chat/conversations/{conversation_id}/search", web::get().to(handlers::chat::search_conversation))
+            .route("/api/chat/conversations/{conversation_id}/fork", web::post().to(handlers::chat::fork_conversation))
+            .route("/api/chat/conversations/{conversation_id}/read", web::post().to(handlers::reads::mark_read))
+            .route("/api/chat/conversations/{conversation_id}/draft", web::get().to(handlers::drafts::get_draft))
+            .route("/api/chat/conversations/{conversation_id}/draft", web::put().to(handlers::drafts::save_draft))
+            .route("/api/chat/conversations/{conversation_id}/share", web::post().to(handlers::share::share_conversation))
+            .route("/api/chat/conversations/{conversation_id}/share", web::delete().to(handlers::share::revoke_share))
+            .route("/api/chat/conversations/{conversation_id}/export", web::get().to(handlers::chat::export_conversation))
+            .route("/api/chat/conversations/{conversation_id}/stats", web::get().to(handlers::chat::conversation_stats))
+            .route("/api/chat/conversations/{conversation_id}/files", web::get().to(handlers::files::list_conversation_files))
+            .route("/api/chat/search", web::get().to(handlers::chat::search_conversations))
+            .route("/api/chat/models", web::get().to(handlers::chat::list_models))
+            .route("/api/chat/unread/{user_id}", web::get().to(handlers::reads::unread_counts))
+            .route("/api/chat/read-all", web::post().to(handlers::reads::mark_all_read))
             .route("/api/chat/history/{conversation_id}", web::get().to(handlers::chat::get_conversation_history))
+            .route("/api/chat/messages/{message_id}", web::put().to(handlers::chat::edit_message))
+            .route("/api/chat/messages/{message_id}/retry", web::post().to(handlers::chat::retry_message))
+            .route("/api/chat/messages/{message_id}/feedback", web::post().to(handlers::feedback::submit_feedback))
+            .route("/ws/chat", web::get().to(handlers::ws::chat_socket))
             
             .route("/api/auth/register", web::post().to(handlers::auth::register))
             .route("/api/auth/login", web::post().to(handlers::auth::login))
@@ -71,6 +137,25 @@ async fn main() -> std::io::Result<()> {
             .route("/api/auth/profile/{user_id}", web::get().to(handlers::auth::get_profile))
             .route("/api/auth/profile", web::put().to(handlers::auth::update_profile))
             .route("/api/auth/profile-picture", web::post().to(handlers::auth::upload_profile_picture))
+            .route("/api/auth/device", web::put().to(handlers::auth::rename_device))
+            .route("/api/auth/logout", web::post().to(handlers::auth::logout))
+            .route("/api/auth/refresh", web::post().to(handlers::auth::refresh))
+            .route("/api/auth/change-password", web::post().to(handlers::auth::change_password))
+            .route("/api/auth/change-email", web::post().to(handlers::auth::change_email))
+            .route("/api/auth/change-email/confirm", web::get().to(handlers::auth::confirm_email_change))
+            .route("/api/auth/account", web::delete().to(handlers::auth::delete_account))
+            .route("/api/auth/export", web::get().to(handlers::auth::export_account))
+            .route("/api/auth/oauth/{provider}", web::post().to(handlers::oauth::sign_in))
+            .route("/api/auth/otp/request", web::post().to(handlers::otp::request_code))
+            .route("/api/auth/otp/verify", web::post().to(handlers::otp::verify_code))
+            .route("/api/auth/onboarding", web::get().to(handlers::onboarding::get_onboarding))
+            .route("/api/auth/onboarding", web::post().to(handlers::onboarding::mark_step))
+            .route("/api/auth/preferences", web::get().to(handlers::preferences::get_preferences))
+            .route("/api/auth/preferences", web::put().to(handlers::preferences::update_preferences))
+            .route("/api/auth/sessions", web::get().to(handlers::auth::list_sessions))
+            .route("/api/auth/sessions", web::delete().to(handlers::auth::revoke_other_sessions))
+            .route("/api/auth/sessions/{session_id}", web::delete().to(handlers::auth::revoke_session))
+            .route("/api/auth/stats/{user_id}", web::get().to(handlers::stats::get_user_stats))
 
             .route("/api/telegram/users", web::post().to(handlers::telegram::create_or_get_telegram_user))
             .route("/api/telegram/users/{telegram_user_id}", web::get().to(handlers::telegram::get_telegram_user_by_id))
@@ -82,16 +167,113 @@ async fn main() -> std::io::Result<()> {
             .route("/api/analytics/ai-analytics", web::post().to(handlers::analytics::upsert_ai_analytics))
             .route("/api/analytics/niches-month", web::get().to(handlers::analytics::get_niches_month))
             .route("/api/analytics/niches-month", web::post().to(handlers::analytics::upsert_niches_month))
+            .route("/api/analytics/for-me", web::get().to(handlers::analytics::get_for_me))
 
             .route("/api/analytics/top-trend", web::get().to(handlers::analytics::get_top_trend))
             .route("/api/analytics/top-trend", web::post().to(handlers::analytics::upsert_top_trend))
             .route("/api/analytics/popularity", web::get().to(handlers::analytics::get_popularity_trends))
             .route("/api/analytics/popularity", web::post().to(handlers::analytics::upsert_popularity_trend))
             
+            .route("/api/leads/import", web::post().to(handlers::leads::import_leads))
+            .route("/api/leads/export", web::get().to(handlers::leads::export_leads))
+
+            .route("/api/bookings", web::post().to(handlers::bookings::create_booking))
+            .route("/api/bookings", web::get().to(handlers::bookings::list_bookings))
+            .route("/api/bookings/services", web::post().to(handlers::bookings::create_service))
+            .route("/api/bookings/services", web::get().to(handlers::bookings::list_services))
+            .route("/api/bookings/services/{service_id}", web::get().to(handlers::bookings::get_service))
+            .route("/api/bookings/services/{service_id}/slots", web::get().to(handlers::bookings::get_slots))
+
+            .route("/api/inventory", web::get().to(handlers::inventory::list_items))
+            .route("/api/inventory", web::post().to(handlers::inventory::create_item))
+            .route("/api/inventory/{item_id}", web::put().to(handlers::inventory::update_item))
+            .route("/api/inventory/{item_id}", web::delete().to(handlers::inventory::delete_item))
+
+            .route("/api/tools/invoice", web::post().to(handlers::invoices::create_invoice))
+            .route("/api/tools/invoices", web::get().to(handlers::invoices::list_invoices))
+            .route("/api/payments/links", web::post().to(handlers::payments::create_payment_link))
+            .route("/api/payments/links", web::get().to(handlers::payments::list_payment_links))
+            .route("/api/payments/webhook/yookassa", web::post().to(handlers::payments::yookassa_webhook))
+
+            .route("/api/admin/exports/run", web::post().to(handlers::admin::run_export))
+            .route("/api/admin/exports/schema", web::get().to(handlers::admin::export_schema))
+            .route("/api/admin/analytics/topics", web::get().to(handlers::admin::topic_report))
+            .route("/api/admin/analytics/feedback", web::get().to(handlers::feedback::feedback_report))
+            .route("/api/admin/analytics/quality", web::get().to(handlers::quality::quality_report))
+            .route("/api/admin/analytics/quality/run", web::post().to(handlers::quality::run_quality_eval))
+            .route("/api/admin/analytics/trends/refresh", web::post().to(handlers::analytics::refresh_trends))
+            .route("/api/admin/storage/tables", web::get().to(handlers::admin::table_size_report))
+            .route("/api/admin/storage/orphans/purge", web::post().to(handlers::files::purge_orphaned_files))
+            .route("/api/admin/archives", web::get().to(handlers::admin::list_archives))
+            .route("/api/admin/archives/run", web::post().to(handlers::admin::run_archive))
+            .route("/api/admin/archives/{id}", web::get().to(handlers::admin::download_archive))
+            .route("/api/admin/config/reload", web::post().to(handlers::admin::reload_config))
+            .route("/api/admin/support/greeting", web::get().to(handlers::admin::get_support_greeting))
+            .route("/api/admin/support/greeting", web::put().to(handlers::admin::update_support_greeting))
+            .route("/api/admin/disclaimers", web::get().to(handlers::admin::list_disclaimers))
+            .route("/api/admin/disclaimers", web::post().to(handlers::admin::publish_disclaimer))
+            .route("/api/admin/dead-letters", web::get().to(handlers::admin::list_dead_letters))
+            .route("/api/admin/dead-letters/{id}/replay", web::post().to(handlers::admin::replay_dead_letter))
+            .route("/api/admin/category-models", web::get().to(handlers::category_models::list_category_models))
+            .route("/api/admin/category-models/{category}", web::put().to(handlers::category_models::set_category_model))
+            .route("/api/admin/category-models/{category}", web::delete().to(handlers::category_models::delete_category_model))
+            .route("/api/admin/support/faqs", web::get().to(handlers::faq::list_faqs))
+            .route("/api/admin/support/faqs", web::post().to(handlers::faq::create_faq))
+            .route("/api/admin/support/faqs/{id}", web::put().to(handlers::faq::update_faq))
+            .route("/api/admin/support/faqs/{id}", web::delete().to(handlers::faq::delete_faq))
+            .route("/api/admin/support/{user_id}/messages", web::post().to(handlers::support::post_agent_message))
+            .route("/api/admin/support/{user_id}/thread", web::get().to(handlers::support::get_agent_thread))
+            .route("/api/admin/users/lookup", web::get().to(handlers::admin::lookup_user))
+            .route("/api/admin/feedback/{id}", web::put().to(handlers::roadmap::update_item))
+
+            .route("/share/{token}", web::get().to(handlers::share::view_shared))
             .route("/privacy-policy", web::get().to(handlers::legal::privacy_policy))
+            .route("/api/support/history/{user_id}", web::get().to(handlers::support::get_support_history))
+            .route("/api/support/faq", web::get().to(handlers::faq::list_public_faqs))
+            .route("/api/feedback", web::post().to(handlers::roadmap::create_item))
+            .route("/api/feedback", web::get().to(handlers::roadmap::list_items))
+            .route("/api/feedback/{id}/vote", web::post().to(handlers::roadmap::vote))
+            .route("/api/feedback/{id}/vote", web::delete().to(handlers::roadmap::unvote))
+            .route("/api/support/message", web::post().to(handlers::support::send_support_message))
+            .route("/api/support/messages/{id}/human", web::post().to(handlers::support::request_human))
+            .route("/api/uploads", web::post().to(handlers::uploads::create_upload))
+            .service(
+                web::resource("/api/uploads/{id}")
+                    .app_data(web::PayloadConfig::new(handlers::uploads::MAX_CHUNK_SIZE))
+                    .route(web::patch().to(handlers::uploads::upload_chunk))
+                    .route(web::get().to(handlers::uploads::get_upload))
+                    .route(web::delete().to(handlers::uploads::cancel_upload))
+            )
+            .route("/api/knowledge/documents", web::post().to(handlers::knowledge::upload_document))
+            .route("/api/knowledge/documents", web::get().to(handlers::knowledge::list_documents))
+            .route("/api/knowledge/documents/{id}", web::delete().to(handlers::knowledge::delete_document))
+            .route("/api/widgets", web::post().to(handlers::widgets::create_widget))
+            .route("/api/widgets", web::get().to(handlers::widgets::list_widgets))
+            .route("/api/widgets/{id}", web::put().to(handlers::widgets::update_widget))
+            .route("/api/widgets/{id}", web::delete().to(handlers::widgets::delete_widget))
+            .route("/api/widgets/{id}/rotate-key", web::post().to(handlers::widgets::rotate_widget_key))
+            .route("/api/widgets/{id}/usage", web::get().to(handlers::widgets::widget_usage))
+            .route("/widget.js", web::get().to(handlers::widgets::widget_script))
+            .route("/api/widget/config", web::get().to(handlers::widgets::widget_config))
+            .route("/api/widget/messages", web::post().to(handlers::widgets::widget_message))
+            .route("/api/reference/countries", web::get().to(handlers::reference::countries))
+            .route("/api/reference/currencies", web::get().to(handlers::reference::currencies))
+            .route("/api/reference/timezones", web::get().to(handlers::reference::timezones))
+            .route("/api/files/bundle", web::post().to(handlers::files::create_bundle))
+            .route("/api/files/bundle/{job_id}", web::get().to(handlers::files::get_bundle))
             .route("/api/files/{id}", web::get().to(handlers::files::download_file))
-    })
-    .bind(("0.0.0.0", port))?
-    .run()
-    .await
+            .route("/api/files/{id}", web::delete().to(handlers::files::delete_file))
+            .route("/api/files/{id}/restore", web::post().to(handlers::files::restore_file))
+            .route("/api/files/usage/{user_id}", web::get().to(handlers::files::get_storage_usage))
+    });
+
+    let server = match &tls {
+        Some(settings) => {
+            println!("Serving HTTPS (HTTP/2 and HTTP/1.1) on port {}", port);
+            server.bind_rustls_0_23(("0.0.0.0", port), settings.server_config()?)?
+        }
+        None if services::tls::h2c_enabled() => server.bind_auto_h2c(("0.0.0.0", port))?,
+        None => server.bind(("0.0.0.0", port))?,
+    };
+    server.run().await
 }
\ No newline at end of file
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+use actix_web::{web, HttpResponse};
+
+use crate::i18n::Locale;
+use crate::state::AppState;
+
+/// Quality signals of LLM answers, counted per model and locale
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LlmSignal {
+    EmptyResponse,
+    FileIntentParseFailure,
+    TitleMissing,
+    Refusal,
+    SchemaViolation,
+}
+
+impl LlmSignal {
+    const ALL: [LlmSignal; 5] = [
+        LlmSignal::EmptyResponse,
+        LlmSignal::FileIntentParseFailure,
+        LlmSignal::TitleMissing,
+        LlmSignal::Refusal,
+        LlmSignal::SchemaViolation,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            LlmSignal::EmptyResponse => "llm_empty_responses",
+            LlmSignal::FileIntentParseFailure => "llm_file_intent_parse_failures",
+            LlmSignal::TitleMissing => "llm_title_extraction_failures",
+            LlmSignal::Refusal => "llm_refusals",
+            LlmSignal::SchemaViolation => "llm_schema_violations",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            LlmSignal::EmptyResponse => "Assistant replies that were empty or failed outright",
+            LlmSignal::FileIntentParseFailure => "Replies that looked like a file intent but did not parse as JSON",
+            LlmSignal::TitleMissing => "Title calls that failed, so the question's first line was used",
+            LlmSignal::Refusal => "Replies where the model declined to answer",
+            LlmSignal::SchemaViolation => "Structured replies that did not match the requested response_schema",
+        }
+    }
+}
+
+type Counters = BTreeMap<(LlmSignal, String, &'static str), u64>;
+
+fn counters() -> &'static Mutex<Counters> {
+    static COUNTERS: OnceLock<Mutex<Counters>> = OnceLock::new();
+    COUNTERS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+pub fn record(signal: LlmSignal, model: &str, locale: Locale) {
+    let locale = match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    };
+    *counters()
+        .lock()
+        .unwrap()
+        .entry((signal, model.to_string(), locale))
+        .or_insert(0) += 1;
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// OpenMetrics text exposition of all counters
+pub fn render() -> String {
+    let counters = counters().lock().unwrap();
+    let mut out = String::new();
+
+    for signal in LlmSignal::ALL {
+        out.push_str(&format!("# TYPE {} counter\n", signal.name()));
+        out.push_str(&format!("# HELP {} {}\n", signal.name(), signal.help()));
+        for ((s, model, locale), value) in counters.iter() {
+            if *s == signal {
+                out.push_str(&format!(
+                    "{}_total{{model=\"{}\",locale=\"{}\"}} {}\n",
+                    signal.name(),
+                    escape_label(model),
+                    locale,
+                    value
+                ));
+            }
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Connection pool gauges; in-use close to the maximum under load means requests queue for SQLite
+fn render_pool(pool: &sqlx::SqlitePool) -> String {
+    let size = pool.size();
+    let idle = pool.num_idle() as u32;
+    format!(
+        "# TYPE sqlite_pool_connections gauge\n\
+         # HELP sqlite_pool_connections Open SQLite connections\n\
+         sqlite_pool_connections {}\n\
+         # TYPE sqlite_pool_connections_in_use gauge\n\
+         # HELP sqlite_pool_connections_in_use Connections currently checked out by requests\n\
+         sqlite_pool_connections_in_use {}\n\
+         # TYPE sqlite_pool_max_connections gauge\n\
+         # HELP sqlite_pool_max_connections Configured pool size\n\
+         sqlite_pool_max_connections {}\n",
+        size,
+        size.saturating_sub(idle),
+        pool.options().get_max_connections(),
+    )
+}
+
+pub async fn metrics(state: web::Data<AppState>) -> HttpResponse {
+    let mut body = render_pool(&state.pool);
+    body.push_str(&render());
+    HttpResponse::Ok()
+        .content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+        .body(body)
+}
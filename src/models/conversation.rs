@@ -1,12 +1,5 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Message {
-    pub role: String,
-    pub content: String,
-    pub timestamp: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatRequest {
     pub message: String,
@@ -18,6 +11,31 @@ pub struct ChatRequest {
     pub table: Option<TableSpec>,
     pub language: Option<String>, // e.g. "en" | "ru"
     pub context_filters: Option<ContextFilters>, // переопределения контекста для этого сообщения
+    /// Uploaded files (see `/api/uploads`) to attach to this message
+    #[serde(default)]
+    pub attachment_ids: Vec<String>,
+    /// Images sent inline for the vision model
+    #[serde(default)]
+    pub images: Vec<InlineImage>,
+    /// JSON Schema (object at the top level) the answer must match; the reply then comes back
+    /// parsed in `ChatResponse.structured` instead of as markdown
+    pub response_schema: Option<serde_json::Value>,
+    /// Ask clarifying questions first if the message is too vague; defaults to the runtime config
+    pub clarify: Option<bool>,
+    /// Model for this and later messages of the conversation, one of `GET /api/chat/models`;
+    /// an empty string goes back to the default
+    pub model: Option<String>,
+    /// Also read the answer out: an MP3 is attached to the reply next to any generated files
+    #[serde(default)]
+    pub tts: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InlineImage {
+    pub mime: String,
+    /// Base64 bytes, optionally as a `data:` URL
+    pub data: String,
+    pub filename: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -34,6 +52,50 @@ pub struct ChatResponse {
     pub timestamp: String,
     pub conversation_id: String,
     pub files: Option<Vec<FileAttachment>>,
+    /// The answer as JSON when the request had a `response_schema` and the model met it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured: Option<serde_json::Value>,
+    /// Why the answer was rejected against `response_schema`, as `path: problem`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_errors: Option<Vec<String>>,
+    /// Disclaimer for the category, already appended to `response` unless the answer is structured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disclaimer: Option<String>,
+    /// Set when `response` is a clarifying question rather than the answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clarification: Option<Clarification>,
+    /// Analytics rows the model looked up while answering, for "based on this week's trends" chips
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub sources: Vec<AnalyticsSource>,
+    /// Short answers to a clarifying question, for quick-reply buttons; sending one is a normal message
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub suggested_replies: Vec<String>,
+    /// Set when no answer could be generated: `response` is the error text, `message_id` the
+    /// stored question, marked `failed`, to pass to `POST /api/chat/messages/{id}/retry`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_message_id: Option<String>,
+}
+
+/// One row of our analytics tables that was put into the prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalyticsSource {
+    /// `weekly_trend`, `geo_trend`, `niche` or `trend`
+    pub kind: String,
+    /// Row id in its table; trends are keyed by name
+    pub id: String,
+    /// Localized title as the model saw it
+    pub title: String,
+    /// `week_start` or `month_start` the row belongs to; tracked trends have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Clarification {
+    /// Which question this is, starting at 1
+    pub question: u32,
+    /// After this many the model has to answer
+    pub max_questions: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -69,6 +131,16 @@ pub struct ConversationSummary {
     pub title: Option<String>,
     pub created_at: String,
     pub context: Option<ConversationContext>,
+    pub archived_at: Option<String>,
+    pub pinned: bool,
+    pub last_message_at: Option<String>,
+    /// Model picked for the conversation, `None` for the default
+    pub model: Option<String>,
+    /// `ru` or `en`, from the first message unless changed; `None` until the first message
+    pub language: Option<String>,
+    /// Assistant messages the user hasn't seen yet
+    pub unread_count: i64,
+    pub last_read_message_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -77,6 +149,13 @@ pub struct MessageRecord {
     pub role: String,
     pub content: String,
     pub timestamp: String,
+    /// `timestamp` in the zone the history was requested for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_timestamp: Option<String>,
+    /// `pending` while a message accepted during an LLM outage waits for its answer,
+    /// `failed` if it was given up on or its answer failed; either can be retried
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -5,9 +5,11 @@ pub mod telegram_user;
 pub use user::{User, AuthRequest};
 pub use telegram_user::{TelegramUser, CreateTelegramUserRequest, TelegramUserResponse};
 pub use conversation::{
-    Message,
     ChatRequest,
+    InlineImage,
     ChatResponse,
+    Clarification,
+    AnalyticsSource,
     ConversationSummary,
     MessageRecord,
     FileAttachment,
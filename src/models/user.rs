@@ -14,6 +14,7 @@ pub struct User {
     pub gender: Option<String>,
     pub profile_picture: Option<String>,
     pub telegram_username: Option<String>,
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,4 +29,14 @@ pub struct AuthRequest {
     pub gender: Option<String>,
     pub profile_picture: Option<String>,
     pub telegram_username: Option<String>,
+    /// IANA zone id; when omitted on registration the `X-Timezone` header is used
+    pub timezone: Option<String>,
+    pub captcha_token: Option<String>,
+    /// Honeypot: hidden in the registration form, only bots fill it in
+    pub website: Option<String>,
+    /// Login only: keep the session for 30 days instead of a sliding short session
+    pub remember_me: Option<bool>,
+    /// Stable per-install identifier; the issued token only works from this device
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
 }
\ No newline at end of file
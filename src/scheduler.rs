@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use actix_web::rt;
+use chrono::Weekday;
+use chrono_tz::Tz;
+use sqlx::{Row, SqlitePool};
+
+use crate::config::SharedConfig;
+use crate::handlers::chat::CONVERSATION_RETENTION_DAYS;
+use crate::handlers::files::TRASH_RETENTION_DAYS;
+use crate::handlers::uploads::UPLOAD_SESSION_HOURS;
+use crate::i18n::Locale;
+use crate::services::archive;
+use crate::services::bundle;
+use crate::services::escalation;
+use crate::services::export;
+use crate::services::fcm::{self, FcmService};
+use crate::services::preferences;
+use crate::services::telegram::TelegramBot;
+use crate::services::timezone;
+use crate::services::topics;
+
+const TICK: Duration = Duration::from_secs(60);
+const EXPORT_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+const ARCHIVE_EVERY: Duration = Duration::from_secs(24 * 60 * 60);
+/// The weekly digest goes out on Monday at 9:00 in each user's own time zone
+const DIGEST_WEEKDAY: Weekday = Weekday::Mon;
+const DIGEST_HOUR: u32 = 9;
+/// A digest missed by more than this (server down) waits for next week instead of arriving mid-week
+const DIGEST_GRACE_HOURS: i64 = 6;
+
+/// Starts the background loop; every job runs once per tick and logs its own failures
+pub fn spawn(pool: SqlitePool, config: SharedConfig) {
+    rt::spawn(async move {
+        let fcm = match FcmService::new() {
+            Ok(f) => Some(f),
+            Err(e) => {
+                eprintln!("Scheduler: FCM unavailable, push jobs disabled: {}", e);
+                None
+            }
+        };
+        let telegram = match TelegramBot::new() {
+            Ok(t) => Some(t),
+            Err(e) => {
+                eprintln!("Scheduler: Telegram unavailable, support escalation disabled: {}", e);
+                None
+            }
+        };
+
+        // Warehouse dumps are opt-in: they only run once EXPORT_SALT is configured
+        let export_enabled = std::env::var("EXPORT_SALT").is_ok();
+        let mut last_export: Option<Instant> = None;
+        let mut last_archive: Option<Instant> = None;
+
+        let mut interval = rt::time::interval(TICK);
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = send_booking_reminders(&pool, fcm.as_ref()).await {
+                eprintln!("Scheduler: booking reminders failed: {}", e);
+            }
+            if let Err(e) = send_low_stock_alerts(&pool, fcm.as_ref()).await {
+                eprintln!("Scheduler: low-stock alerts failed: {}", e);
+            }
+            if let Err(e) = send_weekly_digests(&pool, fcm.as_ref()).await {
+                eprintln!("Scheduler: weekly digests failed: {}", e);
+            }
+            if let Err(e) = topics::classify_pending(&pool).await {
+                eprintln!("Scheduler: topic classification failed: {}", e);
+            }
+            if let Err(e) = purge_trashed_files(&pool).await {
+                eprintln!("Scheduler: trash purge failed: {}", e);
+            }
+            if let Err(e) = purge_deleted_conversations(&pool).await {
+                eprintln!("Scheduler: conversation purge failed: {}", e);
+            }
+            if let Err(e) = expire_upload_sessions(&pool).await {
+                eprintln!("Scheduler: upload session cleanup failed: {}", e);
+            }
+            if let Err(e) = bundle::run_pending(&pool).await {
+                eprintln!("Scheduler: bundle jobs failed: {}", e);
+            }
+            if let Some(bot) = &telegram {
+                let policy = config.load().support_escalation.clone();
+                if let Err(e) = escalation::run(&pool, bot, &policy).await {
+                    eprintln!("Scheduler: support escalation failed: {}", e);
+                }
+            }
+            if export_enabled && last_export.is_none_or(|t| t.elapsed() >= EXPORT_EVERY) {
+                last_export = Some(Instant::now());
+                if let Err(e) = export::run_export(&pool).await {
+                    eprintln!("Scheduler: warehouse export failed: {}", e);
+                }
+            }
+            let archive_policy = config.load().archive.clone();
+            if archive_policy.enabled && last_archive.is_none_or(|t| t.elapsed() >= ARCHIVE_EVERY) {
+                last_archive = Some(Instant::now());
+                match archive::run(&pool, &archive_policy).await {
+                    Ok(done) if !done.is_empty() => println!("Scheduler: archived {} monthly partitions", done.len()),
+                    Ok(_) => {}
+                    Err(e) => eprintln!("Scheduler: partition archival failed: {}", e),
+                }
+            }
+        }
+    });
+}
+
+/// Permanently removes files that have been in the trash longer than the retention period
+async fn purge_trashed_files(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(TRASH_RETENTION_DAYS);
+    let purged = sqlx::query("DELETE FROM files WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?)")
+        .bind(cutoff.to_rfc3339())
+        .execute(pool)
+        .await?;
+    if purged.rows_affected() > 0 {
+        println!("Scheduler: purged {} trashed files", purged.rows_affected());
+    }
+    Ok(())
+}
+
+/// Permanently removes conversations deleted longer ago than the retention period
+async fn purge_deleted_conversations(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::days(CONVERSATION_RETENTION_DAYS)).to_rfc3339();
+    let mut tx = pool.begin().await?;
+    // Attachments and generated files go with their messages; blobs follow through the refcount trigger
+    sqlx::query(
+        "DELETE FROM files WHERE message_id IN (SELECT id FROM messages WHERE conversation_id IN
+            (SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?)))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM messages WHERE conversation_id IN
+            (SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM conversation_summaries WHERE conversation_id IN
+            (SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM conversation_shares WHERE conversation_id IN
+            (SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM conversation_reads WHERE conversation_id IN
+            (SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query(
+        "DELETE FROM conversation_drafts WHERE conversation_id IN
+            (SELECT id FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    let purged = sqlx::query("DELETE FROM conversations WHERE deleted_at IS NOT NULL AND julianday(deleted_at) < julianday(?)")
+        .bind(&cutoff)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    if purged.rows_affected() > 0 {
+        println!("Scheduler: purged {} deleted conversations", purged.rows_affected());
+    }
+    Ok(())
+}
+
+/// Drops resumable uploads that were abandoned before their last chunk arrived
+async fn expire_upload_sessions(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let cutoff = (chrono::Utc::now() - chrono::Duration::hours(UPLOAD_SESSION_HOURS)).to_rfc3339();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "DELETE FROM upload_chunks WHERE upload_id IN
+            (SELECT id FROM upload_sessions WHERE status IN ('open', 'finalizing') AND julianday(updated_at) < julianday(?))"
+    )
+    .bind(&cutoff)
+    .execute(&mut tx)
+    .await?;
+    sqlx::query("UPDATE upload_sessions SET status = 'expired' WHERE status IN ('open', 'finalizing') AND julianday(updated_at) < julianday(?)")
+        .bind(&cutoff)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Pushes a reminder to the business owner one hour before each booking
+async fn send_booking_reminders(pool: &SqlitePool, fcm: Option<&FcmService>) -> Result<(), Box<dyn std::error::Error>> {
+    let fcm = match fcm {
+        Some(f) => f,
+        None => return Ok(()),
+    };
+
+    let now = chrono::Utc::now();
+    let horizon = now + chrono::Duration::hours(1);
+
+    let rows = sqlx::query(
+        "SELECT b.id, b.owner_user_id, b.client_name, b.starts_at, s.title
+         FROM bookings b
+         JOIN booking_services s ON s.id = b.service_id
+         WHERE b.status = 'confirmed' AND b.reminder_sent_at IS NULL AND b.starts_at > ? AND b.starts_at <= ?"
+    )
+    .bind(now.to_rfc3339())
+    .bind(horizon.to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+
+    for r in rows {
+        let booking_id: String = r.get("id");
+        let owner: String = r.get("owner_user_id");
+        let tokens = fcm::user_tokens(pool, &owner).await;
+
+        if !tokens.is_empty() {
+            let title: String = r.get("title");
+            let client: String = r.get("client_name");
+            let starts_at: String = r.get("starts_at");
+            // Shown in the owner's zone; owners without one see UTC
+            let tz = timezone::user_timezone(pool, &owner).await.unwrap_or(Tz::UTC);
+            let starts_at = timezone::parse_stored(&starts_at)
+                .map(|t| t.with_timezone(&tz).format("%d.%m %H:%M").to_string())
+                .unwrap_or(starts_at);
+            let mut data = HashMap::new();
+            data.insert("type".to_string(), "booking_reminder".to_string());
+            data.insert("booking_id".to_string(), booking_id.clone());
+
+            // A failed push is not retried: the reminder would be late anyway, and the other bookings still get theirs
+            if let Err(e) = fcm
+                .send_notification(pool, tokens, &title, &format!("{} — {}", client, starts_at), Some(data))
+                .await
+            {
+                eprintln!("Scheduler: reminder for booking {} failed: {}", booking_id, e);
+            }
+        }
+
+        sqlx::query("UPDATE bookings SET reminder_sent_at = ? WHERE id = ?")
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(&booking_id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// One push per user listing items that dropped to or below their threshold
+async fn send_low_stock_alerts(pool: &SqlitePool, fcm: Option<&FcmService>) -> Result<(), Box<dyn std::error::Error>> {
+    let fcm = match fcm {
+        Some(f) => f,
+        None => return Ok(()),
+    };
+
+    let rows = sqlx::query(
+        "SELECT i.id, i.user_id, i.name, u.country,
+            (SELECT p.locale FROM user_preferences p WHERE p.user_id = i.user_id) AS locale
+         FROM inventory_items i LEFT JOIN users u ON u.id = i.user_id
+         WHERE i.low_stock_threshold IS NOT NULL AND i.quantity <= i.low_stock_threshold AND i.low_stock_notified_at IS NULL
+         ORDER BY i.user_id"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_user: HashMap<String, (Locale, Vec<(String, String)>)> = HashMap::new();
+    for r in rows {
+        let locale = preferences::background_locale(
+            r.get::<Option<String>, _>("locale").as_deref(),
+            r.get::<Option<String>, _>("country").as_deref(),
+        );
+        by_user
+            .entry(r.get("user_id"))
+            .or_insert_with(|| (locale, Vec::new()))
+            .1
+            .push((r.get("id"), r.get("name")));
+    }
+
+    for (user_id, (locale, items)) in by_user {
+        let tokens = fcm::user_tokens(pool, &user_id).await;
+        if !tokens.is_empty() {
+            let names: Vec<&str> = items.iter().map(|(_, name)| name.as_str()).collect();
+            let mut data = HashMap::new();
+            data.insert("type".to_string(), "low_stock".to_string());
+            let title = match locale {
+                Locale::Ru => "Товар заканчивается",
+                Locale::En => "Low stock",
+            };
+
+            if let Err(e) = fcm.send_notification(pool, tokens, title, &names.join(", "), Some(data)).await {
+                eprintln!("Scheduler: low stock alert for {} failed: {}", user_id, e);
+            }
+        }
+
+        let now = chrono::Utc::now().to_rfc3339();
+        for (item_id, _) in &items {
+            sqlx::query("UPDATE inventory_items SET low_stock_notified_at = ? WHERE id = ?")
+                .bind(&now)
+                .bind(item_id)
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Monday-morning push summing up the week ahead: upcoming bookings, new leads, low stock.
+/// Each user gets it at their local time; users without a time zone get it at 9:00 UTC.
+async fn send_weekly_digests(pool: &SqlitePool, fcm: Option<&FcmService>) -> Result<(), Box<dyn std::error::Error>> {
+    let fcm = match fcm {
+        Some(f) => f,
+        None => return Ok(()),
+    };
+
+    let now = chrono::Utc::now();
+    let users = sqlx::query(
+        "SELECT u.id, u.timezone, u.country, u.digest_sent_at,
+            (SELECT p.locale FROM user_preferences p WHERE p.user_id = u.id) AS locale
+         FROM users u
+         WHERE EXISTS (SELECT 1 FROM device_tokens d WHERE d.user_id = u.id)"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for u in users {
+        let user_id: String = u.get("id");
+        let tz = u
+            .get::<Option<String>, _>("timezone")
+            .as_deref()
+            .and_then(timezone::parse)
+            .unwrap_or(Tz::UTC);
+        let slot = match timezone::last_weekly_slot(tz, now, DIGEST_WEEKDAY, DIGEST_HOUR) {
+            Some(s) => s,
+            None => continue,
+        };
+        let already_sent = u
+            .get::<Option<String>, _>("digest_sent_at")
+            .as_deref()
+            .and_then(timezone::parse_stored)
+            .is_some_and(|sent| sent >= slot);
+        if already_sent || now - slot > chrono::Duration::hours(DIGEST_GRACE_HOURS) {
+            continue;
+        }
+
+        let week_ahead = (now + chrono::Duration::days(7)).to_rfc3339();
+        let week_ago = (now - chrono::Duration::days(7)).to_rfc3339();
+        let bookings: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM bookings WHERE owner_user_id = ? AND status = 'confirmed' AND starts_at > ? AND starts_at <= ?"
+        )
+        .bind(&user_id)
+        .bind(now.to_rfc3339())
+        .bind(&week_ahead)
+        .fetch_one(pool)
+        .await?;
+        let leads: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM leads WHERE user_id = ? AND julianday(created_at) > julianday(?)")
+            .bind(&user_id)
+            .bind(&week_ago)
+            .fetch_one(pool)
+            .await?;
+        let low_stock: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM inventory_items WHERE user_id = ? AND low_stock_threshold IS NOT NULL AND quantity <= low_stock_threshold"
+        )
+        .bind(&user_id)
+        .fetch_one(pool)
+        .await?;
+
+        // Nothing to report is not worth a push, but still counts as this week's digest
+        if bookings + leads + low_stock > 0 {
+            let tokens = fcm::user_tokens(pool, &user_id).await;
+            let mut data = HashMap::new();
+            data.insert("type".to_string(), "weekly_digest".to_string());
+            data.insert("bookings".to_string(), bookings.to_string());
+            data.insert("leads".to_string(), leads.to_string());
+            data.insert("low_stock".to_string(), low_stock.to_string());
+            let locale = preferences::background_locale(
+                u.get::<Option<String>, _>("locale").as_deref(),
+                u.get::<Option<String>, _>("country").as_deref(),
+            );
+            let (title, body) = match locale {
+                Locale::Ru => (
+                    "Ваша неделя",
+                    format!("Записей на неделе: {}, новых заявок: {}, товаров заканчивается: {}", bookings, leads, low_stock),
+                ),
+                Locale::En => (
+                    "Your week",
+                    format!("{} bookings this week, {} new leads, {} items low on stock", bookings, leads, low_stock),
+                ),
+            };
+            fcm.send_notification(pool, tokens, title, &body, Some(data))
+                .await?;
+        }
+
+        sqlx::query("UPDATE users SET digest_sent_at = ? WHERE id = ?")
+            .bind(now.to_rfc3339())
+            .bind(&user_id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Throwaway-mailbox providers rejected at registration
+const DISPOSABLE_DOMAINS: &[&str] = &[
+    "10minutemail.com", "guerrillamail.com", "guerrillamail.net", "mailinator.com", "maildrop.cc",
+    "tempmail.com", "temp-mail.org", "throwawaymail.com", "yopmail.com", "getnada.com",
+    "trashmail.com", "sharklasers.com", "dispostable.com", "fakeinbox.com", "mintemail.com",
+    "emailondeck.com", "mohmal.com", "tempail.com", "moakt.com", "dropmail.me",
+];
+
+pub fn is_disposable_email(email: &str) -> bool {
+    let domain = match email.rsplit_once('@') {
+        Some((_, d)) => d.trim().to_ascii_lowercase(),
+        None => return false,
+    };
+    DISPOSABLE_DOMAINS
+        .iter()
+        .any(|d| domain == *d || domain.ends_with(&format!(".{}", d)))
+}
+
+type Hits = HashMap<(&'static str, String), VecDeque<Instant>>;
+
+fn hits() -> &'static Mutex<Hits> {
+    static HITS: OnceLock<Mutex<Hits>> = OnceLock::new();
+    HITS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Sliding-window velocity check; records the attempt and returns false once `max` is exceeded
+pub fn allow(bucket: &'static str, key: &str, max: usize, window: Duration) -> bool {
+    let now = Instant::now();
+    let mut map = hits().lock().unwrap();
+
+    // Keep memory bounded under key churn
+    if map.len() > 10_000 {
+        map.retain(|_, q| q.back().is_some_and(|t| now.duration_since(*t) < window));
+    }
+
+    let queue = map.entry((bucket, key.to_string())).or_default();
+    while queue.front().is_some_and(|t| now.duration_since(*t) >= window) {
+        queue.pop_front();
+    }
+    if queue.len() >= max {
+        return false;
+    }
+    queue.push_back(now);
+    true
+}
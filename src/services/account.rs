@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+
+use serde_json::{Map, Value};
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Column, Row, SqlitePool, TypeInfo, ValueRef};
+use zip::write::FileOptions;
+
+/// Stands in for the owner of feedback items kept on the public roadmap after erasure
+const DELETED_USER: &str = "deleted-user";
+
+/// One JSON file of the personal data export; `query` takes the user id as its only parameter
+struct PersonalData {
+    file: &'static str,
+    query: &'static str,
+}
+
+const PERSONAL_DATA: &[PersonalData] = &[
+    PersonalData {
+        file: "profile.json",
+        query: "SELECT id, email, business_type, created_at, full_name, nickname, phone, country, gender, profile_picture, profile_picture_thumb,
+                telegram_username, timezone, plan, analytics_opt_in, daily_digest
+                FROM users WHERE id = ?",
+    },
+    PersonalData {
+        file: "conversations.json",
+        query: "SELECT id, title, created_at, archived_at, deleted_at, pinned, language, model, forked_from
+                FROM conversations WHERE user_id = ? ORDER BY created_at",
+    },
+    PersonalData {
+        file: "messages.json",
+        query: "SELECT m.id, m.conversation_id, m.role, m.content, m.timestamp, m.edited_at, m.category
+                FROM messages m JOIN conversations c ON c.id = m.conversation_id
+                WHERE c.user_id = ? ORDER BY m.conversation_id, m.timestamp",
+    },
+    PersonalData {
+        file: "conversation_context.json",
+        query: "SELECT x.conversation_id, x.user_role, x.business_stage, x.goal, x.urgency, x.region, x.business_niche, x.updated_at
+                FROM conversation_context x JOIN conversations c ON c.id = x.conversation_id WHERE c.user_id = ?",
+    },
+    PersonalData {
+        file: "drafts.json",
+        query: "SELECT conversation_id, content, updated_at FROM conversation_drafts WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "message_feedback.json",
+        query: "SELECT message_id, rating, comment, category, created_at FROM message_feedback WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "files.json",
+        query: "SELECT id, filename, mime, size, message_id, created_at, deleted_at FROM files WHERE user_id = ? ORDER BY created_at",
+    },
+    PersonalData {
+        file: "support_messages.json",
+        query: "SELECT id, message, photo_url, direction, created_at FROM support_messages WHERE user_id = ? ORDER BY created_at",
+    },
+    PersonalData {
+        file: "sessions.json",
+        query: "SELECT device_id, device_name, platform, user_agent, created_ip, last_ip, created_at, last_used_at, expires_at FROM sessions WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "devices.json",
+        query: "SELECT platform, device_id, created_at FROM device_tokens WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "telegram.json",
+        query: "SELECT telegram_user_id, telegram_username, first_name, last_name, created_at FROM telegram_users WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "sign_in_providers.json",
+        query: "SELECT provider, email, created_at FROM oauth_identities WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "leads.json",
+        query: "SELECT id, name, email, phone, company, notes, created_at FROM leads WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "booking_services.json",
+        query: "SELECT id, title, duration_minutes, work_start, work_end, created_at FROM booking_services WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "bookings.json",
+        query: "SELECT id, service_id, client_name, client_email, client_phone, starts_at, ends_at, status, created_at
+                FROM bookings WHERE owner_user_id = ?",
+    },
+    PersonalData {
+        file: "inventory.json",
+        query: "SELECT id, name, sku, quantity, unit, low_stock_threshold, created_at, updated_at FROM inventory_items WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "invoices.json",
+        query: "SELECT id, number, buyer_name, currency, total, status, issued_at, paid_at FROM invoices WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "payment_links.json",
+        query: "SELECT id, invoice_id, provider, amount, currency, description, status, created_at, paid_at FROM payment_links WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "knowledge_documents.json",
+        query: "SELECT id, filename, mime, chunk_count, created_at FROM knowledge_documents WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "widgets.json",
+        query: "SELECT id, name, allowed_origins, theme, created_at, updated_at, revoked_at FROM widgets WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "feedback.json",
+        query: "SELECT id, kind, title, description, locale, status, created_at FROM feedback_items WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "preferences.json",
+        query: "SELECT locale, push_notifications, default_category, default_output_format, default_model, updated_at
+                FROM user_preferences WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "onboarding.json",
+        query: "SELECT step, completed_at FROM onboarding_steps WHERE user_id = ?",
+    },
+    PersonalData {
+        file: "feedback_votes.json",
+        query: "SELECT item_id, created_at FROM feedback_votes WHERE user_id = ?",
+    },
+];
+
+/// Statements run in order by `erase`, each taking the user id as its only parameter.
+/// Children go before their parents; blobs follow deleted files through the refcount trigger.
+const ERASE: &[&str] = &[
+    "DELETE FROM files WHERE message_id IN (SELECT m.id FROM messages m JOIN conversations c ON c.id = m.conversation_id WHERE c.user_id = ?)",
+    "DELETE FROM answer_scores WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM queued_turns WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM message_feedback WHERE message_id IN (SELECT m.id FROM messages m JOIN conversations c ON c.id = m.conversation_id WHERE c.user_id = ?)",
+    "DELETE FROM messages WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversation_summaries WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversation_shares WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversation_reads WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversation_drafts WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversation_context WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversation_topics WHERE conversation_id IN (SELECT id FROM conversations WHERE user_id = ?)",
+    "DELETE FROM conversations WHERE user_id = ?",
+    "DELETE FROM message_feedback WHERE user_id = ?",
+    "DELETE FROM conversation_reads WHERE user_id = ?",
+    "DELETE FROM conversation_drafts WHERE user_id = ?",
+    "DELETE FROM support_escalations WHERE user_id = ?",
+    "DELETE FROM message_mapping WHERE user_id = ?",
+    "DELETE FROM support_messages WHERE user_id = ?",
+    "DELETE FROM embeddings WHERE user_id = ?",
+    "DELETE FROM knowledge_documents WHERE user_id = ?",
+    "DELETE FROM bundle_jobs WHERE user_id = ?",
+    "DELETE FROM upload_chunks WHERE upload_id IN (SELECT id FROM upload_sessions WHERE user_id = ?)",
+    "DELETE FROM upload_sessions WHERE user_id = ?",
+    "DELETE FROM files WHERE user_id = ?",
+    "DELETE FROM widget_messages WHERE session_id IN (SELECT s.id FROM widget_sessions s JOIN widgets w ON w.id = s.widget_id WHERE w.user_id = ?)",
+    "DELETE FROM widget_sessions WHERE widget_id IN (SELECT id FROM widgets WHERE user_id = ?)",
+    "DELETE FROM widget_usage WHERE widget_id IN (SELECT id FROM widgets WHERE user_id = ?)",
+    "DELETE FROM widgets WHERE user_id = ?",
+    "DELETE FROM bookings WHERE owner_user_id = ?",
+    "DELETE FROM booking_services WHERE user_id = ?",
+    "DELETE FROM inventory_items WHERE user_id = ?",
+    "DELETE FROM payment_links WHERE user_id = ?",
+    "DELETE FROM invoices WHERE user_id = ?",
+    "DELETE FROM leads WHERE user_id = ?",
+    "DELETE FROM feedback_votes WHERE user_id = ?",
+    "DELETE FROM user_stats WHERE user_id = ?",
+    "DELETE FROM greetings_sent WHERE user_id = ?",
+    "DELETE FROM onboarding_steps WHERE user_id = ?",
+    "DELETE FROM email_changes WHERE user_id = ?",
+    "DELETE FROM user_preferences WHERE user_id = ?",
+    "DELETE FROM device_tokens WHERE user_id = ?",
+    "DELETE FROM oauth_identities WHERE user_id = ?",
+    "DELETE FROM sessions WHERE user_id = ?",
+    // The Telegram account outlives the link; roadmap items stay public without their author
+    "UPDATE telegram_users SET user_id = NULL WHERE user_id = ?",
+    "DELETE FROM users WHERE id = ?",
+];
+
+/// Column values as JSON; BLOB columns are never selected
+fn row_json(row: &SqliteRow) -> Value {
+    let mut object = Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = match row.try_get_raw(i) {
+            Ok(raw) if !raw.is_null() => match raw.type_info().name() {
+                "INTEGER" => row.try_get::<i64, _>(i).map(Value::from).unwrap_or(Value::Null),
+                "REAL" => row.try_get::<f64, _>(i).map(Value::from).unwrap_or(Value::Null),
+                _ => row.try_get::<String, _>(i).map(Value::from).unwrap_or(Value::Null),
+            },
+            _ => Value::Null,
+        };
+        object.insert(column.name().to_string(), value);
+    }
+    Value::Object(object)
+}
+
+/// Zip of everything stored about the user: one JSON file per kind of record, plus the
+/// user's own files under `files/`
+pub async fn export(pool: &SqlitePool, user_id: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for data in PERSONAL_DATA {
+        let rows = sqlx::query(data.query).bind(user_id).fetch_all(pool).await?;
+        let records: Vec<Value> = rows.iter().map(row_json).collect();
+        archive.start_file(data.file, options)?;
+        archive.write_all(&serde_json::to_vec_pretty(&records)?)?;
+    }
+
+    let files = sqlx::query(
+        "SELECT f.id, f.filename, COALESCE(b.bytes, f.bytes) AS bytes
+         FROM files f LEFT JOIN file_blobs b ON b.sha256 = f.sha256
+         WHERE f.user_id = ? AND f.deleted_at IS NULL AND f.scan_status IS NOT 'quarantined'"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let mut used_names: HashSet<String> = HashSet::new();
+    for f in files {
+        let bytes: Option<Vec<u8>> = f.get("bytes");
+        let Some(bytes) = bytes else { continue };
+        // The id prefix keeps same-named files apart and ties them to files.json
+        let name = format!("files/{}-{}", f.get::<String, _>("id"), f.get::<String, _>("filename").replace('/', "_"));
+        if !used_names.insert(name.clone()) {
+            continue;
+        }
+        archive.start_file(name, options)?;
+        archive.write_all(&bytes)?;
+    }
+
+    Ok(archive.finish()?.into_inner())
+}
+
+/// Deletes the account and everything personal tied to it in one transaction; returns the
+/// session ids that were dropped so callers can evict them from caches
+pub async fn erase(pool: &SqlitePool, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let sessions: Vec<String> = sqlx::query_scalar("SELECT token FROM sessions WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(&mut tx)
+        .await?;
+    sqlx::query("UPDATE feedback_items SET user_id = ? WHERE user_id = ?")
+        .bind(DELETED_USER)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+    for statement in ERASE {
+        sqlx::query(statement).bind(user_id).execute(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(sessions)
+}
@@ -0,0 +1,409 @@
+use std::time::Duration;
+
+use actix_web::rt;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+use crate::handlers::analytics::{
+    save_ai_analytics, save_niches_month, save_weekly_trends, AiAnalyticsUpsert, GeoTrendItem, NicheItem,
+    NichesMonthUpsert, Scope, TopTrendItem, WeeklyTrendsUpsert,
+};
+use crate::services::openai::{self, MarketReport};
+use crate::services::{breaker, extract, reference};
+use crate::state::AppState;
+
+/// Market analytics regenerated by the model once per period instead of by hand, tunable
+/// through the runtime config file. Weekly trends and AI analytics are refreshed every week,
+/// niches of the month every month; a manual POST in between stands until the next period.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyticsRefreshPolicy {
+    /// Off by default: every dataset is an extra completion
+    pub enabled: bool,
+    /// Pages or feeds fetched on each run and handed to the model as source material
+    pub sources: Vec<String>,
+    /// Also hand over how often each chat topic came up this week against last week
+    pub platform_topics: bool,
+    /// Region and niche pairs that get their own AI analytics and niches of the month on top of the global ones
+    pub scopes: Vec<RefreshScope>,
+    /// The default chat model when unset
+    pub model: Option<String>,
+}
+
+impl Default for AnalyticsRefreshPolicy {
+    fn default() -> Self {
+        AnalyticsRefreshPolicy {
+            enabled: false,
+            sources: Vec::new(),
+            platform_topics: true,
+            scopes: Vec::new(),
+            model: None,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct RefreshScope {
+    /// ISO code or country name
+    pub region: Option<String>,
+    pub niche: Option<String>,
+}
+
+const TICK: Duration = Duration::from_secs(60 * 60);
+/// Each source is cut at this many characters so one page can't crowd out the others
+const MAX_SOURCE_CHARS: usize = 6_000;
+
+/// Regenerates due datasets in the background whenever the policy is enabled.
+/// Separate from the scheduler because the completion needs the whole app state.
+pub fn spawn(state: AppState) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let policy = state.config.load().analytics_refresh.clone();
+            if !policy.enabled || breaker::retry_after().is_some() {
+                continue;
+            }
+            match run(&state, &policy).await {
+                Ok(saved) if saved > 0 => println!("Analytics refresh: regenerated {} datasets", saved),
+                Ok(_) => {}
+                Err(e) => eprintln!("Analytics refresh: run failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Generates every dataset not yet generated for the current period; returns how many were saved.
+/// A failed completion or an unreadable reply leaves the dataset due, so the next tick tries again.
+pub async fn run(state: &AppState, policy: &AnalyticsRefreshPolicy) -> Result<usize, Box<dyn std::error::Error>> {
+    let pool = &state.pool;
+    let now = chrono::Utc::now();
+    let week_start = now.date_naive().week(chrono::Weekday::Mon).first_day().format("%Y-%m-%d").to_string();
+    let month_start = now.format("%Y-%m-01").to_string();
+    let model = policy.model.clone().unwrap_or_else(|| openai::current_model(state));
+
+    let mut scopes = vec![Scope::default()];
+    for s in &policy.scopes {
+        let region = s.region.as_deref().and_then(reference::country_code);
+        let scope = Scope::new(region, s.niche.as_deref());
+        if !scopes.iter().any(|known| known.region == scope.region && known.niche == scope.niche) {
+            scopes.push(scope);
+        }
+    }
+
+    // Measured search interest beats the model's estimate wherever the trends connector is on
+    let trends_connected = state.config.load().trends.enabled;
+    let mut due = Vec::new();
+    if !trends_connected && !generated(pool, "weekly_trends", &Scope::default(), &week_start).await? {
+        due.push((MarketReport::WeeklyTrends, Scope::default(), week_start.clone()));
+    }
+    for scope in &scopes {
+        if !generated(pool, "ai_analytics", scope, &week_start).await? {
+            due.push((MarketReport::AiAnalytics, scope.clone(), week_start.clone()));
+        }
+        if !generated(pool, "niches_month", scope, &month_start).await? {
+            due.push((MarketReport::NichesMonth, scope.clone(), month_start.clone()));
+        }
+    }
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let sources = gather_sources(pool, policy, now).await?;
+    let mut saved = 0;
+    for (report, scope, period) in due {
+        if breaker::retry_after().is_some() {
+            break;
+        }
+        let facts = format!(
+            "[date]\n{}\n\n[region]\n{}\n\n[niche]\n{}\n{}",
+            now.format("%Y-%m-%d"),
+            if scope.region.is_empty() { "worldwide" } else { scope.region.as_str() },
+            if scope.niche.is_empty() { "all small business" } else { scope.niche.as_str() },
+            sources
+        );
+        let raw = match openai::generate_market_report(&model, report, &facts).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                eprintln!("Analytics refresh: {:?} for {:?}/{:?} failed: {}", report, scope.region, scope.niche, e);
+                continue;
+            }
+        };
+        let dataset = match report {
+            MarketReport::WeeklyTrends => {
+                let (en, ru) = match parse::<GeneratedWeekly>(&raw).and_then(GeneratedWeekly::versions) {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("Analytics refresh: unreadable weekly trends: {}", raw);
+                        continue;
+                    }
+                };
+                save_weekly_trends(pool, &[("en", &en), ("ru", &ru)]).await?;
+                "weekly_trends"
+            }
+            MarketReport::AiAnalytics => {
+                let (en, ru) = match parse::<GeneratedAnalytics>(&raw).and_then(GeneratedAnalytics::versions) {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("Analytics refresh: unreadable AI analytics: {}", raw);
+                        continue;
+                    }
+                };
+                save_ai_analytics(pool, &scope, &[("en", &en), ("ru", &ru)]).await?;
+                "ai_analytics"
+            }
+            MarketReport::NichesMonth => {
+                let (en, ru) = match parse::<GeneratedNiches>(&raw).and_then(GeneratedNiches::versions) {
+                    Some(v) => v,
+                    None => {
+                        eprintln!("Analytics refresh: unreadable niches of the month: {}", raw);
+                        continue;
+                    }
+                };
+                save_niches_month(pool, &scope, &[("en", &en), ("ru", &ru)]).await?;
+                "niches_month"
+            }
+        };
+
+        record_run(pool, dataset, &scope, &period, &model).await?;
+        saved += 1;
+    }
+    Ok(saved)
+}
+
+/// Whether `dataset` was already generated for `scope` and `period` (a week or month start)
+pub(crate) async fn generated(pool: &SqlitePool, dataset: &str, scope: &Scope, period: &str) -> Result<bool, sqlx::Error> {
+    let found: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM analytics_refresh_runs WHERE dataset = ? AND region = ? AND niche = ? AND period = ?"
+    )
+    .bind(dataset)
+    .bind(&scope.region)
+    .bind(&scope.niche)
+    .bind(period)
+    .fetch_optional(pool)
+    .await?;
+    Ok(found.is_some())
+}
+
+/// Marks `dataset` as generated for `scope` and `period`; `source` is the model or connector behind it
+pub(crate) async fn record_run(pool: &SqlitePool, dataset: &str, scope: &Scope, period: &str, source: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO analytics_refresh_runs (dataset, region, niche, period, model, generated_at)
+         VALUES (?, ?, ?, ?, ?, ?)"
+    )
+    .bind(dataset)
+    .bind(&scope.region)
+    .bind(&scope.niche)
+    .bind(period)
+    .bind(source)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Plain-text source material shared by every dataset of a run: the configured pages and,
+/// when enabled, this week's platform demand by chat topic. Unreachable pages are skipped.
+async fn gather_sources(
+    pool: &SqlitePool,
+    policy: &AnalyticsRefreshPolicy,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut out = String::new();
+
+    if policy.platform_topics {
+        let week_ago = (now - chrono::Duration::days(7)).to_rfc3339();
+        let two_weeks_ago = (now - chrono::Duration::days(14)).to_rfc3339();
+        let topics = sqlx::query(
+            "SELECT topic,
+                SUM(julianday(classified_at) >= julianday(?)) AS this_week,
+                SUM(julianday(classified_at) < julianday(?)) AS last_week
+             FROM conversation_topics WHERE julianday(classified_at) >= julianday(?)
+             GROUP BY topic ORDER BY this_week DESC LIMIT 15"
+        )
+        .bind(&week_ago)
+        .bind(&week_ago)
+        .bind(&two_weeks_ago)
+        .fetch_all(pool)
+        .await?;
+        if !topics.is_empty() {
+            out.push_str("\n[platform demand: conversations by topic, this week / last week]\n");
+            for t in topics {
+                out.push_str(&format!(
+                    "- {}: {} / {}\n",
+                    t.get::<String, _>("topic"),
+                    t.get::<i64, _>("this_week"),
+                    t.get::<i64, _>("last_week")
+                ));
+            }
+        }
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(20))
+        .build()?;
+    for url in &policy.sources {
+        let res = match client.get(url).send().await {
+            Ok(r) if r.status().is_success() => r,
+            Ok(r) => {
+                eprintln!("Analytics refresh: source {} answered {}", url, r.status());
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Analytics refresh: source {} unreachable: {}", url, e);
+                continue;
+            }
+        };
+        let mime = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(';').next())
+            .unwrap_or("text/plain")
+            .trim()
+            .to_ascii_lowercase();
+        let bytes = match res.bytes().await {
+            Ok(b) => b,
+            Err(_) => continue,
+        };
+        if let Some(text) = extract::extract_text_up_to(&mime, &bytes, MAX_SOURCE_CHARS) {
+            out.push_str(&format!("\n[source: {}]\n{}\n", url, text));
+        }
+    }
+
+    if out.is_empty() {
+        out.push_str("\n[sources]\nnone available\n");
+    }
+    Ok(out)
+}
+
+/// The model's JSON reply, tolerating a code fence around it
+fn parse<T: for<'de> Deserialize<'de>>(raw: &str) -> Option<T> {
+    let trimmed = raw.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(body.trim()).ok()
+}
+
+fn filled(values: &[&str]) -> bool {
+    values.iter().all(|v| !v.trim().is_empty())
+}
+
+#[derive(Deserialize)]
+struct GeneratedTrend {
+    title_en: String,
+    title_ru: String,
+    increase: f64,
+    request_percent: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct GeneratedGeo {
+    country_en: String,
+    country_ru: String,
+    increase: f64,
+}
+
+#[derive(Deserialize)]
+struct GeneratedWeekly {
+    current_top: GeneratedTrend,
+    second_place: GeneratedTrend,
+    geo_trends: Vec<GeneratedGeo>,
+}
+
+impl GeneratedWeekly {
+    /// English and Russian upserts with the same figures
+    fn versions(self) -> Option<(WeeklyTrendsUpsert, WeeklyTrendsUpsert)> {
+        let trends = [&self.current_top, &self.second_place];
+        if !trends.iter().all(|t| filled(&[t.title_en.as_str(), t.title_ru.as_str()]))
+            || !self.geo_trends.iter().all(|g| filled(&[g.country_en.as_str(), g.country_ru.as_str()]))
+        {
+            return None;
+        }
+        let build = |ru: bool| WeeklyTrendsUpsert {
+            current_top: TopTrendItem {
+                title: if ru { self.current_top.title_ru.clone() } else { self.current_top.title_en.clone() },
+                increase: self.current_top.increase,
+                request_percent: self.current_top.request_percent,
+            },
+            second_place: TopTrendItem {
+                title: if ru { self.second_place.title_ru.clone() } else { self.second_place.title_en.clone() },
+                increase: self.second_place.increase,
+                request_percent: None,
+            },
+            geo_trends: self
+                .geo_trends
+                .iter()
+                .take(3)
+                .map(|g| GeoTrendItem {
+                    country: if ru { g.country_ru.clone() } else { g.country_en.clone() },
+                    increase: g.increase,
+                })
+                .collect(),
+        };
+        Some((build(false), build(true)))
+    }
+}
+
+#[derive(Deserialize)]
+struct GeneratedAnalytics {
+    increase: f64,
+    description_en: String,
+    description_ru: String,
+    level_of_competitiveness: Vec<f64>,
+}
+
+impl GeneratedAnalytics {
+    /// English and Russian upserts with the same figures; the graph needs at least 5 points
+    fn versions(self) -> Option<(AiAnalyticsUpsert, AiAnalyticsUpsert)> {
+        if !filled(&[self.description_en.as_str(), self.description_ru.as_str()]) || self.level_of_competitiveness.len() < 5 {
+            return None;
+        }
+        let en = AiAnalyticsUpsert {
+            increase: self.increase,
+            description: self.description_en,
+            level_of_competitiveness: self.level_of_competitiveness.clone(),
+        };
+        let ru = AiAnalyticsUpsert {
+            increase: self.increase,
+            description: self.description_ru,
+            level_of_competitiveness: self.level_of_competitiveness,
+        };
+        Some((en, ru))
+    }
+}
+
+#[derive(Deserialize)]
+struct GeneratedNiche {
+    title_en: String,
+    title_ru: String,
+    change: f64,
+}
+
+#[derive(Deserialize)]
+struct GeneratedNiches {
+    niches: Vec<GeneratedNiche>,
+}
+
+impl GeneratedNiches {
+    /// English and Russian upserts with the same changes
+    fn versions(self) -> Option<(NichesMonthUpsert, NichesMonthUpsert)> {
+        if self.niches.is_empty() || !self.niches.iter().all(|n| filled(&[n.title_en.as_str(), n.title_ru.as_str()])) {
+            return None;
+        }
+        let build = |ru: bool| NichesMonthUpsert {
+            niches: self
+                .niches
+                .iter()
+                .map(|n| NicheItem {
+                    title: if ru { n.title_ru.clone() } else { n.title_en.clone() },
+                    change: n.change,
+                })
+                .collect(),
+        };
+        Some((build(false), build(true)))
+    }
+}
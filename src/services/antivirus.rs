@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use actix_web::rt::{net::TcpStream, time::timeout};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub enum ScanVerdict {
+    Clean,
+    /// Carries the signature name reported by clamd
+    Infected(String),
+}
+
+/// Streams `bytes` to clamd (`CLAMAV_ADDR`, e.g. `127.0.0.1:3310`) with INSTREAM;
+/// passes when scanning is not configured
+pub async fn scan(bytes: &[u8]) -> Result<ScanVerdict, Box<dyn std::error::Error>> {
+    let addr = match std::env::var("CLAMAV_ADDR") {
+        Ok(a) => a,
+        Err(_) => return Ok(ScanVerdict::Clean),
+    };
+    let reply = timeout(SCAN_TIMEOUT, instream(&addr, bytes)).await??;
+
+    // Replies look like `stream: OK` or `stream: Eicar-Signature FOUND`
+    let reply = reply.trim_end_matches('\0').trim();
+    let verdict = reply.strip_prefix("stream:").unwrap_or(reply).trim();
+    if verdict == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = verdict.strip_suffix("FOUND") {
+        Ok(ScanVerdict::Infected(signature.trim().to_string()))
+    } else {
+        Err(format!("unexpected clamd reply: {}", reply).into())
+    }
+}
+
+async fn instream(addr: &str, bytes: &[u8]) -> Result<String, std::io::Error> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"zINSTREAM\0").await?;
+    for chunk in bytes.chunks(CHUNK_SIZE) {
+        stream.write_all(&(chunk.len() as u32).to_be_bytes()).await?;
+        stream.write_all(chunk).await?;
+    }
+    stream.write_all(&0u32.to_be_bytes()).await?;
+
+    // With the `z` prefix clamd terminates its reply with a NUL byte
+    let mut reply = Vec::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = stream.read(&mut buf).await?;
+        reply.extend_from_slice(&buf[..n]);
+        if n == 0 || reply.contains(&0) {
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
@@ -0,0 +1,246 @@
+use std::env;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+use zip::write::FileOptions;
+
+/// Moving old months of chat and support history out of the database, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchivePolicy {
+    /// Off by default: archived months disappear from chat and support history
+    pub enabled: bool,
+    /// Whole months kept in the database, the current one included
+    pub keep_months: u32,
+}
+
+impl Default for ArchivePolicy {
+    fn default() -> Self {
+        ArchivePolicy {
+            enabled: false,
+            keep_months: 12,
+        }
+    }
+}
+
+/// A table split into monthly partitions by its creation timestamp
+pub struct PartitionedTable {
+    pub name: &'static str,
+    /// RFC3339 or SQLite datetime; the first 7 characters are the month
+    month_column: &'static str,
+    /// One JSON object per row, written to the archive as NDJSON
+    row_json: &'static str,
+    /// Rows in other tables that reference this one and must go first, `?` being the month
+    dependents: &'static [&'static str],
+}
+
+pub const PARTITIONED_TABLES: &[PartitionedTable] = &[
+    PartitionedTable {
+        name: "messages",
+        month_column: "timestamp",
+        row_json: "json_object('id', id, 'conversation_id', conversation_id, 'user_id', user_id, 'role', role,
+                    'content', content, 'timestamp', timestamp, 'edited_at', edited_at,
+                    'category', category, 'model', model)",
+        dependents: &[],
+    },
+    PartitionedTable {
+        name: "support_messages",
+        month_column: "created_at",
+        row_json: "json_object('id', id, 'user_id', user_id, 'message', message, 'photo_url', photo_url,
+                    'direction', direction, 'visibility', visibility, 'telegram_message_id', telegram_message_id,
+                    'faq_id', faq_id, 'in_reply_to', in_reply_to, 'auto_resolved_at', auto_resolved_at,
+                    'human_requested_at', human_requested_at, 'created_at', created_at)",
+        dependents: &[
+            "DELETE FROM support_escalations WHERE support_message_id IN
+                (SELECT id FROM support_messages WHERE substr(created_at, 1, 7) = ?)",
+            "DELETE FROM message_mapping WHERE support_message_id IN
+                (SELECT id FROM support_messages WHERE substr(created_at, 1, 7) = ?)",
+        ],
+    },
+];
+
+#[derive(Serialize)]
+pub struct PartitionStats {
+    /// `YYYY-MM`
+    pub month: String,
+    pub rows: i64,
+    /// Uncompressed size of the rows as they would be archived
+    pub content_bytes: i64,
+}
+
+#[derive(Serialize)]
+pub struct ArchivedPartition {
+    pub id: String,
+    pub table: String,
+    pub month: String,
+    pub rows: i64,
+    pub size_bytes: i64,
+    pub sha256: String,
+    pub created_at: String,
+}
+
+fn archive_dir() -> PathBuf {
+    PathBuf::from(env::var("ARCHIVE_DIR").unwrap_or_else(|_| "archives".to_string()))
+}
+
+/// Live rows per month of a partitioned table, oldest first
+pub async fn partition_stats(pool: &SqlitePool, table: &PartitionedTable) -> Result<Vec<PartitionStats>, sqlx::Error> {
+    let rows = sqlx::query(&format!(
+        "SELECT substr({col}, 1, 7) AS month, COUNT(*) AS rows, COALESCE(SUM(length(CAST({json} AS BLOB))), 0) AS bytes
+         FROM {table} GROUP BY month ORDER BY month",
+        col = table.month_column,
+        json = table.row_json,
+        table = table.name,
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(|r| PartitionStats {
+        month: r.get("month"),
+        rows: r.get("rows"),
+        content_bytes: r.get("bytes"),
+    }).collect())
+}
+
+/// Size of the whole database file in bytes
+pub async fn database_size(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count").fetch_one(pool).await?;
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(pool).await?;
+    Ok(page_count * page_size)
+}
+
+pub async fn list_archives(pool: &SqlitePool) -> Result<Vec<ArchivedPartition>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, table_name, month, rows, size_bytes, sha256, created_at FROM archived_partitions
+         ORDER BY table_name, month"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.iter().map(|r| ArchivedPartition {
+        id: r.get("id"),
+        table: r.get("table_name"),
+        month: r.get("month"),
+        rows: r.get("rows"),
+        size_bytes: r.get("size_bytes"),
+        sha256: r.get("sha256"),
+        created_at: r.get("created_at"),
+    }).collect())
+}
+
+/// The zip of an archived partition with its file name, `None` if unknown
+pub async fn read_archive(pool: &SqlitePool, id: &str) -> Result<Option<(String, Vec<u8>)>, Box<dyn std::error::Error>> {
+    let path: Option<String> = sqlx::query_scalar("SELECT path FROM archived_partitions WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    let path = match path {
+        Some(p) => PathBuf::from(p),
+        None => return Ok(None),
+    };
+    let bytes = tokio::fs::read(&path).await?;
+    let filename = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "archive.zip".to_string());
+    Ok(Some((filename, bytes)))
+}
+
+/// Archives every month older than the policy keeps, across all partitioned tables
+pub async fn run(pool: &SqlitePool, policy: &ArchivePolicy) -> Result<Vec<ArchivedPartition>, Box<dyn std::error::Error>> {
+    // The current month always stays, even with keep_months = 0
+    let cutoff: String = sqlx::query_scalar("SELECT strftime('%Y-%m', 'now', 'start of month', ?)")
+        .bind(format!("-{} months", policy.keep_months.saturating_sub(1)))
+        .fetch_one(pool)
+        .await?;
+
+    let mut archived = Vec::new();
+    for table in PARTITIONED_TABLES {
+        let months: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT substr({col}, 1, 7) AS month FROM {table} WHERE substr({col}, 1, 7) < ? ORDER BY month",
+            col = table.month_column,
+            table = table.name,
+        ))
+        .bind(&cutoff)
+        .fetch_all(pool)
+        .await?;
+
+        for month in months {
+            archived.push(archive_month(pool, table, &month).await?);
+        }
+    }
+    Ok(archived)
+}
+
+/// Writes one month to ARCHIVE_DIR/<table>/<table>-<month>.zip, then removes it from the table
+async fn archive_month(pool: &SqlitePool, table: &PartitionedTable, month: &str) -> Result<ArchivedPartition, Box<dyn std::error::Error>> {
+    let rows: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT {json} FROM {table} WHERE substr({col}, 1, 7) = ? ORDER BY {col}",
+        json = table.row_json,
+        table = table.name,
+        col = table.month_column,
+    ))
+    .bind(month)
+    .fetch_all(pool)
+    .await?;
+
+    let stem = format!("{}-{}", table.name, month);
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    archive.start_file(format!("{}.ndjson", stem), FileOptions::default().compression_method(zip::CompressionMethod::Deflated))?;
+    for row in &rows {
+        archive.write_all(row.as_bytes())?;
+        archive.write_all(b"\n")?;
+    }
+    let bytes = archive.finish()?.into_inner();
+    let sha256: String = Sha256::digest(&bytes).iter().map(|b| format!("{:02x}", b)).collect();
+
+    let dir = archive_dir().join(table.name);
+    tokio::fs::create_dir_all(&dir).await?;
+    // A month archived twice (rows arriving late with old timestamps) keeps both files
+    let mut path = dir.join(format!("{}.zip", stem));
+    let mut n = 2;
+    while tokio::fs::try_exists(&path).await? {
+        path = dir.join(format!("{}-{}.zip", stem, n));
+        n += 1;
+    }
+    tokio::fs::write(&path, &bytes).await?;
+
+    // Rows only leave the table once their archive is on disk
+    let entry = ArchivedPartition {
+        id: uuid::Uuid::new_v4().to_string(),
+        table: table.name.to_string(),
+        month: month.to_string(),
+        rows: rows.len() as i64,
+        size_bytes: bytes.len() as i64,
+        sha256,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO archived_partitions (id, table_name, month, rows, size_bytes, sha256, path, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&entry.id)
+    .bind(&entry.table)
+    .bind(&entry.month)
+    .bind(entry.rows)
+    .bind(entry.size_bytes)
+    .bind(&entry.sha256)
+    .bind(path.to_string_lossy().into_owned())
+    .bind(&entry.created_at)
+    .execute(&mut tx)
+    .await?;
+    for dependent in table.dependents {
+        sqlx::query(dependent).bind(month).execute(&mut tx).await?;
+    }
+    sqlx::query(&format!("DELETE FROM {} WHERE substr({}, 1, 7) = ?", table.name, table.month_column))
+        .bind(month)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(entry)
+}
@@ -0,0 +1,70 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+
+/// Side of the square profile picture shown on the profile screen
+pub const LARGE_SIZE: u32 = 512;
+/// Side of the square thumbnail for chat lists and headers
+pub const THUMB_SIZE: u32 = 64;
+/// Larger images are refused before decoding, so a tiny file can't expand into gigabytes
+const MAX_DIMENSION: u32 = 8_000;
+const JPEG_QUALITY: u8 = 85;
+
+/// An encoded picture ready for `files`
+pub struct Variant {
+    pub bytes: Vec<u8>,
+    pub mime: &'static str,
+    pub extension: &'static str,
+}
+
+pub struct Avatar {
+    pub large: Variant,
+    pub thumb: Variant,
+}
+
+/// Decodes an uploaded picture by its content, whatever the declared type, and re-encodes it as
+/// center-cropped squares. Re-encoding drops EXIF and any other metadata; the EXIF orientation is
+/// applied first so phone photos aren't stored sideways. `None` when the bytes aren't a JPEG,
+/// PNG, WebP or GIF image.
+pub fn process(bytes: &[u8]) -> Option<Avatar> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+    if !matches!(reader.format(), Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif)) {
+        return None;
+    }
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().ok()?;
+    let orientation = decoder.orientation().ok()?;
+    let mut img = DynamicImage::from_decoder(decoder).ok()?;
+    img.apply_orientation(orientation);
+    if img.width() == 0 || img.height() == 0 {
+        return None;
+    }
+
+    Some(Avatar {
+        large: encode(&square(&img, LARGE_SIZE))?,
+        thumb: encode(&square(&img, THUMB_SIZE))?,
+    })
+}
+
+/// Center crop to a square of `size`, never upscaling a smaller picture
+fn square(img: &DynamicImage, size: u32) -> DynamicImage {
+    let side = size.min(img.width()).min(img.height());
+    img.resize_to_fill(side, side, FilterType::Lanczos3)
+}
+
+/// JPEG, or PNG when the picture has transparency to keep
+fn encode(img: &DynamicImage) -> Option<Variant> {
+    let mut bytes = Vec::new();
+    if img.color().has_alpha() {
+        img.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).ok()?;
+        Some(Variant { bytes, mime: "image/png", extension: "png" })
+    } else {
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY).encode_image(&img.to_rgb8()).ok()?;
+        Some(Variant { bytes, mime: "image/jpeg", extension: "jpg" })
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Consecutive failed OpenRouter calls that open the breaker
+const FAILURE_THRESHOLD: u32 = 5;
+/// How long the breaker stays open; after that one call is let through to probe the provider
+const OPEN_FOR: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+fn breaker() -> &'static Mutex<Breaker> {
+    static BREAKER: OnceLock<Mutex<Breaker>> = OnceLock::new();
+    BREAKER.get_or_init(|| Mutex::new(Breaker::default()))
+}
+
+/// Time left until the provider is tried again; `None` while calls go through
+pub fn retry_after() -> Option<Duration> {
+    let b = breaker().lock().unwrap();
+    b.open_until
+        .map(|until| until.saturating_duration_since(Instant::now()))
+        .filter(|left| !left.is_zero())
+}
+
+pub fn record_success() {
+    let mut b = breaker().lock().unwrap();
+    if b.open_until.is_some() {
+        println!("OpenRouter recovered, closing the circuit breaker");
+    }
+    *b = Breaker::default();
+}
+
+/// Counts a failed call; a failed probe after the breaker was open opens it again right away
+pub fn record_failure() {
+    let mut b = breaker().lock().unwrap();
+    b.failures += 1;
+    if b.failures >= FAILURE_THRESHOLD {
+        if b.failures == FAILURE_THRESHOLD {
+            eprintln!("OpenRouter failed {} times in a row, opening the circuit breaker", b.failures);
+        }
+        b.open_until = Some(Instant::now() + OPEN_FOR);
+    }
+}
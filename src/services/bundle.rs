@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+
+use sqlx::{Row, SqlitePool};
+use zip::write::FileOptions;
+
+use crate::handlers::files::store_file;
+
+/// Jobs stuck in `running` this long (e.g. after a restart) are picked up again
+const STALE_RUNNING_MINUTES: i64 = 10;
+
+/// Builds the archive for one job; only the caller that claims the job does the work
+pub async fn process(pool: &SqlitePool, job_id: &str) {
+    let claimed = sqlx::query(
+        "UPDATE bundle_jobs SET status = 'running', started_at = ? WHERE id = ? AND status = 'pending'"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(job_id)
+    .execute(pool)
+    .await;
+    if !matches!(claimed, Ok(r) if r.rows_affected() == 1) {
+        return;
+    }
+
+    let outcome = build(pool, job_id).await;
+    let now = chrono::Utc::now().to_rfc3339();
+    let _ = match outcome {
+        Ok(file_id) => {
+            sqlx::query("UPDATE bundle_jobs SET status = 'done', file_id = ?, completed_at = ? WHERE id = ?")
+                .bind(file_id)
+                .bind(&now)
+                .bind(job_id)
+                .execute(pool)
+                .await
+        }
+        Err(e) => {
+            eprintln!("Bundle job {} failed: {}", job_id, e);
+            sqlx::query("UPDATE bundle_jobs SET status = 'failed', error = ?, completed_at = ? WHERE id = ?")
+                .bind(e.to_string())
+                .bind(&now)
+                .bind(job_id)
+                .execute(pool)
+                .await
+        }
+    };
+}
+
+async fn build(pool: &SqlitePool, job_id: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let job = sqlx::query("SELECT user_id, file_ids FROM bundle_jobs WHERE id = ?")
+        .bind(job_id)
+        .fetch_one(pool)
+        .await?;
+    let user_id: String = job.get("user_id");
+    let file_ids: Vec<String> = serde_json::from_str(&job.get::<String, _>("file_ids"))?;
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut used_names: HashSet<String> = HashSet::new();
+
+    for file_id in &file_ids {
+        // Ownership is re-checked here since files may have been trashed since the request
+        let row = sqlx::query(
+            "SELECT f.filename, COALESCE(b.bytes, f.bytes) AS bytes
+             FROM files f LEFT JOIN file_blobs b ON b.sha256 = f.sha256
+             WHERE f.id = ? AND f.user_id = ? AND f.deleted_at IS NULL AND f.scan_status IS NOT 'quarantined'"
+        )
+        .bind(file_id)
+        .bind(&user_id)
+        .fetch_optional(pool)
+        .await?;
+        let row = match row {
+            Some(r) => r,
+            None => return Err(format!("file {} is no longer available", file_id).into()),
+        };
+
+        let name = unique_name(&mut used_names, &row.get::<String, _>("filename"));
+        archive.start_file(name, options)?;
+        archive.write_all(&row.get::<Vec<u8>, _>("bytes"))?;
+    }
+
+    let bytes = archive.finish()?.into_inner();
+    let filename = format!("bundle-{}.zip", chrono::Utc::now().format("%Y%m%d-%H%M%S"));
+    let attachment = store_file(pool, filename, "application/zip".to_string(), bytes, None, Some(&user_id)).await?;
+    Ok(attachment.id.unwrap_or_default())
+}
+
+/// `report.xlsx`, `report (2).xlsx`, ... so same-named files don't overwrite each other
+fn unique_name(used: &mut HashSet<String>, filename: &str) -> String {
+    let (stem, ext) = match filename.rsplit_once('.') {
+        Some((s, e)) if !s.is_empty() => (s.to_string(), format!(".{}", e)),
+        _ => (filename.to_string(), String::new()),
+    };
+    let mut candidate = filename.to_string();
+    let mut n = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{} ({}){}", stem, n, ext);
+        n += 1;
+    }
+    candidate
+}
+
+/// Scheduler hook: retries jobs that were never started or were interrupted
+pub async fn run_pending(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let stale = (chrono::Utc::now() - chrono::Duration::minutes(STALE_RUNNING_MINUTES)).to_rfc3339();
+    sqlx::query("UPDATE bundle_jobs SET status = 'pending' WHERE status = 'running' AND started_at < ?")
+        .bind(&stale)
+        .execute(pool)
+        .await?;
+
+    let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM bundle_jobs WHERE status = 'pending' ORDER BY created_at LIMIT 10")
+        .fetch_all(pool)
+        .await?;
+    for id in ids {
+        process(pool, &id).await;
+    }
+    Ok(())
+}
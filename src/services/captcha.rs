@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct VerifyResponse {
+    success: bool,
+}
+
+fn siteverify_url(provider: &str) -> Option<&'static str> {
+    match provider {
+        "turnstile" => Some("https://challenges.cloudflare.com/turnstile/v0/siteverify"),
+        "hcaptcha" => Some("https://api.hcaptcha.com/siteverify"),
+        _ => None,
+    }
+}
+
+/// Validates a Turnstile/hCaptcha token; passes when captcha is not configured
+pub async fn verify(token: Option<&str>, remote_ip: Option<&str>) -> bool {
+    let provider = std::env::var("CAPTCHA_PROVIDER").unwrap_or_default();
+    let (url, secret) = match (siteverify_url(&provider), std::env::var("CAPTCHA_SECRET")) {
+        (Some(url), Ok(secret)) => (url, secret),
+        _ => return true,
+    };
+    let token = match token {
+        Some(t) if !t.is_empty() => t,
+        _ => return false,
+    };
+
+    let client = match Client::builder().timeout(Duration::from_secs(5)).build() {
+        Ok(c) => c,
+        Err(_) => return false,
+    };
+
+    let mut form = vec![("secret", secret.as_str()), ("response", token)];
+    if let Some(ip) = remote_ip {
+        form.push(("remoteip", ip));
+    }
+
+    match client.post(url).form(&form).send().await {
+        Ok(resp) => resp.json::<VerifyResponse>().await.map(|r| r.success).unwrap_or(false),
+        Err(e) => {
+            eprintln!("Captcha verification request failed: {}", e);
+            false
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::i18n::Locale;
+
+/// Asking follow-up questions before answering vague requests, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClarificationPolicy {
+    /// Default for requests that don't set `clarify` themselves
+    pub enabled: bool,
+    /// Questions asked at most before the full answer
+    pub max_questions: u32,
+}
+
+impl Default for ClarificationPolicy {
+    fn default() -> Self {
+        ClarificationPolicy {
+            enabled: false,
+            max_questions: 2,
+        }
+    }
+}
+
+/// First line of a reply that asks a question instead of answering
+pub const MARKER: &str = "CLARIFY:";
+/// Optional second line of such a reply: a JSON array of short answers to offer as buttons
+pub const OPTIONS_MARKER: &str = "OPTIONS:";
+/// Quick replies kept per question, and their length in characters
+const MAX_OPTIONS: usize = 4;
+const MAX_OPTION_CHARS: usize = 60;
+
+/// A clarifying question with the answers the model suggests for it
+pub struct Question {
+    pub text: String,
+    pub options: Vec<String>,
+}
+
+/// Told to the model as a system note while it may still ask
+pub fn instruction(asked: u32, max_questions: u32, locale: Locale) -> String {
+    let left = max_questions.saturating_sub(asked);
+    match locale {
+        Locale::Ru => format!(
+            "Режим уточнений: если запрос пользователя слишком общий, чтобы дать конкретный ответ под его бизнес \
+            (например, «как развить бизнес»), не отвечай, а задай ОДИН самый важный уточняющий вопрос. \
+            Такой ответ начинай строкой `{}` с вопросом в ней же. Следующей строкой можешь добавить `{}` \
+            и JSON-массив из 2–4 коротких вариантов ответа, например `{} [\"Розница\", \"Онлайн\"]`. Другого текста не пиши. \
+            Осталось вопросов: {}. Если информации достаточно, отвечай полностью как обычно.",
+            MARKER, OPTIONS_MARKER, OPTIONS_MARKER, left
+        ),
+        Locale::En => format!(
+            "Clarification mode: if the user's request is too vague to answer specifically for their business \
+            (e.g. \"how do I grow my business\"), do not answer yet; ask the ONE most important clarifying question. \
+            Start such a reply with `{}` followed by the question on the same line. On the next line you may add \
+            `{}` and a JSON array of 2-4 short answers the user could pick, e.g. `{} [\"Retail\", \"Online\"]`. \
+            Write nothing else. Questions left: {}. If you have enough information, answer in full as usual.",
+            MARKER, OPTIONS_MARKER, OPTIONS_MARKER, left
+        ),
+    }
+}
+
+/// The question when the reply asks for clarification rather than answering. Options that
+/// aren't a JSON array of strings are dropped; the question stands without them.
+pub fn parse(reply: &str) -> Option<Question> {
+    let rest = reply.trim_start().strip_prefix(MARKER)?;
+    let (line, after) = rest.split_once('\n').unwrap_or((rest, ""));
+    let text = line.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    let options = after
+        .lines()
+        .find_map(|l| l.trim().strip_prefix(OPTIONS_MARKER))
+        .and_then(|json| serde_json::from_str::<Vec<String>>(json.trim()).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|o| o.trim().to_string())
+        .filter(|o| !o.is_empty() && o.chars().count() <= MAX_OPTION_CHARS)
+        .take(MAX_OPTIONS)
+        .collect();
+    Some(Question { text: text.to_string(), options })
+}
+
+/// Questions asked so far while the conversation waits for the full answer, `None` when not clarifying
+pub async fn pending(pool: &SqlitePool, conversation_id: &str) -> Option<u32> {
+    sqlx::query_scalar::<_, Option<i64>>("SELECT pending_clarification FROM conversations WHERE id = ?")
+        .bind(conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten()
+        .map(|n| n.max(0) as u32)
+}
+
+pub async fn set_pending(pool: &SqlitePool, conversation_id: &str, asked: Option<u32>) {
+    let _ = sqlx::query("UPDATE conversations SET pending_clarification = ? WHERE id = ?")
+        .bind(asked.map(i64::from))
+        .bind(conversation_id)
+        .execute(pool)
+        .await;
+}
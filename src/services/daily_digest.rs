@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::rt;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::handlers::analytics::{resolve_scope, Scope};
+use crate::i18n::Locale;
+use crate::services::fcm::{self, FcmService};
+use crate::services::{breaker, openai, preferences, reference, timezone};
+use crate::state::AppState;
+
+/// Morning digest posted by the model for users who opted in, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyDigestPolicy {
+    /// Off by default: every digest is an extra completion
+    pub enabled: bool,
+    /// Local hour the digest is written at in each user's time zone
+    pub hour: u32,
+    /// The default chat model when unset
+    pub model: Option<String>,
+}
+
+impl Default for DailyDigestPolicy {
+    fn default() -> Self {
+        DailyDigestPolicy {
+            enabled: false,
+            hour: 8,
+            model: None,
+        }
+    }
+}
+
+const TICK: Duration = Duration::from_secs(5 * 60);
+/// A digest missed by more than this (server down, provider outage) is skipped for the day
+const GRACE_HOURS: i64 = 4;
+
+/// Writes due digests in the background whenever the policy is enabled.
+/// Separate from the scheduler because the completion needs the whole app state.
+pub fn spawn(state: AppState) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let policy = state.config.load().daily_digest.clone();
+            if !policy.enabled || breaker::retry_after().is_some() {
+                continue;
+            }
+            match run(&state, &policy).await {
+                Ok(posted) if posted > 0 => println!("Daily digest: posted {} digests", posted),
+                Ok(_) => {}
+                Err(e) => eprintln!("Daily digest: run failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Posts the digest for every opted-in user whose local hour has come today; returns how many
+/// were posted. A failed completion leaves the user due, so the next tick tries again.
+pub async fn run(state: &AppState, policy: &DailyDigestPolicy) -> Result<usize, Box<dyn std::error::Error>> {
+    let pool = &state.pool;
+    let now = chrono::Utc::now();
+    let model = policy.model.clone().unwrap_or_else(|| openai::current_model(state));
+
+    let users = sqlx::query(
+        "SELECT u.id, u.timezone, u.country, u.daily_digest_conversation_id, u.daily_digest_sent_at,
+            (SELECT x.business_niche FROM conversation_context x JOIN conversations c ON c.id = x.conversation_id
+             WHERE c.user_id = u.id AND c.deleted_at IS NULL AND x.business_niche IS NOT NULL
+             ORDER BY x.updated_at DESC LIMIT 1) AS business_niche,
+            (SELECT p.locale FROM user_preferences p WHERE p.user_id = u.id) AS locale
+         FROM users u WHERE u.daily_digest = 1"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut posted = 0;
+    for u in users {
+        let user_id: String = u.get("id");
+        let tz = u
+            .get::<Option<String>, _>("timezone")
+            .as_deref()
+            .and_then(timezone::parse)
+            .unwrap_or(Tz::UTC);
+        let slot = match timezone::last_daily_slot(tz, now, policy.hour.min(23)) {
+            Some(s) => s,
+            None => continue,
+        };
+        let already_sent = u
+            .get::<Option<String>, _>("daily_digest_sent_at")
+            .as_deref()
+            .and_then(timezone::parse_stored)
+            .is_some_and(|sent| sent >= slot);
+        if already_sent || now - slot > chrono::Duration::hours(GRACE_HOURS) {
+            continue;
+        }
+
+        let locale = preferences::background_locale(
+            u.get::<Option<String>, _>("locale").as_deref(),
+            u.get::<Option<String>, _>("country").as_deref(),
+        );
+        let niche: Option<String> = u.get("business_niche");
+        let country = u.get::<Option<String>, _>("country").as_deref().and_then(reference::country_code);
+        let scope = Scope::new(country, niche.as_deref());
+        let facts = gather_facts(pool, &user_id, niche.as_deref(), &scope, locale, now).await?;
+        let digest = match openai::generate_digest(&model, locale, &facts).await {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Daily digest: writing for {} failed: {}", user_id, e);
+                continue;
+            }
+        };
+
+        let conversation_id = ensure_conversation(pool, &user_id, u.get("daily_digest_conversation_id"), locale).await?;
+        let message_id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO messages (id, conversation_id, user_id, role, content, timestamp, category, model) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&message_id)
+        .bind(&conversation_id)
+        .bind(&user_id)
+        .bind("assistant")
+        .bind(&digest)
+        .bind(now.to_rfc3339())
+        .bind("digest")
+        .bind(&model)
+        .execute(pool)
+        .await?;
+        sqlx::query("UPDATE users SET daily_digest_sent_at = ? WHERE id = ?")
+            .bind(now.to_rfc3339())
+            .bind(&user_id)
+            .execute(pool)
+            .await?;
+        posted += 1;
+
+        notify(pool, &user_id, locale, &conversation_id, &message_id, &digest).await;
+    }
+    Ok(posted)
+}
+
+/// Plain-text input for the model: the owner's niche, the latest market analytics in their
+/// language for their country and niche where there are any, and what is coming up in their
+/// own business today
+pub(crate) async fn gather_facts(
+    pool: &SqlitePool,
+    user_id: &str,
+    niche: Option<&str>,
+    scope: &Scope,
+    locale: Locale,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<String, sqlx::Error> {
+    let loc = match locale { Locale::Ru => "ru", Locale::En => "en" };
+    let mut facts = format!(
+        "[date]\n{}\n\n[business niche]\n{}\n",
+        now.format("%Y-%m-%d"),
+        niche.filter(|n| !n.trim().is_empty()).unwrap_or("not specified")
+    );
+
+    let analytics_scope = resolve_scope(pool, "ai_analytics", None, scope).await;
+    let analytics = sqlx::query(
+        "SELECT a.increase, a.level_of_competitiveness, COALESCE(i.description, a.description) AS description
+         FROM ai_analytics a LEFT JOIN ai_analytics_i18n i ON i.id = a.id AND i.locale = ?
+         WHERE a.region = ? AND a.niche = ?
+         ORDER BY a.created_at DESC LIMIT 1"
+    )
+    .bind(loc)
+    .bind(&analytics_scope.region)
+    .bind(&analytics_scope.niche)
+    .fetch_optional(pool)
+    .await?;
+    if let Some(a) = analytics {
+        facts.push_str(&format!(
+            "\n[market overview]\ngrowth: {}%, competition: {}\n{}\n",
+            a.get::<Option<f64>, _>("increase").unwrap_or_default(),
+            a.get::<Option<String>, _>("level_of_competitiveness").unwrap_or_default(),
+            a.get::<Option<String>, _>("description").unwrap_or_default()
+        ));
+    }
+
+    let month_start = now.format("%Y-%m-01").to_string();
+    let niches_scope = resolve_scope(pool, "niches_month", Some(("month_start", month_start.as_str())), scope).await;
+    let niches = sqlx::query(
+        "SELECT COALESCE(i.title, n.title) AS title, n.change
+         FROM niches_month n LEFT JOIN niches_month_i18n i ON i.id = n.id AND i.locale = ?
+         WHERE n.month_start = ? AND n.region = ? AND n.niche = ? ORDER BY n.change DESC LIMIT 5"
+    )
+    .bind(loc)
+    .bind(&month_start)
+    .bind(&niches_scope.region)
+    .bind(&niches_scope.niche)
+    .fetch_all(pool)
+    .await?;
+    if !niches.is_empty() {
+        facts.push_str("\n[niches of the month]\n");
+        for n in niches {
+            facts.push_str(&format!("- {}: {:+}%\n", n.get::<String, _>("title"), n.get::<f64, _>("change")));
+        }
+    }
+
+    let trends = sqlx::query(
+        "SELECT t.name, t.percent_change, COALESCE(i.why_popular, t.why_popular) AS why_popular
+         FROM analytics_trends t LEFT JOIN analytics_trends_i18n i ON i.name = t.name AND i.locale = ?
+         ORDER BY t.percent_change DESC LIMIT 3"
+    )
+    .bind(loc)
+    .fetch_all(pool)
+    .await?;
+    if !trends.is_empty() {
+        facts.push_str("\n[trends]\n");
+        for t in trends {
+            facts.push_str(&format!(
+                "- {} ({:+}%): {}\n",
+                t.get::<String, _>("name"),
+                t.get::<Option<f64>, _>("percent_change").unwrap_or_default(),
+                t.get::<Option<String>, _>("why_popular").unwrap_or_default()
+            ));
+        }
+    }
+
+    let day_ahead = (now + chrono::Duration::days(1)).to_rfc3339();
+    let day_ago = (now - chrono::Duration::days(1)).to_rfc3339();
+    let bookings: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM bookings WHERE owner_user_id = ? AND status = 'confirmed' AND starts_at > ? AND starts_at <= ?"
+    )
+    .bind(user_id)
+    .bind(now.to_rfc3339())
+    .bind(&day_ahead)
+    .fetch_one(pool)
+    .await?;
+    let leads: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM leads WHERE user_id = ? AND julianday(created_at) > julianday(?)")
+        .bind(user_id)
+        .bind(&day_ago)
+        .fetch_one(pool)
+        .await?;
+    let low_stock: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM inventory_items WHERE user_id = ? AND low_stock_threshold IS NOT NULL AND quantity <= low_stock_threshold LIMIT 5"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    facts.push_str(&format!(
+        "\n[own business]\nbookings in the next 24 hours: {}\nnew leads in the last 24 hours: {}\nlow on stock: {}\n",
+        bookings,
+        leads,
+        if low_stock.is_empty() { "nothing".to_string() } else { low_stock.join(", ") }
+    ));
+
+    Ok(facts)
+}
+
+/// The user's "Daily digest" conversation, created again if they deleted it
+async fn ensure_conversation(
+    pool: &SqlitePool,
+    user_id: &str,
+    known: Option<String>,
+    locale: Locale,
+) -> Result<String, sqlx::Error> {
+    if let Some(id) = known {
+        let alive: Option<String> = sqlx::query_scalar(
+            "SELECT id FROM conversations WHERE id = ? AND user_id = ? AND deleted_at IS NULL"
+        )
+        .bind(&id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+        if alive.is_some() {
+            return Ok(id);
+        }
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let title = match locale {
+        Locale::Ru => "Ежедневная сводка",
+        Locale::En => "Daily digest",
+    };
+    sqlx::query("INSERT INTO conversations (id, user_id, title, created_at) VALUES (?, ?, ?, ?)")
+        .bind(&id)
+        .bind(user_id)
+        .bind(title)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    sqlx::query("UPDATE users SET daily_digest_conversation_id = ? WHERE id = ?")
+        .bind(&id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(id)
+}
+
+async fn notify(pool: &SqlitePool, user_id: &str, locale: Locale, conversation_id: &str, message_id: &str, digest: &str) {
+    let tokens = fcm::user_tokens(pool, user_id).await;
+    if tokens.is_empty() {
+        return;
+    }
+    let fcm = match FcmService::new() {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+
+    let mut data = HashMap::new();
+    data.insert("type".to_string(), "daily_digest".to_string());
+    data.insert("conversation_id".to_string(), conversation_id.to_string());
+    data.insert("message_id".to_string(), message_id.to_string());
+    let title = match locale {
+        Locale::Ru => "Сводка на сегодня",
+        Locale::En => "Your daily digest",
+    };
+    let preview: String = digest.chars().take(120).collect();
+    if let Err(e) = fcm.send_notification(pool, tokens, title, &preview, Some(data)).await {
+        eprintln!("Daily digest: push to {} failed: {}", user_id, e);
+    }
+}
@@ -0,0 +1,146 @@
+use std::future::Future;
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::handlers::support;
+use crate::services::fcm::FcmService;
+
+/// Tries per delivery before it goes to the dead-letter queue; waits 1s, 2s, ... in between
+pub const MAX_ATTEMPTS: u32 = 3;
+
+/// A support message forwarded to the Telegram support group
+pub const CHANNEL_SUPPORT_TELEGRAM: &str = "support_telegram";
+/// A push to a single device token
+pub const CHANNEL_FCM: &str = "fcm";
+
+/// A delivery that failed every attempt, kept with its payload until an admin replays it
+#[derive(Serialize)]
+pub struct DeadLetter {
+    pub id: String,
+    pub channel: String,
+    pub payload: serde_json::Value,
+    pub error: String,
+    pub attempts: i64,
+    /// `pending` until a replay succeeds, then `replayed`
+    pub status: String,
+    pub created_at: String,
+    pub last_attempt_at: String,
+    pub replayed_at: Option<String>,
+}
+
+pub fn from_row(r: &sqlx::sqlite::SqliteRow) -> DeadLetter {
+    DeadLetter {
+        id: r.get("id"),
+        channel: r.get("channel"),
+        payload: serde_json::from_str(&r.get::<String, _>("payload")).unwrap_or_default(),
+        error: r.get("error"),
+        attempts: r.get("attempts"),
+        status: r.get("status"),
+        created_at: r.get("created_at"),
+        last_attempt_at: r.get("last_attempt_at"),
+        replayed_at: r.get("replayed_at"),
+    }
+}
+
+/// Runs `send` until it succeeds or `MAX_ATTEMPTS` are used up, returning the last error
+pub async fn with_retries<T, F, Fut>(mut send: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    let mut attempt = 1;
+    loop {
+        match send().await {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt >= MAX_ATTEMPTS => return Err(e),
+            Err(e) => {
+                eprintln!("Delivery attempt {} failed, retrying: {}", attempt, e);
+                actix_web::rt::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Keeps a delivery that exhausted its retries so it can be replayed later
+pub async fn record(pool: &SqlitePool, channel: &str, payload: &serde_json::Value, error: &str, attempts: u32) {
+    let now = chrono::Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        "INSERT INTO dead_letters (id, channel, payload, error, attempts, status, created_at, last_attempt_at)
+         VALUES (?, ?, ?, ?, ?, 'pending', ?, ?)"
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(channel)
+    .bind(payload.to_string())
+    .bind(error)
+    .bind(i64::from(attempts))
+    .bind(&now)
+    .bind(&now)
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        // Last resort: the payload at least ends up in the logs
+        eprintln!("Dead letter for {} could not be stored ({}): {}", channel, e, payload);
+    }
+}
+
+pub async fn get(pool: &SqlitePool, id: &str) -> Result<Option<DeadLetter>, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT id, channel, payload, error, attempts, status, created_at, last_attempt_at, replayed_at
+         FROM dead_letters WHERE id = ?"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.as_ref().map(from_row))
+}
+
+/// Sends the stored payload once more through its channel
+pub async fn deliver(pool: &SqlitePool, letter: &DeadLetter) -> Result<(), String> {
+    let p = &letter.payload;
+    match letter.channel.as_str() {
+        CHANNEL_SUPPORT_TELEGRAM => {
+            support::send_support_to_telegram(
+                pool,
+                p["user_id"].as_str().unwrap_or_default(),
+                p["support_message_id"].as_str().unwrap_or_default(),
+                p["text"].as_str().unwrap_or_default(),
+                p["photo_url"].as_str(),
+            )
+            .await
+        }
+        CHANNEL_FCM => {
+            let fcm = FcmService::new().map_err(|e| e.to_string())?;
+            fcm.push(&p["message"]).await
+        }
+        other => Err(format!("unknown channel {}", other)),
+    }
+}
+
+/// Records the outcome of a replay; a failed one stays pending with the new error
+pub async fn finish_replay(pool: &SqlitePool, id: &str, outcome: &Result<(), String>) -> Result<Option<DeadLetter>, sqlx::Error> {
+    let now = chrono::Utc::now().to_rfc3339();
+    match outcome {
+        Ok(()) => {
+            sqlx::query(
+                "UPDATE dead_letters SET status = 'replayed', attempts = attempts + 1, last_attempt_at = ?, replayed_at = ? WHERE id = ?"
+            )
+            .bind(&now)
+            .bind(&now)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        }
+        Err(e) => {
+            sqlx::query("UPDATE dead_letters SET attempts = attempts + 1, last_attempt_at = ?, error = ? WHERE id = ?")
+                .bind(&now)
+                .bind(e)
+                .bind(id)
+                .execute(pool)
+                .await?;
+        }
+    }
+    get(pool, id).await
+}
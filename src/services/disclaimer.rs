@@ -0,0 +1,87 @@
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::i18n::Locale;
+
+/// Put between the answer and the disclaimer block
+pub const SEPARATOR: &str = "\n\n---\n";
+
+/// One version of the disclaimer for a category and language. Versions are never edited,
+/// so a message's `disclaimer_id` always points at the exact text it was sent with
+#[derive(Serialize)]
+pub struct Disclaimer {
+    pub id: String,
+    pub category: String,
+    /// `ru` or `en`
+    pub locale: String,
+    pub version: i64,
+    pub text: String,
+    pub created_at: String,
+}
+
+pub fn locale_code(locale: Locale) -> &'static str {
+    match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    }
+}
+
+pub fn from_row(r: &sqlx::sqlite::SqliteRow) -> Disclaimer {
+    Disclaimer {
+        id: r.get("id"),
+        category: r.get("category"),
+        locale: r.get("locale"),
+        version: r.get("version"),
+        text: r.get("text"),
+        created_at: r.get("created_at"),
+    }
+}
+
+/// Latest version for the category in that language; categories without one get no disclaimer
+pub async fn current(pool: &SqlitePool, category: &str, locale: Locale) -> Option<Disclaimer> {
+    let row = sqlx::query(
+        "SELECT id, category, locale, version, text, created_at FROM disclaimers
+         WHERE category = ? AND locale = ? ORDER BY version DESC LIMIT 1"
+    )
+    .bind(category)
+    .bind(locale_code(locale))
+    .fetch_optional(pool)
+    .await;
+
+    match row {
+        Ok(r) => r.as_ref().map(from_row),
+        Err(e) => {
+            eprintln!("Disclaimer lookup failed for {}: {}", category, e);
+            None
+        }
+    }
+}
+
+/// Stores `text` as the next version; the previous ones stay for the audit trail
+pub async fn publish(pool: &SqlitePool, category: &str, locale: &str, text: &str) -> Result<Disclaimer, sqlx::Error> {
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let version: i64 = sqlx::query_scalar(
+        "INSERT INTO disclaimers (id, category, locale, version, text, created_at)
+         SELECT ?, ?, ?, COALESCE(MAX(version), 0) + 1, ?, ? FROM disclaimers WHERE category = ? AND locale = ?
+         RETURNING version"
+    )
+    .bind(&id)
+    .bind(category)
+    .bind(locale)
+    .bind(text)
+    .bind(&now)
+    .bind(category)
+    .bind(locale)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Disclaimer {
+        id,
+        category: category.to_string(),
+        locale: locale.to_string(),
+        version,
+        text: text.to_string(),
+        created_at: now,
+    })
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde_json::json;
+
+/// Sends a plain-text email from EMAIL_FROM through the provider named by EMAIL_PROVIDER:
+/// `resend` (RESEND_API_KEY) or `sendgrid` (SENDGRID_API_KEY)
+pub async fn send(to: &str, subject: &str, text: &str) -> Result<(), String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let from = std::env::var("EMAIL_FROM").map_err(|_| "EMAIL_FROM is not set".to_string())?;
+    let provider = std::env::var("EMAIL_PROVIDER").unwrap_or_default();
+
+    let resp = match provider.as_str() {
+        "resend" => {
+            let key = std::env::var("RESEND_API_KEY").map_err(|_| "RESEND_API_KEY is not set".to_string())?;
+            client
+                .post("https://api.resend.com/emails")
+                .bearer_auth(key)
+                .json(&json!({ "from": from, "to": [to], "subject": subject, "text": text }))
+                .send()
+                .await
+        }
+        "sendgrid" => {
+            let key = std::env::var("SENDGRID_API_KEY").map_err(|_| "SENDGRID_API_KEY is not set".to_string())?;
+            client
+                .post("https://api.sendgrid.com/v3/mail/send")
+                .bearer_auth(key)
+                .json(&json!({
+                    "personalizations": [{ "to": [{ "email": to }] }],
+                    "from": { "email": from },
+                    "subject": subject,
+                    "content": [{ "type": "text/plain", "value": text }],
+                }))
+                .send()
+                .await
+        }
+        _ => return Err("EMAIL_PROVIDER is not configured".to_string()),
+    };
+
+    let resp = resp.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("{} {}", status, body));
+    }
+    Ok(())
+}
+
+pub fn configured() -> bool {
+    matches!(std::env::var("EMAIL_PROVIDER").as_deref(), Ok("resend") | Ok("sendgrid"))
+        && std::env::var("EMAIL_FROM").is_ok_and(|f| !f.trim().is_empty())
+}
+
+/// Loose shape check: something before the `@` and a dotted domain after it
+pub fn is_valid(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') && !email.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
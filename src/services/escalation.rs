@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use uuid::Uuid;
+
+use crate::services::telegram::TelegramBot;
+
+/// Reminders for support messages nobody answered; tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EscalationPolicy {
+    pub enabled: bool,
+    /// Minutes a user message may wait before the support group is pinged again
+    pub sla_minutes: i64,
+    /// Prepended to reminders, e.g. `@support_lead`
+    pub mention: Option<String>,
+    /// Second chat that gets the ticket once it has waited twice the SLA
+    pub escalation_chat_id: Option<i64>,
+}
+
+impl Default for EscalationPolicy {
+    fn default() -> Self {
+        EscalationPolicy {
+            enabled: true,
+            sla_minutes: 60,
+            mention: None,
+            escalation_chat_id: None,
+        }
+    }
+}
+
+/// Level 1 re-pings the support group, level 2 goes to the escalation chat
+const MAX_LEVEL: i64 = 2;
+
+/// Scheduler hook: sends the next due reminder for every thread whose oldest
+/// unanswered user message is past the SLA. Internal notes and FAQ auto answers don't count
+/// as replies; auto-resolved questions only wait once the user asks for a human.
+pub async fn run(pool: &SqlitePool, bot: &TelegramBot, policy: &EscalationPolicy) -> Result<(), Box<dyn std::error::Error>> {
+    if !policy.enabled || policy.sla_minutes <= 0 {
+        return Ok(());
+    }
+    let now = chrono::Utc::now();
+    let cutoff = (now - chrono::Duration::minutes(policy.sla_minutes)).format("%Y-%m-%d %H:%M:%S").to_string();
+
+    let waiting = sqlx::query(
+        "SELECT m.id, m.user_id, m.message, m.telegram_message_id,
+                COALESCE(m.human_requested_at, m.created_at) AS waiting_since,
+                COALESCE(u.full_name, u.email) AS user_name,
+                (SELECT MAX(e.level) FROM support_escalations e WHERE e.support_message_id = m.id) AS level
+         FROM support_messages m
+         LEFT JOIN users u ON u.id = m.user_id
+         WHERE m.direction = 'user' AND m.visibility = 'public' AND m.auto_resolved_at IS NULL
+           AND julianday(COALESCE(m.human_requested_at, m.created_at)) < julianday(?)
+           AND NOT EXISTS (
+               SELECT 1 FROM support_messages r
+               WHERE r.user_id = m.user_id AND r.direction = 'support' AND r.visibility = 'public' AND r.faq_id IS NULL
+                 AND julianday(r.created_at) >= julianday(m.created_at))
+           AND NOT EXISTS (
+               SELECT 1 FROM support_messages p
+               WHERE p.user_id = m.user_id AND p.direction = 'user' AND p.visibility = 'public' AND p.auto_resolved_at IS NULL
+                 AND (julianday(p.created_at) < julianday(m.created_at) OR (p.created_at = m.created_at AND p.rowid < m.rowid))
+                 AND NOT EXISTS (
+                     SELECT 1 FROM support_messages r
+                     WHERE r.user_id = p.user_id AND r.direction = 'support' AND r.visibility = 'public' AND r.faq_id IS NULL
+                       AND julianday(r.created_at) >= julianday(p.created_at)))"
+    )
+    .bind(&cutoff)
+    .fetch_all(pool)
+    .await?;
+
+    for r in waiting {
+        let message_id: String = r.get("id");
+        let waiting_since: String = r.get("waiting_since");
+        let level = r.get::<Option<i64>, _>("level").unwrap_or(0) + 1;
+        if level > MAX_LEVEL {
+            continue;
+        }
+
+        let waited = chrono::NaiveDateTime::parse_from_str(&waiting_since, "%Y-%m-%d %H:%M:%S")
+            .map(|t| (now.naive_utc() - t).num_minutes())
+            .unwrap_or(policy.sla_minutes);
+        // Level n is due after n times the SLA
+        if waited < policy.sla_minutes * level {
+            continue;
+        }
+        let (chat_id, reply_to) = match level {
+            1 => (None, r.get::<Option<i64>, _>("telegram_message_id")),
+            _ => match policy.escalation_chat_id {
+                Some(id) => (Some(id), None),
+                None => continue,
+            },
+        };
+
+        let text = reminder_text(
+            policy.mention.as_deref(),
+            r.get::<Option<String>, _>("user_name").as_deref(),
+            &r.get::<String, _>("message"),
+            waited,
+            level,
+        );
+        let telegram_message_id = bot.send_text(chat_id, &text, reply_to).await?;
+
+        sqlx::query(
+            "INSERT INTO support_escalations (id, user_id, support_message_id, level, chat_id, telegram_message_id, waited_minutes, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(r.get::<String, _>("user_id"))
+        .bind(&message_id)
+        .bind(level)
+        .bind(chat_id.unwrap_or_else(|| bot.group_chat_id()))
+        .bind(telegram_message_id)
+        .bind(waited)
+        .bind(now.to_rfc3339())
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+fn reminder_text(mention: Option<&str>, user_name: Option<&str>, message: &str, waited: i64, level: i64) -> String {
+    let preview: String = message.chars().take(300).collect();
+    let headline = if level == 1 { "⏰ Нет ответа" } else { "🚨 Эскалация: нет ответа" };
+    let mention = mention.map(|m| format!("{} ", m)).unwrap_or_default();
+    format!(
+        "{}<b>{} {} мин.</b>\n👤 {}\n\n{}",
+        mention,
+        headline,
+        waited,
+        html_escape(user_name.unwrap_or("Пользователь")),
+        html_escape(&preview)
+    )
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
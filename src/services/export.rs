@@ -0,0 +1,208 @@
+use std::env;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+
+use crate::services::s3::S3Target;
+
+pub struct ExportColumn {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Identifiers are replaced by a salted hash so rows can be joined but not traced back
+    pub pseudonymize: bool,
+}
+
+pub struct ExportTable {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Must select every column as TEXT plus the watermark as `wm` and the rowid as `wm_rowid`,
+    /// filtered by `(wm, rowid) > (?, ?)` so rows sharing the last exported timestamp aren't skipped
+    query: &'static str,
+    pub columns: &'static [ExportColumn],
+}
+
+const fn col(name: &'static str, description: &'static str) -> ExportColumn {
+    ExportColumn { name, description, pseudonymize: false }
+}
+
+const fn pseudo(name: &'static str, description: &'static str) -> ExportColumn {
+    ExportColumn { name, description, pseudonymize: true }
+}
+
+pub const EXPORT_TABLES: &[ExportTable] = &[
+    ExportTable {
+        name: "messages",
+        description: "Chat messages without content, one row per message",
+        query: "SELECT id, conversation_id, user_id, role, CAST(length(content) AS TEXT) AS content_length, timestamp, timestamp AS wm, rowid AS wm_rowid
+                FROM messages WHERE (timestamp, rowid) > (?, ?) ORDER BY timestamp, rowid",
+        columns: &[
+            pseudo("id", "Message id"),
+            pseudo("conversation_id", "Conversation id"),
+            pseudo("user_id", "Author user id, empty for assistant messages"),
+            col("role", "user or assistant"),
+            col("content_length", "Message length in characters"),
+            col("timestamp", "RFC3339 creation time"),
+        ],
+    },
+    ExportTable {
+        name: "conversations",
+        description: "Conversations without titles",
+        query: "SELECT id, user_id, created_at, created_at AS wm, rowid AS wm_rowid
+                FROM conversations WHERE (created_at, rowid) > (?, ?) ORDER BY created_at, rowid",
+        columns: &[
+            pseudo("id", "Conversation id"),
+            pseudo("user_id", "Owner user id"),
+            col("created_at", "RFC3339 creation time"),
+        ],
+    },
+    ExportTable {
+        name: "users",
+        description: "Accounts reduced to segmentation attributes",
+        query: "SELECT id, business_type, country, created_at, created_at AS wm, rowid AS wm_rowid
+                FROM users WHERE (created_at, rowid) > (?, ?) ORDER BY created_at, rowid",
+        columns: &[
+            pseudo("id", "User id"),
+            col("business_type", "Declared business type"),
+            col("country", "Profile country"),
+            col("created_at", "RFC3339 registration time"),
+        ],
+    },
+    ExportTable {
+        name: "top_weekly_trends",
+        description: "Published weekly top trends",
+        query: "SELECT week_start, CAST(position AS TEXT) AS position, title, CAST(increase AS TEXT) AS increase,
+                       CAST(request_percent AS TEXT) AS request_percent, created_at, created_at AS wm, rowid AS wm_rowid
+                FROM top_weekly_trends WHERE (created_at, rowid) > (?, ?) ORDER BY created_at, rowid",
+        columns: &[
+            col("week_start", "Week start date"),
+            col("position", "1 or 2"),
+            col("title", "Trend title"),
+            col("increase", "Growth percent"),
+            col("request_percent", "Share of requests"),
+            col("created_at", "Publication time"),
+        ],
+    },
+    ExportTable {
+        name: "niches_month",
+        description: "Published niches of the month",
+        query: "SELECT month_start, title, CAST(change AS TEXT) AS change, created_at, created_at AS wm, rowid AS wm_rowid
+                FROM niches_month WHERE (created_at, rowid) > (?, ?) ORDER BY created_at, rowid",
+        columns: &[
+            col("month_start", "Month start date"),
+            col("title", "Niche title"),
+            col("change", "Change percent"),
+            col("created_at", "Publication time"),
+        ],
+    },
+];
+
+#[derive(Serialize)]
+pub struct ExportResult {
+    pub table: &'static str,
+    pub rows: usize,
+    pub path: Option<String>,
+    /// Object key the dump was uploaded to, when EXPORT_S3_BUCKET is set
+    pub s3_key: Option<String>,
+    pub watermark: Option<String>,
+}
+
+fn export_dir() -> PathBuf {
+    PathBuf::from(env::var("EXPORT_DIR").unwrap_or_else(|_| "exports".to_string()))
+}
+
+fn pseudonymize(salt: &str, value: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", salt, value).as_bytes());
+    digest.iter().take(12).map(|b| format!("{:02x}", b)).collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Dumps rows newer than each table's watermark to EXPORT_DIR/<table>/<table>-<ts>.csv and, when
+/// EXPORT_S3_BUCKET is set, uploads each dump to the bucket under the same path. Dumps are CSV only;
+/// BI tools that want Parquet convert on their side.
+pub async fn run_export(pool: &SqlitePool) -> Result<Vec<ExportResult>, Box<dyn std::error::Error>> {
+    let salt = env::var("EXPORT_SALT").map_err(|_| "EXPORT_SALT is not set")?;
+    let s3 = S3Target::from_env()?;
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut results = Vec::new();
+
+    for table in EXPORT_TABLES {
+        let (since, since_rowid): (String, i64) = sqlx::query_as(
+            "SELECT watermark, watermark_rowid FROM export_watermarks WHERE table_name = ?"
+        )
+        .bind(table.name)
+        .fetch_optional(pool)
+        .await?
+        .unwrap_or_default();
+
+        let rows = sqlx::query(table.query).bind(&since).bind(since_rowid).fetch_all(pool).await?;
+        if rows.is_empty() {
+            results.push(ExportResult { table: table.name, rows: 0, path: None, s3_key: None, watermark: None });
+            continue;
+        }
+
+        let mut csv = table.columns.iter().map(|c| c.name).collect::<Vec<_>>().join(",");
+        csv.push('\n');
+        let (mut watermark, mut watermark_rowid) = (since.clone(), since_rowid);
+        for r in &rows {
+            let fields: Vec<String> = table.columns.iter().map(|c| {
+                let value: String = r.try_get::<Option<String>, _>(c.name).ok().flatten().unwrap_or_default();
+                if c.pseudonymize && !value.is_empty() {
+                    pseudonymize(&salt, &value)
+                } else {
+                    csv_field(&value)
+                }
+            }).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+            watermark = r.get("wm");
+            watermark_rowid = r.get("wm_rowid");
+        }
+
+        let dir = export_dir().join(table.name);
+        tokio::fs::create_dir_all(&dir).await?;
+        let file_name = format!("{}-{}.csv", table.name, stamp);
+        let path = dir.join(&file_name);
+        tokio::fs::write(&path, csv.as_bytes()).await?;
+
+        let s3_key = match &s3 {
+            Some(target) => {
+                let key = target.key(&format!("{}/{}", table.name, file_name));
+                target.put(&key, "text/csv", csv.into_bytes()).await?;
+                Some(key)
+            }
+            None => None,
+        };
+
+        // Only advance the watermark once the dump is on disk (and in the bucket)
+        sqlx::query(
+            "INSERT INTO export_watermarks (table_name, watermark, watermark_rowid, exported_at) VALUES (?, ?, ?, ?)
+             ON CONFLICT(table_name) DO UPDATE SET watermark = excluded.watermark,
+                 watermark_rowid = excluded.watermark_rowid, exported_at = excluded.exported_at"
+        )
+        .bind(table.name)
+        .bind(&watermark)
+        .bind(watermark_rowid)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+
+        results.push(ExportResult {
+            table: table.name,
+            rows: rows.len(),
+            path: Some(path.to_string_lossy().into_owned()),
+            s3_key,
+            watermark: Some(watermark),
+        });
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,30 @@
+/// Extracted text is cut at this many characters so one attachment can't crowd out the prompt
+pub const MAX_EXTRACTED_CHARS: usize = 20_000;
+
+/// Plain text of a document for the model; `None` for formats without a text layer (images)
+pub fn extract_text(mime: &str, bytes: &[u8]) -> Option<String> {
+    extract_text_up_to(mime, bytes, MAX_EXTRACTED_CHARS)
+}
+
+/// `extract_text` with a caller-chosen cap, for documents that are chunked rather than inlined
+pub fn extract_text_up_to(mime: &str, bytes: &[u8], max_chars: usize) -> Option<String> {
+    let text = if mime == "application/pdf" {
+        pdf_text(bytes)?
+    } else if mime.starts_with("text/") || mime == "application/json" {
+        String::from_utf8_lossy(bytes).trim_start_matches('\u{feff}').to_string()
+    } else {
+        return None;
+    };
+
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.chars().take(max_chars).collect())
+}
+
+fn pdf_text(bytes: &[u8]) -> Option<String> {
+    let doc = lopdf::Document::load_mem(bytes).ok()?;
+    let pages: Vec<u32> = doc.get_pages().keys().copied().collect();
+    doc.extract_text(&pages).ok()
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::i18n::Locale;
+use crate::services::openai;
+use crate::state::AppState;
+
+/// FAQ entries offered to the model per question
+const MAX_FAQ_ENTRIES: i64 = 50;
+
+/// Per-entry outcome of automatic answers, joined as `u`: `answered` counts auto replies,
+/// `handed_off` those where the user still asked for a human. The rest resolved the ticket.
+pub const USAGE_JOIN: &str = "LEFT JOIN (
+        SELECT a.faq_id,
+               COUNT(*) AS answered,
+               SUM(CASE WHEN q.human_requested_at IS NOT NULL THEN 1 ELSE 0 END) AS handed_off
+        FROM support_messages a JOIN support_messages q ON q.id = a.in_reply_to
+        WHERE a.faq_id IS NOT NULL
+        GROUP BY a.faq_id
+    ) u ON u.faq_id = f.id";
+
+/// Instant FAQ answers for support messages, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoAnswerPolicy {
+    pub enabled: bool,
+    /// Answers the model is less sure about go to a human instead
+    pub min_confidence: f64,
+    /// Entries answered at least `min_samples` times are only offered while this share
+    /// of their answers resolves the ticket
+    pub min_resolution_rate: f64,
+    pub min_samples: i64,
+}
+
+impl Default for AutoAnswerPolicy {
+    fn default() -> Self {
+        AutoAnswerPolicy {
+            enabled: true,
+            min_confidence: 0.8,
+            min_resolution_rate: 0.5,
+            min_samples: 5,
+        }
+    }
+}
+
+pub struct AutoAnswer {
+    pub faq_id: String,
+    pub answer: String,
+    pub confidence: f64,
+}
+
+#[derive(Deserialize)]
+struct Verdict {
+    faq_id: Option<String>,
+    #[serde(default)]
+    answer: String,
+    #[serde(default)]
+    confidence: f64,
+}
+
+/// An answer from the FAQ when the model is confident enough; `None` means a human should reply
+pub async fn try_answer(state: &AppState, locale: Locale, question: &str) -> Option<AutoAnswer> {
+    let policy = state.config.load().support_auto_answer.clone();
+    if !policy.enabled || question.trim().is_empty() {
+        return None;
+    }
+
+    // Entries that keep resolving tickets come first, ones users keep escalating past are left out
+    let sql = format!(
+        "SELECT f.id, f.question, f.answer FROM faqs f {}
+         WHERE f.locale = ? AND f.active = 1
+           AND (COALESCE(u.answered, 0) < ? OR 1.0 * (u.answered - u.handed_off) / u.answered >= ?)
+         ORDER BY COALESCE(u.answered - u.handed_off, 0) DESC, f.updated_at DESC
+         LIMIT ?",
+        USAGE_JOIN
+    );
+    let rows = sqlx::query(&sql)
+    .bind(match locale { Locale::Ru => "ru", Locale::En => "en" })
+    .bind(policy.min_samples)
+    .bind(policy.min_resolution_rate)
+    .bind(MAX_FAQ_ENTRIES)
+    .fetch_all(&state.pool)
+    .await
+    .ok()?;
+    if rows.is_empty() {
+        return None;
+    }
+    let faqs: Vec<(String, String, String)> = rows
+        .into_iter()
+        .map(|r| (r.get("id"), r.get("question"), r.get("answer")))
+        .collect();
+
+    let raw = match openai::answer_from_faq(state, locale, question, &faqs).await {
+        Ok(raw) => raw,
+        Err(e) => {
+            eprintln!("Support auto-answer failed: {}", e);
+            return None;
+        }
+    };
+    let verdict = parse_verdict(&raw)?;
+    let faq_id = verdict.faq_id.filter(|id| faqs.iter().any(|(known, _, _)| known == id))?;
+    if verdict.confidence < policy.min_confidence || verdict.answer.trim().is_empty() {
+        return None;
+    }
+    Some(AutoAnswer {
+        faq_id,
+        answer: verdict.answer.trim().to_string(),
+        confidence: verdict.confidence,
+    })
+}
+
+/// Models sometimes wrap the JSON in prose or code fences, so only the outermost object is read
+fn parse_verdict(raw: &str) -> Option<Verdict> {
+    let start = raw.find('{')?;
+    let end = raw.rfind('}')?;
+    serde_json::from_str(raw.get(start..=end)?).ok()
+}
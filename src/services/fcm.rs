@@ -7,6 +7,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
 use base64::{Engine as _, engine::general_purpose};
+use sqlx::SqlitePool;
+
+use crate::services::dead_letters;
 
 #[derive(Deserialize, Debug)]
 struct ServiceAccount {
@@ -135,24 +138,22 @@ impl FcmService {
         Ok(token_response.access_token)
     }
 
+    /// Pushes to every token, retrying each a few times; pushes that still fail go to the
+    /// dead-letter queue. Errors only when FCM is configured but unusable.
     pub async fn send_notification(
         &self,
+        pool: &SqlitePool,
         tokens: Vec<String>,
         title: &str,
         body: &str,
         data: Option<HashMap<String, String>>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let project_id = match &self.project_id {
-            Some(id) => id,
-            None => {
-                eprintln!("FCM not configured - skipping push notifications");
-                return Ok(());
-            }
-        };
-
-        let access_token = self.get_access_token().await?;
-
-        let url = format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", project_id);
+        if self.project_id.is_none() {
+            eprintln!("FCM not configured - skipping push notifications");
+            return Ok(());
+        }
+        // Fail fast on bad credentials instead of dead-lettering every token
+        self.get_access_token().await?;
 
         for token in tokens {
             let mut message = json!({
@@ -173,28 +174,62 @@ impl FcmService {
                 message["message"]["data"] = data_obj;
             }
 
-            let response = self.client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", access_token))
-                .header("Content-Type", "application/json")
-                .json(&message)
-                .send()
-                .await;
-
-            match response {
-                Ok(resp) => {
-                    let status = resp.status();
-                    if !status.is_success() {
-                        let error_text = resp.text().await.unwrap_or_default();
-                        eprintln!("FCM v1 API error: {} - {}", status, error_text);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to send FCM notification: {}", e);
-                }
+            if let Err(e) = dead_letters::with_retries(|| self.push(&message)).await {
+                eprintln!("Failed to send FCM notification: {}", e);
+                let payload = json!({ "token": token, "message": message });
+                dead_letters::record(pool, dead_letters::CHANNEL_FCM, &payload, &e, dead_letters::MAX_ATTEMPTS).await;
             }
         }
 
         Ok(())
     }
+
+    /// One attempt at sending a prepared `{"message": ...}` body. Tokens FCM rejects as
+    /// unregistered or malformed are logged and dropped: no retry would ever deliver them.
+    pub async fn push(&self, message: &serde_json::Value) -> Result<(), String> {
+        let project_id = self.project_id.as_ref().ok_or("FCM not configured")?;
+        let access_token = self.get_access_token().await.map_err(|e| e.to_string())?;
+        let url = format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", project_id);
+
+        let resp = self.client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Content-Type", "application/json")
+            .json(message)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let error_text = resp.text().await.unwrap_or_default();
+        if matches!(status.as_u16(), 400 | 404) {
+            eprintln!("FCM v1 API rejected token: {} - {}", status, error_text);
+            return Ok(());
+        }
+        Err(format!("FCM v1 API error: {} - {}", status, error_text))
+    }
+}
+
+/// Registered push tokens of every device the user is signed in on
+pub async fn user_tokens(pool: &sqlx::SqlitePool, user_id: &str) -> Vec<String> {
+    sqlx::query_scalar(
+        "SELECT fcm_token FROM device_tokens d WHERE d.user_id = ?
+         AND NOT EXISTS(SELECT 1 FROM user_preferences p WHERE p.user_id = d.user_id AND p.push_notifications = 0)"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default()
+}
+
+/// Every device of the user, push preference or not; only for account security alerts
+pub async fn security_tokens(pool: &sqlx::SqlitePool, user_id: &str) -> Vec<String> {
+    sqlx::query_scalar("SELECT fcm_token FROM device_tokens WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
 }
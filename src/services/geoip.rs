@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::HttpRequest;
+use reqwest::Client;
+
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Addresses remembered at once; expired entries go first, then the whole cache is dropped
+const MAX_CACHED: usize = 10_000;
+
+struct GeoIp {
+    client: Client,
+    url_template: String,
+    cache: Mutex<HashMap<IpAddr, (Option<String>, Instant)>>,
+}
+
+fn geoip() -> Option<&'static GeoIp> {
+    static INSTANCE: OnceLock<Option<GeoIp>> = OnceLock::new();
+    INSTANCE
+        .get_or_init(|| {
+            // e.g. https://ipapi.co/{ip}/country_name/ — must answer with the bare country name
+            let url_template = std::env::var("GEOIP_API_URL").ok()?;
+            let client = Client::builder().timeout(Duration::from_secs(2)).build().ok()?;
+            Some(GeoIp { client, url_template, cache: Mutex::new(HashMap::new()) })
+        })
+        .as_ref()
+}
+
+/// Reverse proxies allowed to say who the client is, from TRUSTED_PROXIES (comma-separated IPs)
+fn trusted_proxies() -> &'static Vec<IpAddr> {
+    static PROXIES: OnceLock<Vec<IpAddr>> = OnceLock::new();
+    PROXIES.get_or_init(|| {
+        std::env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|p| p.trim().parse().ok())
+            .collect()
+    })
+}
+
+fn parse_ip(raw: &str) -> Option<IpAddr> {
+    let raw = raw.trim();
+    raw.parse::<IpAddr>()
+        .ok()
+        .or_else(|| raw.parse::<SocketAddr>().ok().map(|s| s.ip()))
+}
+
+/// Address rate limits and sessions record: the TCP peer, unless the peer is a trusted proxy, in
+/// which case the nearest `X-Forwarded-For` hop that isn't one of our proxies. Hops further left
+/// are whatever the client wrote and are never believed.
+pub(crate) fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let proxies = trusted_proxies();
+    if !proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let forwarded = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').filter_map(parse_ip).find(|ip| !proxies.contains(ip)));
+    Some(forwarded.unwrap_or(peer))
+}
+
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => !(v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_unspecified()),
+        IpAddr::V6(v6) => !(v6.is_loopback() || v6.is_unspecified()),
+    }
+}
+
+/// Cached lookup; failures are cached too so an outage doesn't slow every request
+async fn lookup(geo: &GeoIp, ip: IpAddr) -> Option<String> {
+    if let Some((country, at)) = geo.cache.lock().unwrap().get(&ip) {
+        if at.elapsed() < CACHE_TTL {
+            return country.clone();
+        }
+    }
+
+    let url = geo.url_template.replace("{ip}", &ip.to_string());
+    let country = match geo.client.get(&url).send().await {
+        Ok(resp) if resp.status().is_success() => resp
+            .text()
+            .await
+            .ok()
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty() && t.len() <= 64),
+        _ => None,
+    };
+
+    let mut cache = geo.cache.lock().unwrap();
+    if cache.len() >= MAX_CACHED {
+        cache.retain(|_, (_, at)| at.elapsed() < CACHE_TTL);
+        if cache.len() >= MAX_CACHED {
+            cache.clear();
+        }
+    }
+    cache.insert(ip, (country.clone(), Instant::now()));
+    country
+}
+
+/// Country of the requester's address when GEOIP_API_URL is configured. Looked up only by
+/// handlers that use it, so other requests never wait on the lookup service.
+pub async fn request_country(req: &HttpRequest) -> Option<String> {
+    let geo = geoip()?;
+    let ip = client_ip(req).filter(is_public)?;
+    lookup(geo, ip).await
+}
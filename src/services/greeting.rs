@@ -0,0 +1,72 @@
+use chrono::{Datelike, FixedOffset, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::Locale;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GreetingText {
+    pub in_hours: String,
+    /// `{hours}` is replaced with `reply_within_hours`
+    pub off_hours: String,
+}
+
+/// Support greeting shown above the support thread, tunable through the runtime config
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GreetingSettings {
+    pub ru: GreetingText,
+    pub en: GreetingText,
+    /// Offset of the support team's timezone from UTC, in minutes (Moscow is 180)
+    pub utc_offset_minutes: i32,
+    /// ISO weekdays the team works, 1 = Monday
+    pub work_days: Vec<u32>,
+    /// Working hours as `[open, close)` in local time
+    pub open_hour: u32,
+    pub close_hour: u32,
+    pub reply_within_hours: u32,
+}
+
+impl Default for GreetingSettings {
+    fn default() -> Self {
+        GreetingSettings {
+            ru: GreetingText {
+                in_hours: "Здравствуйте! Чем можем помочь? Опишите вопрос, и мы скоро ответим.".to_string(),
+                off_hours: "Здравствуйте! Сейчас нерабочее время, мы ответим в течение {hours} ч.".to_string(),
+            },
+            en: GreetingText {
+                in_hours: "Hello! How can we help? Describe your question and we'll reply shortly.".to_string(),
+                off_hours: "Hello! We're outside business hours right now and will reply within {hours} hours.".to_string(),
+            },
+            utc_offset_minutes: 180,
+            work_days: vec![1, 2, 3, 4, 5],
+            open_hour: 9,
+            close_hour: 18,
+            reply_within_hours: 12,
+        }
+    }
+}
+
+impl GreetingSettings {
+    pub fn in_business_hours(&self, now: chrono::DateTime<Utc>) -> bool {
+        let offset = match FixedOffset::east_opt(self.utc_offset_minutes * 60) {
+            Some(o) => o,
+            None => return true,
+        };
+        let local = offset.from_utc_datetime(&now.naive_utc());
+        self.work_days.contains(&local.weekday().number_from_monday())
+            && local.hour() >= self.open_hour
+            && local.hour() < self.close_hour
+    }
+
+    pub fn render(&self, locale: Locale, now: chrono::DateTime<Utc>) -> String {
+        let text = match locale {
+            Locale::Ru => &self.ru,
+            Locale::En => &self.en,
+        };
+        if self.in_business_hours(now) {
+            text.in_hours.clone()
+        } else {
+            text.off_hours.replace("{hours}", &self.reply_within_hours.to_string())
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::http::header;
+use actix_web::{web, HttpMessage, HttpRequest};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::{self, Locale};
+use crate::state::AppState;
+
+/// A session confirmed against the database is trusted for this long before it is looked up again,
+/// so revoking a session takes effect on other workers within a minute
+const SESSION_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Claims carried by a session token
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// User id
+    pub sub: String,
+    /// Id of the `sessions` row, which is what gets revoked
+    pub sid: String,
+    /// Language the session was opened in: `ru` or `en`
+    pub locale: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// A request token that passed signature and expiry checks; stored in request extensions
+#[derive(Clone)]
+pub struct VerifiedToken {
+    pub raw: String,
+    pub claims: Claims,
+}
+
+/// HS256 key from JWT_SECRET. Without it a random key is used, which signs everyone out on restart.
+pub fn load_secret() -> Vec<u8> {
+    match std::env::var("JWT_SECRET") {
+        Ok(s) if !s.trim().is_empty() => s.into_bytes(),
+        _ => {
+            eprintln!("JWT_SECRET is not set; session tokens will not survive a restart");
+            rand::random::<[u8; 32]>().to_vec()
+        }
+    }
+}
+
+pub fn issue(secret: &[u8], user_id: &str, session_id: &str, locale: Locale, expires_at: chrono::DateTime<chrono::Utc>) -> Option<String> {
+    let claims = Claims {
+        sub: user_id.to_string(),
+        sid: session_id.to_string(),
+        locale: i18n::language_code(locale).to_string(),
+        iat: chrono::Utc::now().timestamp(),
+        exp: expires_at.timestamp(),
+    };
+    encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(secret))
+        .map_err(|e| eprintln!("Failed to sign session token: {}", e))
+        .ok()
+}
+
+/// Signature and expiry check; says nothing about whether the session was revoked
+pub fn verify(secret: &[u8], token: &str) -> Option<Claims> {
+    decode::<Claims>(token, &DecodingKey::from_secret(secret), &Validation::new(Algorithm::HS256))
+        .ok()
+        .map(|data| data.claims)
+}
+
+/// Session tokens issued before JWTs are bare UUIDs and have no dots
+pub fn is_jwt(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+#[derive(Deserialize)]
+struct TokenParam {
+    token: Option<String>,
+}
+
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// Session token of the request: `?token=`, else `Authorization: Bearer`
+pub fn request_token(req: &HttpRequest) -> Option<String> {
+    web::Query::<TokenParam>::from_query(req.query_string())
+        .ok()
+        .and_then(|q| q.into_inner().token)
+        .filter(|t| !t.is_empty())
+        .or_else(|| bearer_token(req))
+}
+
+/// Middleware attaching `VerifiedToken` when the request token is a valid JWT. Bad or expired
+/// tokens are not rejected here: endpoints where the token is optional treat them as anonymous.
+pub async fn verify_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = request_token(req.request()).filter(|t| is_jwt(t));
+    if let (Some(token), Some(state)) = (token, req.app_data::<web::Data<AppState>>()) {
+        if let Some(claims) = verify(&state.jwt_secret, &token) {
+            req.extensions_mut().insert(VerifiedToken { raw: token, claims });
+        }
+    }
+    next.call(req).await
+}
+
+/// Claims for `token` if the middleware verified it on this request
+pub fn request_claims(req: &HttpRequest, token: &str) -> Option<Claims> {
+    req.extensions()
+        .get::<VerifiedToken>()
+        .filter(|v| v.raw == token)
+        .map(|v| v.claims.clone())
+}
+
+/// (session id, presented device id) -> (user id, confirmed at)
+fn session_cache() -> &'static Mutex<HashMap<(String, Option<String>), (String, Instant)>> {
+    static CACHE: OnceLock<Mutex<HashMap<(String, Option<String>), (String, Instant)>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn cached_session(session_id: &str, device_id: Option<&str>) -> Option<String> {
+    let key = (session_id.to_string(), device_id.map(str::to_string));
+    let mut cache = session_cache().lock().unwrap();
+    match cache.get(&key) {
+        Some((user_id, at)) if at.elapsed() < SESSION_CACHE_TTL => Some(user_id.clone()),
+        Some(_) => {
+            cache.remove(&key);
+            None
+        }
+        None => None,
+    }
+}
+
+pub fn remember_session(session_id: &str, device_id: Option<&str>, user_id: &str) {
+    let mut cache = session_cache().lock().unwrap();
+    cache.retain(|_, (_, at)| at.elapsed() < SESSION_CACHE_TTL);
+    cache.insert(
+        (session_id.to_string(), device_id.map(str::to_string)),
+        (user_id.to_string(), Instant::now()),
+    );
+}
+
+pub fn forget_session(session_id: &str) {
+    session_cache().lock().unwrap().retain(|(sid, _), _| sid != session_id);
+}
@@ -0,0 +1,211 @@
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::i18n::Locale;
+use crate::services::openai;
+
+/// Target chunk length in characters, and how much consecutive chunks overlap
+const CHUNK_CHARS: usize = 1500;
+const CHUNK_OVERLAP: usize = 200;
+/// Chunks sent to the embeddings API per request
+const EMBED_BATCH: usize = 32;
+/// Chunks added to the prompt for one message
+pub const TOP_K: usize = 5;
+/// Cosine similarity below which a chunk is not considered relevant at all
+const MIN_SIMILARITY: f32 = 0.3;
+
+#[derive(Serialize)]
+pub struct KnowledgeDocument {
+    pub id: String,
+    pub file_id: String,
+    pub filename: String,
+    pub mime: String,
+    pub chunk_count: i64,
+    pub embedding_model: String,
+    pub created_at: String,
+    pub download_url: String,
+}
+
+pub fn from_row(r: &sqlx::sqlite::SqliteRow) -> KnowledgeDocument {
+    let file_id: String = r.get("file_id");
+    KnowledgeDocument {
+        id: r.get("id"),
+        download_url: format!("/api/files/{}", file_id),
+        file_id,
+        filename: r.get("filename"),
+        mime: r.get("mime"),
+        chunk_count: r.get("chunk_count"),
+        embedding_model: r.get("embedding_model"),
+        created_at: r.get("created_at"),
+    }
+}
+
+/// Splits `text` into overlapping chunks, ending each on a paragraph or sentence break
+/// in its second half when there is one
+pub fn chunk_text(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + CHUNK_CHARS).min(chars.len());
+        if end < chars.len() {
+            let half = start + CHUNK_CHARS / 2;
+            let paragraph = (half..end).rev().find(|&i| chars[i] == '\n');
+            let sentence = (half..end).rev().find(|&i| matches!(chars[i], '.' | '!' | '?'));
+            if let Some(i) = paragraph.or(sentence) {
+                end = i + 1;
+            }
+        }
+        let chunk: String = chars[start..end].iter().collect();
+        if !chunk.trim().is_empty() {
+            chunks.push(chunk.trim().to_string());
+        }
+        if end == chars.len() {
+            break;
+        }
+        start = end.saturating_sub(CHUNK_OVERLAP).max(start + 1);
+    }
+    chunks
+}
+
+fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn from_blob(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+    if norm == 0.0 { 0.0 } else { dot / norm }
+}
+
+/// Embeds the chunks of an uploaded document and stores them with the document record
+pub async fn index_document(
+    pool: &SqlitePool,
+    user_id: &str,
+    file_id: &str,
+    filename: &str,
+    mime: &str,
+    text: &str,
+) -> Result<KnowledgeDocument, Box<dyn std::error::Error>> {
+    let chunks = chunk_text(text);
+    let mut vectors = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(EMBED_BATCH) {
+        vectors.extend(openai::embed(batch).await?);
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let model = openai::embedding_model();
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO knowledge_documents (id, user_id, file_id, filename, mime, chunk_count, embedding_model, created_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+    )
+    .bind(&id)
+    .bind(user_id)
+    .bind(file_id)
+    .bind(filename)
+    .bind(mime)
+    .bind(chunks.len() as i64)
+    .bind(&model)
+    .bind(&now)
+    .execute(&mut tx)
+    .await?;
+    for (index, (chunk, vector)) in chunks.iter().zip(&vectors).enumerate() {
+        sqlx::query(
+            "INSERT INTO embeddings (id, document_id, user_id, chunk_index, content, vector, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(uuid::Uuid::new_v4().to_string())
+        .bind(&id)
+        .bind(user_id)
+        .bind(index as i64)
+        .bind(chunk)
+        .bind(to_blob(vector))
+        .bind(&now)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(KnowledgeDocument {
+        id,
+        download_url: format!("/api/files/{}", file_id),
+        file_id: file_id.to_string(),
+        filename: filename.to_string(),
+        mime: mime.to_string(),
+        chunk_count: chunks.len() as i64,
+        embedding_model: model,
+        created_at: now,
+    })
+}
+
+/// The user's chunks most similar to `query` as (filename, chunk), best first. Chunks embedded
+/// with another model than the current one are skipped, and any failure just means no context.
+pub async fn relevant_chunks(pool: &SqlitePool, user_id: &str, query: &str) -> Vec<(String, String)> {
+    let model = openai::embedding_model();
+    let has_chunks: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM knowledge_documents WHERE user_id = ? AND embedding_model = ? LIMIT 1"
+    )
+    .bind(user_id)
+    .bind(&model)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten();
+    if has_chunks.is_none() {
+        return Vec::new();
+    }
+
+    let query_vector = match openai::embed(&[query.to_string()]).await {
+        Ok(mut v) if !v.is_empty() => v.remove(0),
+        Ok(_) => return Vec::new(),
+        Err(e) => {
+            eprintln!("Knowledge base: query embedding failed: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let rows = sqlx::query(
+        "SELECT d.filename, e.content, e.vector FROM embeddings e
+         JOIN knowledge_documents d ON d.id = e.document_id
+         WHERE e.user_id = ? AND d.embedding_model = ?"
+    )
+    .bind(user_id)
+    .bind(&model)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_default();
+
+    let mut scored: Vec<(f32, String, String)> = rows
+        .iter()
+        .map(|r| {
+            let vector = from_blob(&r.get::<Vec<u8>, _>("vector"));
+            (cosine(&query_vector, &vector), r.get("filename"), r.get("content"))
+        })
+        .filter(|(score, _, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().take(TOP_K).map(|(_, filename, content)| (filename, content)).collect()
+}
+
+/// System note with the retrieved excerpts, asking the model to rely on and name them
+pub fn prompt_note(chunks: &[(String, String)], locale: Locale) -> String {
+    let header = match locale {
+        Locale::Ru => "Фрагменты документов пользователя, относящиеся к вопросу. Опирайся на эти данные \
+            (цифры, условия договоров) и указывай, из какого документа они взяты. Не выдумывай того, чего в них нет:",
+        Locale::En => "Excerpts from the user's own documents relevant to the question. Base the answer on these \
+            figures and terms and say which document they come from. Do not invent anything they don't say:",
+    };
+    let mut note = header.to_string();
+    for (filename, content) in chunks {
+        note.push_str(&format!("\n\n--- {} ---\n{}", filename, content));
+    }
+    note
+}
@@ -1,3 +1,51 @@
 pub mod openai;
 pub mod telegram;
-pub mod fcm;
\ No newline at end of file
+pub mod fcm;
+pub mod pdf;
+pub mod payments;
+pub mod export;
+pub mod s3;
+pub mod topics;
+pub mod geoip;
+pub mod captcha;
+pub mod abuse;
+pub mod password;
+pub mod storage;
+pub mod bundle;
+pub mod antivirus;
+pub mod extract;
+pub mod greeting;
+pub mod summary;
+pub mod escalation;
+pub mod tokens;
+pub mod faq;
+pub mod archive;
+pub mod transcript;
+pub mod selfcheck;
+pub mod tls;
+pub mod structured;
+pub mod disclaimer;
+pub mod tools;
+pub mod clarify;
+pub mod quality;
+pub mod knowledge;
+pub mod dead_letters;
+pub mod breaker;
+pub mod retry_queue;
+pub mod reference;
+pub mod titles;
+pub mod timezone;
+pub mod widget;
+pub mod transcription;
+pub mod tts;
+pub mod daily_digest;
+pub mod jwt;
+pub mod oauth;
+pub mod account;
+pub mod sms;
+pub mod email;
+pub mod preferences;
+pub mod avatar;
+pub mod onboarding;
+pub mod analytics_refresh;
+pub mod trends;
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::Client;
+use serde::Deserialize;
+
+/// Providers rotate signing keys rarely and publish new ones well ahead of use
+const JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Provider {
+    Google,
+    Apple,
+}
+
+impl Provider {
+    pub fn parse(name: &str) -> Option<Provider> {
+        match name {
+            "google" => Some(Provider::Google),
+            "apple" => Some(Provider::Apple),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Google => "google",
+            Provider::Apple => "apple",
+        }
+    }
+
+    fn jwks_url(self) -> &'static str {
+        match self {
+            Provider::Google => "https://www.googleapis.com/oauth2/v3/certs",
+            Provider::Apple => "https://appleid.apple.com/auth/keys",
+        }
+    }
+
+    fn issuers(self) -> &'static [&'static str] {
+        match self {
+            Provider::Google => &["https://accounts.google.com", "accounts.google.com"],
+            Provider::Apple => &["https://appleid.apple.com"],
+        }
+    }
+
+    /// Comma-separated client ids (web, iOS, Android) the tokens must be issued to
+    fn audiences(self) -> Vec<String> {
+        let var = match self {
+            Provider::Google => "GOOGLE_CLIENT_IDS",
+            Provider::Apple => "APPLE_CLIENT_IDS",
+        };
+        std::env::var(var)
+            .unwrap_or_default()
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect()
+    }
+}
+
+/// Account the provider vouched for
+pub struct Identity {
+    /// Stable provider user id (`sub`)
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+}
+
+pub enum OAuthError {
+    /// No client ids configured for the provider
+    NotConfigured,
+    /// Bad signature, wrong audience or issuer, expired, or nonce mismatch
+    InvalidToken,
+    /// The provider's keys couldn't be fetched
+    Unavailable,
+}
+
+#[derive(Deserialize)]
+struct IdClaims {
+    sub: String,
+    email: Option<String>,
+    /// Google sends a bool, Apple a "true"/"false" string
+    email_verified: Option<serde_json::Value>,
+    nonce: Option<String>,
+}
+
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| Client::builder().timeout(Duration::from_secs(5)).build().unwrap_or_default())
+}
+
+async fn jwks(provider: Provider, refresh: bool) -> Result<JwkSet, OAuthError> {
+    static CACHE: OnceLock<Mutex<HashMap<Provider, (JwkSet, Instant)>>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if !refresh {
+        if let Some((set, at)) = cache.lock().unwrap().get(&provider) {
+            if at.elapsed() < JWKS_TTL {
+                return Ok(set.clone());
+            }
+        }
+    }
+
+    let set = client()
+        .get(provider.jwks_url())
+        .send()
+        .await
+        .map_err(|e| {
+            eprintln!("Fetching {} signing keys failed: {}", provider.name(), e);
+            OAuthError::Unavailable
+        })?
+        .json::<JwkSet>()
+        .await
+        .map_err(|e| {
+            eprintln!("Unreadable {} signing keys: {}", provider.name(), e);
+            OAuthError::Unavailable
+        })?;
+    cache.lock().unwrap().insert(provider, (set.clone(), Instant::now()));
+    Ok(set)
+}
+
+/// Checks an ID token from the provider's mobile or web SDK. When the client sent a nonce with
+/// the sign-in request, the token has to carry the same one.
+pub async fn verify(provider: Provider, id_token: &str, nonce: Option<&str>) -> Result<Identity, OAuthError> {
+    let audiences = provider.audiences();
+    if audiences.is_empty() {
+        return Err(OAuthError::NotConfigured);
+    }
+    let header = decode_header(id_token).map_err(|_| OAuthError::InvalidToken)?;
+    let kid = header.kid.ok_or(OAuthError::InvalidToken)?;
+
+    // An unknown key id may mean the provider just rotated; look once more before giving up
+    let jwk = match jwks(provider, false).await?.find(&kid) {
+        Some(jwk) => jwk.clone(),
+        None => jwks(provider, true).await?.find(&kid).cloned().ok_or(OAuthError::InvalidToken)?,
+    };
+    let key = DecodingKey::from_jwk(&jwk).map_err(|_| OAuthError::InvalidToken)?;
+
+    // Both providers sign with RS256; the header's own alg is not trusted
+    let mut validation = Validation::new(Algorithm::RS256);
+    validation.set_audience(&audiences);
+    validation.set_issuer(provider.issuers());
+    let claims = decode::<IdClaims>(id_token, &key, &validation)
+        .map_err(|_| OAuthError::InvalidToken)?
+        .claims;
+
+    if let Some(expected) = nonce.filter(|n| !n.is_empty()) {
+        if claims.nonce.as_deref() != Some(expected) {
+            return Err(OAuthError::InvalidToken);
+        }
+    }
+
+    let email_verified = match claims.email_verified {
+        Some(serde_json::Value::Bool(b)) => b,
+        Some(serde_json::Value::String(s)) => s == "true",
+        _ => false,
+    };
+    Ok(Identity {
+        subject: claims.sub,
+        email: claims.email.map(|e| e.trim().to_string()).filter(|e| !e.is_empty()),
+        email_verified,
+    })
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+/// Steps in the order clients show them. All but `tour` also complete on their own once the
+/// account has the data; any step can be marked done (or skipped) by the client.
+pub const STEPS: &[&str] = &["profile", "business_context", "first_conversation", "notifications", "tour"];
+
+#[derive(Serialize)]
+pub struct StepState {
+    pub id: &'static str,
+    pub completed: bool,
+    /// `auto` when the account's data completes the step, `marked` when the client marked it
+    pub source: Option<&'static str>,
+    /// When the step was marked; unset for `auto`
+    pub completed_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct OnboardingState {
+    pub steps: Vec<StepState>,
+    pub completed: usize,
+    pub total: usize,
+    pub done: bool,
+}
+
+pub fn is_step(step: &str) -> bool {
+    STEPS.contains(&step)
+}
+
+pub async fn state(pool: &SqlitePool, user_id: &str) -> Result<OnboardingState, sqlx::Error> {
+    let auto = sqlx::query(
+        "SELECT
+            (COALESCE(TRIM(u.full_name), '') != '' AND COALESCE(TRIM(u.country), '') != '') AS profile,
+            EXISTS(SELECT 1 FROM conversation_context x JOIN conversations c ON c.id = x.conversation_id
+                   WHERE c.user_id = u.id AND (x.business_niche IS NOT NULL OR x.business_stage IS NOT NULL)) AS business_context,
+            EXISTS(SELECT 1 FROM messages m JOIN conversations c ON c.id = m.conversation_id
+                   WHERE c.user_id = u.id AND m.role = 'user') AS first_conversation,
+            EXISTS(SELECT 1 FROM device_tokens d WHERE d.user_id = u.id) AS notifications
+         FROM users u WHERE u.id = ?"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    let marked: HashMap<String, String> = sqlx::query("SELECT step, completed_at FROM onboarding_steps WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|r| (r.get("step"), r.get("completed_at")))
+        .collect();
+
+    let steps: Vec<StepState> = STEPS
+        .iter()
+        .map(|&id| {
+            let done_by_data = id != "tour" && auto.try_get::<bool, _>(id).unwrap_or(false);
+            let completed_at = marked.get(id).cloned();
+            StepState {
+                id,
+                completed: done_by_data || completed_at.is_some(),
+                source: if done_by_data {
+                    Some("auto")
+                } else if completed_at.is_some() {
+                    Some("marked")
+                } else {
+                    None
+                },
+                completed_at: completed_at.filter(|_| !done_by_data),
+            }
+        })
+        .collect();
+    let completed = steps.iter().filter(|s| s.completed).count();
+    Ok(OnboardingState {
+        total: steps.len(),
+        done: completed == steps.len(),
+        completed,
+        steps,
+    })
+}
+
+/// Marks a step done, or clears the mark; a step completed by the account's data stays complete
+pub async fn mark(pool: &SqlitePool, user_id: &str, step: &str, completed: bool) -> Result<(), sqlx::Error> {
+    if completed {
+        sqlx::query(
+            "INSERT INTO onboarding_steps (user_id, step, completed_at) VALUES (?, ?, ?)
+             ON CONFLICT(user_id, step) DO NOTHING"
+        )
+        .bind(user_id)
+        .bind(step)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+    } else {
+        sqlx::query("DELETE FROM onboarding_steps WHERE user_id = ? AND step = ?")
+            .bind(user_id)
+            .bind(step)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
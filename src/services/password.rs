@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::i18n::Locale;
+
+/// Password rules, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Query the HaveIBeenPwned range API (only a 5-char hash prefix leaves the server)
+    pub check_breaches: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            check_breaches: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PasswordRule {
+    TooShort,
+    MissingLowercase,
+    MissingUppercase,
+    MissingDigit,
+    MissingSymbol,
+    Breached,
+}
+
+impl PasswordRule {
+    pub fn message(self, locale: Locale, policy: &PasswordPolicy) -> String {
+        match (self, locale) {
+            (PasswordRule::TooShort, Locale::Ru) => format!("Пароль должен содержать не менее {} символов", policy.min_length),
+            (PasswordRule::TooShort, Locale::En) => format!("password-min-length-{}", policy.min_length),
+            (PasswordRule::MissingLowercase, Locale::Ru) => "Добавьте строчную букву".to_string(),
+            (PasswordRule::MissingLowercase, Locale::En) => "password-needs-lowercase".to_string(),
+            (PasswordRule::MissingUppercase, Locale::Ru) => "Добавьте заглавную букву".to_string(),
+            (PasswordRule::MissingUppercase, Locale::En) => "password-needs-uppercase".to_string(),
+            (PasswordRule::MissingDigit, Locale::Ru) => "Добавьте цифру".to_string(),
+            (PasswordRule::MissingDigit, Locale::En) => "password-needs-digit".to_string(),
+            (PasswordRule::MissingSymbol, Locale::Ru) => "Добавьте специальный символ".to_string(),
+            (PasswordRule::MissingSymbol, Locale::En) => "password-needs-symbol".to_string(),
+            (PasswordRule::Breached, Locale::Ru) => "Этот пароль встречался в утечках данных".to_string(),
+            (PasswordRule::Breached, Locale::En) => "password-found-in-breach".to_string(),
+        }
+    }
+}
+
+/// Returns every rule the password breaks; empty means it is acceptable
+pub async fn check(policy: &PasswordPolicy, password: &str) -> Vec<PasswordRule> {
+    let mut failed = Vec::new();
+
+    if password.chars().count() < policy.min_length {
+        failed.push(PasswordRule::TooShort);
+    }
+    if policy.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+        failed.push(PasswordRule::MissingLowercase);
+    }
+    if policy.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+        failed.push(PasswordRule::MissingUppercase);
+    }
+    if policy.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        failed.push(PasswordRule::MissingDigit);
+    }
+    if policy.require_symbol && password.chars().all(|c| c.is_alphanumeric()) {
+        failed.push(PasswordRule::MissingSymbol);
+    }
+
+    if failed.is_empty() && policy.check_breaches && is_breached(password).await {
+        failed.push(PasswordRule::Breached);
+    }
+
+    failed
+}
+
+/// HIBP k-anonymity lookup; fails open so an outage never blocks sign-up
+async fn is_breached(password: &str) -> bool {
+    let hash: String = Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    let (prefix, suffix) = hash.split_at(5);
+
+    let client = match Client::builder().timeout(Duration::from_secs(3)).build() {
+        Ok(c) => c,
+        Err(_) => return false,
+    };
+
+    let body = match client
+        .get(format!("https://api.pwnedpasswords.com/range/{}", prefix))
+        .header("Add-Padding", "true")
+        .send()
+        .await
+    {
+        Ok(resp) if resp.status().is_success() => resp.text().await.unwrap_or_default(),
+        Ok(resp) => {
+            eprintln!("HIBP range check returned {}", resp.status());
+            return false;
+        }
+        Err(e) => {
+            eprintln!("HIBP range check failed: {}", e);
+            return false;
+        }
+    };
+
+    body.lines().any(|line| {
+        let mut parts = line.trim().splitn(2, ':');
+        let candidate = parts.next().unwrap_or("");
+        let count: u64 = parts.next().and_then(|c| c.trim().parse().ok()).unwrap_or(0);
+        // Padding entries come back with a zero count
+        candidate.eq_ignore_ascii_case(suffix) && count > 0
+    })
+}
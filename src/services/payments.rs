@@ -0,0 +1,87 @@
+use std::env;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize)]
+struct PaymentConfirmation {
+    confirmation_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ProviderPayment {
+    pub id: String,
+    pub status: String,
+    confirmation: Option<PaymentConfirmation>,
+}
+
+impl ProviderPayment {
+    pub fn confirmation_url(&self) -> Option<String> {
+        self.confirmation.as_ref().and_then(|c| c.confirmation_url.clone())
+    }
+}
+
+/// YooKassa API client; credentials come from YOOKASSA_SHOP_ID / YOOKASSA_SECRET_KEY
+pub struct YooKassaClient {
+    client: Client,
+    shop_id: String,
+    secret_key: String,
+}
+
+impl YooKassaClient {
+    pub fn from_env() -> Option<Self> {
+        let shop_id = env::var("YOOKASSA_SHOP_ID").ok()?;
+        let secret_key = env::var("YOOKASSA_SECRET_KEY").ok()?;
+        Some(YooKassaClient { client: Client::new(), shop_id, secret_key })
+    }
+
+    /// Creates a redirect payment; `idempotence_key` makes retries safe
+    pub async fn create_payment(
+        &self,
+        amount: f64,
+        currency: &str,
+        description: &str,
+        return_url: &str,
+        link_id: &str,
+        idempotence_key: &str,
+    ) -> Result<ProviderPayment, Box<dyn std::error::Error>> {
+        let body = json!({
+            "amount": { "value": format!("{:.2}", amount), "currency": currency },
+            "capture": true,
+            "confirmation": { "type": "redirect", "return_url": return_url },
+            "description": description,
+            "metadata": { "link_id": link_id },
+        });
+
+        let resp = self.client
+            .post("https://api.yookassa.ru/v3/payments")
+            .basic_auth(&self.shop_id, Some(&self.secret_key))
+            .header("Idempotence-Key", idempotence_key)
+            .json(&body)
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(format!("YooKassa error {}: {}", status, text).into());
+        }
+
+        Ok(resp.json::<ProviderPayment>().await?)
+    }
+
+    /// Webhooks are unsigned, so their payload is only a hint: the status is re-read from the API
+    pub async fn get_payment(&self, payment_id: &str) -> Result<ProviderPayment, Box<dyn std::error::Error>> {
+        let resp = self.client
+            .get(format!("https://api.yookassa.ru/v3/payments/{}", payment_id))
+            .basic_auth(&self.shop_id, Some(&self.secret_key))
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            return Err(format!("YooKassa error {}", resp.status()).into());
+        }
+
+        Ok(resp.json::<ProviderPayment>().await?)
+    }
+}
@@ -0,0 +1,82 @@
+use printpdf::{Mm, PdfDocument};
+use std::io::Cursor;
+
+// DejaVu Sans covers Cyrillic, the PDF base-14 fonts do not
+const FONT: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans.ttf");
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const PT_TO_MM: f32 = 0.3528;
+
+pub struct PdfLine {
+    pub text: String,
+    pub size: f32,
+    pub indent: f32, // mm from the left margin
+}
+
+impl PdfLine {
+    pub fn heading(text: impl Into<String>) -> Self {
+        PdfLine { text: text.into(), size: 16.0, indent: 0.0 }
+    }
+
+    pub fn text(text: impl Into<String>) -> Self {
+        PdfLine { text: text.into(), size: 10.0, indent: 0.0 }
+    }
+
+    pub fn blank() -> Self {
+        PdfLine { text: String::new(), size: 10.0, indent: 0.0 }
+    }
+}
+
+/// Renders a flow of text lines onto A4 pages, wrapping long lines by words
+pub fn render_lines(title: &str, lines: &[PdfLine]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+    let font = doc.add_external_font(Cursor::new(FONT))?;
+
+    let mut current = doc.get_page(page).get_layer(layer);
+    let mut y = PAGE_HEIGHT - MARGIN;
+
+    for line in lines {
+        let line_height = line.size * PT_TO_MM * 1.4;
+        // Rough average glyph width of DejaVu Sans is ~0.55em
+        let max_chars = ((PAGE_WIDTH - 2.0 * MARGIN - line.indent) / (line.size * PT_TO_MM * 0.55)).max(10.0) as usize;
+
+        for chunk in wrap(&line.text, max_chars) {
+            if y < MARGIN + line_height {
+                let (p, l) = doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "content");
+                current = doc.get_page(p).get_layer(l);
+                y = PAGE_HEIGHT - MARGIN;
+            }
+            y -= line_height;
+            if !chunk.is_empty() {
+                current.use_text(chunk, line.size, Mm(MARGIN + line.indent), Mm(y), &font);
+            }
+        }
+    }
+
+    Ok(doc.save_to_bytes()?)
+}
+
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    if text.chars().count() <= max_chars {
+        return vec![text.to_string()];
+    }
+
+    let mut out = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let needed = current.chars().count() + word.chars().count() + usize::from(!current.is_empty());
+        if needed > max_chars && !current.is_empty() {
+            out.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
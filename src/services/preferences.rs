@@ -0,0 +1,107 @@
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+use crate::i18n::{self, Locale};
+use crate::services::reference;
+
+/// Output formats a generated table can be saved in
+pub const OUTPUT_FORMATS: [&str; 2] = ["xlsx", "csv"];
+/// Countries whose users get digests and pushes in Russian unless they saved a language preference
+const RU_COUNTRIES: [&str; 4] = ["RU", "BY", "KZ", "KG"];
+const MAX_CATEGORY_LEN: usize = 40;
+
+/// Per-user settings; a user who never saved any gets `Default`
+#[derive(Serialize, Clone)]
+pub struct Preferences {
+    /// `ru` or `en`; unset means the device's language
+    pub locale: Option<String>,
+    pub push_notifications: bool,
+    /// Chat category for messages that don't name one
+    pub default_category: Option<String>,
+    /// `xlsx` or `csv`, for tables in answers when the message doesn't ask for a format
+    pub default_output_format: Option<String>,
+    /// One of `openai::allowed_models`, for conversations that haven't picked a model
+    pub default_model: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences {
+            locale: None,
+            push_notifications: true,
+            default_category: None,
+            default_output_format: None,
+            default_model: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn locale(&self) -> Option<Locale> {
+        self.locale.as_deref().and_then(i18n::parse_language)
+    }
+}
+
+/// Language for messages sent outside a request, where there is no device language to go by:
+/// the saved preference, then the profile country
+pub fn background_locale(preferred: Option<&str>, country: Option<&str>) -> Locale {
+    match (preferred.and_then(i18n::parse_language), country.and_then(reference::country_code)) {
+        (Some(preferred), _) => preferred,
+        (None, Some(code)) if RU_COUNTRIES.contains(&code) => Locale::Ru,
+        _ => Locale::En,
+    }
+}
+
+/// Saved preferences, or the defaults when there are none or the lookup fails
+pub async fn load(pool: &SqlitePool, user_id: &str) -> Preferences {
+    let row = sqlx::query(
+        "SELECT locale, push_notifications, default_category, default_output_format, default_model, updated_at
+         FROM user_preferences WHERE user_id = ?"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("Preferences lookup for {} failed: {}", user_id, e);
+        None
+    });
+    match row {
+        Some(r) => Preferences {
+            locale: r.get("locale"),
+            push_notifications: r.get("push_notifications"),
+            default_category: r.get("default_category"),
+            default_output_format: r.get("default_output_format"),
+            default_model: r.get("default_model"),
+            updated_at: r.get("updated_at"),
+        },
+        None => Preferences::default(),
+    }
+}
+
+pub async fn save(pool: &SqlitePool, user_id: &str, prefs: &Preferences) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO user_preferences (user_id, locale, push_notifications, default_category, default_output_format, default_model, updated_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)
+         ON CONFLICT(user_id) DO UPDATE SET locale = excluded.locale, push_notifications = excluded.push_notifications,
+            default_category = excluded.default_category, default_output_format = excluded.default_output_format,
+            default_model = excluded.default_model, updated_at = excluded.updated_at"
+    )
+    .bind(user_id)
+    .bind(&prefs.locale)
+    .bind(prefs.push_notifications)
+    .bind(&prefs.default_category)
+    .bind(&prefs.default_output_format)
+    .bind(&prefs.default_model)
+    .bind(&prefs.updated_at)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Category as stored on messages: trimmed and lowercase, like the admin's category routes
+pub fn normalize_category(category: &str) -> Option<String> {
+    let category = category.trim().to_lowercase();
+    (!category.is_empty() && category.len() <= MAX_CATEGORY_LEN).then_some(category)
+}
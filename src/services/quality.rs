@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+use actix_web::rt;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::services::openai;
+use crate::state::AppState;
+
+/// Offline scoring of assistant answers by a judge model, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QualityPolicy {
+    /// Off by default: every scored answer is an extra completion
+    pub enabled: bool,
+    /// Answers scored per run
+    pub sample_size: u32,
+    pub every_hours: u32,
+    /// Only answers this recent are sampled
+    pub lookback_days: u32,
+    /// Judge model; the default chat model when unset
+    pub judge_model: Option<String>,
+}
+
+impl Default for QualityPolicy {
+    fn default() -> Self {
+        QualityPolicy {
+            enabled: false,
+            sample_size: 20,
+            every_hours: 24,
+            lookback_days: 7,
+            judge_model: None,
+        }
+    }
+}
+
+/// Rubric scores from 1 to 5, as the judge returns them
+#[derive(Deserialize)]
+struct Verdict {
+    correctness: f64,
+    actionability: f64,
+    locale_compliance: f64,
+    comment: Option<String>,
+}
+
+const TICK: Duration = Duration::from_secs(15 * 60);
+
+/// Runs the evaluation in the background whenever the policy is enabled and a run is due.
+/// Separate from the scheduler because the judge call needs the whole app state.
+pub fn spawn(state: AppState) {
+    rt::spawn(async move {
+        let mut last_run: Option<Instant> = None;
+        let mut interval = rt::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let policy = state.config.load().quality_eval.clone();
+            let every = Duration::from_secs(u64::from(policy.every_hours.max(1)) * 60 * 60);
+            if !policy.enabled || last_run.is_some_and(|t| t.elapsed() < every) {
+                continue;
+            }
+            last_run = Some(Instant::now());
+            match run(&state, &policy).await {
+                Ok(scored) if scored > 0 => println!("Quality: scored {} answers", scored),
+                Ok(_) => {}
+                Err(e) => eprintln!("Quality: evaluation run failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Scores a random sample of recent, not yet scored answers; returns how many were stored.
+/// A failed judge call skips that answer so it can be sampled again next run.
+pub async fn run(state: &AppState, policy: &QualityPolicy) -> Result<usize, Box<dyn std::error::Error>> {
+    let pool = &state.pool;
+    let since = (chrono::Utc::now() - chrono::Duration::days(i64::from(policy.lookback_days.max(1)))).to_rfc3339();
+    let judge_model = policy.judge_model.clone().unwrap_or_else(|| openai::current_model(state));
+
+    // Each answer with the user message right before it
+    let rows = sqlx::query(
+        "SELECT a.id, a.conversation_id, a.content, a.category, a.model, a.prompt_version,
+                (SELECT q.content FROM messages q
+                 WHERE q.conversation_id = a.conversation_id AND q.role = 'user'
+                   AND julianday(q.timestamp) <= julianday(a.timestamp)
+                 ORDER BY julianday(q.timestamp) DESC LIMIT 1) AS question
+         FROM messages a
+         JOIN conversations c ON c.id = a.conversation_id
+         LEFT JOIN answer_scores s ON s.message_id = a.id
+         WHERE a.role = 'assistant' AND s.message_id IS NULL AND c.deleted_at IS NULL
+           AND julianday(a.timestamp) >= julianday(?)
+         ORDER BY RANDOM() LIMIT ?"
+    )
+    .bind(&since)
+    .bind(i64::from(policy.sample_size))
+    .fetch_all(pool)
+    .await?;
+
+    let mut scored = 0;
+    for r in rows {
+        let message_id: String = r.get("id");
+        let question: Option<String> = r.get("question");
+        let question = match question {
+            Some(q) => q,
+            None => continue,
+        };
+        let answer: String = r.get("content");
+
+        let raw = match openai::judge_answer(&judge_model, &question, &answer).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                eprintln!("Quality: judging {} failed: {}", message_id, e);
+                continue;
+            }
+        };
+        let verdict = match parse_verdict(&raw) {
+            Some(v) => v,
+            None => {
+                eprintln!("Quality: unreadable verdict for {}: {}", message_id, raw);
+                continue;
+            }
+        };
+        let overall = (verdict.correctness + verdict.actionability + verdict.locale_compliance) / 3.0;
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO answer_scores
+                (message_id, conversation_id, category, model, prompt_version, correctness, actionability,
+                 locale_compliance, overall, comment, judge_model, scored_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&message_id)
+        .bind(r.get::<String, _>("conversation_id"))
+        .bind(r.get::<Option<String>, _>("category"))
+        .bind(r.get::<Option<String>, _>("model"))
+        .bind(r.get::<Option<String>, _>("prompt_version"))
+        .bind(verdict.correctness)
+        .bind(verdict.actionability)
+        .bind(verdict.locale_compliance)
+        .bind(overall)
+        .bind(&verdict.comment)
+        .bind(&judge_model)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+        scored += 1;
+    }
+    Ok(scored)
+}
+
+/// The judge's JSON verdict with every score within 1..=5, tolerating a code fence around it
+fn parse_verdict(raw: &str) -> Option<Verdict> {
+    let trimmed = raw.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed);
+    let verdict: Verdict = serde_json::from_str(body.trim()).ok()?;
+    let in_range = |s: f64| (1.0..=5.0).contains(&s);
+    if in_range(verdict.correctness) && in_range(verdict.actionability) && in_range(verdict.locale_compliance) {
+        Some(verdict)
+    } else {
+        None
+    }
+}
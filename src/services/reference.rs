@@ -0,0 +1,108 @@
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::i18n::Locale;
+
+/// One entry of an embedded reference dataset: an ISO or IANA code with its names
+#[derive(Deserialize)]
+pub struct Entry {
+    pub code: String,
+    pub en: String,
+    pub ru: String,
+}
+
+impl Entry {
+    pub fn name(&self, locale: Locale) -> &str {
+        match locale {
+            Locale::Ru => &self.ru,
+            Locale::En => &self.en,
+        }
+    }
+}
+
+/// An entry as the reference endpoints return it
+#[derive(Serialize)]
+pub struct LocalizedEntry<'a> {
+    pub code: &'a str,
+    pub name: &'a str,
+}
+
+fn load(cell: &'static OnceLock<Vec<Entry>>, raw: &str) -> &'static [Entry] {
+    cell.get_or_init(|| serde_json::from_str(raw).expect("embedded reference dataset is valid JSON"))
+}
+
+/// ISO 3166-1 alpha-2 countries
+pub fn countries() -> &'static [Entry] {
+    static DATA: OnceLock<Vec<Entry>> = OnceLock::new();
+    load(&DATA, include_str!("../../assets/reference/countries.json"))
+}
+
+/// ISO 4217 currencies
+pub fn currencies() -> &'static [Entry] {
+    static DATA: OnceLock<Vec<Entry>> = OnceLock::new();
+    load(&DATA, include_str!("../../assets/reference/currencies.json"))
+}
+
+/// IANA time zones
+pub fn timezones() -> &'static [Entry] {
+    static DATA: OnceLock<Vec<Entry>> = OnceLock::new();
+    load(&DATA, include_str!("../../assets/reference/timezones.json"))
+}
+
+/// The dataset in `locale`, sorted by name
+pub fn localized(entries: &'static [Entry], locale: Locale) -> Vec<LocalizedEntry<'static>> {
+    let mut out: Vec<_> = entries
+        .iter()
+        .map(|e| LocalizedEntry { code: &e.code, name: e.name(locale) })
+        .collect();
+    out.sort_by(|a, b| a.name.cmp(b.name));
+    out
+}
+
+/// ISO code for a country given by code or by its English or Russian name, case-insensitive
+pub fn country_code(value: &str) -> Option<&'static str> {
+    let value = value.trim().to_lowercase();
+    countries()
+        .iter()
+        .find(|c| c.code.to_lowercase() == value || c.en.to_lowercase() == value || c.ru.to_lowercase() == value)
+        .map(|c| c.code.as_str())
+}
+
+/// Country name for a stored ISO code; values that aren't codes come back unchanged
+pub fn country_name(value: &str, locale: Locale) -> &str {
+    countries()
+        .iter()
+        .find(|c| c.code == value)
+        .map(|c| c.name(locale))
+        .unwrap_or(value)
+}
+
+/// Rewrites country names stored before they were validated as ISO codes.
+/// Values that match no country are left for the user to fix.
+pub async fn normalize_stored(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let mut updated = 0;
+    for (table, column) in [("users", "country"), ("conversation_context", "region")] {
+        let values: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT {column} FROM {table} WHERE {column} IS NOT NULL",
+            table = table,
+            column = column,
+        ))
+        .fetch_all(pool)
+        .await?;
+        for value in values {
+            let code = match country_code(&value) {
+                Some(code) if code != value => code,
+                _ => continue,
+            };
+            updated += sqlx::query(&format!("UPDATE {table} SET {column} = ? WHERE {column} = ?", table = table, column = column))
+                .bind(code)
+                .bind(&value)
+                .execute(pool)
+                .await?
+                .rows_affected();
+        }
+    }
+    Ok(updated)
+}
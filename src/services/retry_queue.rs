@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::rt;
+use sqlx::Row;
+
+use crate::handlers::chat;
+use crate::i18n::Locale;
+use crate::models::{ChatRequest, ChatResponse};
+use crate::services::breaker;
+use crate::services::fcm::{self, FcmService};
+use crate::state::AppState;
+
+const TICK: Duration = Duration::from_secs(30);
+/// Queued messages answered per tick
+const BATCH: i64 = 20;
+/// Failed tries before a queued message is given up on; waits 1, 2, ... minutes in between
+const MAX_ATTEMPTS: i64 = 10;
+
+/// Answers messages accepted while the LLM provider was down, once the circuit breaker lets
+/// calls through again. Separate from the scheduler because answering needs the whole app state.
+pub fn spawn(state: AppState) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            if breaker::retry_after().is_some() {
+                continue;
+            }
+            match run(&state).await {
+                Ok(answered) if answered > 0 => println!("Retry queue: answered {} pending messages", answered),
+                Ok(_) => {}
+                Err(e) => eprintln!("Retry queue: run failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Works through the due queued messages, oldest first; returns how many were answered
+pub async fn run(state: &AppState) -> Result<usize, sqlx::Error> {
+    let pool = &state.pool;
+    let rows = sqlx::query(
+        "SELECT id, message_id, user_id, request, locale, region, attempts FROM queued_turns
+         WHERE julianday(next_attempt_at) <= julianday(?) ORDER BY created_at LIMIT ?"
+    )
+    .bind(chrono::Utc::now().to_rfc3339())
+    .bind(BATCH)
+    .fetch_all(pool)
+    .await?;
+
+    let mut answered = 0;
+    for r in rows {
+        // A failed call may have opened the breaker again; the rest waits for the next tick
+        if breaker::retry_after().is_some() {
+            break;
+        }
+        let id: String = r.get("id");
+        let message_id: String = r.get("message_id");
+        let user_id: String = r.get("user_id");
+        let locale = match r.get::<String, _>("locale").as_str() {
+            "ru" => Locale::Ru,
+            _ => Locale::En,
+        };
+        let chat_req: ChatRequest = match serde_json::from_str(&r.get::<String, _>("request")) {
+            Ok(req) => req,
+            Err(e) => {
+                eprintln!("Retry queue: unreadable request for message {}: {}", message_id, e);
+                finish(state, &id, &message_id, Some("failed")).await?;
+                continue;
+            }
+        };
+
+        match chat::answer_queued(state, &message_id, chat_req, locale, r.get("region")).await {
+            Ok(Some(reply)) if reply.retry_message_id.is_none() => {
+                finish(state, &id, &message_id, None).await?;
+                notify(state, &user_id, locale, Some(&reply)).await;
+                answered += 1;
+            }
+            // The provider answered but nothing usable came back; the user can retry by hand
+            Ok(Some(_)) => {
+                finish(state, &id, &message_id, Some("failed")).await?;
+                notify(state, &user_id, locale, None).await;
+            }
+            Ok(None) => {
+                let attempts = r.get::<i64, _>("attempts") + 1;
+                if attempts >= MAX_ATTEMPTS {
+                    finish(state, &id, &message_id, Some("failed")).await?;
+                    notify(state, &user_id, locale, None).await;
+                    continue;
+                }
+                let next = chrono::Utc::now() + chrono::Duration::minutes(attempts);
+                sqlx::query("UPDATE queued_turns SET attempts = ?, next_attempt_at = ? WHERE id = ?")
+                    .bind(attempts)
+                    .bind(next.to_rfc3339())
+                    .bind(&id)
+                    .execute(pool)
+                    .await?;
+            }
+            Err(resp) => {
+                eprintln!("Retry queue: message {} can no longer be answered ({})", message_id, resp.status());
+                finish(state, &id, &message_id, Some("failed")).await?;
+            }
+        }
+    }
+    Ok(answered)
+}
+
+/// Takes the message off the queue, leaving it with `status` (`None` once answered)
+async fn finish(state: &AppState, id: &str, message_id: &str, status: Option<&str>) -> Result<(), sqlx::Error> {
+    let mut tx = state.pool.begin().await?;
+    sqlx::query("DELETE FROM queued_turns WHERE id = ?")
+        .bind(id)
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("UPDATE messages SET status = ? WHERE id = ?")
+        .bind(status)
+        .bind(message_id)
+        .execute(&mut tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Pushes the late answer to the user's devices, or that it could not be answered at all
+async fn notify(state: &AppState, user_id: &str, locale: Locale, reply: Option<&ChatResponse>) {
+    let pool = &state.pool;
+    let tokens = fcm::user_tokens(pool, user_id).await;
+    if tokens.is_empty() {
+        return;
+    }
+    let fcm = match FcmService::new() {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+
+    let mut data = HashMap::new();
+    let (title, body) = match reply {
+        Some(reply) => {
+            data.insert("type".to_string(), "chat_answer".to_string());
+            data.insert("conversation_id".to_string(), reply.conversation_id.clone());
+            data.insert("message_id".to_string(), reply.message_id.clone());
+            let title = match locale {
+                Locale::Ru => "Ответ готов",
+                Locale::En => "Your answer is ready",
+            };
+            let preview: String = reply.response.chars().take(120).collect();
+            (title, preview)
+        }
+        None => {
+            data.insert("type".to_string(), "chat_answer_failed".to_string());
+            match locale {
+                Locale::Ru => ("Не удалось ответить", "Сервис ответов недоступен, отправьте сообщение ещё раз позже".to_string()),
+                Locale::En => ("Could not answer", "The assistant is unavailable, please send your message again later".to_string()),
+            }
+        }
+    };
+    if let Err(e) = fcm.send_notification(pool, tokens, title, &body, Some(data)).await {
+        eprintln!("Retry queue: push to {} failed: {}", user_id, e);
+    }
+}
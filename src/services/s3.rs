@@ -0,0 +1,128 @@
+use std::env;
+use std::time::Duration;
+
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+
+/// Bucket settings read from the environment; `None` while EXPORT_S3_BUCKET is unset
+pub struct S3Target {
+    bucket: String,
+    region: String,
+    /// Path-style endpoint, so S3-compatible stores (MinIO, Yandex Object Storage) work too
+    endpoint: String,
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Target {
+    pub fn from_env() -> Result<Option<S3Target>, String> {
+        let bucket = match env::var("EXPORT_S3_BUCKET") {
+            Ok(b) if !b.trim().is_empty() => b.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let region = env::var("EXPORT_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = env::var("EXPORT_S3_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region))
+            .trim_end_matches('/')
+            .to_string();
+        let access_key = env::var("AWS_ACCESS_KEY_ID").map_err(|_| "AWS_ACCESS_KEY_ID is not set")?;
+        let secret_key = env::var("AWS_SECRET_ACCESS_KEY").map_err(|_| "AWS_SECRET_ACCESS_KEY is not set")?;
+        let prefix = env::var("EXPORT_S3_PREFIX").unwrap_or_default().trim_matches('/').to_string();
+        Ok(Some(S3Target { bucket, region, endpoint, prefix, access_key, secret_key }))
+    }
+
+    /// Object key under the configured prefix
+    pub fn key(&self, path: &str) -> String {
+        if self.prefix.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.prefix, path)
+        }
+    }
+
+    /// Uploads `body` with a single SigV4-signed PUT
+    pub async fn put(&self, key: &str, content_type: &str, body: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
+        let path = format!("/{}/{}", uri_encode(&self.bucket), key.split('/').map(uri_encode).collect::<Vec<_>>().join("/"));
+        let url = Url::parse(&format!("{}{}", self.endpoint, path))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(h), Some(p)) => format!("{}:{}", h, p),
+            (Some(h), None) => h.to_string(),
+            _ => return Err("EXPORT_S3_ENDPOINT has no host".into()),
+        };
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let signed_headers = "content-type;host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{}\n\ncontent-type:{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            path, content_type, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes());
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let client = Client::builder().timeout(Duration::from_secs(120)).build()?;
+        let res = client
+            .put(url)
+            .header("Content-Type", content_type)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header("Authorization", authorization)
+            .body(body)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            let status = res.status();
+            let detail = res.text().await.unwrap_or_default();
+            return Err(format!("S3 answered {}: {}", status, detail.chars().take(300).collect::<String>()).into());
+        }
+        Ok(())
+    }
+}
+
+/// RFC 3986 encoding of one path segment, as SigV4 expects it
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|b| b ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|b| b ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
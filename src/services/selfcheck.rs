@@ -0,0 +1,233 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Tables the handlers rely on, with the columns that were added by migrations after the
+/// table was first created. Those `ALTER TABLE`s ignore errors in `db::init_pool`, so a
+/// failed one only shows up here. Keep in sync with `db.rs`.
+const EXPECTED_SCHEMA: &[(&str, &[&str])] = &[
+    ("users", &["full_name", "nickname", "phone", "country", "gender", "profile_picture", "profile_picture_thumb", "telegram_username", "analytics_opt_in", "plan", "timezone", "digest_sent_at", "daily_digest", "daily_digest_conversation_id", "daily_digest_sent_at", "has_password"]),
+    ("sessions", &["remember_me", "device_id", "device_name", "refresh_token_hash", "refresh_previous_hash", "last_used_at", "user_agent", "platform", "created_ip", "last_ip", "legacy_bearer"]),
+    ("conversations", &["archived_at", "deleted_at", "pinned", "last_message_at", "forked_from", "forked_from_message_id", "pending_clarification", "model", "language"]),
+    ("conversation_context", &[]),
+    ("conversation_summaries", &[]),
+    ("conversation_topics", &[]),
+    ("conversation_shares", &[]),
+    ("conversation_reads", &["identity", "channel"]),
+    ("conversation_drafts", &[]),
+    ("oauth_identities", &[]),
+    ("otp_codes", &[]),
+    ("onboarding_steps", &[]),
+    ("email_changes", &[]),
+    ("user_preferences", &["default_model"]),
+    ("widgets", &[]),
+    ("widget_sessions", &[]),
+    ("widget_messages", &[]),
+    ("widget_usage", &[]),
+    ("feedback_items", &[]),
+    ("feedback_votes", &[]),
+    ("messages", &["edited_at", "category", "model", "disclaimer_id", "prompt_version", "status"]),
+    ("messages_fts", &[]),
+    ("conversations_fts", &[]),
+    ("message_feedback", &[]),
+    ("files", &["message_id", "deleted_at", "user_id", "sha256", "scan_status", "scan_signature", "extracted_text"]),
+    ("file_blobs", &[]),
+    ("bundle_jobs", &[]),
+    ("upload_sessions", &[]),
+    ("upload_chunks", &[]),
+    ("support_messages", &["visibility", "faq_id", "in_reply_to", "auto_resolved_at", "human_requested_at"]),
+    ("support_escalations", &[]),
+    ("message_mapping", &[]),
+    ("greetings_sent", &[]),
+    ("faqs", &["tags"]),
+    ("telegram_users", &[]),
+    ("device_tokens", &[]),
+    ("leads", &[]),
+    ("booking_services", &[]),
+    ("bookings", &[]),
+    ("inventory_items", &[]),
+    ("invoices", &["status", "paid_at"]),
+    ("payment_links", &[]),
+    ("user_stats", &[]),
+    ("export_watermarks", &["watermark_rowid"]),
+    ("archived_partitions", &[]),
+    ("category_models", &[]),
+    ("disclaimers", &[]),
+    ("answer_scores", &[]),
+    ("knowledge_documents", &[]),
+    ("embeddings", &[]),
+    ("dead_letters", &[]),
+    ("queued_turns", &[]),
+    ("ai_analytics", &["region", "niche"]),
+    ("niches_month", &["region", "niche"]),
+    ("analytics_refresh_runs", &[]),
+];
+
+const EXPECTED_INDEXES: &[&str] = &[
+    "idx_leads_user_email",
+    "idx_leads_user_phone",
+    "idx_bookings_service_start",
+    "idx_inventory_items_user",
+    "idx_messages_conversation_jd",
+    "idx_conversations_user_activity",
+    "idx_files_message",
+    "idx_files_user",
+    "idx_support_messages_user_created",
+    "idx_messages_month",
+    "idx_support_messages_month",
+    "idx_conversation_shares_conversation",
+    "idx_answer_scores_scored",
+    "idx_knowledge_documents_user",
+    "idx_embeddings_user",
+    "idx_dead_letters_status",
+    "idx_queued_turns_due",
+    "idx_conversation_reads_conversation",
+    "idx_widgets_user",
+    "idx_widget_messages_session",
+    "idx_feedback_items_locale_status",
+    "idx_sessions_refresh",
+    "idx_oauth_identities_user",
+    "idx_ai_analytics_scope",
+    "idx_niches_month_scope",
+];
+
+struct EnvRequirement {
+    name: &'static str,
+    /// What stops working without it
+    needed_for: &'static str,
+    /// Missing required vars make the service not ready; the rest only degrade a feature
+    required: bool,
+    valid: fn(&str) -> bool,
+}
+
+fn non_empty(value: &str) -> bool {
+    !value.trim().is_empty()
+}
+
+fn chat_id(value: &str) -> bool {
+    value.trim().parse::<i64>().is_ok()
+}
+
+const ENV_REQUIREMENTS: &[EnvRequirement] = &[
+    EnvRequirement { name: "OPENROUTER_API_KEY", needed_for: "chat answers", required: true, valid: non_empty },
+    EnvRequirement { name: "TELEGRAM_BOT_TOKEN", needed_for: "support forwarding and escalation", required: true, valid: non_empty },
+    EnvRequirement { name: "TELEGRAM_GROUP_CHAT_ID", needed_for: "support forwarding and escalation", required: true, valid: chat_id },
+    EnvRequirement { name: "ADMIN_TOKEN", needed_for: "admin endpoints", required: false, valid: non_empty },
+    EnvRequirement { name: "GOOGLE_CLIENT_IDS", needed_for: "Google sign-in", required: false, valid: non_empty },
+    EnvRequirement { name: "APPLE_CLIENT_IDS", needed_for: "Apple sign-in", required: false, valid: non_empty },
+    EnvRequirement { name: "SMS_PROVIDER", needed_for: "phone sign-in codes", required: false, valid: non_empty },
+    EnvRequirement { name: "EMAIL_PROVIDER", needed_for: "email change confirmations", required: false, valid: non_empty },
+    EnvRequirement { name: "EMAIL_FROM", needed_for: "email change confirmations", required: false, valid: non_empty },
+    EnvRequirement { name: "SERPAPI_API_KEY", needed_for: "Google Trends weekly trends", required: false, valid: non_empty },
+    EnvRequirement { name: "EXPORT_S3_BUCKET", needed_for: "warehouse dumps in S3", required: false, valid: non_empty },
+    EnvRequirement { name: "TRUSTED_PROXIES", needed_for: "client addresses behind a reverse proxy", required: false, valid: non_empty },
+    EnvRequirement { name: "JWT_SECRET", needed_for: "sign-ins surviving a restart", required: false, valid: non_empty },
+];
+
+#[derive(Serialize)]
+pub struct EnvIssue {
+    pub name: &'static str,
+    pub needed_for: &'static str,
+    /// `missing` or `invalid`
+    pub problem: &'static str,
+    pub required: bool,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checked_at: String,
+    pub missing_tables: Vec<String>,
+    /// `table.column`
+    pub missing_columns: Vec<String>,
+    pub missing_indexes: Vec<String>,
+    pub env: Vec<EnvIssue>,
+}
+
+impl Readiness {
+    /// One line per problem for the boot log
+    pub fn log(&self) {
+        if self.ready && self.env.is_empty() {
+            println!("Self-check: schema and environment OK");
+            return;
+        }
+        for table in &self.missing_tables {
+            eprintln!("Self-check: missing table {}", table);
+        }
+        for column in &self.missing_columns {
+            eprintln!("Self-check: missing column {}", column);
+        }
+        for index in &self.missing_indexes {
+            eprintln!("Self-check: missing index {}", index);
+        }
+        for issue in &self.env {
+            eprintln!(
+                "Self-check: {} {} {} ({})",
+                if issue.required { "required" } else { "optional" },
+                issue.name,
+                issue.problem,
+                issue.needed_for
+            );
+        }
+        if !self.ready {
+            eprintln!("Self-check: service is NOT ready, /ready reports 503 until fixed and restarted");
+        }
+    }
+}
+
+/// Compares the live database and environment against what the code expects
+pub async fn run(pool: &SqlitePool) -> Readiness {
+    let mut missing_tables = Vec::new();
+    let mut missing_columns = Vec::new();
+    for (table, columns) in EXPECTED_SCHEMA {
+        let present: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+            .bind(table)
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+        if present.is_empty() {
+            missing_tables.push(table.to_string());
+            continue;
+        }
+        for column in *columns {
+            if !present.iter().any(|p| p == column) {
+                missing_columns.push(format!("{}.{}", table, column));
+            }
+        }
+    }
+
+    let indexes: Vec<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'index'")
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+    let missing_indexes: Vec<String> = EXPECTED_INDEXES
+        .iter()
+        .filter(|name| !indexes.iter().any(|i| i == *name))
+        .map(|name| name.to_string())
+        .collect();
+
+    let env: Vec<EnvIssue> = ENV_REQUIREMENTS
+        .iter()
+        .filter_map(|req| {
+            let problem = match std::env::var(req.name) {
+                Ok(value) if (req.valid)(&value) => return None,
+                Ok(_) => "invalid",
+                Err(_) => "missing",
+            };
+            Some(EnvIssue { name: req.name, needed_for: req.needed_for, problem, required: req.required })
+        })
+        .collect();
+
+    let ready = missing_tables.is_empty()
+        && missing_columns.is_empty()
+        && missing_indexes.is_empty()
+        && env.iter().all(|issue| !issue.required);
+
+    Readiness {
+        ready,
+        checked_at: chrono::Utc::now().to_rfc3339(),
+        missing_tables,
+        missing_columns,
+        missing_indexes,
+        env,
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use reqwest::Client;
+
+/// Sends a text message through the provider named by SMS_PROVIDER:
+/// `smsru` (SMSRU_API_ID) or `twilio` (TWILIO_ACCOUNT_SID, TWILIO_AUTH_TOKEN, TWILIO_FROM)
+pub async fn send(phone: &str, text: &str) -> Result<(), String> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let provider = std::env::var("SMS_PROVIDER").unwrap_or_default();
+
+    let resp = match provider.as_str() {
+        "smsru" => {
+            let api_id = std::env::var("SMSRU_API_ID").map_err(|_| "SMSRU_API_ID is not set".to_string())?;
+            client
+                .post("https://sms.ru/sms/send")
+                .form(&[("api_id", api_id.as_str()), ("to", phone.trim_start_matches('+')), ("msg", text), ("json", "1")])
+                .send()
+                .await
+        }
+        "twilio" => {
+            let (sid, token, from) = match (
+                std::env::var("TWILIO_ACCOUNT_SID"),
+                std::env::var("TWILIO_AUTH_TOKEN"),
+                std::env::var("TWILIO_FROM"),
+            ) {
+                (Ok(sid), Ok(token), Ok(from)) => (sid, token, from),
+                _ => return Err("TWILIO_ACCOUNT_SID, TWILIO_AUTH_TOKEN and TWILIO_FROM are required".to_string()),
+            };
+            client
+                .post(format!("https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json", sid))
+                .basic_auth(&sid, Some(&token))
+                .form(&[("To", phone), ("From", from.as_str()), ("Body", text)])
+                .send()
+                .await
+        }
+        _ => return Err("SMS_PROVIDER is not configured".to_string()),
+    };
+
+    let resp = resp.map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("{} {}", status, body));
+    }
+    // sms.ru answers 200 with its own status inside
+    if provider == "smsru" {
+        let body: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
+        if body.get("status").and_then(|s| s.as_str()) != Some("OK") {
+            return Err(body.to_string());
+        }
+    }
+    Ok(())
+}
+
+pub fn configured() -> bool {
+    matches!(std::env::var("SMS_PROVIDER").as_deref(), Ok("smsru") | Ok("twilio"))
+}
+
+/// E.164 form (`+79991234567`) of a phone number typed with spaces, dashes or brackets.
+/// A Russian number starting with 8 is read as +7.
+pub fn normalize_phone(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+    if trimmed.chars().any(|c| !(c.is_ascii_digit() || " +-()".contains(c))) {
+        return None;
+    }
+    let digits = if !trimmed.starts_with('+') && digits.len() == 11 && digits.starts_with('8') {
+        format!("7{}", &digits[1..])
+    } else if trimmed.starts_with('+') {
+        digits
+    } else {
+        return None;
+    };
+    if (8..=15).contains(&digits.len()) {
+        Some(format!("+{}", digits))
+    } else {
+        None
+    }
+}
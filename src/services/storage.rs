@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+
+/// Per-plan storage quotas, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoragePolicy {
+    /// Plan name -> quota in megabytes
+    pub plan_quotas_mb: HashMap<String, u64>,
+    /// Applies to plans missing from `plan_quotas_mb`
+    pub default_quota_mb: u64,
+}
+
+impl Default for StoragePolicy {
+    fn default() -> Self {
+        StoragePolicy {
+            plan_quotas_mb: HashMap::from([
+                ("free".to_string(), 100),
+                ("pro".to_string(), 2048),
+            ]),
+            default_quota_mb: 100,
+        }
+    }
+}
+
+impl StoragePolicy {
+    pub fn quota_bytes(&self, plan: &str) -> i64 {
+        let mb = self.plan_quotas_mb.get(plan).copied().unwrap_or(self.default_quota_mb);
+        (mb as i64).saturating_mul(1024 * 1024)
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct StorageBreakdown {
+    pub generated: i64,
+    pub uploads: i64,
+    pub profile_pictures: i64,
+    /// Already counted above; shown so users know what emptying the trash frees
+    pub trash: i64,
+}
+
+pub async fn user_plan(pool: &SqlitePool, user_id: &str) -> String {
+    sqlx::query_scalar("SELECT plan FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "free".to_string())
+}
+
+/// Bytes stored for a user, trashed files included since they still occupy space;
+/// quarantined uploads are held for review and not charged to the user
+pub async fn used_bytes(pool: &SqlitePool, user_id: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COALESCE(SUM(size), 0) FROM files WHERE user_id = ? AND scan_status IS NOT 'quarantined'")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn breakdown(pool: &SqlitePool, user_id: &str) -> Result<StorageBreakdown, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT
+            CASE
+                WHEN f.id = u.profile_picture THEN 'profile_pictures'
+                WHEN m.role = 'user' OR (f.message_id IS NULL AND f.mime LIKE 'image/%') THEN 'uploads'
+                ELSE 'generated'
+            END AS category,
+            COALESCE(SUM(f.size), 0) AS bytes,
+            COALESCE(SUM(CASE WHEN f.deleted_at IS NOT NULL THEN f.size ELSE 0 END), 0) AS trashed
+         FROM files f
+         LEFT JOIN messages m ON m.id = f.message_id
+         LEFT JOIN users u ON u.id = f.user_id
+         WHERE f.user_id = ? AND f.scan_status IS NOT 'quarantined'
+         GROUP BY category"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut result = StorageBreakdown::default();
+    for r in rows {
+        let category: String = r.get("category");
+        let bytes: i64 = r.get("bytes");
+        result.trash += r.get::<i64, _>("trashed");
+        match category.as_str() {
+            "profile_pictures" => result.profile_pictures += bytes,
+            "uploads" => result.uploads += bytes,
+            _ => result.generated += bytes,
+        }
+    }
+    Ok(result)
+}
+
+/// True when `incoming` more bytes still fit in the user's plan
+pub async fn has_room(pool: &SqlitePool, policy: &StoragePolicy, user_id: &str, incoming: usize) -> bool {
+    let quota = policy.quota_bytes(&user_plan(pool, user_id).await);
+    match used_bytes(pool, user_id).await {
+        Ok(used) => used.saturating_add(incoming as i64) <= quota,
+        // Never block writes because the usage query failed
+        Err(_) => true,
+    }
+}
+
+/// Files still pointing at a message that no longer exists. Messages of archived months are
+/// gone from the database on purpose, so files from those months are left alone.
+const ORPHANED_FILES_SQL: &str = "FROM files f
+    WHERE f.message_id IS NOT NULL
+      AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.id = f.message_id)
+      AND substr(f.created_at, 1, 7) NOT IN (SELECT month FROM archived_partitions WHERE table_name = 'messages')";
+
+#[derive(Serialize, Default)]
+pub struct OrphanReport {
+    pub files: i64,
+    pub file_bytes: i64,
+    pub blobs: i64,
+    pub blob_bytes: i64,
+}
+
+async fn blob_totals(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> Result<(i64, i64), sqlx::Error> {
+    let row = sqlx::query("SELECT COUNT(*) AS n, COALESCE(SUM(size), 0) AS bytes FROM file_blobs")
+        .fetch_one(&mut *tx)
+        .await?;
+    Ok((row.get("n"), row.get("bytes")))
+}
+
+/// Deletes files left behind by messages removed before deletes cascaded, then recounts blob
+/// references and drops blobs nothing points at. With `dry_run` the same work is rolled back,
+/// so the report shows what a real run would free.
+pub async fn purge_orphans(pool: &SqlitePool, dry_run: bool) -> Result<OrphanReport, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut report = OrphanReport::default();
+
+    let files = sqlx::query(&format!("SELECT COUNT(*) AS n, COALESCE(SUM(f.size), 0) AS bytes {}", ORPHANED_FILES_SQL))
+        .fetch_one(&mut tx)
+        .await?;
+    report.files = files.get("n");
+    report.file_bytes = files.get("bytes");
+
+    let (blobs_before, blob_bytes_before) = blob_totals(&mut tx).await?;
+    sqlx::query(&format!("DELETE FROM files WHERE id IN (SELECT f.id {})", ORPHANED_FILES_SQL))
+        .execute(&mut tx)
+        .await?;
+    // Counts can drift from rows removed before the release trigger existed
+    sqlx::query("UPDATE file_blobs SET ref_count = (SELECT COUNT(*) FROM files WHERE files.sha256 = file_blobs.sha256)")
+        .execute(&mut tx)
+        .await?;
+    sqlx::query("DELETE FROM file_blobs WHERE ref_count <= 0")
+        .execute(&mut tx)
+        .await?;
+    let (blobs_after, blob_bytes_after) = blob_totals(&mut tx).await?;
+    report.blobs = blobs_before - blobs_after;
+    report.blob_bytes = blob_bytes_before - blob_bytes_after;
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+    Ok(report)
+}
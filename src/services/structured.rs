@@ -0,0 +1,163 @@
+use serde_json::Value;
+
+use crate::i18n::Locale;
+
+/// Nesting and size caps for client-supplied schemas, which end up in every prompt of the turn
+const MAX_SCHEMA_DEPTH: usize = 8;
+const MAX_SCHEMA_BYTES: usize = 16 * 1024;
+
+/// Checks a `response_schema` before it is sent to the model. Only the subset of JSON Schema
+/// that `validate` understands is meaningful: `type`, `properties`, `required`, `items`,
+/// `enum`, `minItems`/`maxItems`, `minimum`/`maximum` and `additionalProperties: false`.
+pub fn check_schema(schema: &Value) -> Result<(), String> {
+    if schema.to_string().len() > MAX_SCHEMA_BYTES {
+        return Err(format!("schema is larger than {} bytes", MAX_SCHEMA_BYTES));
+    }
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        return Err("top-level type must be \"object\"".to_string());
+    }
+    check_node(schema, "$", 0)
+}
+
+fn check_node(node: &Value, path: &str, depth: usize) -> Result<(), String> {
+    if depth > MAX_SCHEMA_DEPTH {
+        return Err(format!("{}: nested deeper than {} levels", path, MAX_SCHEMA_DEPTH));
+    }
+    let obj = node.as_object().ok_or_else(|| format!("{}: schema must be an object", path))?;
+    if let Some(t) = obj.get("type") {
+        let known = |v: &Value| matches!(v.as_str(), Some("object" | "array" | "string" | "number" | "integer" | "boolean" | "null"));
+        let ok = match t {
+            Value::Array(types) => types.iter().all(known),
+            other => known(other),
+        };
+        if !ok {
+            return Err(format!("{}: unknown type {}", path, t));
+        }
+    }
+    if let Some(props) = obj.get("properties") {
+        let props = props.as_object().ok_or_else(|| format!("{}: properties must be an object", path))?;
+        for (name, sub) in props {
+            check_node(sub, &format!("{}.{}", path, name), depth + 1)?;
+        }
+    }
+    if let Some(items) = obj.get("items") {
+        check_node(items, &format!("{}[]", path), depth + 1)?;
+    }
+    Ok(())
+}
+
+/// Added to the system prompt; models without native JSON-schema support still get told
+pub fn instruction(schema: &Value, locale: Locale) -> String {
+    match locale {
+        Locale::Ru => format!(
+            "\n\nФОРМАТ ОТВЕТА: ответь одним JSON-объектом, строго соответствующим этой JSON Schema, без markdown и пояснений вне JSON. Текстовые поля пиши на русском.\n{}",
+            schema
+        ),
+        Locale::En => format!(
+            "\n\nRESPONSE FORMAT: reply with a single JSON object that strictly matches this JSON Schema, with no markdown or text outside the JSON.\n{}",
+            schema
+        ),
+    }
+}
+
+/// The model's reply as JSON, tolerating a ```json fence around it
+pub fn parse_answer(raw: &str) -> Option<Value> {
+    let trimmed = raw.trim();
+    let body = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(body.trim()).ok()
+}
+
+/// Every place `value` breaks `schema`, as `path: problem`; empty when it conforms
+pub fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_node(value, schema, "$", &mut errors);
+    errors
+}
+
+fn type_matches(value: &Value, t: &str) -> bool {
+    match t {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn validate_node(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    match schema.get("type") {
+        Some(Value::String(t)) if !type_matches(value, t) => {
+            errors.push(format!("{}: expected {}", path, t));
+            return;
+        }
+        Some(Value::Array(types)) if !types.iter().filter_map(Value::as_str).any(|t| type_matches(value, t)) => {
+            errors.push(format!("{}: expected one of {}", path, Value::Array(types.clone())));
+            return;
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            errors.push(format!("{}: not one of {}", path, Value::Array(allowed.clone())));
+        }
+    }
+
+    if let Some(n) = value.as_f64() {
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            if n < min {
+                errors.push(format!("{}: below minimum {}", path, min));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            if n > max {
+                errors.push(format!("{}: above maximum {}", path, max));
+            }
+        }
+    }
+
+    if let Some(obj) = value.as_object() {
+        let props = schema.get("properties").and_then(Value::as_object);
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !obj.contains_key(name) {
+                    errors.push(format!("{}.{}: required", path, name));
+                }
+            }
+        }
+        for (name, field) in obj {
+            match props.and_then(|p| p.get(name)) {
+                Some(sub) => validate_node(field, sub, &format!("{}.{}", path, name), errors),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    errors.push(format!("{}.{}: not allowed", path, name));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+            if (items.len() as u64) < min {
+                errors.push(format!("{}: fewer than {} items", path, min));
+            }
+        }
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+            if items.len() as u64 > max {
+                errors.push(format!("{}: more than {} items", path, max));
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                validate_node(item, item_schema, &format!("{}[{}]", path, i), errors);
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+
+use crate::i18n::Locale;
+use crate::services::{openai, tokens};
+use crate::state::AppState;
+
+/// When history gets summarized, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummaryPolicy {
+    pub enabled: bool,
+    /// Estimated tokens of history sent with each message
+    pub token_budget: usize,
+    /// Most recent history entries that are always sent verbatim
+    pub keep_recent: usize,
+}
+
+impl Default for SummaryPolicy {
+    fn default() -> Self {
+        SummaryPolicy {
+            enabled: true,
+            token_budget: 12_000,
+            keep_recent: 12,
+        }
+    }
+}
+
+pub fn estimate_tokens(entries: &[(String, String)]) -> usize {
+    entries.iter().map(|(_, content)| tokens::count_message(content)).sum()
+}
+
+/// Identifies the exact entries a summary was built from, so edits to older messages invalidate it
+fn digest(entries: &[(String, String)]) -> String {
+    let mut hasher = Sha256::new();
+    for (role, content) in entries {
+        hasher.update(role.as_bytes());
+        hasher.update([0]);
+        hasher.update(content.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn summary_note(summary: &str, locale: Locale) -> (String, String) {
+    let header = match locale {
+        Locale::Ru => "Краткое содержание предыдущей части разговора:",
+        Locale::En => "Summary of the earlier part of this conversation:",
+    };
+    ("system".to_string(), format!("{}\n{}", header, summary))
+}
+
+/// Drops the oldest entries until the rest fits the budget; used when summarizing fails
+fn truncate_to_budget(mut history: Vec<(String, String)>, budget: usize) -> Vec<(String, String)> {
+    let mut used = estimate_tokens(&history);
+    while history.len() > 1 && used > budget {
+        used -= tokens::count_message(&history.remove(0).1);
+    }
+    history
+}
+
+/// Returns history that fits the token budget: a stored summary of older entries plus
+/// the recent ones. The summary is extended only when the uncovered part no longer fits.
+pub async fn compact_history(
+    state: &AppState,
+    conversation_id: &str,
+    history: Vec<(String, String)>,
+    locale: Locale,
+) -> Vec<(String, String)> {
+    let policy = state.config.load().summarization.clone();
+    if !policy.enabled || estimate_tokens(&history) <= policy.token_budget {
+        return history;
+    }
+    let pool = &state.pool;
+
+    // Reuse the stored summary only if the entries it covers are unchanged
+    let stored = load_summary(pool, conversation_id).await
+        .filter(|(_, covered, hash)| *covered <= history.len() && digest(&history[..*covered]) == *hash);
+    let (previous, covered) = match &stored {
+        Some((summary, covered, _)) => (Some(summary.as_str()), *covered),
+        None => (None, 0),
+    };
+
+    if let Some(summary) = previous {
+        let mut candidate = vec![summary_note(summary, locale)];
+        candidate.extend_from_slice(&history[covered..]);
+        if estimate_tokens(&candidate) <= policy.token_budget {
+            return candidate;
+        }
+    }
+
+    let split = history.len().saturating_sub(policy.keep_recent).max(covered);
+    if split == covered {
+        // Nothing new to fold in; the recent entries alone are over budget
+        let mut trimmed = previous.map(|s| vec![summary_note(s, locale)]).unwrap_or_default();
+        trimmed.extend(truncate_to_budget(history[covered..].to_vec(), policy.token_budget));
+        return trimmed;
+    }
+
+    match openai::summarize_history(state, locale, previous, &history[covered..split]).await {
+        Ok(summary) => {
+            if let Err(e) = save_summary(pool, conversation_id, &summary, split, &digest(&history[..split])).await {
+                eprintln!("Conversation summary save failed: {}", e);
+            }
+            let mut compacted = vec![summary_note(&summary, locale)];
+            compacted.extend_from_slice(&history[split..]);
+            compacted
+        }
+        Err(e) => {
+            eprintln!("Conversation summarization failed: {}", e);
+            truncate_to_budget(history, policy.token_budget)
+        }
+    }
+}
+
+async fn load_summary(pool: &SqlitePool, conversation_id: &str) -> Option<(String, usize, String)> {
+    let row = sqlx::query("SELECT summary, covered_entries, covered_digest FROM conversation_summaries WHERE conversation_id = ?")
+        .bind(conversation_id)
+        .fetch_optional(pool)
+        .await
+        .ok()??;
+    Some((row.get("summary"), row.get::<i64, _>("covered_entries") as usize, row.get("covered_digest")))
+}
+
+async fn save_summary(pool: &SqlitePool, conversation_id: &str, summary: &str, covered: usize, covered_digest: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO conversation_summaries (conversation_id, summary, covered_entries, covered_digest, updated_at)
+         VALUES (?, ?, ?, ?, ?)
+         ON CONFLICT(conversation_id) DO UPDATE SET
+            summary = excluded.summary,
+            covered_entries = excluded.covered_entries,
+            covered_digest = excluded.covered_digest,
+            updated_at = excluded.updated_at"
+    )
+    .bind(conversation_id)
+    .bind(summary)
+    .bind(covered as i64)
+    .bind(covered_digest)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -8,6 +8,8 @@ struct SendMessageRequest {
     text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     parse_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to_message_id: Option<i64>,
 }
 
 #[derive(Serialize)]
@@ -71,6 +73,7 @@ impl TelegramBot {
             chat_id: self.group_chat_id,
             text: message,
             parse_mode: Some("HTML".to_string()),
+            reply_to_message_id: None,
         };
 
         let url = format!("{}/sendMessage", self.api_url);
@@ -97,6 +100,48 @@ impl TelegramBot {
         }
     }
 
+    pub fn group_chat_id(&self) -> i64 {
+        self.group_chat_id
+    }
+
+    /// Sends pre-formatted HTML to `chat_id` (the support group when `None`), optionally as a reply
+    pub async fn send_text(
+        &self,
+        chat_id: Option<i64>,
+        text: &str,
+        reply_to_message_id: Option<i64>,
+    ) -> Result<i64, Box<dyn std::error::Error>> {
+        let request = SendMessageRequest {
+            chat_id: chat_id.unwrap_or(self.group_chat_id),
+            text: text.to_string(),
+            parse_mode: Some("HTML".to_string()),
+            reply_to_message_id,
+        };
+
+        let url = format!("{}/sendMessage", self.api_url);
+        let response_text = self
+            .client
+            .post(&url)
+            .json(&request)
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        let response: TelegramResponse = serde_json::from_str(&response_text)
+            .map_err(|e| format!("Failed to parse Telegram response: {}", e))?;
+
+        if response.ok {
+            if let Some(msg) = response.result {
+                Ok(msg.message_id)
+            } else {
+                Err("No message ID in response".into())
+            }
+        } else {
+            Err(format!("Telegram API error: {:?}", response.description).into())
+        }
+    }
+
     pub async fn send_photo(
         &self,
         photo_url: &str,
@@ -0,0 +1,92 @@
+use actix_web::HttpRequest;
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use sqlx::SqlitePool;
+
+/// IANA zone for an id such as `Europe/Moscow`
+pub fn parse(value: &str) -> Option<Tz> {
+    value.trim().parse().ok()
+}
+
+/// Zone the client reports in `X-Timezone`, if it is a known IANA id
+pub fn detect(req: &HttpRequest) -> Option<Tz> {
+    req.headers()
+        .get("X-Timezone")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse)
+}
+
+/// Zone saved in the user's profile
+pub async fn user_timezone(pool: &SqlitePool, user_id: &str) -> Option<Tz> {
+    let stored: Option<String> = sqlx::query_scalar("SELECT timezone FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .flatten();
+    stored.as_deref().and_then(parse)
+}
+
+/// Zone to show a user's timestamps in: the device's current zone, then the profile's, then UTC
+pub async fn for_request(req: &HttpRequest, pool: &SqlitePool, user_id: &str) -> Tz {
+    match detect(req) {
+        Some(tz) => tz,
+        None => user_timezone(pool, user_id).await.unwrap_or(Tz::UTC),
+    }
+}
+
+/// Reads a stored timestamp: RFC 3339, or SQLite's `YYYY-MM-DD HH:MM:SS`, which is UTC
+pub fn parse_stored(ts: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(ts) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|naive| Utc.from_utc_datetime(&naive))
+}
+
+/// A stored timestamp as RFC 3339 carrying the zone's offset
+pub fn to_local(ts: &str, tz: Tz) -> Option<String> {
+    parse_stored(ts).map(|dt| dt.with_timezone(&tz).to_rfc3339())
+}
+
+/// The latest `weekday` `hour`:00 in `tz` that is not after `now`, e.g. "Monday 9:00 local".
+/// `None` only when that hour doesn't exist on that day because of a DST jump.
+pub fn last_weekly_slot(tz: Tz, now: DateTime<Utc>, weekday: Weekday, hour: u32) -> Option<DateTime<Utc>> {
+    let local = now.with_timezone(&tz);
+    let days_back = (local.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+    let time = NaiveTime::from_hms_opt(hour, 0, 0)?;
+    let slot_on = |days_back: i64| {
+        let date = local.date_naive() - chrono::Duration::days(days_back);
+        tz.from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+    };
+    let slot = slot_on(days_back as i64)?;
+    if slot <= now {
+        Some(slot)
+    } else {
+        // Today is the day but the hour is still ahead
+        slot_on(days_back as i64 + 7)
+    }
+}
+
+/// The latest `hour`:00 in `tz` that is not after `now`: today's if it has passed, else yesterday's.
+/// `None` only when that hour doesn't exist on that day because of a DST jump.
+pub fn last_daily_slot(tz: Tz, now: DateTime<Utc>, hour: u32) -> Option<DateTime<Utc>> {
+    let local = now.with_timezone(&tz);
+    let time = NaiveTime::from_hms_opt(hour, 0, 0)?;
+    let slot_on = |days_back: i64| {
+        let date = local.date_naive() - chrono::Duration::days(days_back);
+        tz.from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc))
+    };
+    let slot = slot_on(0)?;
+    if slot <= now {
+        Some(slot)
+    } else {
+        slot_on(1)
+    }
+}
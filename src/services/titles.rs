@@ -0,0 +1,47 @@
+use crate::i18n::Locale;
+use crate::metrics::{self, LlmSignal};
+use crate::services::openai;
+use crate::state::AppState;
+
+/// First line of the question, for when the title call fails
+fn fallback(message: &str) -> Option<String> {
+    message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.chars().take(80).collect())
+}
+
+/// Names a still untitled conversation from its first question with the small title model,
+/// falling back to the question's first line. Stored only once; returns the title if it was.
+pub async fn ensure_title(state: &AppState, conversation_id: &str, message: &str, locale: Locale) -> Option<String> {
+    let pool = &state.pool;
+    let untitled: Option<i64> = sqlx::query_scalar(
+        "SELECT 1 FROM conversations WHERE id = ? AND (title IS NULL OR title = '')"
+    )
+    .bind(conversation_id)
+    .fetch_optional(pool)
+    .await
+    .ok()
+    .flatten();
+    untitled?;
+
+    let title = match openai::generate_title(state, locale, message).await {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Title generation failed for {}: {}", conversation_id, e);
+            metrics::record(LlmSignal::TitleMissing, &openai::title_model(state), locale);
+            fallback(message)?
+        }
+    };
+
+    // A title set by the user in the meantime wins
+    let stored = sqlx::query("UPDATE conversations SET title = ? WHERE id = ? AND (title IS NULL OR title = '')")
+        .bind(&title)
+        .bind(conversation_id)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+        .unwrap_or(0);
+    (stored > 0).then_some(title)
+}
@@ -0,0 +1,69 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::Arc;
+
+use actix_web::middleware::DefaultHeaders;
+
+/// Serving HTTPS directly, for small deployments without a reverse proxy in front
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+    /// Seconds browsers should stick to HTTPS; 0 leaves the HSTS header out
+    pub hsts_max_age: u64,
+}
+
+impl TlsSettings {
+    /// Enabled when both TLS_CERT_PATH and TLS_KEY_PATH are set
+    pub fn from_env() -> Option<Self> {
+        let cert_path = env::var("TLS_CERT_PATH").ok().filter(|v| !v.trim().is_empty())?;
+        let key_path = env::var("TLS_KEY_PATH").ok().filter(|v| !v.trim().is_empty())?;
+        let hsts_max_age = env::var("HSTS_MAX_AGE")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(31_536_000);
+        Some(TlsSettings { cert_path, key_path, hsts_max_age })
+    }
+
+    /// Reads the PEM certificate chain and private key. HTTP/2 is negotiated over ALPN by
+    /// actix when binding with this config, falling back to HTTP/1.1
+    pub fn server_config(&self) -> io::Result<rustls::ServerConfig> {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&self.cert_path)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        if certs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("no certificate in {}", self.cert_path)));
+        }
+        let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&self.key_path)?))?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("no private key in {}", self.key_path)))?;
+
+        // Explicit provider: reqwest pulls in rustls too, and two enabled providers would make the default ambiguous
+        rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|b| b.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Plain-text HTTP/2 (h2c) next to HTTP/1.1 when not serving TLS, for proxies that speak it
+pub fn h2c_enabled() -> bool {
+    env::var("HTTP2_CLEARTEXT")
+        .map(|v| matches!(v.trim(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Security headers on every response. HSTS is only sent when this process terminates TLS,
+/// as browsers ignore it over plain HTTP anyway. Handlers that set one of these keep their own value
+pub fn secure_headers(hsts_max_age: Option<u64>) -> DefaultHeaders {
+    let headers = DefaultHeaders::new()
+        .add(("X-Content-Type-Options", "nosniff"))
+        .add(("X-Frame-Options", "DENY"))
+        // Share links carry their token in the URL
+        .add(("Referrer-Policy", "no-referrer"));
+    match hsts_max_age {
+        Some(max_age) if max_age > 0 => headers.add((
+            "Strict-Transport-Security",
+            format!("max-age={}; includeSubDomains", max_age),
+        )),
+        _ => headers,
+    }
+}
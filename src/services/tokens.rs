@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use tiktoken_rs::CoreBPE;
+
+/// Framing the chat format adds around every message
+const MESSAGE_OVERHEAD: usize = 4;
+/// Rough cost of one image on the vision models we route to
+const IMAGE_TOKENS: usize = 1_000;
+/// Prompt budget when neither OPENROUTER_CONTEXT_BUDGETS nor OPENROUTER_CONTEXT_TOKENS covers the model
+const DEFAULT_PROMPT_BUDGET: usize = 16_000;
+
+/// o200k is the closest public match for the models behind OpenRouter and handles Cyrillic well
+fn encoder() -> Option<&'static CoreBPE> {
+    static ENCODER: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    ENCODER
+        .get_or_init(|| match tiktoken_rs::o200k_base() {
+            Ok(bpe) => Some(bpe),
+            Err(e) => {
+                eprintln!("Tokenizer unavailable, estimating by length: {}", e);
+                None
+            }
+        })
+        .as_ref()
+}
+
+pub fn count(text: &str) -> usize {
+    match encoder() {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => text.chars().count() / 4 + 1,
+    }
+}
+
+/// Tokens one chat message takes in the prompt
+pub fn count_message(content: &str) -> usize {
+    count(content) + MESSAGE_OVERHEAD
+}
+
+pub fn count_images(n: usize) -> usize {
+    n * IMAGE_TOKENS
+}
+
+/// Prompt budget for `model`: its entry in OPENROUTER_CONTEXT_BUDGETS
+/// (`model=tokens,model=tokens`), then OPENROUTER_CONTEXT_TOKENS, then the default
+pub fn prompt_budget(model: &str) -> usize {
+    let per_model: HashMap<String, usize> = std::env::var("OPENROUTER_CONTEXT_BUDGETS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|entry| {
+            let (name, tokens) = entry.split_once('=')?;
+            Some((name.trim().to_string(), tokens.trim().parse().ok()?))
+        })
+        .collect();
+    per_model
+        .get(model)
+        .copied()
+        .or_else(|| std::env::var("OPENROUTER_CONTEXT_TOKENS").ok()?.parse().ok())
+        .unwrap_or(DEFAULT_PROMPT_BUDGET)
+}
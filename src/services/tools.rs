@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use chrono::Datelike;
+use chrono_tz::Tz;
+use serde_json::{json, Value};
+use sqlx::{Row, SqlitePool};
+
+use crate::handlers::bookings;
+use crate::i18n::Locale;
+use crate::models::AnalyticsSource;
+use crate::services::timezone;
+
+/// Rounds of tool calls before the model has to answer with what it has
+pub const MAX_TOOL_ROUNDS: usize = 4;
+
+/// Server tools the model may call, in OpenRouter's `tools` format
+pub fn definitions() -> Vec<Value> {
+    vec![
+        json!({
+            "type": "function",
+            "function": {
+                "name": "get_market_analytics",
+                "description": "Current market analytics from our own data: this week's top trends and fastest growing countries, this month's growing and shrinking niches, and the tracked trends with why they are popular. Use it instead of guessing market numbers.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "section": {
+                            "type": "string",
+                            "enum": ["weekly", "niches", "trends", "all"],
+                            "description": "Which analytics to return"
+                        }
+                    },
+                    "required": ["section"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "convert_currency",
+                "description": "Converts an amount between currencies at today's Central Bank of Russia rate. Currency codes are ISO 4217, e.g. RUB, USD, EUR, KZT.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "amount": { "type": "number" },
+                        "from": { "type": "string" },
+                        "to": { "type": "string" }
+                    },
+                    "required": ["amount", "from", "to"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "break_even_table",
+                "description": "Computes the break-even point for a product and a table of profit at several monthly sales volumes.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "fixed_costs": { "type": "number", "description": "Fixed costs per month" },
+                        "price_per_unit": { "type": "number" },
+                        "variable_cost_per_unit": { "type": "number" },
+                        "volumes": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "description": "Monthly unit volumes to tabulate; defaults to steps around the break-even point"
+                        }
+                    },
+                    "required": ["fixed_costs", "price_per_unit", "variable_cost_per_unit"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "get_booking_link",
+                "description": "The user's own bookable services with their public booking links, work hours and time zone. Use it whenever the user wants to share a booking link or let clients sign up.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "service": { "type": "string", "description": "Part of a service title to narrow the list down" }
+                    }
+                }
+            }
+        }),
+    ]
+}
+
+/// Runs a tool the model asked for. Failures are reported back to the model as
+/// `{"error": ...}` so it can recover instead of failing the whole answer.
+/// Analytics rows handed to the model are added to `sources`, each once.
+pub async fn call(pool: &SqlitePool, user_id: &str, locale: Locale, name: &str, arguments: &str, sources: &mut Vec<AnalyticsSource>) -> Value {
+    let args: Value = match serde_json::from_str(arguments) {
+        Ok(v) => v,
+        Err(_) => return json!({ "error": "arguments are not valid JSON" }),
+    };
+    let result = match name {
+        "get_market_analytics" => market_analytics(pool, locale, args["section"].as_str().unwrap_or("all"), sources).await,
+        "convert_currency" => convert_currency(&args).await,
+        "break_even_table" => break_even_table(&args),
+        "get_booking_link" => booking_links(pool, user_id, args["service"].as_str()).await,
+        _ => Err(format!("unknown tool {}", name)),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Assistant tool {} failed: {}", name, e);
+        json!({ "error": e })
+    })
+}
+
+fn cite(sources: &mut Vec<AnalyticsSource>, kind: &str, id: String, title: &str, period: Option<&str>) {
+    if !sources.iter().any(|s| s.kind == kind && s.id == id) {
+        sources.push(AnalyticsSource {
+            kind: kind.to_string(),
+            id,
+            title: title.to_string(),
+            period: period.map(str::to_string),
+        });
+    }
+}
+
+async fn market_analytics(
+    pool: &SqlitePool,
+    locale: Locale,
+    section: &str,
+    sources: &mut Vec<AnalyticsSource>,
+) -> Result<Value, String> {
+    let lang = match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    };
+    let today = chrono::Utc::now().date_naive();
+    let week_start = today.week(chrono::Weekday::Mon).first_day().format("%Y-%m-%d").to_string();
+    let month_start = format!("{}-01", today.format("%Y-%m"));
+    let mut out = json!({});
+
+    if matches!(section, "weekly" | "all") {
+        let top = sqlx::query(
+            "SELECT t.id, t.position, COALESCE(i.title, t.title) AS title, t.increase, t.request_percent
+             FROM top_weekly_trends t LEFT JOIN top_weekly_trends_i18n i ON i.id = t.id AND i.locale = ?
+             WHERE t.week_start = ? ORDER BY t.position"
+        )
+        .bind(lang)
+        .bind(&week_start)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        let geo = sqlx::query(
+            "SELECT g.id, COALESCE(i.country, g.country) AS country, g.increase
+             FROM geo_trends g LEFT JOIN geo_trends_i18n i ON i.id = g.id AND i.locale = ?
+             WHERE g.week_start = ? ORDER BY g.rank"
+        )
+        .bind(lang)
+        .bind(&week_start)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        for r in &top {
+            cite(sources, "weekly_trend", r.get("id"), &r.get::<String, _>("title"), Some(&week_start));
+        }
+        for r in &geo {
+            cite(sources, "geo_trend", r.get("id"), &r.get::<String, _>("country"), Some(&week_start));
+        }
+        out["weekly"] = json!({
+            "week_start": week_start,
+            "top_trends": top.iter().map(|r| json!({
+                "position": r.get::<i64, _>("position"),
+                "title": r.get::<String, _>("title"),
+                "increase_percent": r.get::<f64, _>("increase"),
+                "request_percent": r.get::<Option<f64>, _>("request_percent"),
+            })).collect::<Vec<_>>(),
+            "growing_countries": geo.iter().map(|r| json!({
+                "country": r.get::<String, _>("country"),
+                "increase_percent": r.get::<f64, _>("increase"),
+            })).collect::<Vec<_>>(),
+        });
+    }
+
+    if matches!(section, "niches" | "all") {
+        let niches = sqlx::query(
+            "SELECT n.id, COALESCE(i.title, n.title) AS title, n.change
+             FROM niches_month n LEFT JOIN niches_month_i18n i ON i.id = n.id AND i.locale = ?
+             WHERE n.month_start = ? ORDER BY ABS(n.change) DESC"
+        )
+        .bind(lang)
+        .bind(&month_start)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        for r in &niches {
+            cite(sources, "niche", r.get("id"), &r.get::<String, _>("title"), Some(&month_start));
+        }
+        out["niches"] = json!({
+            "month_start": month_start,
+            "niches": niches.iter().map(|r| json!({
+                "title": r.get::<String, _>("title"),
+                "change_percent": r.get::<f64, _>("change"),
+            })).collect::<Vec<_>>(),
+        });
+    }
+
+    if matches!(section, "trends" | "all") {
+        let trends = sqlx::query(
+            "SELECT t.name, t.percent_change, COALESCE(i.description, t.description) AS description,
+                    COALESCE(i.why_popular, t.why_popular) AS why_popular
+             FROM analytics_trends t LEFT JOIN analytics_trends_i18n i ON i.name = t.name AND i.locale = ?
+             ORDER BY t.percent_change DESC LIMIT 20"
+        )
+        .bind(lang)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        for r in &trends {
+            let name: String = r.get("name");
+            cite(sources, "trend", name.clone(), &name, None);
+        }
+        out["trends"] = json!(trends.iter().map(|r| json!({
+            "name": r.get::<String, _>("name"),
+            "percent_change": r.get::<Option<f64>, _>("percent_change"),
+            "description": r.get::<Option<String>, _>("description"),
+            "why_popular": r.get::<Option<String>, _>("why_popular"),
+        })).collect::<Vec<_>>());
+    }
+
+    Ok(out)
+}
+
+/// Rubles per one unit of each currency, with when they were fetched
+type Rates = (HashMap<String, f64>, Instant);
+
+fn rates_cache() -> &'static Mutex<Option<Rates>> {
+    static CACHE: OnceLock<Mutex<Option<Rates>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(None))
+}
+
+const RATES_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// CBR daily rates (CURRENCY_RATES_URL overrides the feed), cached for an hour
+async fn rub_rates() -> Result<HashMap<String, f64>, String> {
+    if let Some((rates, fetched)) = rates_cache().lock().unwrap().as_ref() {
+        if fetched.elapsed() < RATES_TTL {
+            return Ok(rates.clone());
+        }
+    }
+
+    let url = std::env::var("CURRENCY_RATES_URL").unwrap_or_else(|_| "https://www.cbr-xml-daily.ru/daily_json.js".to_string());
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let body: Value = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| format!("rates request failed: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("rates response is not JSON: {}", e))?;
+
+    // {"Valute": {"USD": {"Nominal": 1, "Value": 92.5}, ...}}, Value being rubles per Nominal units
+    let mut rates: HashMap<String, f64> = HashMap::new();
+    rates.insert("RUB".to_string(), 1.0);
+    if let Some(valute) = body["Valute"].as_object() {
+        for (code, entry) in valute {
+            if let (Some(value), Some(nominal)) = (entry["Value"].as_f64(), entry["Nominal"].as_f64()) {
+                if nominal > 0.0 {
+                    rates.insert(code.to_uppercase(), value / nominal);
+                }
+            }
+        }
+    }
+    if rates.len() == 1 {
+        return Err("rates feed returned no currencies".to_string());
+    }
+
+    *rates_cache().lock().unwrap() = Some((rates.clone(), Instant::now()));
+    Ok(rates)
+}
+
+async fn convert_currency(args: &Value) -> Result<Value, String> {
+    let amount = args["amount"].as_f64().ok_or("amount must be a number")?;
+    let from = args["from"].as_str().ok_or("from is required")?.trim().to_uppercase();
+    let to = args["to"].as_str().ok_or("to is required")?.trim().to_uppercase();
+
+    let rates = rub_rates().await?;
+    let from_rub = *rates.get(&from).ok_or_else(|| format!("unknown currency {}", from))?;
+    let to_rub = *rates.get(&to).ok_or_else(|| format!("unknown currency {}", to))?;
+    let rate = from_rub / to_rub;
+
+    Ok(json!({
+        "amount": amount,
+        "from": from,
+        "to": to,
+        "rate": rate,
+        "result": (amount * rate * 100.0).round() / 100.0,
+        "source": "Central Bank of Russia daily rates",
+    }))
+}
+
+async fn booking_links(pool: &SqlitePool, user_id: &str, service: Option<&str>) -> Result<Value, String> {
+    let rows = sqlx::query(
+        "SELECT id, title, duration_minutes, work_start, work_end FROM booking_services WHERE user_id = ? ORDER BY title"
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?;
+    let filter = service.map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty());
+    let tz = timezone::user_timezone(pool, user_id).await.unwrap_or(Tz::UTC);
+
+    let services: Vec<Value> = rows
+        .iter()
+        .filter(|r| match &filter {
+            Some(f) => r.get::<String, _>("title").to_lowercase().contains(f),
+            None => true,
+        })
+        .map(|r| json!({
+            "title": r.get::<String, _>("title"),
+            "duration_minutes": r.get::<i64, _>("duration_minutes"),
+            "work_start": r.get::<String, _>("work_start"),
+            "work_end": r.get::<String, _>("work_end"),
+            "booking_url": bookings::booking_url(&r.get::<String, _>("id")),
+        }))
+        .collect();
+    if services.is_empty() {
+        return Ok(json!({
+            "services": [],
+            "note": "No matching bookable service yet; the user can add one under Bookings in the app.",
+        }));
+    }
+    Ok(json!({ "timezone": tz.name(), "services": services }))
+}
+
+fn break_even_table(args: &Value) -> Result<Value, String> {
+    let fixed = args["fixed_costs"].as_f64().ok_or("fixed_costs must be a number")?;
+    let price = args["price_per_unit"].as_f64().ok_or("price_per_unit must be a number")?;
+    let variable = args["variable_cost_per_unit"].as_f64().ok_or("variable_cost_per_unit must be a number")?;
+    let margin = price - variable;
+    if margin <= 0.0 {
+        return Err("price_per_unit must be higher than variable_cost_per_unit, otherwise there is no break-even point".to_string());
+    }
+
+    let break_even_units = (fixed / margin).ceil();
+    let volumes: Vec<f64> = match args["volumes"].as_array() {
+        Some(list) if !list.is_empty() => list.iter().filter_map(Value::as_f64).take(20).collect(),
+        // Half the break-even volume up to double it
+        _ => [0.5, 0.75, 1.0, 1.25, 1.5, 2.0].iter().map(|k| (break_even_units * k).round()).collect(),
+    };
+    let round = |v: f64| (v * 100.0).round() / 100.0;
+
+    Ok(json!({
+        "contribution_margin_per_unit": round(margin),
+        "contribution_margin_ratio": round(margin / price),
+        "break_even_units": break_even_units,
+        "break_even_revenue": round(break_even_units * price),
+        "table": volumes.iter().map(|&units| json!({
+            "units": units,
+            "revenue": round(units * price),
+            "total_costs": round(fixed + units * variable),
+            "profit": round(units * margin - fixed),
+        })).collect::<Vec<_>>(),
+    }))
+}
@@ -0,0 +1,70 @@
+use sqlx::{Row, SqlitePool};
+
+/// Coarse topics with lowercase stems matched against user messages (RU + EN)
+const TOPICS: &[(&str, &[&str])] = &[
+    ("taxes", &["налог", "ндс", "усн", "патент", "декларац", "фнс", "tax", "vat", "irs"]),
+    ("ads", &["реклам", "таргет", "продвижен", "маркетинг", "smm", "seo", "advert", "marketing", "promotion"]),
+    ("hiring", &["найм", "ваканс", "сотрудник", "персонал", "зарплат", "hiring", "recruit", "employee", "salary"]),
+    ("finance", &["кредит", "инвест", "бюджет", "прибыл", "финанс", "loan", "invest", "budget", "profit", "cash flow"]),
+    ("legal", &["договор", "юрист", "лиценз", "ип ", "ооо", "contract", "lawyer", "license", "register a company"]),
+    ("sales", &["продаж", "клиент", "цен", "sales", "customer", "pricing"]),
+];
+
+pub const OTHER_TOPIC: &str = "other";
+
+/// Conversations idle for this long are considered finished and get classified
+const IDLE_MINUTES: i64 = 60;
+
+/// Picks the topic whose stems occur most often; ties go to the earlier topic
+pub fn classify(texts: &[String]) -> &'static str {
+    let joined = texts.join(" ").to_lowercase();
+    TOPICS
+        .iter()
+        .map(|(topic, stems)| (*topic, stems.iter().map(|s| joined.matches(s).count()).sum::<usize>()))
+        .fold((OTHER_TOPIC, 0), |best, cur| if cur.1 > best.1 { cur } else { best })
+        .0
+}
+
+/// Tags finished conversations of users who opted in to anonymized analytics
+pub async fn classify_pending(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let idle_before = (chrono::Utc::now() - chrono::Duration::minutes(IDLE_MINUTES)).to_rfc3339();
+
+    let rows = sqlx::query(
+        "SELECT c.id, u.business_type
+         FROM conversations c
+         JOIN users u ON u.id = c.user_id
+         LEFT JOIN conversation_topics t ON t.conversation_id = c.id
+         WHERE u.analytics_opt_in = 1 AND t.conversation_id IS NULL
+           AND (SELECT MAX(timestamp) FROM messages m WHERE m.conversation_id = c.id) <= ?
+         LIMIT 200"
+    )
+    .bind(&idle_before)
+    .fetch_all(pool)
+    .await?;
+
+    let mut classified = 0;
+    for r in rows {
+        let conversation_id: String = r.get("id");
+        let business_type: Option<String> = r.try_get("business_type").ok();
+
+        let texts: Vec<String> = sqlx::query_scalar(
+            "SELECT content FROM messages WHERE conversation_id = ? AND role = 'user'"
+        )
+        .bind(&conversation_id)
+        .fetch_all(pool)
+        .await?;
+
+        sqlx::query(
+            "INSERT OR IGNORE INTO conversation_topics (conversation_id, topic, business_type, classified_at) VALUES (?, ?, ?, ?)"
+        )
+        .bind(&conversation_id)
+        .bind(classify(&texts))
+        .bind(business_type)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+        classified += 1;
+    }
+
+    Ok(classified)
+}
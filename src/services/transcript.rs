@@ -0,0 +1,192 @@
+use crate::i18n::Locale;
+use crate::services::pdf::{self, PdfLine};
+
+pub struct TranscriptMessage {
+    pub role: String,
+    pub content: String,
+    pub timestamp: String,
+    /// File names attached to the message
+    pub attachments: Vec<String>,
+}
+
+pub struct Transcript {
+    pub title: String,
+    pub created_at: String,
+    pub messages: Vec<TranscriptMessage>,
+}
+
+#[derive(Clone, Copy)]
+pub enum TranscriptFormat {
+    Pdf,
+    Markdown,
+    Text,
+}
+
+impl TranscriptFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "pdf" => Some(TranscriptFormat::Pdf),
+            "md" | "markdown" => Some(TranscriptFormat::Markdown),
+            "txt" | "text" => Some(TranscriptFormat::Text),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Pdf => "pdf",
+            TranscriptFormat::Markdown => "md",
+            TranscriptFormat::Text => "txt",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            TranscriptFormat::Pdf => "application/pdf",
+            TranscriptFormat::Markdown => "text/markdown; charset=utf-8",
+            TranscriptFormat::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+fn speaker(locale: Locale, role: &str) -> &'static str {
+    match (locale, role) {
+        (Locale::Ru, "user") => "Вы",
+        (Locale::Ru, _) => "Ассистент",
+        (Locale::En, "user") => "You",
+        (Locale::En, _) => "Assistant",
+    }
+}
+
+fn attachments_label(locale: Locale) -> &'static str {
+    match locale {
+        Locale::Ru => "Вложения",
+        Locale::En => "Attachments",
+    }
+}
+
+/// `2025-03-01T10:15:00+00:00` -> `2025-03-01 10:15`
+fn short_time(timestamp: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| timestamp.to_string())
+}
+
+pub fn render(transcript: &Transcript, format: TranscriptFormat, locale: Locale) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match format {
+        TranscriptFormat::Markdown => Ok(render_markdown(transcript, locale).into_bytes()),
+        TranscriptFormat::Text => Ok(render_text(transcript, locale).into_bytes()),
+        TranscriptFormat::Pdf => pdf::render_lines(&transcript.title, &pdf_lines(transcript, locale)),
+    }
+}
+
+fn render_markdown(transcript: &Transcript, locale: Locale) -> String {
+    let mut out = format!("# {}\n\n_{}_\n", transcript.title, short_time(&transcript.created_at));
+    for m in &transcript.messages {
+        out.push_str(&format!("\n## {} · {}\n\n{}\n", speaker(locale, &m.role), short_time(&m.timestamp), m.content.trim()));
+        if !m.attachments.is_empty() {
+            out.push_str(&format!("\n**{}:**\n", attachments_label(locale)));
+            for name in &m.attachments {
+                out.push_str(&format!("- {}\n", name));
+            }
+        }
+    }
+    out
+}
+
+fn render_text(transcript: &Transcript, locale: Locale) -> String {
+    let mut out = format!("{}\n{}\n", transcript.title, short_time(&transcript.created_at));
+    for m in &transcript.messages {
+        out.push_str(&format!("\n[{}] {}:\n{}\n", short_time(&m.timestamp), speaker(locale, &m.role), m.content.trim()));
+        if !m.attachments.is_empty() {
+            out.push_str(&format!("{}: {}\n", attachments_label(locale), m.attachments.join(", ")));
+        }
+    }
+    out
+}
+
+fn pdf_lines(transcript: &Transcript, locale: Locale) -> Vec<PdfLine> {
+    let mut lines = vec![
+        PdfLine::heading(transcript.title.clone()),
+        PdfLine::text(short_time(&transcript.created_at)),
+    ];
+    for m in &transcript.messages {
+        lines.push(PdfLine::blank());
+        lines.push(PdfLine {
+            text: format!("{} · {}", speaker(locale, &m.role), short_time(&m.timestamp)),
+            size: 11.0,
+            indent: 0.0,
+        });
+        // Paragraphs are wrapped by the renderer, blank lines are kept
+        for paragraph in m.content.trim().lines() {
+            lines.push(PdfLine { text: paragraph.to_string(), size: 10.0, indent: 4.0 });
+        }
+        if !m.attachments.is_empty() {
+            lines.push(PdfLine {
+                text: format!("{}: {}", attachments_label(locale), m.attachments.join(", ")),
+                size: 9.0,
+                indent: 4.0,
+            });
+        }
+    }
+    lines
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Standalone read-only page for shared conversations; message text is shown as is, not as markdown
+pub fn render_html(transcript: &Transcript, locale: Locale) -> String {
+    let lang = match locale {
+        Locale::Ru => "ru",
+        Locale::En => "en",
+    };
+    let mut body = String::new();
+    for m in &transcript.messages {
+        body.push_str(&format!(
+            "<div class=\"msg {}\"><div class=\"meta\">{} · {}</div><div class=\"text\">{}</div>",
+            if m.role == "user" { "user" } else { "assistant" },
+            speaker(locale, &m.role),
+            short_time(&m.timestamp),
+            escape_html(m.content.trim()),
+        ));
+        if !m.attachments.is_empty() {
+            let names: Vec<String> = m.attachments.iter().map(|n| escape_html(n)).collect();
+            body.push_str(&format!("<div class=\"files\">{}: {}</div>", attachments_label(locale), names.join(", ")));
+        }
+        body.push_str("</div>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"{lang}\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<meta name=\"robots\" content=\"noindex\">
+<title>{title}</title>
+<style>
+body {{ font-family: -apple-system, 'Segoe UI', Roboto, sans-serif; max-width: 760px; margin: 2rem auto; padding: 0 1rem; color: #1d1d1f; }}
+.msg {{ margin: 1rem 0; padding: .75rem 1rem; border-radius: 12px; background: #f2f2f7; }}
+.msg.user {{ background: #e3f0ff; }}
+.meta {{ font-size: .8rem; color: #6e6e73; margin-bottom: .25rem; }}
+.text {{ white-space: pre-wrap; }}
+.files {{ font-size: .8rem; color: #6e6e73; margin-top: .5rem; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p class=\"meta\">{created}</p>
+{body}</body>
+</html>
+",
+        lang = lang,
+        title = escape_html(&transcript.title),
+        created = short_time(&transcript.created_at),
+        body = body,
+    )
+}
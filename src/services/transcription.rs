@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::i18n::Locale;
+
+/// Largest recording accepted, the upload limit of OpenAI's Whisper API
+pub const MAX_AUDIO_BYTES: usize = 25 * 1024 * 1024;
+
+/// Voice notes from the apps (m4a, ogg/opus, webm) and common recorder formats
+pub fn audio_mime_allowed(mime: &str) -> bool {
+    mime.starts_with("audio/") || matches!(mime, "video/webm" | "video/mp4" | "application/ogg")
+}
+
+/// Any Whisper-compatible `/audio/transcriptions` endpoint: OpenAI by default, or a self-hosted
+/// server through TRANSCRIPTION_API_URL
+fn endpoint() -> String {
+    std::env::var("TRANSCRIPTION_API_URL")
+        .ok()
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| "https://api.openai.com/v1/audio/transcriptions".to_string())
+}
+
+fn model() -> String {
+    std::env::var("TRANSCRIPTION_MODEL").unwrap_or_else(|_| "whisper-1".to_string())
+}
+
+/// A key for the default endpoint, or a custom URL: self-hosted servers often need no key
+pub fn is_configured() -> bool {
+    std::env::var("TRANSCRIPTION_API_KEY").is_ok_and(|k| !k.trim().is_empty())
+        || std::env::var("TRANSCRIPTION_API_URL").is_ok_and(|u| !u.trim().is_empty())
+}
+
+#[derive(Deserialize)]
+struct TranscriptionResponse {
+    text: String,
+}
+
+/// Text spoken in the recording, trimmed; empty when nothing was said. `locale` is passed as a
+/// language hint, which helps short clips the most.
+pub async fn transcribe(
+    bytes: Vec<u8>,
+    filename: &str,
+    mime: &str,
+    locale: Locale,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(120))
+        .build()?;
+
+    let form = reqwest::multipart::Form::new()
+        .text("model", model())
+        .text("language", match locale { Locale::Ru => "ru", Locale::En => "en" })
+        .text("response_format", "json")
+        .part(
+            "file",
+            reqwest::multipart::Part::bytes(bytes)
+                .file_name(filename.to_string())
+                .mime_str(mime)?,
+        );
+
+    let mut request = client.post(endpoint()).multipart(form);
+    if let Ok(key) = std::env::var("TRANSCRIPTION_API_KEY") {
+        request = request.bearer_auth(key.trim());
+    }
+    let res = request.send().await?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let body = res.text().await.unwrap_or_default();
+        return Err(format!("Transcription API returned {}: {}", status, body).into());
+    }
+
+    let body: TranscriptionResponse = res.json().await?;
+    Ok(body.text.trim().to_string())
+}
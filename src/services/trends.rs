@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use actix_web::rt;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::handlers::analytics::{save_weekly_trends, GeoTrendItem, Scope, TopTrendItem, WeeklyTrendsUpsert};
+use crate::i18n::Locale;
+use crate::services::{analytics_refresh, reference};
+use crate::state::AppState;
+
+/// Weekly trends from measured Google Trends search interest (through SerpApi, SERPAPI_API_KEY)
+/// instead of hand-entered or model-estimated figures, tunable through the runtime config file
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrendsPolicy {
+    /// Off by default: every refresh spends API credits
+    pub enabled: bool,
+    /// Searches compared against each other; Google Trends takes at most five at once
+    pub keywords: Vec<TrendKeyword>,
+    /// ISO codes of the countries ranked for the top keyword's geo trends
+    pub countries: Vec<String>,
+}
+
+impl Default for TrendsPolicy {
+    fn default() -> Self {
+        TrendsPolicy {
+            enabled: false,
+            keywords: Vec::new(),
+            countries: ["RU", "KZ", "BY", "UZ", "US", "DE", "GB"].iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TrendKeyword {
+    /// Search term sent to Google Trends
+    pub query: String,
+    /// Titles shown in the app; the query when unset
+    pub title_en: Option<String>,
+    pub title_ru: Option<String>,
+}
+
+impl TrendKeyword {
+    fn title(&self, locale: Locale) -> String {
+        let title = match locale {
+            Locale::Ru => self.title_ru.as_deref(),
+            Locale::En => self.title_en.as_deref(),
+        };
+        title.filter(|t| !t.trim().is_empty()).unwrap_or(self.query.as_str()).to_string()
+    }
+}
+
+const MAX_KEYWORDS: usize = 5;
+const MAX_COUNTRIES: usize = 10;
+const TICK: Duration = Duration::from_secs(60 * 60);
+/// Daily points compared: the last week against the week before
+const WINDOW_DAYS: usize = 7;
+/// Recorded in `analytics_refresh_runs` as the source of the week's trends
+const SOURCE: &str = "google-trends";
+
+/// Refreshes this week's trends in the background once per week whenever the policy is enabled.
+/// A failed refresh is retried on the next tick.
+pub fn spawn(state: AppState) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(TICK);
+        loop {
+            interval.tick().await;
+            let policy = state.config.load().trends.clone();
+            if !policy.enabled {
+                continue;
+            }
+            let week_start = current_week_start();
+            match analytics_refresh::generated(&state.pool, "weekly_trends", &Scope::default(), &week_start).await {
+                Ok(false) => {}
+                Ok(true) => continue,
+                Err(e) => {
+                    eprintln!("Trends: run lookup failed: {}", e);
+                    continue;
+                }
+            }
+            match refresh(&state, &policy).await {
+                Ok(()) => println!("Trends: refreshed weekly trends for {}", week_start),
+                Err(e) => eprintln!("Trends: refresh failed: {}", e),
+            }
+        }
+    });
+}
+
+fn current_week_start() -> String {
+    chrono::Utc::now().date_naive().week(chrono::Weekday::Mon).first_day().format("%Y-%m-%d").to_string()
+}
+
+/// Replaces this week's top trends and geo trends with the configured keywords' search interest:
+/// the two fastest-growing keywords over the last week, and the three countries where the top
+/// one grew most
+pub async fn refresh(state: &AppState, policy: &TrendsPolicy) -> Result<(), Box<dyn std::error::Error>> {
+    let keywords: Vec<&TrendKeyword> = policy
+        .keywords
+        .iter()
+        .filter(|k| !k.query.trim().is_empty())
+        .take(MAX_KEYWORDS)
+        .collect();
+    if keywords.len() < 2 {
+        return Err("trends.keywords needs at least two keywords".into());
+    }
+    let api_key = std::env::var("SERPAPI_API_KEY")?;
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let queries: Vec<&str> = keywords.iter().map(|k| k.query.trim()).collect();
+    let series = interest_over_time(&client, &api_key, &queries, None).await?;
+    let mut ranked: Vec<(usize, f64, f64)> = series
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, values)| {
+            let (last, previous) = weekly_sums(values)?;
+            growth(last, previous).map(|g| (idx, g, last))
+        })
+        .collect();
+    if ranked.len() < 2 {
+        return Err("not enough search interest history for the keywords".into());
+    }
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let total_last_week: f64 = ranked.iter().map(|(_, _, last)| last).sum();
+    let (top_idx, top_growth, top_last) = ranked[0];
+    let (second_idx, second_growth, _) = ranked[1];
+    let request_percent = (total_last_week > 0.0).then(|| round1(top_last / total_last_week * 100.0));
+
+    let mut geo: Vec<(&'static str, f64)> = Vec::new();
+    for country in policy.countries.iter().take(MAX_COUNTRIES) {
+        let code = match reference::country_code(country) {
+            Some(c) => c,
+            None => continue,
+        };
+        let values = match interest_over_time(&client, &api_key, &queries[top_idx..=top_idx], Some(code)).await {
+            Ok(mut s) if !s.is_empty() => s.remove(0),
+            Ok(_) => continue,
+            Err(e) => {
+                eprintln!("Trends: interest in {} failed: {}", code, e);
+                continue;
+            }
+        };
+        if let Some(g) = weekly_sums(&values).and_then(|(last, previous)| growth(last, previous)) {
+            geo.push((code, g));
+        }
+    }
+    geo.sort_by(|a, b| b.1.total_cmp(&a.1));
+    geo.truncate(3);
+
+    let build = |locale: Locale| WeeklyTrendsUpsert {
+        current_top: TopTrendItem {
+            title: keywords[top_idx].title(locale),
+            increase: round1(top_growth),
+            request_percent,
+        },
+        second_place: TopTrendItem {
+            title: keywords[second_idx].title(locale),
+            increase: round1(second_growth),
+            request_percent: None,
+        },
+        geo_trends: geo
+            .iter()
+            .map(|(code, g)| GeoTrendItem {
+                country: reference::country_name(code, locale).to_string(),
+                increase: round1(*g),
+            })
+            .collect(),
+    };
+    let (en, ru) = (build(Locale::En), build(Locale::Ru));
+    save_weekly_trends(&state.pool, &[("en", &en), ("ru", &ru)]).await?;
+    analytics_refresh::record_run(&state.pool, "weekly_trends", &Scope::default(), &current_week_start(), SOURCE).await?;
+    Ok(())
+}
+
+/// Daily interest over the past month per query, oldest first, scaled 0..100 across all `queries`.
+/// Days Google still marks as partial are left out.
+async fn interest_over_time(
+    client: &Client,
+    api_key: &str,
+    queries: &[&str],
+    geo: Option<&str>,
+) -> Result<Vec<Vec<f64>>, Box<dyn std::error::Error>> {
+    let q = queries.join(",");
+    let mut params = vec![
+        ("engine", "google_trends"),
+        ("data_type", "TIMESERIES"),
+        ("date", "today 1-m"),
+        ("q", q.as_str()),
+        ("api_key", api_key),
+    ];
+    if let Some(geo) = geo {
+        params.push(("geo", geo));
+    }
+    let res = client.get("https://serpapi.com/search.json").query(&params).send().await?;
+    if !res.status().is_success() {
+        return Err(format!("SerpApi answered {}", res.status()).into());
+    }
+    let body: serde_json::Value = res.json().await?;
+    if let Some(error) = body.get("error").and_then(|e| e.as_str()) {
+        return Err(format!("SerpApi: {}", error).into());
+    }
+
+    let mut series = vec![Vec::new(); queries.len()];
+    let timeline = body
+        .pointer("/interest_over_time/timeline_data")
+        .and_then(|t| t.as_array())
+        .cloned()
+        .unwrap_or_default();
+    for point in timeline {
+        if point.get("partial_data").and_then(|p| p.as_bool()).unwrap_or(false) {
+            continue;
+        }
+        let values = point.get("values").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        for (idx, value) in values.iter().enumerate().take(queries.len()) {
+            let v = value.get("extracted_value").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            series[idx].push(v);
+        }
+    }
+    Ok(series)
+}
+
+/// Interest summed over the last week and over the week before; `None` without two full weeks
+fn weekly_sums(values: &[f64]) -> Option<(f64, f64)> {
+    if values.len() < WINDOW_DAYS * 2 {
+        return None;
+    }
+    let end = values.len();
+    let last: f64 = values[end - WINDOW_DAYS..].iter().sum();
+    let previous: f64 = values[end - WINDOW_DAYS * 2..end - WINDOW_DAYS].iter().sum();
+    Some((last, previous))
+}
+
+/// Week-over-week change in percent; `None` when there was no interest the week before
+fn growth(last: f64, previous: f64) -> Option<f64> {
+    (previous > 0.0).then(|| (last - previous) / previous * 100.0)
+}
+
+fn round1(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Serialize;
+
+/// Format of the synthesized answers
+pub const MIME: &str = "audio/mpeg";
+/// Input limit of OpenAI's speech API; longer answers are cut at a sentence boundary
+const MAX_INPUT_CHARS: usize = 4096;
+
+/// Any OpenAI-compatible `/audio/speech` endpoint: OpenAI by default, or a self-hosted server
+/// through TTS_API_URL
+fn endpoint() -> String {
+    std::env::var("TTS_API_URL")
+        .ok()
+        .filter(|u| !u.trim().is_empty())
+        .unwrap_or_else(|| "https://api.openai.com/v1/audio/speech".to_string())
+}
+
+/// A key for the default endpoint, or a custom URL: self-hosted servers often need no key
+pub fn is_configured() -> bool {
+    std::env::var("TTS_API_KEY").is_ok_and(|k| !k.trim().is_empty())
+        || std::env::var("TTS_API_URL").is_ok_and(|u| !u.trim().is_empty())
+}
+
+#[derive(Serialize)]
+struct SpeechRequest<'a> {
+    model: String,
+    input: &'a str,
+    voice: String,
+    response_format: &'static str,
+}
+
+/// The answer as it should be read out: table rows, code fences and markdown markers dropped
+pub fn speakable(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut in_code = false;
+    for line in markdown.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code || line.starts_with('|') {
+            continue;
+        }
+        let line = line.trim_start_matches(['#', '>']).trim_start();
+        let line = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")).unwrap_or(line);
+        let line: String = line.chars().filter(|c| !matches!(c, '*' | '_' | '`')).collect();
+        if !line.is_empty() {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+
+    if out.chars().count() <= MAX_INPUT_CHARS {
+        return out.trim_end().to_string();
+    }
+    let cut: String = out.chars().take(MAX_INPUT_CHARS).collect();
+    match cut.rfind(['.', '!', '?', '\n']) {
+        Some(end) => cut[..=end].trim_end().to_string(),
+        None => cut,
+    }
+}
+
+/// MP3 of `text` read out by TTS_VOICE (default `alloy`) with TTS_MODEL (default `tts-1`)
+pub async fn synthesize(text: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let input = speakable(text);
+    if input.is_empty() {
+        return Err("Nothing to read out".into());
+    }
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(120))
+        .build()?;
+    let body = SpeechRequest {
+        model: std::env::var("TTS_MODEL").unwrap_or_else(|_| "tts-1".to_string()),
+        input: &input,
+        voice: std::env::var("TTS_VOICE").unwrap_or_else(|_| "alloy".to_string()),
+        response_format: "mp3",
+    };
+
+    let mut request = client.post(endpoint()).json(&body);
+    if let Ok(key) = std::env::var("TTS_API_KEY") {
+        request = request.bearer_auth(key.trim());
+    }
+    let res = request.send().await?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let body = res.text().await.unwrap_or_default();
+        return Err(format!("Speech API returned {}: {}", status, body).into());
+    }
+    Ok(res.bytes().await?.to_vec())
+}
@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::i18n::Locale;
+
+/// Look of the embedded chat; every field has a default so a widget works unstyled
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WidgetTheme {
+    /// `#rrggbb`
+    pub primary_color: String,
+    /// `bottom-right` or `bottom-left`
+    pub position: String,
+    pub title: Option<String>,
+    /// Shown as the assistant's first message before the visitor writes
+    pub greeting: Option<String>,
+    pub placeholder: Option<String>,
+}
+
+impl Default for WidgetTheme {
+    fn default() -> Self {
+        Self {
+            primary_color: "#1f6feb".to_string(),
+            position: "bottom-right".to_string(),
+            title: None,
+            greeting: None,
+            placeholder: None,
+        }
+    }
+}
+
+/// Longest title, greeting or placeholder accepted
+const MAX_THEME_TEXT: usize = 300;
+
+impl WidgetTheme {
+    pub fn is_valid(&self) -> bool {
+        let color_ok = self.primary_color.len() == 7
+            && self.primary_color.starts_with('#')
+            && self.primary_color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        let texts_ok = [&self.title, &self.greeting, &self.placeholder]
+            .into_iter()
+            .flatten()
+            .all(|t| t.chars().count() <= MAX_THEME_TEXT);
+        color_ok && matches!(self.position.as_str(), "bottom-right" | "bottom-left") && texts_ok
+    }
+}
+
+/// `scheme://host[:port]` in the form browsers send as `Origin`; `None` for anything else
+pub fn normalize_origin(value: &str) -> Option<String> {
+    let origin = value.trim().trim_end_matches('/').to_lowercase();
+    let host = origin
+        .strip_prefix("https://")
+        .or_else(|| origin.strip_prefix("http://"))?;
+    let valid = !host.is_empty() && !host.contains(['/', '?', '#', '@', ' ']);
+    valid.then_some(origin)
+}
+
+/// Public key the embed snippet carries; it only opens the widget endpoints of one widget
+pub fn new_key() -> String {
+    format!("wk_{}", Uuid::new_v4().simple())
+}
+
+pub fn embed_code(public_key: &str) -> String {
+    let base = std::env::var("PUBLIC_BASE_URL").unwrap_or_default();
+    format!(
+        r#"<script src="{}/widget.js" data-widget="{}" async></script>"#,
+        base.trim_end_matches('/'),
+        public_key
+    )
+}
+
+/// Counts a visitor message, and a new visitor session, towards today's usage
+pub async fn record_usage(pool: &SqlitePool, widget_id: &str, new_session: bool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO widget_usage (widget_id, day, messages, sessions) VALUES (?, ?, 1, ?)
+         ON CONFLICT(widget_id, day) DO UPDATE SET
+            messages = messages + 1,
+            sessions = sessions + excluded.sessions"
+    )
+    .bind(widget_id)
+    .bind(chrono::Utc::now().format("%Y-%m-%d").to_string())
+    .bind(new_session as i64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Puts the model in the business's place: visitors are its customers, not the owner
+pub fn visitor_instruction(business_type: &str, locale: Locale) -> String {
+    match locale {
+        Locale::Ru => format!(
+            "Ты отвечаешь посетителям сайта компании (сфера: {}) от ее имени. Собеседник — клиент, а не владелец бизнеса: \
+             отвечай на вопросы о товарах, услугах и условиях, опираясь на материалы компании. \
+             Не давай советов по ведению бизнеса и не придумывай цены, сроки и обещания, которых нет в материалах.",
+            business_type
+        ),
+        Locale::En => format!(
+            "You are answering visitors of a company's website ({}) on its behalf. The person is a customer, not the business owner: \
+             answer questions about products, services and terms using the company's materials. \
+             Don't give business advice and don't make up prices, timelines or promises that aren't in the materials.",
+            business_type
+        ),
+    }
+}
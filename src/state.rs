@@ -1,22 +1,56 @@
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use crate::models::{Message};
+use crate::config::SharedConfig;
+use crate::services::selfcheck::Readiness;
 use sqlx::SqlitePool;
+use tokio::sync::watch;
 
-pub type UserId = String;
-pub type ConversationHistory = Arc<Mutex<HashMap<UserId, Vec<Message>>>>;
+/// Answers being generated, by conversation id: (generation id, cancel signal)
+pub type InFlightGenerations = Arc<Mutex<HashMap<String, (String, watch::Sender<bool>)>>>;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub conversations: ConversationHistory,
     pub pool: SqlitePool,
+    pub config: SharedConfig,
+    pub generations: InFlightGenerations,
+    /// Result of the startup self-check
+    pub readiness: Arc<Readiness>,
+    /// HS256 key for session tokens
+    pub jwt_secret: Arc<Vec<u8>>,
 }
 
 impl AppState {
-    pub fn new(pool: SqlitePool) -> Self {
+    pub fn new(pool: SqlitePool, config: SharedConfig, readiness: Readiness) -> Self {
         Self {
-            conversations: Arc::new(Mutex::new(HashMap::new())),
             pool,
+            config,
+            generations: Arc::new(Mutex::new(HashMap::new())),
+            readiness: Arc::new(readiness),
+            jwt_secret: Arc::new(crate::services::jwt::load_secret()),
+        }
+    }
+
+    /// Registers an answer being generated for `conversation_id`; the receiver turns true on cancel.
+    /// A newer generation for the same conversation replaces the older one's registration.
+    pub fn begin_generation(&self, conversation_id: &str) -> (String, watch::Receiver<bool>) {
+        let generation_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = watch::channel(false);
+        self.generations.lock().unwrap().insert(conversation_id.to_string(), (generation_id.clone(), tx));
+        (generation_id, rx)
+    }
+
+    /// Signals the in-flight generation for `conversation_id`; false when nothing is running
+    pub fn cancel_generation(&self, conversation_id: &str) -> bool {
+        match self.generations.lock().unwrap().remove(conversation_id) {
+            Some((_, tx)) => tx.send(true).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn finish_generation(&self, conversation_id: &str, generation_id: &str) {
+        let mut generations = self.generations.lock().unwrap();
+        if generations.get(conversation_id).is_some_and(|(id, _)| id == generation_id) {
+            generations.remove(conversation_id);
         }
     }
 }
\ No newline at end of file